@@ -32,6 +32,18 @@ impl Collection {
         &mut self,
         req: AddImageOcclusionNoteRequest,
     ) -> Result<OpOutput<()>> {
+        let deck_id = self.get_current_deck()?.id;
+        self.add_image_occlusion_note_to_deck(req, deck_id)
+            .map(|out| out.map(|_| ()))
+    }
+
+    /// Like [Collection::add_image_occlusion_note], but adds the note's cards
+    /// to the provided deck, and returns the id of the new note.
+    pub fn add_image_occlusion_note_to_deck(
+        &mut self,
+        req: AddImageOcclusionNoteRequest,
+        deck_id: DeckId,
+    ) -> Result<OpOutput<NoteId>> {
         // image file
         let image_bytes = read_file(&req.image_path)?;
         let image_filename = Path::new(&req.image_path)
@@ -46,7 +58,6 @@ impl Collection {
 
         let image_tag = format!(r#"<img src="{}">"#, &actual_image_name_after_adding);
 
-        let current_deck = self.get_current_deck()?;
         let notetype_id: NotetypeId = req.notetype_id.into();
         self.transact(Op::ImageOcclusion, |col| {
             let nt = if notetype_id.0 == 0 {
@@ -65,9 +76,9 @@ impl Collection {
             note.set_field(idxs.header as usize, req.header)?;
             note.set_field(idxs.back_extra as usize, req.back_extra)?;
             note.tags = req.tags;
-            col.add_note_inner(&mut note, current_deck.id)?;
+            col.add_note_inner(&mut note, deck_id)?;
 
-            Ok(())
+            Ok(note.id)
         })
     }
 
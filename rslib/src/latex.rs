@@ -2,6 +2,7 @@
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::sync::LazyLock;
 
 use regex::Captures;
@@ -9,6 +10,7 @@ use regex::Regex;
 
 use crate::cloze::expand_clozes_to_reveal_latex;
 use crate::media::files::sha1_of_data;
+use crate::prelude::*;
 use crate::text::strip_html;
 
 pub(crate) static LATEX: LazyLock<Regex> = LazyLock::new(|| {
@@ -111,10 +113,61 @@ fn image_link_for_fname(src: &str, fname: &str) -> String {
     )
 }
 
+/// LaTeX whose image has not been generated yet, with the header and footer
+/// of the notetype it is rendered with.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MissingLatex {
+    pub note_id: NoteId,
+    pub fname: String,
+    pub latex: String,
+    pub header: String,
+    pub footer: String,
+    pub svg: bool,
+}
+
+impl Collection {
+    /// LaTeX in notes whose image is not in the media folder. Each image is
+    /// listed once, with the first note that uses it.
+    pub fn missing_latex(&mut self) -> Result<Vec<MissingLatex>> {
+        let notetypes = self.get_all_notetypes()?;
+        let mut seen = HashSet::new();
+        let mut missing = vec![];
+
+        for nid in self.search_notes_unordered("")? {
+            let note = self.storage.get_note(nid)?.or_not_found(nid)?;
+            let nt = notetypes
+                .iter()
+                .find(|nt| nt.id == note.notetype_id)
+                .or_not_found(note.notetype_id)?;
+            for field in note.fields() {
+                let (_, extracted) = extract_latex_expanding_clozes(field, nt.config.latex_svg);
+                for latex in extracted {
+                    if seen.contains(&latex.fname) || self.media_folder.join(&latex.fname).exists()
+                    {
+                        continue;
+                    }
+                    seen.insert(latex.fname.clone());
+                    missing.push(MissingLatex {
+                        note_id: nid,
+                        fname: latex.fname,
+                        latex: latex.latex,
+                        header: nt.config.latex_pre.clone(),
+                        footer: nt.config.latex_post.clone(),
+                        svg: nt.config.latex_svg,
+                    });
+                }
+            }
+        }
+
+        Ok(missing)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::latex::extract_latex;
     use crate::latex::ExtractedLatex;
+    use crate::tests::NoteAdder;
 
     #[test]
     fn latex() {
@@ -146,4 +199,18 @@ mod test {
             }]
         );
     }
+
+    #[test]
+    fn missing_latex() {
+        let mut col = crate::collection::Collection::new();
+        NoteAdder::basic(&mut col)
+            .fields(&["[$]x^2[/$]", "[$]x^2[/$] [latex]y[/latex]"])
+            .add(&mut col);
+
+        let missing = col.missing_latex().unwrap();
+        assert_eq!(missing.len(), 2);
+        assert_eq!(missing[0].latex, "$x^2$");
+        assert!(missing[0].header.contains(r"\begin{document}"));
+        assert!(!missing[0].svg);
+    }
 }
@@ -58,6 +58,41 @@ impl Collection {
     pub fn media_checker(&mut self) -> Result<MediaChecker<'_>> {
         MediaChecker::new(self)
     }
+
+    /// Map each file referenced by a note, including LaTeX images, to the
+    /// notes that reference it. Unlike a media check, notes are not modified,
+    /// so references that a check would normalize are reported as written.
+    pub fn media_references(&mut self) -> Result<HashMap<String, Vec<NoteId>>> {
+        let notetypes = self.get_all_notetypes()?;
+        let mut referenced_files: HashMap<String, Vec<NoteId>> = HashMap::new();
+
+        for nid in self.search_notes_unordered("")? {
+            let note = self.storage.get_note(nid)?.or_not_found(nid)?;
+            let nt = notetypes
+                .iter()
+                .find(|nt| nt.id == note.notetype_id)
+                .or_not_found(note.notetype_id)?;
+            let mut tracker = |fname: String| {
+                let nids = referenced_files.entry(fname).or_default();
+                if nids.last() != Some(&nid) {
+                    nids.push(nid);
+                }
+            };
+            for field in note.fields() {
+                for media_ref in extract_media_refs(field) {
+                    if REMOTE_FILENAME.is_match(media_ref.fname)
+                        || media_ref.fname_decoded.starts_with("data:")
+                    {
+                        continue;
+                    }
+                    tracker(normalize_to_nfc(&media_ref.fname_decoded).into_owned());
+                }
+            }
+            extract_latex_refs(&note, &mut tracker, nt.config.latex_svg);
+        }
+
+        Ok(referenced_files)
+    }
 }
 
 pub struct MediaChecker<'a> {
@@ -635,6 +670,26 @@ Unused: unused.jpg
         Ok(())
     }
 
+    #[test]
+    fn media_references() -> Result<()> {
+        let (_dir, _mgr, mut col) = common_setup()?;
+        let note = NoteAdder::basic(&mut col)
+            .fields(&[
+                "<img src=\"a&amp;b.jpg\">[sound:a.mp3]",
+                "<img src=\"http://x/y.jpg\">",
+            ])
+            .add(&mut col);
+
+        let refs = col.media_references()?;
+        assert_eq!(refs["a&b.jpg"], vec![note.id]);
+        assert_eq!(refs["a.mp3"], vec![note.id]);
+        assert!(!refs.contains_key("http://x/y.jpg"));
+        // existing notes in the test collection are included
+        assert_eq!(refs["ぱぱ.jpg"], vec![NoteId(1581236461568)]);
+
+        Ok(())
+    }
+
     fn files_in_dir(dir: &Path) -> Vec<String> {
         let mut files = fs::read_dir(dir)
             .unwrap()
@@ -86,6 +86,28 @@ impl TryFrom<anki_proto::notes::AddNoteRequest> for AddNoteRequest {
     }
 }
 
+/// How [Collection::add_notes_with_duplicate_policy] handles a note whose
+/// first field matches an existing note of the same notetype.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Leave the existing note alone and don't add the new one.
+    #[default]
+    Skip,
+    /// Add the new note anyway.
+    Allow,
+    /// Replace the existing note's fields and tags with the new note's.
+    UpdateExisting,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddNoteOutcome {
+    Added(NoteId),
+    /// The id of the existing duplicate, which was left unchanged.
+    Skipped(NoteId),
+    /// The id of the existing duplicate, which was updated.
+    Updated(NoteId),
+}
+
 impl Collection {
     pub fn add_note(&mut self, note: &mut Note, did: DeckId) -> Result<OpOutput<usize>> {
         self.transact(Op::AddNote, |col| col.add_note_inner(note, did))
@@ -101,6 +123,43 @@ impl Collection {
         })
     }
 
+    /// Add notes in a single undoable step, handling notes that duplicate
+    /// an existing note (or one added earlier in the same call) according to
+    /// `policy`. Returns an outcome for each request, in order.
+    pub fn add_notes_with_duplicate_policy(
+        &mut self,
+        requests: &mut [AddNoteRequest],
+        policy: DuplicatePolicy,
+    ) -> Result<OpOutput<Vec<AddNoteOutcome>>> {
+        self.transact(Op::AddNote, |col| {
+            let mut outcomes = Vec::with_capacity(requests.len());
+            for request in requests {
+                let duplicate = match policy {
+                    DuplicatePolicy::Allow => None,
+                    _ => col.first_field_duplicate(&request.note)?,
+                };
+                let outcome = match duplicate {
+                    None => {
+                        col.add_note_inner(&mut request.note, request.deck_id)?;
+                        AddNoteOutcome::Added(request.note.id)
+                    }
+                    Some(nid) if policy == DuplicatePolicy::Skip => AddNoteOutcome::Skipped(nid),
+                    Some(nid) => {
+                        let mut existing = col.storage.get_note(nid)?.or_not_found(nid)?;
+                        for (idx, field) in request.note.fields().iter().enumerate() {
+                            existing.set_field(idx, field)?;
+                        }
+                        existing.tags.clone_from(&request.note.tags);
+                        col.update_note_inner(&mut existing)?;
+                        AddNoteOutcome::Updated(nid)
+                    }
+                };
+                outcomes.push(outcome);
+            }
+            Ok(outcomes)
+        })
+    }
+
     /// Remove provided notes, and any cards that use them.
     pub fn remove_notes(&mut self, nids: &[NoteId]) -> Result<OpOutput<usize>> {
         let usn = self.usn()?;
@@ -610,7 +669,7 @@ impl Collection {
                     NoteFieldsState::Empty
                 } else if cloze_state != NoteFieldsState::Normal {
                     cloze_state
-                } else if self.is_duplicate(&stripped, note)? {
+                } else if self.find_duplicate(&stripped, note)?.is_some() {
                     NoteFieldsState::Duplicate
                 } else {
                     NoteFieldsState::Normal
@@ -621,15 +680,34 @@ impl Collection {
         })
     }
 
-    fn is_duplicate(&self, first_field: &str, note: &Note) -> Result<bool> {
+    /// The first other note of the same notetype whose first field matches
+    /// the note's. A note with an empty first field has no duplicates.
+    pub fn first_field_duplicate(&mut self, note: &Note) -> Result<Option<NoteId>> {
+        let Some(text) = note.fields.first() else {
+            return Ok(None);
+        };
+        let field1 = if self.get_config_bool(BoolKey::NormalizeNoteText) {
+            normalize_to_nfc(text)
+        } else {
+            text.into()
+        };
+        let stripped = strip_html_preserving_media_filenames(&field1);
+        if stripped.trim().is_empty() {
+            return Ok(None);
+        }
+        self.find_duplicate(&stripped, note)
+    }
+
+    fn find_duplicate(&self, first_field: &str, note: &Note) -> Result<Option<NoteId>> {
         let csum = field_checksum(first_field);
         Ok(self
             .storage
             .note_fields_by_checksum(note.notetype_id, csum)?
             .into_iter()
-            .any(|(nid, field)| {
-                nid != note.id && strip_html_preserving_media_filenames(&field) == first_field
-            }))
+            .find(|(nid, field)| {
+                *nid != note.id && strip_html_preserving_media_filenames(field) == first_field
+            })
+            .map(|(nid, _)| nid))
     }
 
     fn field_cloze_check(&mut self, note: &Note) -> Result<NoteFieldsState> {
@@ -685,6 +763,9 @@ fn note_differs_from_db(existing_note: &mut Note, note: &mut Note) -> bool {
 mod test {
     use super::anki_base91;
     use super::field_checksum;
+    use super::AddNoteOutcome;
+    use super::AddNoteRequest;
+    use super::DuplicatePolicy;
     use crate::config::BoolKey;
     use crate::decks::DeckId;
     use crate::error::Result;
@@ -855,4 +936,55 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn duplicate_policy() -> Result<()> {
+        let mut col = Collection::new();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let request = |front: &str, back: &str| -> Result<AddNoteRequest> {
+            let mut note = nt.new_note();
+            note.set_field(0, front)?;
+            note.set_field(1, back)?;
+            note.tags = vec![back.to_string()];
+            Ok(AddNoteRequest {
+                note,
+                deck_id: DeckId(1),
+            })
+        };
+
+        // duplicates within the batch are detected too
+        let mut requests = vec![request("a", "1")?, request("<b>a</b>", "2")?];
+        let outcomes = col
+            .add_notes_with_duplicate_policy(&mut requests, DuplicatePolicy::Skip)?
+            .output;
+        let AddNoteOutcome::Added(nid) = outcomes[0] else {
+            panic!("note not added");
+        };
+        assert_eq!(outcomes[1], AddNoteOutcome::Skipped(nid));
+
+        let mut requests = vec![request("a", "3")?, request("b", "4")?];
+        let outcomes = col
+            .add_notes_with_duplicate_policy(&mut requests, DuplicatePolicy::UpdateExisting)?
+            .output;
+        assert_eq!(outcomes[0], AddNoteOutcome::Updated(nid));
+        assert!(matches!(outcomes[1], AddNoteOutcome::Added(_)));
+        let note = col.storage.get_note(nid)?.unwrap();
+        assert_eq!(note.fields()[1], "3");
+        assert_eq!(note.tags, vec!["3"]);
+        assert_eq!(col.search_notes_unordered("")?.len(), 2);
+
+        // the whole batch is a single undo step
+        col.undo()?;
+        assert_eq!(col.search_notes_unordered("")?.len(), 1);
+        assert_eq!(col.storage.get_note(nid)?.unwrap().fields()[1], "1");
+
+        let mut requests = vec![request("a", "5")?];
+        let outcomes = col
+            .add_notes_with_duplicate_policy(&mut requests, DuplicatePolicy::Allow)?
+            .output;
+        assert!(matches!(outcomes[0], AddNoteOutcome::Added(id) if id != nid));
+        assert_eq!(col.search_notes_unordered("")?.len(), 2);
+
+        Ok(())
+    }
 }
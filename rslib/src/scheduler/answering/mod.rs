@@ -381,6 +381,9 @@ impl Collection {
                     }))
                 ),
             )?;
+        } else {
+            // The card may still be queued, so rebuild the queues on next fetch
+            self.clear_study_queues();
         }
 
         Ok(())
@@ -949,6 +952,42 @@ pub(crate) mod test {
         Ok(())
     }
 
+    // answers that bypass the queue, from grade_now or from a client holding
+    // a card's states, must not leave the answered card in the cached queue
+    #[test]
+    fn answering_outside_queue_rebuilds_queues() -> Result<()> {
+        let mut col = Collection::new();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        for _ in 0..2 {
+            let mut note = nt.new_note();
+            col.add_note(&mut note, DeckId(1))?;
+        }
+        let queued = col.get_queued_cards(2, false)?;
+        assert_eq!(queued.new_count, 2);
+        let first = queued.cards[0].card.id;
+        let second = queued.cards[1].card.id;
+
+        let states = col.get_scheduling_states(first)?;
+        col.answer_card(&mut CardAnswer {
+            card_id: first,
+            current_state: states.current,
+            new_state: states.good,
+            rating: Rating::Good,
+            answered_at: TimestampMillis::now(),
+            milliseconds_taken: 0,
+            custom_data: None,
+            from_queue: false,
+        })?;
+        let queued = col.get_queued_cards(2, false)?;
+        assert_eq!(queued.new_count, 1);
+        assert_eq!(queued.cards[0].card.id, second);
+
+        col.grade_now(&[second], 2)?;
+        assert_eq!(col.get_queued_cards(2, false)?.new_count, 0);
+
+        Ok(())
+    }
+
     fn assert_elapsed_secs_approx_equal(
         col: &mut Collection,
         shift_due_time: i32,
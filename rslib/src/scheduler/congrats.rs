@@ -16,8 +16,17 @@ pub(crate) struct CongratsInfo {
 impl Collection {
     pub fn congrats_info(&mut self) -> Result<anki_proto::scheduler::CongratsInfoResponse> {
         let deck = self.get_current_deck()?;
+        self.congrats_info_for_deck(&deck)
+    }
+
+    /// Like [Collection::congrats_info], for a deck other than the current
+    /// one. Nothing is written to the collection.
+    pub fn congrats_info_for_deck(
+        &mut self,
+        deck: &Deck,
+    ) -> Result<anki_proto::scheduler::CongratsInfoResponse> {
         let today = self.timing_today()?.days_elapsed;
-        let info = self.storage.congrats_info(&deck, today)?;
+        let info = self.storage.congrats_info(deck, today)?;
         let is_filtered_deck = deck.is_filtered();
         let deck_description = deck.rendered_description();
         let secs_until_next_learn = if info.next_learn_due == 0 {
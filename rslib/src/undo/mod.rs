@@ -211,6 +211,11 @@ impl Collection {
     pub fn add_custom_undo_step(&mut self, name: String) -> usize {
         self.state.undo.add_custom_step(name)
     }
+
+    /// Forget all undo and redo steps.
+    pub fn clear_undo(&mut self) {
+        self.discard_undo_and_study_queues();
+    }
 }
 
 impl Collection {
@@ -0,0 +1,561 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! Generates the client from the web app's OpenAPI spec: a type for each
+//! schema in `components/schemas`, and a method for each operation.
+//!
+//! The spec is read from the checked-in `openapi.json`, so building the
+//! client doesn't build the server. The server's contract tests fail when
+//! that file is out of date.
+
+use std::env;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use anki_io::read_to_string;
+use anki_io::write_file_if_changed;
+use anyhow::Context;
+use anyhow::Result;
+use inflections::Inflect;
+use serde_json::Map;
+use serde_json::Value;
+
+fn main() -> Result<()> {
+    let spec_path = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?).join("openapi.json");
+    println!("cargo:rerun-if-changed={}", spec_path.display());
+    let spec = read_to_string(&spec_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|text| Ok(serde_json::from_str::<Value>(&text)?))
+        .with_context(|| format!("Failed to read {}. {REGENERATE}", spec_path.display()))?;
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+
+    let mut types = String::new();
+    let mut operations = String::new();
+    write_types(&mut types, &spec)?;
+    write_operations(&mut operations, &mut types, &spec)?;
+
+    write_file_if_changed(out_dir.join("types.rs"), types)?;
+    write_file_if_changed(out_dir.join("operations.rs"), operations)?;
+    Ok(())
+}
+
+const REGENERATE: &str = "Regenerate it with \
+    `UPDATE_OPENAPI_SPEC=1 cargo test -p anki-webapp --test openapi_contract_test`.";
+
+const METHODS: &[&str] = &["get", "post", "put", "delete", "patch"];
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "yield",
+];
+
+fn write_types(out: &mut String, spec: &Value) -> Result<()> {
+    writeln!(out, "use serde::Deserialize;")?;
+    writeln!(out, "use serde::Serialize;")?;
+    let Some(schemas) = spec["components"]["schemas"].as_object() else {
+        return Ok(());
+    };
+    for (name, schema) in schemas {
+        writeln!(out)?;
+        write_type(out, &type_name(name), schema)?;
+    }
+    Ok(())
+}
+
+fn write_type(out: &mut String, name: &str, schema: &Value) -> Result<()> {
+    write_docs(out, schema, "")?;
+    if let Some(values) = string_enum(schema) {
+        return write_string_enum(out, name, &values);
+    }
+    if let Some(variants) = schema["oneOf"].as_array() {
+        if !schema["properties"].is_object() {
+            return match common_tag(variants) {
+                Some(tag) => write_tagged_enum(out, name, &tag, variants),
+                None => Ok(writeln!(out, "pub type {name} = serde_json::Value;")?),
+            };
+        }
+    }
+    if schema["properties"].is_object()
+        || (schema["type"] == "object" && !schema["additionalProperties"].is_object())
+    {
+        return write_struct(out, name, schema);
+    }
+    writeln!(out, "pub type {name} = {};", rust_type(schema, name))?;
+    Ok(())
+}
+
+fn write_struct(out: &mut String, name: &str, schema: &Value) -> Result<()> {
+    writeln!(
+        out,
+        "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]"
+    )?;
+    writeln!(out, "pub struct {name} {{")?;
+    write_fields(out, name, schema, None, "    pub ")?;
+    // A flattened enum, such as the shape of an occlusion
+    let mut kind = None;
+    if let Some(variants) = schema["oneOf"].as_array() {
+        writeln!(out, "    #[serde(flatten)]")?;
+        match common_tag(variants) {
+            Some(tag) => {
+                writeln!(out, "    pub kind: {name}Kind,")?;
+                kind = Some((tag, variants));
+            }
+            None => writeln!(
+                out,
+                "    pub other: serde_json::Map<String, serde_json::Value>,"
+            )?,
+        }
+    }
+    writeln!(out, "}}")?;
+
+    if let Some((tag, variants)) = kind {
+        writeln!(out)?;
+        write_tagged_enum(out, &format!("{name}Kind"), &tag, variants)?;
+    }
+    Ok(())
+}
+
+/// Write the properties of an object schema as fields, leaving out `skip`
+fn write_fields(
+    out: &mut String,
+    owner: &str,
+    schema: &Value,
+    skip: Option<&str>,
+    prefix: &str,
+) -> Result<()> {
+    let indent = &prefix[..prefix.len() - prefix.trim_start().len()];
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let Some(properties) = schema["properties"].as_object() else {
+        return Ok(());
+    };
+    for (property, property_schema) in properties {
+        if Some(property.as_str()) == skip {
+            continue;
+        }
+        let ident = field_name(property);
+        let mut ty = rust_type(property_schema, owner);
+        write_docs(out, property_schema, indent)?;
+        if ident.trim_start_matches("r#") != property {
+            writeln!(out, "{indent}#[serde(rename = \"{property}\")]")?;
+        }
+        if !required.contains(&property.as_str()) {
+            writeln!(
+                out,
+                "{indent}#[serde(default, skip_serializing_if = \"Option::is_none\")]"
+            )?;
+            ty = format!("Option<{ty}>");
+        } else if property_schema["nullable"] == true {
+            ty = format!("Option<{ty}>");
+        }
+        writeln!(out, "{prefix}{ident}: {ty},")?;
+    }
+    Ok(())
+}
+
+fn write_string_enum(out: &mut String, name: &str, values: &[(String, Value)]) -> Result<()> {
+    writeln!(
+        out,
+        "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]"
+    )?;
+    writeln!(out, "pub enum {name} {{")?;
+    for (value, schema) in values {
+        write_docs(out, schema, "    ")?;
+        writeln!(out, "    #[serde(rename = \"{value}\")]")?;
+        writeln!(out, "    {},", variant_name(value))?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// An enum whose variants are objects, told apart by the value of `tag`
+fn write_tagged_enum(out: &mut String, name: &str, tag: &str, variants: &[Value]) -> Result<()> {
+    writeln!(
+        out,
+        "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]"
+    )?;
+    writeln!(out, "#[serde(tag = \"{tag}\")]")?;
+    writeln!(out, "pub enum {name} {{")?;
+    for variant in variants {
+        let value = single_string(&variant["properties"][tag]).unwrap();
+        write_docs(out, variant, "    ")?;
+        writeln!(out, "    #[serde(rename = \"{value}\")]")?;
+        writeln!(out, "    {} {{", variant_name(value))?;
+        write_fields(out, name, variant, Some(tag), "        ")?;
+        writeln!(out, "    }},")?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// The values of a schema that only allows certain strings, with the
+/// schema each value was documented in
+fn string_enum(schema: &Value) -> Option<Vec<(String, Value)>> {
+    if schema["type"] == "string" {
+        let values = schema["enum"].as_array()?;
+        return values
+            .iter()
+            .map(|value| Some((value.as_str()?.to_string(), Value::Null)))
+            .collect();
+    }
+    schema["oneOf"]
+        .as_array()?
+        .iter()
+        .map(|variant| Some((single_string(variant)?.to_string(), variant.clone())))
+        .collect()
+}
+
+/// The property that holds a different constant string in each variant
+fn common_tag(variants: &[Value]) -> Option<String> {
+    let first = variants.first()?["properties"].as_object()?;
+    first
+        .keys()
+        .find(|property| {
+            variants
+                .iter()
+                .all(|variant| single_string(&variant["properties"][property.as_str()]).is_some())
+        })
+        .cloned()
+}
+
+fn single_string(schema: &Value) -> Option<&str> {
+    match schema["enum"].as_array()?.as_slice() {
+        [value] => value.as_str(),
+        _ => None,
+    }
+}
+
+/// The Rust type of a schema. A reference to `owner` is boxed, so that
+/// recursive types have a known size.
+fn rust_type(schema: &Value, owner: &str) -> String {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = type_name(reference.rsplit('/').next().unwrap());
+        return if name == owner {
+            format!("Box<{name}>")
+        } else {
+            name
+        };
+    }
+    if let Some([single]) = schema["allOf"].as_array().map(Vec::as_slice) {
+        return rust_type(single, owner);
+    }
+    let format = schema["format"].as_str().unwrap_or_default();
+    match schema["type"].as_str() {
+        Some("boolean") => "bool".to_string(),
+        Some("integer") => match format {
+            "int8" => "i8",
+            "int16" => "i16",
+            "int32" => "i32",
+            "uint8" => "u8",
+            "uint16" => "u16",
+            "uint32" => "u32",
+            "uint" | "uint64" => "u64",
+            _ => "i64",
+        }
+        .to_string(),
+        Some("number") if format == "float" => "f32".to_string(),
+        Some("number") => "f64".to_string(),
+        Some("string") => "String".to_string(),
+        Some("array") => format!("Vec<{}>", rust_type(&schema["items"], "")),
+        Some("object") if schema["additionalProperties"].is_object() => format!(
+            "std::collections::HashMap<String, {}>",
+            rust_type(&schema["additionalProperties"], "")
+        ),
+        _ => "serde_json::Value".to_string(),
+    }
+}
+
+fn write_operations(out: &mut String, types: &mut String, spec: &Value) -> Result<()> {
+    writeln!(out, "impl Client {{")?;
+    let paths = spec["paths"].as_object().cloned().unwrap_or_default();
+    let mut first = true;
+    for (path, item) in &paths {
+        for method in METHODS {
+            let Some(operation) = item.get(*method) else {
+                continue;
+            };
+            if !first {
+                writeln!(out)?;
+            }
+            first = false;
+            write_operation(out, types, spec, method, path, operation)?;
+        }
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn write_operation(
+    out: &mut String,
+    types: &mut String,
+    spec: &Value,
+    method: &str,
+    path: &str,
+    operation: &Value,
+) -> Result<()> {
+    let operation_id = operation["operationId"]
+        .as_str()
+        .unwrap_or_else(|| panic!("{method} {path} has no operationId"));
+    let name = operation_id.to_snake_case();
+
+    let parameters: Vec<Value> = operation["parameters"]
+        .as_array()
+        .map(|parameters| {
+            parameters
+                .iter()
+                .map(|parameter| resolve(spec, parameter))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut args = vec!["&self".to_string()];
+    let mut segments = vec![];
+    for parameter in of_kind(&parameters, "path") {
+        let ident = field_name(parameter["name"].as_str().unwrap());
+        let ty = match rust_type(&parameter["schema"], "").as_str() {
+            "String" => "&str".to_string(),
+            ty => ty.to_string(),
+        };
+        args.push(format!("{ident}: {ty}"));
+        segments.push(format!("path_segment(&{ident}.to_string())"));
+    }
+    let headers: Vec<(String, String)> = of_kind(&parameters, "header")
+        .into_iter()
+        .map(|parameter| {
+            let header = parameter["name"].as_str().unwrap().to_string();
+            (field_name(&header), header)
+        })
+        .collect();
+    for (ident, _) in &headers {
+        args.push(format!("{ident}: Option<&str>"));
+    }
+    let query = of_kind(&parameters, "query");
+    if !query.is_empty() {
+        let query_type = format!("{}Query", operation_id.to_pascal_case());
+        writeln!(types)?;
+        writeln!(types, "/// Query parameters of `{name}`")?;
+        writeln!(
+            types,
+            "#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]"
+        )?;
+        writeln!(types, "pub struct {query_type} {{")?;
+        let schema = json_object(&query);
+        write_fields(types, &query_type, &schema, None, "    pub ")?;
+        writeln!(types, "}}")?;
+        args.push(format!("query: &types::{query_type}"));
+    }
+
+    let body_content = &operation["requestBody"]["content"];
+    let json_body = &body_content["application/json"]["schema"];
+    let multipart = body_content["multipart/form-data"].is_object();
+    if json_body.is_object() {
+        args.push(format!("body: &{}", qualified(&rust_type(json_body, ""))));
+    } else if multipart {
+        args.push("file_name: &str".to_string());
+        args.push("file: Vec<u8>".to_string());
+    }
+
+    let (returns, read) = response_type(spec, operation);
+
+    if let Some(summary) = operation["summary"].as_str() {
+        writeln!(out, "    /// {summary}")?;
+        writeln!(out, "    ///")?;
+    }
+    writeln!(out, "    /// `{} {path}`", method.to_uppercase())?;
+    writeln!(
+        out,
+        "    pub async fn {name}({}) -> Result<{returns}> {{",
+        args.join(", ")
+    )?;
+    let path_arg = if segments.is_empty() {
+        format!("\"{path}\"")
+    } else {
+        let mut template = String::new();
+        let mut rest = path;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}').unwrap() + start;
+            template.push_str(&rest[..start]);
+            template.push_str("{}");
+            rest = &rest[end + 1..];
+        }
+        template.push_str(rest);
+        writeln!(
+            out,
+            "        let path = format!(\"{template}\", {});",
+            segments.join(", ")
+        )?;
+        "&path".to_string()
+    };
+    let mutable = if headers.is_empty() { "" } else { "mut " };
+    write!(
+        out,
+        "        let {mutable}request = self.request(reqwest::Method::{}, {path_arg})",
+        method.to_uppercase()
+    )?;
+    if !query.is_empty() {
+        write!(out, ".query(query)")?;
+    }
+    if json_body.is_object() {
+        write!(out, ".json(body)")?;
+    } else if multipart {
+        write!(out, ".multipart(file_form(file_name, file))")?;
+    }
+    writeln!(out, ";")?;
+    for (ident, header) in &headers {
+        writeln!(out, "        if let Some({ident}) = {ident} {{")?;
+        writeln!(
+            out,
+            "            request = request.header(\"{header}\", {ident});"
+        )?;
+        writeln!(out, "        }}")?;
+    }
+    match read {
+        Some(read) => {
+            writeln!(out, "        let response = self.send(request).await?;")?;
+            writeln!(out, "        {read}")?;
+        }
+        None => {
+            writeln!(out, "        self.send(request).await?;")?;
+            writeln!(out, "        Ok(())")?;
+        }
+    }
+    writeln!(out, "    }}")?;
+    Ok(())
+}
+
+/// The return type of an operation's method, and the expression that reads
+/// it from the response, going by the first success response in the spec
+fn response_type(spec: &Value, operation: &Value) -> (String, Option<&'static str>) {
+    let response = operation["responses"]
+        .as_object()
+        .and_then(|responses| {
+            responses
+                .iter()
+                .find(|(status, _)| status.starts_with('2'))
+                .map(|(_, response)| resolve(spec, response))
+        })
+        .unwrap_or(Value::Null);
+    let content = &response["content"];
+    let schema = &content["application/json"]["schema"];
+    if schema.is_object() {
+        (
+            qualified(&rust_type(schema, "")),
+            Some("Ok(response.json().await?)"),
+        )
+    } else if content
+        .as_object()
+        .is_some_and(|content| !content.is_empty())
+    {
+        (
+            "Vec<u8>".to_string(),
+            Some("Ok(response.bytes().await?.to_vec())"),
+        )
+    } else {
+        ("()".to_string(), None)
+    }
+}
+
+fn of_kind<'a>(parameters: &'a [Value], kind: &str) -> Vec<&'a Value> {
+    parameters
+        .iter()
+        .filter(|parameter| parameter["in"] == kind)
+        .collect()
+}
+
+/// Query parameters as the properties of an object schema
+fn json_object(parameters: &[&Value]) -> Value {
+    let mut properties = Map::new();
+    let mut required = vec![];
+    for parameter in parameters {
+        let name = parameter["name"].as_str().unwrap().to_string();
+        let mut schema = parameter["schema"].clone();
+        if let Some(description) = parameter.get("description") {
+            schema["description"] = description.clone();
+        }
+        if parameter["required"] == true {
+            required.push(Value::String(name.clone()));
+        }
+        properties.insert(name, schema);
+    }
+    let mut schema = Map::new();
+    schema.insert("properties".to_string(), Value::Object(properties));
+    schema.insert("required".to_string(), Value::Array(required));
+    Value::Object(schema)
+}
+
+/// Follow a `$ref` to another part of the spec
+fn resolve(spec: &Value, value: &Value) -> Value {
+    match value["$ref"].as_str() {
+        Some(reference) => spec
+            .pointer(reference.trim_start_matches('#'))
+            .unwrap_or_else(|| panic!("unresolved reference {reference}"))
+            .clone(),
+        None => value.clone(),
+    }
+}
+
+/// Refer to generated types through their module, from outside it
+fn qualified(ty: &str) -> String {
+    let mut out = String::new();
+    let mut ident = String::new();
+    for c in ty.chars().chain(std::iter::once(' ')) {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            ident.push(c);
+            continue;
+        }
+        if !ident.is_empty() {
+            let builtin = ident.contains("::")
+                || ident.starts_with(char::is_lowercase)
+                || ["Box", "Vec", "Option", "String"].contains(&ident.as_str());
+            if !builtin {
+                out.push_str("types::");
+            }
+            out.push_str(&ident);
+            ident.clear();
+        }
+        out.push(c);
+    }
+    out.pop();
+    out
+}
+
+fn write_docs(out: &mut String, schema: &Value, indent: &str) -> Result<()> {
+    if let Some(description) = schema["description"].as_str() {
+        for line in description.lines() {
+            if line.is_empty() {
+                writeln!(out, "{indent}///")?;
+            } else {
+                writeln!(out, "{indent}/// {line}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn type_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn field_name(name: &str) -> String {
+    let ident = name.to_snake_case();
+    if KEYWORDS.contains(&ident.as_str()) {
+        format!("r#{ident}")
+    } else {
+        ident
+    }
+}
+
+fn variant_name(value: &str) -> String {
+    let name = value.to_pascal_case();
+    if name.starts_with(char::is_numeric) || name.is_empty() {
+        format!("V{name}")
+    } else {
+        name
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! A typed client for the Anki web app's REST API.
+//!
+//! The types and methods are generated at build time from the server's
+//! OpenAPI spec, checked in as `openapi.json`. The server's contract tests
+//! keep that file in step with the server. Each operation is a method named
+//! after its `operationId`.
+//!
+//! ```no_run
+//! # async fn example() -> anki_webapp_client::Result<()> {
+//! use anki_webapp_client::types::LoginRequest;
+//! use anki_webapp_client::Client;
+//!
+//! let mut client = Client::new("http://127.0.0.1:8080");
+//! let auth = client
+//!     .login(&LoginRequest {
+//!         username: "user".into(),
+//!         password: "password".into(),
+//!     })
+//!     .await?;
+//! client.set_token(auth.data.map(|data| data.token));
+//! let decks = client.get_deck_tree().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+
+use reqwest::multipart;
+use reqwest::Method;
+use reqwest::RequestBuilder;
+use reqwest::Response;
+
+/// Request and response bodies, one for each schema in the spec
+#[allow(clippy::all)]
+pub mod types {
+    include!(concat!(env!("OUT_DIR"), "/types.rs"));
+}
+
+include!(concat!(env!("OUT_DIR"), "/operations.rs"));
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent, or the response could not be read
+    Http(reqwest::Error),
+    /// The server answered with an error status. `error` is missing if the
+    /// body was not the usual error JSON.
+    Api {
+        status: u16,
+        error: Option<types::ErrorDetail>,
+    },
+}
+
+impl Error {
+    /// The HTTP status the server answered with, if it answered
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::Http(err) => err.status().map(|status| status.as_u16()),
+            Error::Api { status, .. } => Some(*status),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(err) => write!(f, "request failed: {err}"),
+            Error::Api {
+                status,
+                error: Some(error),
+            } => write!(f, "{status} {}: {}", error.code, error.message),
+            Error::Api {
+                status,
+                error: None,
+            } => write!(f, "server returned {status}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(err) => Some(err),
+            Error::Api { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Http(err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl Client {
+    /// A client for the server at `base_url`, such as
+    /// `https://anki.example.com`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    /// Like [`Client::new`], but sending requests through `http`, e.g. to
+    /// set timeouts or proxies
+    pub fn with_http_client(http: reqwest::Client, base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Self {
+            http,
+            base_url,
+            token: None,
+        }
+    }
+
+    /// Set the bearer token sent with each request, as returned by
+    /// `login` or `register`
+    pub fn set_token(&mut self, token: Option<String>) {
+        self.token = token;
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}{}", self.base_url, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let error = response
+            .json::<types::ErrorResponse>()
+            .await
+            .ok()
+            .map(|body| body.error);
+        Err(Error::Api {
+            status: status.as_u16(),
+            error,
+        })
+    }
+}
+
+/// A multipart form with the file in its `file` field, as upload endpoints
+/// expect
+fn file_form(file_name: &str, file: Vec<u8>) -> multipart::Form {
+    multipart::Form::new().part(
+        "file",
+        multipart::Part::bytes(file).file_name(file_name.to_string()),
+    )
+}
+
+/// Percent-encode a value for use as a single path segment
+fn path_segment(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_segment() {
+        assert_eq!(path_segment("image.png"), "image.png");
+        assert_eq!(path_segment("a b/c::d"), "a%20b%2Fc%3A%3Ad");
+        assert_eq!(path_segment("日"), "%E6%97%A5");
+    }
+}
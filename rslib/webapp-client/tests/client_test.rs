@@ -0,0 +1,155 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anki_webapp::auth::AuthState;
+use anki_webapp::auth::JwtManager;
+use anki_webapp::config::WebAppConfig;
+use anki_webapp::db::Database;
+use anki_webapp::jobs::JobManager;
+use anki_webapp::server::router::create_router;
+use anki_webapp::session::BackendManager;
+use anki_webapp_client::types;
+use anki_webapp_client::Client;
+use anki_webapp_client::Error;
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+
+/// Start a server on a random port, returning its URL
+async fn start_server(data_dir: &TempDir) -> String {
+    let config = WebAppConfig {
+        host: "127.0.0.1".parse().unwrap(),
+        port: 0,
+        data_dir: data_dir.path().to_path_buf(),
+        jwt_secret: "test-secret-must-be-long-enough-for-hs256-at-least-32-chars".to_string(),
+        session_timeout_hours: 24,
+        ..WebAppConfig::default()
+    };
+    let database = Arc::new(Database::open(data_dir.path().join("webapp.db")).unwrap());
+    database.initialize().unwrap();
+    let auth_state = AuthState {
+        database,
+        jwt_manager: Arc::new(JwtManager::new(&config.jwt_secret)),
+        backend_manager: Arc::new(BackendManager::new(data_dir.path().to_path_buf())),
+        job_manager: Arc::new(JobManager::new()),
+    };
+    let router = create_router(&config, auth_state);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    });
+    format!("http://{addr}")
+}
+
+#[tokio::test]
+async fn test_client_round_trip() {
+    let data_dir = TempDir::new().unwrap();
+    let mut client = Client::new(start_server(&data_dir).await);
+
+    let auth = client
+        .register(&types::RegisterRequest {
+            username: "client".into(),
+            password: "password123".into(),
+            email: None,
+        })
+        .await
+        .unwrap();
+    client.set_token(auth.data.map(|data| data.token));
+    assert_eq!(client.me().await.unwrap().username, "client");
+
+    let deck = client
+        .create_deck(&types::CreateDeckRequest {
+            name: "Client".into(),
+        })
+        .await
+        .unwrap();
+    let deck_id = deck.id.unwrap();
+    let tree = client.get_deck_tree().await.unwrap();
+    assert!(tree.decks.iter().any(|deck| deck.name == "Client"));
+
+    let notetypes = client.list_notetypes().await.unwrap();
+    let basic = notetypes
+        .notetypes
+        .iter()
+        .find(|notetype| notetype.name == "Basic")
+        .unwrap();
+    let note = client
+        .create_note(&types::CreateNoteRequest {
+            deck_id,
+            notetype_id: basic.id,
+            fields: vec!["front".into(), "back".into()],
+            tags: vec!["client".into()],
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        client.get_note(note.note_id).await.unwrap().fields,
+        vec!["front", "back"]
+    );
+
+    let found = client
+        .search_cards(&types::SearchCardsRequest {
+            query: "tag:client".into(),
+            sort_column: None,
+            reverse: None,
+            limit: None,
+            offset: None,
+            fields: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(found.count, 1);
+
+    // Tag names are encoded as a single path segment
+    client
+        .set_tag_collapsed(&types::SetTagCollapsedRequest {
+            tag: "client".into(),
+            collapsed: true,
+        })
+        .await
+        .unwrap();
+    client.delete_tag("client").await.unwrap();
+    assert!(client.get_tags().await.unwrap().tags.is_empty());
+
+    client.logout().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_errors() {
+    let data_dir = TempDir::new().unwrap();
+    let mut client = Client::new(start_server(&data_dir).await);
+
+    // Not logged in yet
+    let err = client.me().await.unwrap_err();
+    assert_eq!(err.status(), Some(401));
+
+    let auth = client
+        .register(&types::RegisterRequest {
+            username: "client".into(),
+            password: "password123".into(),
+            email: None,
+        })
+        .await
+        .unwrap();
+    client.set_token(auth.data.map(|data| data.token));
+
+    match client.get_deck(1234567).await.unwrap_err() {
+        Error::Api {
+            status,
+            error: Some(error),
+        } => {
+            assert_eq!(status, 404);
+            assert_eq!(error.code, "not_found");
+        }
+        err => panic!("unexpected error: {err}"),
+    }
+}
@@ -0,0 +1,678 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! Administrative subcommands of the `anki-webapp` binary.
+//!
+//! Account, session, registration and config commands only touch
+//! `webapp.db`, and are safe to run while the server is up. Collection
+//! commands open the users' collections directly, so they take the lock on
+//! the data directory that a running server holds, and refuse to run if they
+//! can't.
+
+use std::io::BufRead;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anki::services::CollectionService;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::DateTime;
+use clap::Subcommand;
+
+use crate::auth::hash_password;
+use crate::auth::MIN_PASSWORD_LEN;
+use crate::config::WebAppConfig;
+use crate::db::Database;
+use crate::db::User;
+use crate::server::lock::DataDirLock;
+use crate::session::lock_collection;
+use crate::session::BackendManager;
+use crate::session::CLOSE_TIMEOUT;
+
+#[derive(Debug, Subcommand)]
+pub enum AdminCommand {
+    /// Manage user accounts
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Check, back up, restore or export a user's collection. The server
+    /// must be stopped.
+    #[command(subcommand)]
+    Collection(CollectionCommand),
+    /// Manage login sessions
+    #[command(subcommand)]
+    Sessions(SessionsCommand),
+    /// Allow or stop new sign-ups through the API
+    #[command(subcommand)]
+    Registration(RegistrationCommand),
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UserCommand {
+    /// Create an account
+    Add {
+        username: String,
+        #[arg(long)]
+        email: Option<String>,
+        /// Read from the first line of standard input if not given
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// List all accounts
+    List,
+    /// Block an account from logging in and end its sessions
+    Disable { username: String },
+    /// Allow a disabled account to log in again
+    Enable { username: String },
+    /// Set a new password and end the account's sessions
+    Passwd {
+        username: String,
+        /// Read from the first line of standard input if not given
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Delete an account and its sessions
+    Delete {
+        username: String,
+        /// Also delete the user's collection, media and backups. The server
+        /// must be stopped.
+        #[arg(long)]
+        remove_data: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CollectionCommand {
+    /// Run a database check on a user's collection
+    Check { username: String },
+    /// Back up a user's collection now
+    Backup { username: String },
+    /// Replace a user's collection with one of their backups
+    Restore {
+        username: String,
+        /// Backup file name, e.g. backup-2024-01-31-13.45.10.colpkg
+        backup: String,
+    },
+    /// Write a user's collection to a .colpkg file
+    Export {
+        username: String,
+        output: PathBuf,
+        /// Leave media files out of the package
+        #[arg(long)]
+        no_media: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionsCommand {
+    /// Delete expired sessions, or all sessions of a user or everyone
+    Purge {
+        /// End every session of this user
+        #[arg(long)]
+        user: Option<String>,
+        /// End every session of every user
+        #[arg(long, conflicts_with = "user")]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RegistrationCommand {
+    /// Let anyone create an account
+    Enable,
+    /// Only allow accounts created with `user add`
+    Disable,
+    /// Print whether registration is enabled
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the effective configuration, after merging the config file and
+    /// environment variables, as TOML
+    Print {
+        /// Include the JWT secret instead of redacting it
+        #[arg(long)]
+        show_secrets: bool,
+    },
+}
+
+/// Run an admin command, writing its output to `out`
+pub fn run(config: &WebAppConfig, command: AdminCommand, out: &mut impl Write) -> Result<()> {
+    match command {
+        AdminCommand::User(command) => run_user(config, command, out),
+        AdminCommand::Collection(command) => run_collection(config, command, out),
+        AdminCommand::Sessions(command) => run_sessions(config, command, out),
+        AdminCommand::Registration(command) => run_registration(config, command, out),
+        AdminCommand::Config(ConfigCommand::Print { show_secrets }) => {
+            print_config(config, show_secrets, out)
+        }
+    }
+}
+
+fn run_user(config: &WebAppConfig, command: UserCommand, out: &mut impl Write) -> Result<()> {
+    let database = open_database(config)?;
+    match command {
+        UserCommand::Add {
+            username,
+            email,
+            password,
+        } => {
+            if username.trim().is_empty() || username.len() > 50 {
+                bail!("Username must be between 1 and 50 characters");
+            }
+            if database.users().get_by_username(&username)?.is_some() {
+                bail!("User {} already exists", username);
+            }
+            let password = read_password(password)?;
+            let user =
+                database
+                    .users()
+                    .create(&username, &hash_password(&password)?, email.as_deref())?;
+            writeln!(out, "Created user {} with id {}", user.username, user.id)?;
+        }
+        UserCommand::List => {
+            writeln!(
+                out,
+                "{:>6}  {:<20}  {:<8}  {:<19}  email",
+                "id", "username", "status", "created"
+            )?;
+            for user in database.users().list_all()? {
+                writeln!(
+                    out,
+                    "{:>6}  {:<20}  {:<8}  {:<19}  {}",
+                    user.id,
+                    user.username,
+                    if user.is_active { "active" } else { "disabled" },
+                    format_timestamp(user.created_at),
+                    user.email.as_deref().unwrap_or("")
+                )?;
+            }
+        }
+        UserCommand::Disable { username } => {
+            let user = find_user(&database, &username)?;
+            database.users().set_active(user.id, false)?;
+            let sessions = database.sessions().delete_by_user(user.id)?;
+            writeln!(
+                out,
+                "Disabled user {} and ended {} session(s)",
+                username, sessions
+            )?;
+        }
+        UserCommand::Enable { username } => {
+            let user = find_user(&database, &username)?;
+            database.users().set_active(user.id, true)?;
+            writeln!(out, "Enabled user {}", username)?;
+        }
+        UserCommand::Passwd { username, password } => {
+            let user = find_user(&database, &username)?;
+            let password = read_password(password)?;
+            database
+                .users()
+                .update_password(user.id, &hash_password(&password)?)?;
+            let sessions = database.sessions().delete_by_user(user.id)?;
+            writeln!(
+                out,
+                "Changed password for {} and ended {} session(s)",
+                username, sessions
+            )?;
+        }
+        UserCommand::Delete {
+            username,
+            remove_data,
+        } => {
+            let user = find_user(&database, &username)?;
+            let _lock = if remove_data {
+                Some(lock_data_dir(config)?)
+            } else {
+                None
+            };
+            // Deleting the user also unpublishes their shared decks
+            let shared_decks: Vec<_> = database
+                .sharing()
+                .list()?
+                .into_iter()
+                .filter(|deck| deck.owner_id == user.id)
+                .collect();
+            database.users().delete(user.id)?;
+            writeln!(out, "Deleted user {}", username)?;
+            if remove_data {
+                let manager = BackendManager::new(config.data_dir.clone());
+                let folder = manager.get_user_folder_path(user.id);
+                if folder.exists() {
+                    std::fs::remove_dir_all(&folder)
+                        .with_context(|| format!("Failed to remove {}", folder.display()))?;
+                    writeln!(out, "Removed {}", folder.display())?;
+                }
+                for deck in shared_decks {
+                    let path = manager.get_shared_deck_path(user.id, deck.source_deck_id);
+                    if path.exists() {
+                        std::fs::remove_file(&path)
+                            .with_context(|| format!("Failed to remove {}", path.display()))?;
+                        writeln!(out, "Removed {}", path.display())?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_collection(
+    config: &WebAppConfig,
+    command: CollectionCommand,
+    out: &mut impl Write,
+) -> Result<()> {
+    let _lock = lock_data_dir(config)?;
+    let database = open_database(config)?;
+    let manager = BackendManager::new(config.data_dir.clone());
+
+    match command {
+        CollectionCommand::Check { username } => {
+            let user = find_user(&database, &username)?;
+            let backend = manager.get_or_create_backend(user.id, &user.username)?;
+            let output = CollectionService::check_database(&mut *lock_collection(&backend))?;
+            if output.problems.is_empty() {
+                writeln!(out, "No problems found")?;
+            } else {
+                for problem in output.problems {
+                    writeln!(out, "{}", problem)?;
+                }
+            }
+        }
+        CollectionCommand::Backup { username } => {
+            let user = find_user(&database, &username)?;
+            let backend = manager.get_or_create_backend(user.id, &user.username)?;
+            manager.backup_collection(user.id, &backend, true)?;
+            let folder = manager.get_backup_folder_path(user.id);
+            match manager.list_backups(user.id)?.first() {
+                Some(backup) => writeln!(out, "{}", folder.join(&backup.filename).display())?,
+                None => bail!("No backup was written"),
+            }
+        }
+        CollectionCommand::Restore { username, backup } => {
+            let user = find_user(&database, &username)?;
+            manager.restore_backup(user.id, &user.username, &backup)?;
+            writeln!(out, "Restored {} for {}", backup, username)?;
+        }
+        CollectionCommand::Export {
+            username,
+            output,
+            no_media,
+        } => {
+            let user = find_user(&database, &username)?;
+            manager.export_collection(user.id, &user.username, &output, !no_media)?;
+            writeln!(out, "Exported {} to {}", username, output.display())?;
+        }
+    }
+
+    manager.close_all(CLOSE_TIMEOUT)
+}
+
+fn run_sessions(
+    config: &WebAppConfig,
+    command: SessionsCommand,
+    out: &mut impl Write,
+) -> Result<()> {
+    let database = open_database(config)?;
+    let SessionsCommand::Purge { user, all } = command;
+    let count = match (user, all) {
+        (Some(username), _) => {
+            let user = find_user(&database, &username)?;
+            database.sessions().delete_by_user(user.id)?
+        }
+        (None, true) => database.sessions().delete_all()?,
+        (None, false) => database.sessions().cleanup_expired()?,
+    };
+    writeln!(out, "Removed {} session(s)", count)?;
+    Ok(())
+}
+
+fn run_registration(
+    config: &WebAppConfig,
+    command: RegistrationCommand,
+    out: &mut impl Write,
+) -> Result<()> {
+    let database = open_database(config)?;
+    let settings = database.settings();
+    match command {
+        RegistrationCommand::Enable => settings.set_registration_enabled(true)?,
+        RegistrationCommand::Disable => settings.set_registration_enabled(false)?,
+        RegistrationCommand::Status => {}
+    }
+    let status = if settings.registration_enabled()? {
+        "enabled"
+    } else {
+        "disabled"
+    };
+    writeln!(out, "Registration is {}", status)?;
+    Ok(())
+}
+
+fn print_config(config: &WebAppConfig, show_secrets: bool, out: &mut impl Write) -> Result<()> {
+    let mut config = config.clone();
+    if !show_secrets {
+        config.jwt_secret = "<redacted>".to_string();
+    }
+    write!(out, "{}", toml::to_string_pretty(&config)?)?;
+    Ok(())
+}
+
+fn open_database(config: &WebAppConfig) -> Result<Database> {
+    std::fs::create_dir_all(&config.data_dir).with_context(|| {
+        format!(
+            "Failed to create data directory {}",
+            config.data_dir.display()
+        )
+    })?;
+    let database = Database::open(config.data_dir.join("webapp.db"))?;
+    database.initialize()?;
+    Ok(database)
+}
+
+fn find_user(database: &Database, username: &str) -> Result<User> {
+    database
+        .users()
+        .get_by_username(username)?
+        .with_context(|| format!("No such user: {}", username))
+}
+
+fn read_password(password: Option<String>) -> Result<String> {
+    let password = match password {
+        Some(password) => password,
+        None => {
+            let mut line = String::new();
+            std::io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    if password.len() < MIN_PASSWORD_LEN {
+        bail!("Password must be at least {} characters", MIN_PASSWORD_LEN);
+    }
+    Ok(password)
+}
+
+/// Collections are cached by the server between requests, so changing them
+/// from another process could lose data or be silently overwritten. The lock
+/// must be held for as long as collections are open.
+fn lock_data_dir(config: &WebAppConfig) -> Result<DataDirLock> {
+    std::fs::create_dir_all(&config.data_dir).with_context(|| {
+        format!(
+            "Failed to create data directory {}",
+            config.data_dir.display()
+        )
+    })?;
+    DataDirLock::try_acquire(&config.data_dir)?.with_context(|| {
+        format!(
+            "The server is running with data directory {}; stop it first, or use the backup endpoints of the API",
+            config.data_dir.display()
+        )
+    })
+}
+
+fn format_timestamp(secs: i64) -> String {
+    DateTime::from_timestamp(secs, 0)
+        .map(|datetime| datetime.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::auth::verify_password;
+
+    fn test_config(temp_dir: &TempDir) -> WebAppConfig {
+        WebAppConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            ..WebAppConfig::default()
+        }
+    }
+
+    fn run_to_string(config: &WebAppConfig, command: AdminCommand) -> Result<String> {
+        let mut out = vec![];
+        run(config, command, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn add_user(config: &WebAppConfig, username: &str) {
+        run_to_string(
+            config,
+            AdminCommand::User(UserCommand::Add {
+                username: username.to_string(),
+                email: None,
+                password: Some("password123".to_string()),
+            }),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_user_management() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        add_user(&config, "alice");
+
+        let listing = run_to_string(&config, AdminCommand::User(UserCommand::List)).unwrap();
+        assert!(listing.contains("alice"));
+        assert!(listing.contains("active"));
+
+        // Duplicate names and short passwords are rejected
+        assert!(run_to_string(
+            &config,
+            AdminCommand::User(UserCommand::Add {
+                username: "alice".to_string(),
+                email: None,
+                password: Some("password123".to_string()),
+            }),
+        )
+        .is_err());
+        assert!(run_to_string(
+            &config,
+            AdminCommand::User(UserCommand::Add {
+                username: "bob".to_string(),
+                email: None,
+                password: Some("short".to_string()),
+            }),
+        )
+        .is_err());
+
+        let database = open_database(&config).unwrap();
+        let alice = database.users().get_by_username("alice").unwrap().unwrap();
+        database
+            .sessions()
+            .create("session-1", alice.id, 3600)
+            .unwrap();
+
+        // Disabling ends sessions
+        run_to_string(
+            &config,
+            AdminCommand::User(UserCommand::Disable {
+                username: "alice".to_string(),
+            }),
+        )
+        .unwrap();
+        let alice = database.users().get_by_username("alice").unwrap().unwrap();
+        assert!(!alice.is_active);
+        assert!(database.sessions().get("session-1").unwrap().is_none());
+
+        run_to_string(
+            &config,
+            AdminCommand::User(UserCommand::Enable {
+                username: "alice".to_string(),
+            }),
+        )
+        .unwrap();
+        run_to_string(
+            &config,
+            AdminCommand::User(UserCommand::Passwd {
+                username: "alice".to_string(),
+                password: Some("new-password".to_string()),
+            }),
+        )
+        .unwrap();
+        let alice = database.users().get_by_username("alice").unwrap().unwrap();
+        assert!(alice.is_active);
+        assert!(verify_password("new-password", &alice.password_hash).unwrap());
+
+        run_to_string(
+            &config,
+            AdminCommand::User(UserCommand::Delete {
+                username: "alice".to_string(),
+                remove_data: true,
+            }),
+        )
+        .unwrap();
+        assert!(database.users().get_by_username("alice").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_sessions_purge() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        add_user(&config, "alice");
+        add_user(&config, "bob");
+
+        let database = open_database(&config).unwrap();
+        database.sessions().create("expired", 1, -60).unwrap();
+        database.sessions().create("alice", 1, 3600).unwrap();
+        database.sessions().create("bob", 2, 3600).unwrap();
+
+        let purge = |user: Option<&str>, all: bool| {
+            run_to_string(
+                &config,
+                AdminCommand::Sessions(SessionsCommand::Purge {
+                    user: user.map(ToString::to_string),
+                    all,
+                }),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(purge(None, false), "Removed 1 session(s)\n");
+        assert_eq!(purge(Some("alice"), false), "Removed 1 session(s)\n");
+        assert!(database.sessions().get("bob").unwrap().is_some());
+        assert_eq!(purge(None, true), "Removed 1 session(s)\n");
+        assert!(database.sessions().get("bob").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_config_print_redacts_secret() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = test_config(&temp_dir);
+        config.jwt_secret = "super-secret".to_string();
+
+        let printed = run_to_string(
+            &config,
+            AdminCommand::Config(ConfigCommand::Print {
+                show_secrets: false,
+            }),
+        )
+        .unwrap();
+        assert!(!printed.contains("super-secret"));
+        assert!(printed.contains(&format!("port = {}", config.port)));
+
+        let printed = run_to_string(
+            &config,
+            AdminCommand::Config(ConfigCommand::Print { show_secrets: true }),
+        )
+        .unwrap();
+        assert!(printed.contains("super-secret"));
+    }
+
+    #[test]
+    fn test_collection_backup_and_export() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        add_user(&config, "alice");
+
+        let check = run_to_string(
+            &config,
+            AdminCommand::Collection(CollectionCommand::Check {
+                username: "alice".to_string(),
+            }),
+        )
+        .unwrap();
+        assert_eq!(check, "No problems found\n");
+
+        let backup = run_to_string(
+            &config,
+            AdminCommand::Collection(CollectionCommand::Backup {
+                username: "alice".to_string(),
+            }),
+        )
+        .unwrap();
+        assert!(PathBuf::from(backup.trim()).exists());
+
+        let output = temp_dir.path().join("alice.colpkg");
+        run_to_string(
+            &config,
+            AdminCommand::Collection(CollectionCommand::Export {
+                username: "alice".to_string(),
+                output: output.clone(),
+                no_media: false,
+            }),
+        )
+        .unwrap();
+        assert!(output.exists());
+    }
+
+    #[test]
+    fn test_collection_commands_require_stopped_server() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        add_user(&config, "alice");
+
+        // As held by a running server
+        let lock = DataDirLock::try_acquire(&config.data_dir).unwrap().unwrap();
+        let check = || {
+            run_to_string(
+                &config,
+                AdminCommand::Collection(CollectionCommand::Check {
+                    username: "alice".to_string(),
+                }),
+            )
+        };
+        let err = check().unwrap_err();
+        assert!(err.to_string().contains("server is running"));
+
+        // Account commands don't need the lock
+        run_to_string(&config, AdminCommand::User(UserCommand::List)).unwrap();
+
+        drop(lock);
+        check().unwrap();
+    }
+
+    #[test]
+    fn test_registration_toggle() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+
+        let status = run_to_string(
+            &config,
+            AdminCommand::Registration(RegistrationCommand::Status),
+        )
+        .unwrap();
+        assert_eq!(status, "Registration is enabled\n");
+
+        let status = run_to_string(
+            &config,
+            AdminCommand::Registration(RegistrationCommand::Disable),
+        )
+        .unwrap();
+        assert_eq!(status, "Registration is disabled\n");
+        let database = open_database(&config).unwrap();
+        assert!(!database.settings().registration_enabled().unwrap());
+
+        run_to_string(
+            &config,
+            AdminCommand::Registration(RegistrationCommand::Enable),
+        )
+        .unwrap();
+        assert!(database.settings().registration_enabled().unwrap());
+    }
+}
@@ -0,0 +1,227 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! Records every mutating API request in the webapp database's audit log.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anki::ops::Op;
+use axum::body::Body;
+use axum::extract::FromRequestParts;
+use axum::extract::MatchedPath;
+use axum::extract::RawPathParams;
+use axum::extract::Request;
+use axum::extract::State;
+use axum::http::header;
+use axum::http::HeaderMap;
+use axum::http::Method;
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum_client_ip::ClientIp;
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::auth::AuthState;
+use crate::auth::AuthUser;
+use crate::db::current_timestamp;
+use crate::db::Database;
+use crate::db::NewAuditEntry;
+use crate::error::WebAppError;
+use crate::session::lock_collection;
+
+/// JSON bodies up to this size are inspected for affected ids; larger ones
+/// are passed through untouched
+const MAX_INSPECTED_BODY_BYTES: usize = 256 * 1024;
+
+/// How often old audit entries are pruned
+const PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Middleware appending an audit entry for each mutating request. Must run
+/// inside `require_auth`, as entries are attributed to the authenticated
+/// user.
+pub async fn record_audit(
+    State(state): State<AuthState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !is_mutating(request.method()) {
+        return next.run(request).await;
+    }
+    let Some(auth_user) = request.extensions().get::<AuthUser>().cloned() else {
+        return next.run(request).await;
+    };
+
+    let (mut parts, body) = request.into_parts();
+    let method = parts.method.to_string();
+    let route = parts
+        .extensions
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| parts.uri.path().to_string());
+    let client_ip = ClientIp::from_request_parts(&mut parts, &())
+        .await
+        .ok()
+        .map(|ClientIp(ip)| ip.to_string());
+
+    let mut affected_ids = Map::new();
+    if let Ok(params) = RawPathParams::from_request_parts(&mut parts, &()).await {
+        for (key, value) in &params {
+            let value = value
+                .parse::<i64>()
+                .map(Value::from)
+                .unwrap_or_else(|_| Value::from(value));
+            affected_ids.insert(key.to_string(), value);
+        }
+    }
+
+    let body = if is_small_json(&parts.headers) {
+        match axum::body::to_bytes(body, MAX_INSPECTED_BODY_BYTES).await {
+            Ok(bytes) => {
+                if let Ok(Value::Object(fields)) = serde_json::from_slice(&bytes) {
+                    collect_body_ids(&fields, &mut affected_ids);
+                }
+                Body::from(bytes)
+            }
+            // A body that can't be read can't be handled either; reject it
+            // the same way the JSON extractor would
+            Err(e) => {
+                tracing::debug!("Failed to read request body for audit: {}", e);
+                return WebAppError::bad_request("Failed to read request body").into_response();
+            }
+        }
+    } else {
+        body
+    };
+
+    let before = last_undo_step(&state, auth_user.user_id);
+    let response = next.run(Request::from_parts(parts, body)).await;
+    let op = undo_op_since(&state, auth_user.user_id, before);
+
+    let entry = NewAuditEntry {
+        user_id: auth_user.user_id,
+        session_id: Some(auth_user.session_id),
+        client_ip,
+        method,
+        route,
+        op,
+        affected_ids: if affected_ids.is_empty() {
+            Value::Null
+        } else {
+            Value::Object(affected_ids)
+        },
+        status: response.status().as_u16(),
+    };
+    if let Err(e) = state.database.audit().record(&entry) {
+        tracing::error!("Failed to write audit entry: {}", e);
+    }
+
+    response
+}
+
+fn is_mutating(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    )
+}
+
+fn is_small_json(headers: &HeaderMap) -> bool {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with("application/json"))
+        .unwrap_or(false);
+    let length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    is_json && length.is_some_and(|length| length <= MAX_INSPECTED_BODY_BYTES)
+}
+
+/// Copy `id`, `*_id` and `*_ids` fields holding numbers from the top level
+/// of a JSON body
+fn collect_body_ids(fields: &Map<String, Value>, affected_ids: &mut Map<String, Value>) {
+    for (key, value) in fields {
+        let is_id_key = key == "id" || key.ends_with("_id") || key.ends_with("_ids");
+        let is_id_value = match value {
+            Value::Number(_) => true,
+            Value::Array(items) => !items.is_empty() && items.iter().all(Value::is_number),
+            _ => false,
+        };
+        if is_id_key && is_id_value {
+            affected_ids.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+fn last_undo_step(state: &AuthState, user_id: i64) -> Option<usize> {
+    let backend = state.backend_manager.get_backend(user_id)?;
+    let step = lock_collection(&backend).undo_status().last_step;
+    Some(step)
+}
+
+/// The undoable operation performed since `before`, if any. If the
+/// collection was only opened by this request, any recorded step is new.
+fn undo_op_since(state: &AuthState, user_id: i64, before: Option<usize>) -> Option<String> {
+    let backend = state.backend_manager.get_backend(user_id)?;
+    let status = lock_collection(&backend).undo_status();
+    if status.last_step == before.unwrap_or(0) {
+        return None;
+    }
+    status.undo.as_ref().map(op_kind)
+}
+
+fn op_kind(op: &Op) -> String {
+    match op {
+        Op::Custom(name) => name.clone(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Periodically delete audit entries older than the retention period
+pub fn spawn_audit_pruning(database: Arc<Database>, retention_days: u64) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let cutoff = current_timestamp() - (retention_days * 24 * 60 * 60) as i64;
+            match database.audit().prune_before(cutoff) {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Pruned {} old audit entries", count),
+                Err(e) => tracing::error!("Failed to prune audit log: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_collect_body_ids() {
+        let body = json!({
+            "card_ids": [1, 2],
+            "deck_id": 3,
+            "name": "Spanish",
+            "note_ids": [],
+            "parent_id": "not-a-number"
+        });
+        let mut ids = Map::new();
+        collect_body_ids(body.as_object().unwrap(), &mut ids);
+        assert_eq!(
+            Value::Object(ids),
+            json!({ "card_ids": [1, 2], "deck_id": 3 })
+        );
+    }
+
+    #[test]
+    fn test_op_kind() {
+        assert_eq!(op_kind(&Op::RemoveDeck), "RemoveDeck");
+        assert_eq!(op_kind(&Op::Custom("Import".into())), "Import");
+    }
+}
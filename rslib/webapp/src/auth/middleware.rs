@@ -9,6 +9,7 @@ use axum::response::Response;
 use crate::auth::jwt::JwtManager;
 use crate::db::Database;
 use crate::error::WebAppError;
+use crate::jobs::JobManager;
 use crate::session::BackendManager;
 
 #[derive(Clone)]
@@ -16,6 +17,7 @@ pub struct AuthState {
     pub jwt_manager: Arc<JwtManager>,
     pub database: Arc<Database>,
     pub backend_manager: Arc<BackendManager>,
+    pub job_manager: Arc<JobManager>,
 }
 
 /// Extension type to hold authenticated user info
@@ -86,6 +88,9 @@ pub async fn require_auth(
         session_id: claims.session_id.clone(),
     };
 
+    // Attach the user to the request span opened by `track_requests`
+    tracing::Span::current().record("user_id", auth_user.user_id);
+
     request.extensions_mut().insert(auth_user);
 
     Ok(next.run(request).await)
@@ -160,6 +165,7 @@ mod tests {
             jwt_manager,
             database: db,
             backend_manager,
+            job_manager: Arc::new(JobManager::new()),
         }
     }
 
@@ -9,4 +9,5 @@ pub use middleware::require_auth;
 pub use middleware::AuthState;
 pub use middleware::AuthUser;
 pub use password::hash_password;
+pub use password::MIN_PASSWORD_LEN;
 pub use password::verify_password;
@@ -6,6 +6,9 @@ use argon2::password_hash::PasswordVerifier;
 use argon2::password_hash::SaltString;
 use argon2::Argon2;
 
+/// Shortest password accepted for an account
+pub const MIN_PASSWORD_LEN: usize = 8;
+
 /// Hash a password using Argon2
 pub fn hash_password(password: &str) -> Result<String> {
     let salt = SaltString::generate(&mut OsRng);
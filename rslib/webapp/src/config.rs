@@ -3,9 +3,12 @@
 
 use std::net::IpAddr;
 use std::path::PathBuf;
+use std::str::FromStr;
 
+use axum_client_ip::ClientIpSource;
 use serde::Deserialize;
 use serde::Serialize;
+use serde::Serializer;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebAppConfig {
@@ -23,6 +26,85 @@ pub struct WebAppConfig {
 
     #[serde(default = "default_session_timeout_hours")]
     pub session_timeout_hours: u64,
+
+    /// How often open collections are checked for a due backup. The backup
+    /// interval and retention themselves come from each collection's
+    /// preferences. 0 disables automatic backups.
+    #[serde(default = "default_backup_interval_mins")]
+    pub backup_interval_mins: u64,
+
+    #[serde(default)]
+    pub log_format: LogFormat,
+
+    /// How long audit log entries are kept. 0 keeps them forever.
+    #[serde(default = "default_audit_retention_days")]
+    pub audit_retention_days: u64,
+
+    /// How long responses to requests with an `Idempotency-Key` header are
+    /// kept for replay. 0 ignores the header.
+    #[serde(default = "default_idempotency_window_hours")]
+    pub idempotency_window_hours: u64,
+
+    /// PEM certificate chain. When set together with `tls_key_path`, the
+    /// server speaks HTTPS and reloads both files on SIGHUP.
+    #[serde(default)]
+    pub tls_cert_path: Option<PathBuf>,
+
+    /// PEM private key matching `tls_cert_path`
+    #[serde(default)]
+    pub tls_key_path: Option<PathBuf>,
+
+    /// Origins allowed to make cross-origin requests. `*` allows any origin.
+    #[serde(default = "default_cors_allowed_origins")]
+    pub cors_allowed_origins: Vec<String>,
+
+    /// Where the client IP is taken from. Only use a header set by a proxy
+    /// you trust, e.g. `RightmostXForwardedFor` behind nginx; the default
+    /// uses the peer address of the connection.
+    #[serde(default = "default_ip_header", serialize_with = "serialize_ip_header")]
+    pub ip_header: ClientIpSource,
+
+    /// Maximum request body size for JSON endpoints
+    #[serde(default = "default_max_json_body_bytes")]
+    pub max_json_body_bytes: usize,
+
+    /// Maximum request body size for media uploads and package imports
+    #[serde(default = "default_max_upload_body_bytes")]
+    pub max_upload_body_bytes: usize,
+
+    /// On SIGTERM or Ctrl-C, how long to wait for in-flight requests and
+    /// background jobs before closing collections and exiting
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+
+    /// Compile LaTeX in users' notes into images on the server. TeX can read
+    /// files and loop forever, so this is off unless every user is trusted;
+    /// when on, each run is confined to a temporary folder and time limited.
+    #[serde(default)]
+    pub latex_rendering: bool,
+}
+
+/// How log lines are written to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => anyhow::bail!("Invalid log format '{}', expected 'text' or 'json'", s),
+        }
+    }
 }
 
 fn default_host() -> IpAddr {
@@ -45,6 +127,49 @@ fn default_session_timeout_hours() -> u64 {
     24
 }
 
+fn default_backup_interval_mins() -> u64 {
+    15
+}
+
+fn default_audit_retention_days() -> u64 {
+    90
+}
+
+fn default_idempotency_window_hours() -> u64 {
+    24
+}
+
+fn default_cors_allowed_origins() -> Vec<String> {
+    vec![
+        "http://localhost:5173".to_string(),
+        "http://127.0.0.1:5173".to_string(),
+    ]
+}
+
+fn default_ip_header() -> ClientIpSource {
+    ClientIpSource::ConnectInfo
+}
+
+fn default_max_json_body_bytes() -> usize {
+    2 * 1024 * 1024
+}
+
+fn default_max_upload_body_bytes() -> usize {
+    100 * 1024 * 1024
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+/// The variant names are what the source is deserialized from
+fn serialize_ip_header<S: Serializer>(
+    source: &ClientIpSource,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{:?}", source))
+}
+
 impl Default for WebAppConfig {
     fn default() -> Self {
         Self {
@@ -53,6 +178,18 @@ impl Default for WebAppConfig {
             data_dir: default_data_dir(),
             jwt_secret: default_jwt_secret(),
             session_timeout_hours: default_session_timeout_hours(),
+            backup_interval_mins: default_backup_interval_mins(),
+            log_format: LogFormat::default(),
+            audit_retention_days: default_audit_retention_days(),
+            idempotency_window_hours: default_idempotency_window_hours(),
+            tls_cert_path: None,
+            tls_key_path: None,
+            cors_allowed_origins: default_cors_allowed_origins(),
+            ip_header: default_ip_header(),
+            max_json_body_bytes: default_max_json_body_bytes(),
+            max_upload_body_bytes: default_max_upload_body_bytes(),
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            latex_rendering: false,
         }
     }
 }
@@ -90,9 +227,76 @@ impl WebAppConfig {
             config.session_timeout_hours = timeout.parse()?;
         }
 
+        if let Ok(interval) = std::env::var("ANKI_WEBAPP_BACKUP_INTERVAL_MINS") {
+            config.backup_interval_mins = interval.parse()?;
+        }
+
+        if let Ok(log_format) = std::env::var("ANKI_WEBAPP_LOG_FORMAT") {
+            config.log_format = log_format.parse()?;
+        }
+
+        if let Ok(days) = std::env::var("ANKI_WEBAPP_AUDIT_RETENTION_DAYS") {
+            config.audit_retention_days = days.parse()?;
+        }
+
+        if let Ok(hours) = std::env::var("ANKI_WEBAPP_IDEMPOTENCY_WINDOW_HOURS") {
+            config.idempotency_window_hours = hours.parse()?;
+        }
+
+        if let Ok(cert) = std::env::var("ANKI_WEBAPP_TLS_CERT") {
+            config.tls_cert_path = Some(PathBuf::from(cert));
+        }
+
+        if let Ok(key) = std::env::var("ANKI_WEBAPP_TLS_KEY") {
+            config.tls_key_path = Some(PathBuf::from(key));
+        }
+
+        if let Ok(origins) = std::env::var("ANKI_WEBAPP_CORS_ORIGINS") {
+            config.cors_allowed_origins = origins
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+        }
+
+        if let Ok(ip_header) = std::env::var("ANKI_WEBAPP_IP_HEADER") {
+            config.ip_header = serde_json::from_value(serde_json::Value::String(ip_header))
+                .map_err(|e| anyhow::anyhow!("Invalid ANKI_WEBAPP_IP_HEADER: {}", e))?;
+        }
+
+        if let Ok(limit) = std::env::var("ANKI_WEBAPP_MAX_JSON_BODY_BYTES") {
+            config.max_json_body_bytes = limit.parse()?;
+        }
+
+        if let Ok(limit) = std::env::var("ANKI_WEBAPP_MAX_UPLOAD_BODY_BYTES") {
+            config.max_upload_body_bytes = limit.parse()?;
+        }
+
+        if let Ok(timeout) = std::env::var("ANKI_WEBAPP_SHUTDOWN_TIMEOUT_SECS") {
+            config.shutdown_timeout_secs = timeout.parse()?;
+        }
+
+        if let Ok(enabled) = std::env::var("ANKI_WEBAPP_LATEX_RENDERING") {
+            config.latex_rendering = enabled.parse()?;
+        }
+
+        config.validate()?;
+
         Ok(config)
     }
 
+    /// The certificate and key paths, if TLS is enabled
+    pub fn tls_paths(&self) -> Option<(&PathBuf, &PathBuf)> {
+        self.tls_cert_path.as_ref().zip(self.tls_key_path.as_ref())
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.tls_cert_path.is_some() != self.tls_key_path.is_some() {
+            anyhow::bail!("Both a TLS certificate and key must be configured to enable TLS");
+        }
+        Ok(())
+    }
+
     /// Load configuration from a TOML file
     pub fn from_file(path: &str) -> anyhow::Result<Self> {
         let contents = std::fs::read_to_string(path)?;
@@ -117,6 +321,16 @@ mod tests {
         assert_eq!(config.data_dir, PathBuf::from("./data"));
         assert_eq!(config.jwt_secret, "change-this-secret-in-production");
         assert_eq!(config.session_timeout_hours, 24);
+        assert_eq!(config.backup_interval_mins, 15);
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert_eq!(config.audit_retention_days, 90);
+        assert_eq!(config.idempotency_window_hours, 24);
+        assert!(config.tls_paths().is_none());
+        assert_eq!(config.cors_allowed_origins.len(), 2);
+        assert!(matches!(config.ip_header, ClientIpSource::ConnectInfo));
+        assert_eq!(config.max_json_body_bytes, 2 * 1024 * 1024);
+        assert_eq!(config.shutdown_timeout_secs, 30);
+        assert!(!config.latex_rendering);
     }
 
     #[test]
@@ -130,6 +344,12 @@ port = 9000
 data_dir = "/custom/path"
 jwt_secret = "custom-secret"
 session_timeout_hours = 48
+log_format = "json"
+tls_cert_path = "/certs/cert.pem"
+tls_key_path = "/certs/key.pem"
+cors_allowed_origins = ["https://anki.example.com"]
+ip_header = "RightmostXForwardedFor"
+max_upload_body_bytes = 1048576
 "#
         )
         .unwrap();
@@ -140,6 +360,17 @@ session_timeout_hours = 48
         assert_eq!(config.data_dir, PathBuf::from("/custom/path"));
         assert_eq!(config.jwt_secret, "custom-secret");
         assert_eq!(config.session_timeout_hours, 48);
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert!(config.tls_paths().is_some());
+        assert_eq!(
+            config.cors_allowed_origins,
+            vec!["https://anki.example.com"]
+        );
+        assert!(matches!(
+            config.ip_header,
+            ClientIpSource::RightmostXForwardedFor
+        ));
+        assert_eq!(config.max_upload_body_bytes, 1048576);
     }
 
     #[test]
@@ -175,4 +406,13 @@ jwt_secret = "my-secret"
         std::env::remove_var("ANKI_WEBAPP_PORT");
         std::env::remove_var("ANKI_WEBAPP_JWT_SECRET");
     }
+
+    #[test]
+    fn test_tls_requires_cert_and_key() {
+        let config = WebAppConfig {
+            tls_cert_path: Some(PathBuf::from("/certs/cert.pem")),
+            ..WebAppConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
 }
@@ -0,0 +1,256 @@
+use anyhow::Result;
+use rusqlite::params;
+use rusqlite::params_from_iter;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::Row;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::current_timestamp;
+use super::Database;
+
+/// Upper bound on the number of entries returned by a single query
+pub const MAX_AUDIT_QUERY_LIMIT: i64 = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub user_id: i64,
+    pub session_id: Option<String>,
+    pub client_ip: Option<String>,
+    pub method: String,
+    pub route: String,
+    /// Name of the collection operation the request performed, if any
+    pub op: Option<String>,
+    /// Ids taken from the request path and body, keyed by parameter name
+    pub affected_ids: Value,
+    pub status: u16,
+    pub created_at: i64,
+}
+
+impl AuditEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let affected_ids: Option<String> = row.get(7)?;
+        Ok(AuditEntry {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            session_id: row.get(2)?,
+            client_ip: row.get(3)?,
+            method: row.get(4)?,
+            route: row.get(5)?,
+            op: row.get(6)?,
+            affected_ids: affected_ids
+                .and_then(|ids| serde_json::from_str(&ids).ok())
+                .unwrap_or(Value::Null),
+            status: row.get(8)?,
+            created_at: row.get(9)?,
+        })
+    }
+}
+
+/// An entry to be appended to the log
+#[derive(Debug, Clone)]
+pub struct NewAuditEntry {
+    pub user_id: i64,
+    pub session_id: Option<String>,
+    pub client_ip: Option<String>,
+    pub method: String,
+    pub route: String,
+    pub op: Option<String>,
+    pub affected_ids: Value,
+    pub status: u16,
+}
+
+/// Filters for querying a user's audit log. Entries are returned newest
+/// first.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    pub session_id: Option<String>,
+    pub client_ip: Option<String>,
+    pub method: Option<String>,
+    pub route: Option<String>,
+    pub op: Option<String>,
+    /// Only entries created at or after this timestamp
+    pub since: Option<i64>,
+    /// Only entries created before this timestamp
+    pub until: Option<i64>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+pub struct AuditStore<'a> {
+    db: &'a Database,
+}
+
+impl<'a> AuditStore<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    pub fn record(&self, entry: &NewAuditEntry) -> Result<i64> {
+        let now = current_timestamp();
+        let affected_ids = if entry.affected_ids.is_null() {
+            None
+        } else {
+            Some(entry.affected_ids.to_string())
+        };
+
+        self.db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO audit_log (user_id, session_id, client_ip, method, route, op, affected_ids, status, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    entry.user_id,
+                    entry.session_id,
+                    entry.client_ip,
+                    entry.method,
+                    entry.route,
+                    entry.op,
+                    affected_ids,
+                    entry.status,
+                    now
+                ],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+    }
+
+    pub fn query(&self, user_id: i64, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        let mut sql = String::from(
+            "SELECT id, user_id, session_id, client_ip, method, route, op, affected_ids, status, created_at FROM audit_log WHERE user_id = ?",
+        );
+        let mut args: Vec<SqlValue> = vec![user_id.into()];
+
+        for (column, value) in [
+            ("session_id", &query.session_id),
+            ("client_ip", &query.client_ip),
+            ("method", &query.method),
+            ("route", &query.route),
+            ("op", &query.op),
+        ] {
+            if let Some(value) = value {
+                sql.push_str(&format!(" AND {} = ?", column));
+                args.push(value.clone().into());
+            }
+        }
+        if let Some(since) = query.since {
+            sql.push_str(" AND created_at >= ?");
+            args.push(since.into());
+        }
+        if let Some(until) = query.until {
+            sql.push_str(" AND created_at < ?");
+            args.push(until.into());
+        }
+
+        sql.push_str(" ORDER BY created_at DESC, id DESC LIMIT ? OFFSET ?");
+        args.push(
+            query
+                .limit
+                .unwrap_or(100)
+                .clamp(1, MAX_AUDIT_QUERY_LIMIT)
+                .into(),
+        );
+        args.push(query.offset.unwrap_or(0).max(0).into());
+
+        self.db.with_conn(|conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            let entries = stmt
+                .query_map(params_from_iter(args), AuditEntry::from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(entries)
+        })
+    }
+
+    /// Delete entries created before the given timestamp, returning how many
+    /// were removed
+    pub fn prune_before(&self, cutoff: i64) -> Result<usize> {
+        self.db.with_conn(|conn| {
+            let count = conn.execute(
+                "DELETE FROM audit_log WHERE created_at < ?1",
+                params![cutoff],
+            )?;
+            Ok(count)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn entry(user_id: i64, route: &str, op: Option<&str>) -> NewAuditEntry {
+        NewAuditEntry {
+            user_id,
+            session_id: Some("session1".to_string()),
+            client_ip: Some("127.0.0.1".to_string()),
+            method: "DELETE".to_string(),
+            route: route.to_string(),
+            op: op.map(ToString::to_string),
+            affected_ids: json!({ "id": 5 }),
+            status: 200,
+        }
+    }
+
+    #[test]
+    fn test_record_and_query() {
+        let db = Database::open(":memory:").unwrap();
+        db.initialize().unwrap();
+        let store = db.audit();
+
+        store
+            .record(&entry(1, "/api/v1/decks/{id}", Some("RemoveDeck")))
+            .unwrap();
+        store
+            .record(&entry(1, "/api/v1/notes/{id}", Some("RemoveNote")))
+            .unwrap();
+        store
+            .record(&entry(2, "/api/v1/decks/{id}", Some("RemoveDeck")))
+            .unwrap();
+
+        // Only the user's own entries, newest first
+        let entries = store.query(1, &AuditQuery::default()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].route, "/api/v1/notes/{id}");
+        assert_eq!(entries[0].affected_ids["id"], 5);
+
+        let entries = store
+            .query(
+                1,
+                &AuditQuery {
+                    op: Some("RemoveDeck".to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].route, "/api/v1/decks/{id}");
+
+        let entries = store
+            .query(
+                1,
+                &AuditQuery {
+                    limit: Some(1),
+                    offset: Some(1),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].route, "/api/v1/decks/{id}");
+    }
+
+    #[test]
+    fn test_prune_before() {
+        let db = Database::open(":memory:").unwrap();
+        db.initialize().unwrap();
+        let store = db.audit();
+
+        store.record(&entry(1, "/api/v1/decks/{id}", None)).unwrap();
+        assert_eq!(store.prune_before(0).unwrap(), 0);
+        assert_eq!(store.prune_before(current_timestamp() + 1).unwrap(), 1);
+        assert!(store.query(1, &AuditQuery::default()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::Result;
+use rusqlite::params;
+use rusqlite::OptionalExtension;
+
+use super::Database;
+
+/// A response stored so that a retried request can be answered with it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// The result of claiming an idempotency key for a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdempotencyClaim {
+    /// The key was unused; the request should be handled, then completed or
+    /// released
+    Claimed,
+    /// The key belongs to a request that is still being handled
+    InProgress,
+    /// The key was used for a different request
+    Mismatch,
+    /// The key's request already finished with this response
+    Completed(StoredResponse),
+}
+
+pub struct IdempotencyStore<'a> {
+    db: &'a Database,
+}
+
+impl<'a> IdempotencyStore<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    /// Claim a key for a request with the given fingerprint. Keys created
+    /// before `expired_before` are forgotten. An unfinished claim is never
+    /// taken over, however old, as its request may still be running.
+    pub fn claim(
+        &self,
+        user_id: i64,
+        key: &str,
+        fingerprint: &str,
+        now: i64,
+        expired_before: i64,
+    ) -> Result<IdempotencyClaim> {
+        self.db.with_conn(|conn| {
+            conn.execute(
+                "DELETE FROM idempotency_keys WHERE user_id = ?1 AND key = ?2 AND created_at < ?3",
+                params![user_id, key, expired_before],
+            )?;
+
+            let existing = conn
+                .query_row(
+                    "SELECT fingerprint, status, content_type, body FROM idempotency_keys WHERE user_id = ?1 AND key = ?2",
+                    params![user_id, key],
+                    |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, Option<u16>>(1)?,
+                            row.get::<_, Option<String>>(2)?,
+                            row.get::<_, Option<Vec<u8>>>(3)?,
+                        ))
+                    },
+                )
+                .optional()?;
+
+            match existing {
+                Some((stored, _, _, _)) if stored != fingerprint => Ok(IdempotencyClaim::Mismatch),
+                Some((_, None, _, _)) => Ok(IdempotencyClaim::InProgress),
+                Some((_, Some(status), content_type, body)) => {
+                    Ok(IdempotencyClaim::Completed(StoredResponse {
+                        status,
+                        content_type,
+                        body: body.unwrap_or_default(),
+                    }))
+                }
+                None => {
+                    conn.execute(
+                        "INSERT INTO idempotency_keys (user_id, key, fingerprint, created_at) VALUES (?1, ?2, ?3, ?4)",
+                        params![user_id, key, fingerprint, now],
+                    )?;
+                    Ok(IdempotencyClaim::Claimed)
+                }
+            }
+        })
+    }
+
+    /// Store the response for a claimed key
+    pub fn complete(&self, user_id: i64, key: &str, response: &StoredResponse) -> Result<()> {
+        self.db.with_conn(|conn| {
+            conn.execute(
+                "UPDATE idempotency_keys SET status = ?3, content_type = ?4, body = ?5 WHERE user_id = ?1 AND key = ?2",
+                params![
+                    user_id,
+                    key,
+                    response.status,
+                    response.content_type,
+                    response.body
+                ],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Give up a claimed key, so the request can be retried with it
+    pub fn release(&self, user_id: i64, key: &str) -> Result<()> {
+        self.db.with_conn(|conn| {
+            conn.execute(
+                "DELETE FROM idempotency_keys WHERE user_id = ?1 AND key = ?2",
+                params![user_id, key],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Give up every unfinished claim, returning how many there were. Only
+    /// safe when no request can be running, i.e. at startup.
+    pub fn release_unfinished(&self) -> Result<usize> {
+        self.db.with_conn(|conn| {
+            let count = conn.execute("DELETE FROM idempotency_keys WHERE status IS NULL", [])?;
+            Ok(count)
+        })
+    }
+
+    /// Delete keys created before the given timestamp, returning how many
+    /// were removed
+    pub fn prune_before(&self, cutoff: i64) -> Result<usize> {
+        self.db.with_conn(|conn| {
+            let count = conn.execute(
+                "DELETE FROM idempotency_keys WHERE created_at < ?1",
+                params![cutoff],
+            )?;
+            Ok(count)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response() -> StoredResponse {
+        StoredResponse {
+            status: 201,
+            content_type: Some("application/json".to_string()),
+            body: b"{\"note_id\":1}".to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_claim_complete_and_replay() {
+        let db = Database::open(":memory:").unwrap();
+        db.initialize().unwrap();
+        let store = db.idempotency();
+
+        assert_eq!(
+            store.claim(1, "key", "a", 100, 0).unwrap(),
+            IdempotencyClaim::Claimed
+        );
+        assert_eq!(
+            store.claim(1, "key", "a", 101, 0).unwrap(),
+            IdempotencyClaim::InProgress
+        );
+        assert_eq!(
+            store.claim(1, "key", "b", 101, 0).unwrap(),
+            IdempotencyClaim::Mismatch
+        );
+
+        store.complete(1, "key", &response()).unwrap();
+        assert_eq!(
+            store.claim(1, "key", "a", 102, 0).unwrap(),
+            IdempotencyClaim::Completed(response())
+        );
+
+        // Keys are per user
+        assert_eq!(
+            store.claim(2, "key", "b", 102, 0).unwrap(),
+            IdempotencyClaim::Claimed
+        );
+    }
+
+    #[test]
+    fn test_expiry_and_release() {
+        let db = Database::open(":memory:").unwrap();
+        db.initialize().unwrap();
+        let store = db.idempotency();
+
+        store.claim(1, "old", "a", 100, 0).unwrap();
+        store.complete(1, "old", &response()).unwrap();
+        assert_eq!(
+            store.claim(1, "old", "b", 200, 150).unwrap(),
+            IdempotencyClaim::Claimed
+        );
+        store.complete(1, "old", &response()).unwrap();
+
+        // Unfinished claims are kept until released, however old
+        store.claim(1, "stuck", "a", 100, 0).unwrap();
+        assert_eq!(
+            store.claim(1, "stuck", "a", 200, 0).unwrap(),
+            IdempotencyClaim::InProgress
+        );
+
+        store.release(1, "stuck").unwrap();
+        assert_eq!(
+            store.claim(1, "stuck", "c", 200, 0).unwrap(),
+            IdempotencyClaim::Claimed
+        );
+
+        // At startup, claims left by the previous run are released
+        assert_eq!(store.release_unfinished().unwrap(), 1);
+        assert_eq!(
+            store.claim(1, "stuck", "a", 200, 0).unwrap(),
+            IdempotencyClaim::Claimed
+        );
+
+        assert_eq!(store.prune_before(250).unwrap(), 2);
+    }
+}
@@ -1,4 +1,3 @@
--- Anki Web App Database Schema
 -- Users and sessions for multi-user support
 CREATE TABLE IF NOT EXISTS users (
   id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -22,11 +21,3 @@ CREATE TABLE IF NOT EXISTS sessions (
 );
 CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON sessions(user_id);
 CREATE INDEX IF NOT EXISTS idx_sessions_expires_at ON sessions(expires_at);
--- Schema version tracking
-CREATE TABLE IF NOT EXISTS schema_version (
-  version INTEGER PRIMARY KEY,
-  applied_at INTEGER NOT NULL
-);
-INSERT
-  OR IGNORE INTO schema_version (version, applied_at)
-VALUES (1, strftime('%s', 'now'));
\ No newline at end of file
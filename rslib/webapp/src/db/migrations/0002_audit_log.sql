@@ -0,0 +1,17 @@
+-- Append-only log of mutating API requests. Not tied to users by a foreign
+-- key, so history survives account deletion.
+-- Databases created before versioned migrations may already have the table.
+CREATE TABLE IF NOT EXISTS audit_log (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL,
+  session_id TEXT,
+  client_ip TEXT,
+  method TEXT NOT NULL,
+  route TEXT NOT NULL,
+  op TEXT,
+  affected_ids TEXT,
+  status INTEGER NOT NULL,
+  created_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_audit_log_user_created ON audit_log(user_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at);
@@ -0,0 +1,28 @@
+-- Decks published to the server's shared library, and the users subscribed to
+-- them. Each publish of the same source deck bumps its version; a
+-- subscription records the version last imported into the subscriber's
+-- collection.
+CREATE TABLE shared_decks (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  owner_id INTEGER NOT NULL,
+  source_deck_id INTEGER NOT NULL,
+  name TEXT NOT NULL,
+  description TEXT NOT NULL DEFAULT '',
+  version INTEGER NOT NULL,
+  note_count INTEGER NOT NULL,
+  created_at INTEGER NOT NULL,
+  updated_at INTEGER NOT NULL,
+  UNIQUE (owner_id, source_deck_id),
+  FOREIGN KEY (owner_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE TABLE deck_subscriptions (
+  user_id INTEGER NOT NULL,
+  shared_deck_id INTEGER NOT NULL,
+  version INTEGER NOT NULL,
+  subscribed_at INTEGER NOT NULL,
+  updated_at INTEGER NOT NULL,
+  PRIMARY KEY (user_id, shared_deck_id),
+  FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+  FOREIGN KEY (shared_deck_id) REFERENCES shared_decks(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_deck_subscriptions_shared_deck ON deck_subscriptions(shared_deck_id);
@@ -0,0 +1,6 @@
+-- Server-wide settings that operators change at runtime with the admin
+-- commands, stored as text keyed by name. A missing row means the default.
+CREATE TABLE settings (
+  key TEXT PRIMARY KEY,
+  value TEXT NOT NULL
+);
@@ -0,0 +1,244 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! Versioned schema migrations for the webapp database.
+//!
+//! Each migration is applied once, in its own transaction, and recorded in
+//! `schema_version`. Migrations are never edited after release; schema
+//! changes go into a new, higher-numbered file.
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use rusqlite::params;
+use rusqlite::Connection;
+
+use super::current_timestamp;
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    sql: &'static str,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "users and sessions",
+        sql: include_str!("0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "audit log",
+        sql: include_str!("0002_audit_log.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "idempotency keys",
+        sql: include_str!("0003_idempotency_keys.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "shared decks",
+        sql: include_str!("0004_shared_decks.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "settings",
+        sql: include_str!("0005_settings.sql"),
+    },
+];
+
+/// The schema version this build creates and understands.
+pub fn latest_version() -> u32 {
+    latest_of(MIGRATIONS)
+}
+
+/// Bring the database up to [latest_version]. Fails without changing
+/// anything if the database was written by a newer build.
+pub(super) fn migrate(conn: &mut Connection) -> Result<()> {
+    apply(conn, MIGRATIONS)
+}
+
+/// The highest migration applied to the database, or 0 for a new database.
+pub(super) fn current_version(conn: &Connection) -> Result<u32> {
+    let version = conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(version)
+}
+
+fn latest_of(migrations: &[Migration]) -> u32 {
+    migrations.last().map_or(0, |migration| migration.version)
+}
+
+fn apply(conn: &mut Connection, migrations: &[Migration]) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            applied_at INTEGER NOT NULL
+        )",
+    )?;
+
+    let current = current_version(conn)?;
+    let latest = latest_of(migrations);
+    if current > latest {
+        bail!(
+            "Database schema version {} is newer than the latest version this build supports ({}); \
+             upgrade anki-webapp to use this database",
+            current,
+            latest
+        );
+    }
+
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql).with_context(|| {
+            format!(
+                "Failed to apply database migration {} ({})",
+                migration.version, migration.description
+            )
+        })?;
+        tx.execute(
+            "INSERT INTO schema_version (version, applied_at) VALUES (?1, ?2)",
+            params![migration.version, current_timestamp()],
+        )?;
+        tx.commit()?;
+        tracing::info!(
+            version = migration.version,
+            "Applied database migration: {}",
+            migration.description
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::db::Database;
+
+    fn v1_fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/webapp_v1.db");
+        std::fs::copy(fixture, dir.path().join("webapp.db")).unwrap();
+        dir
+    }
+
+    fn table_exists(db: &Database, name: &str) -> bool {
+        db.with_conn(|conn| {
+            conn.query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                [name],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(Into::into)
+        })
+        .unwrap()
+            == 1
+    }
+
+    #[test]
+    fn test_versions_are_ordered() {
+        for (idx, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, idx + 1);
+        }
+    }
+
+    #[test]
+    fn test_new_database_is_latest() {
+        let db = Database::open(":memory:").unwrap();
+        db.initialize().unwrap();
+        assert_eq!(db.schema_version().unwrap(), latest_version());
+
+        // Running again is a no-op
+        db.initialize().unwrap();
+        assert_eq!(db.schema_version().unwrap(), latest_version());
+    }
+
+    #[test]
+    fn test_upgrade_v1_fixture() {
+        let dir = v1_fixture();
+        let db = Database::open(dir.path().join("webapp.db")).unwrap();
+        assert_eq!(db.schema_version().unwrap(), 1);
+        assert!(!table_exists(&db, "audit_log"));
+
+        db.initialize().unwrap();
+        assert_eq!(db.schema_version().unwrap(), latest_version());
+        assert!(table_exists(&db, "audit_log"));
+        assert!(table_exists(&db, "idempotency_keys"));
+        assert!(table_exists(&db, "shared_decks"));
+        assert!(table_exists(&db, "settings"));
+
+        // Existing accounts and sessions survive the upgrade
+        let user = db.users().get_by_username("alice").unwrap().unwrap();
+        assert_eq!(user.email.as_deref(), Some("alice@example.com"));
+        assert!(db.sessions().get("session-1").unwrap().is_some());
+
+        // Only the missing migrations were recorded
+        let applied: Vec<u32> = db
+            .with_conn(|conn| {
+                let mut stmt =
+                    conn.prepare("SELECT version FROM schema_version ORDER BY version")?;
+                let versions = stmt
+                    .query_map([], |row| row.get(0))?
+                    .collect::<rusqlite::Result<_>>()?;
+                Ok(versions)
+            })
+            .unwrap();
+        assert_eq!(applied, (1..=latest_version()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_refuses_newer_schema() {
+        let dir = v1_fixture();
+        let path = dir.path().join("webapp.db");
+        let db = Database::open(&path).unwrap();
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO schema_version (version, applied_at) VALUES (?1, 0)",
+                [latest_version() + 1],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let err = db.initialize().unwrap_err();
+        assert!(err.to_string().contains("newer"));
+        // Nothing was applied
+        assert!(!table_exists(&db, "audit_log"));
+    }
+
+    #[test]
+    fn test_failed_migration_is_rolled_back() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration {
+                version: 1,
+                description: "first",
+                sql: "CREATE TABLE first (id INTEGER);",
+            },
+            Migration {
+                version: 2,
+                description: "broken",
+                sql: "CREATE TABLE second (id INTEGER); INSERT INTO missing VALUES (1);",
+            },
+        ];
+
+        assert!(apply(&mut conn, &migrations).is_err());
+        assert_eq!(current_version(&conn).unwrap(), 1);
+        let second: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE name = 'second'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(second, 0);
+    }
+}
@@ -1,5 +1,6 @@
 use std::path::Path;
 use std::sync::Mutex;
+use std::time::Duration;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
@@ -7,15 +8,32 @@ use anyhow::Result;
 use rusqlite::params;
 use rusqlite::Connection;
 
+pub mod audit;
+pub mod idempotency;
+pub mod migrations;
 pub mod sessions;
+pub mod settings;
+pub mod sharing;
 pub mod users;
 
+pub use audit::AuditEntry;
+pub use audit::AuditQuery;
+pub use audit::AuditStore;
+pub use audit::NewAuditEntry;
+pub use idempotency::IdempotencyClaim;
+pub use idempotency::IdempotencyStore;
+pub use idempotency::StoredResponse;
 pub use sessions::Session;
 pub use sessions::SessionStore;
+pub use settings::SettingsStore;
+pub use sharing::SharedDeck;
+pub use sharing::SharingStore;
+pub use sharing::Subscription;
 pub use users::User;
 pub use users::UserStore;
 
-const SCHEMA_SQL: &str = include_str!("schema.sql");
+/// How long to wait for another process to release a lock on the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct Database {
     conn: Mutex<Connection>,
@@ -25,15 +43,22 @@ impl Database {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
         let conn = Connection::open(path)?;
         conn.execute("PRAGMA foreign_keys = ON", [])?;
+        // The admin CLI may write while the server is running
+        conn.busy_timeout(BUSY_TIMEOUT)?;
         Ok(Self {
             conn: Mutex::new(conn),
         })
     }
 
+    /// Apply any pending schema migrations.
     pub fn initialize(&self) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        migrations::migrate(&mut conn)
+    }
+
+    pub fn schema_version(&self) -> Result<u32> {
         let conn = self.conn.lock().unwrap();
-        conn.execute_batch(SCHEMA_SQL)?;
-        Ok(())
+        migrations::current_version(&conn)
     }
 
     pub fn users(&self) -> UserStore<'_> {
@@ -44,6 +69,22 @@ impl Database {
         SessionStore::new(self)
     }
 
+    pub fn audit(&self) -> AuditStore<'_> {
+        AuditStore::new(self)
+    }
+
+    pub fn idempotency(&self) -> IdempotencyStore<'_> {
+        IdempotencyStore::new(self)
+    }
+
+    pub fn sharing(&self) -> SharingStore<'_> {
+        SharingStore::new(self)
+    }
+
+    pub fn settings(&self) -> SettingsStore<'_> {
+        SettingsStore::new(self)
+    }
+
     pub fn cleanup_expired_sessions(&self) -> Result<usize> {
         let now = current_timestamp();
         let conn = self.conn.lock().unwrap();
@@ -79,13 +120,13 @@ mod tests {
         // Verify tables exist
         let table_count: i64 = db.with_conn(|conn| {
             conn.query_row(
-                "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name IN ('users', 'sessions', 'schema_version')",
+                "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name IN ('users', 'sessions', 'schema_version', 'audit_log')",
                 [],
                 |row| row.get(0),
             )
             .map_err(Into::into)
         }).unwrap();
-        assert_eq!(table_count, 3);
+        assert_eq!(table_count, 4);
     }
 
     #[test]
@@ -93,10 +93,17 @@ impl<'a> SessionStore<'a> {
         })
     }
 
-    pub fn delete_by_user(&self, user_id: i64) -> Result<()> {
+    pub fn delete_by_user(&self, user_id: i64) -> Result<usize> {
         self.db.with_conn(|conn| {
-            conn.execute("DELETE FROM sessions WHERE user_id = ?1", params![user_id])?;
-            Ok(())
+            let count = conn.execute("DELETE FROM sessions WHERE user_id = ?1", params![user_id])?;
+            Ok(count)
+        })
+    }
+
+    pub fn delete_all(&self) -> Result<usize> {
+        self.db.with_conn(|conn| {
+            let count = conn.execute("DELETE FROM sessions", [])?;
+            Ok(count)
         })
     }
 
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use rusqlite::params;
+use rusqlite::OptionalExtension;
+
+use super::Database;
+
+const REGISTRATION_ENABLED: &str = "registration_enabled";
+
+/// Server-wide settings changed with the admin commands. They live in the
+/// database rather than the config file so they apply without a restart.
+pub struct SettingsStore<'a> {
+    db: &'a Database,
+}
+
+impl<'a> SettingsStore<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    /// Whether anyone may create an account through the API. On by default.
+    pub fn registration_enabled(&self) -> Result<bool> {
+        Ok(self
+            .get(REGISTRATION_ENABLED)?
+            .is_none_or(|value| value == "true"))
+    }
+
+    pub fn set_registration_enabled(&self, enabled: bool) -> Result<()> {
+        self.set(REGISTRATION_ENABLED, &enabled.to_string())
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.db.with_conn(|conn| {
+            let value = conn
+                .query_row(
+                    "SELECT value FROM settings WHERE key = ?1",
+                    params![key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(value)
+        })
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registration_toggle() {
+        let db = Database::open(":memory:").unwrap();
+        db.initialize().unwrap();
+        let store = db.settings();
+
+        assert!(store.registration_enabled().unwrap());
+        store.set_registration_enabled(false).unwrap();
+        assert!(!store.registration_enabled().unwrap());
+        store.set_registration_enabled(true).unwrap();
+        assert!(store.registration_enabled().unwrap());
+    }
+}
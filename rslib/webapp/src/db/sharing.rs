@@ -0,0 +1,299 @@
+use anyhow::Result;
+use rusqlite::params;
+use rusqlite::OptionalExtension;
+use rusqlite::Row;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use super::current_timestamp;
+use super::Database;
+
+const SHARED_DECK_COLUMNS: &str = "s.id, s.owner_id, u.username, s.source_deck_id, s.name, s.description, s.version, s.note_count, s.created_at, s.updated_at";
+
+/// A deck in the server's shared library
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct SharedDeck {
+    pub id: i64,
+    pub owner_id: i64,
+    pub owner_username: String,
+    /// The deck in the owner's collection the snapshot was taken from
+    pub source_deck_id: i64,
+    pub name: String,
+    pub description: String,
+    /// Starts at 1 and increases each time the deck is published again
+    pub version: i64,
+    pub note_count: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl SharedDeck {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(SharedDeck {
+            id: row.get(0)?,
+            owner_id: row.get(1)?,
+            owner_username: row.get(2)?,
+            source_deck_id: row.get(3)?,
+            name: row.get(4)?,
+            description: row.get(5)?,
+            version: row.get(6)?,
+            note_count: row.get(7)?,
+            created_at: row.get(8)?,
+            updated_at: row.get(9)?,
+        })
+    }
+}
+
+/// A user's subscription to a shared deck
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subscription {
+    pub user_id: i64,
+    pub shared_deck_id: i64,
+    /// The version of the shared deck last imported into the user's
+    /// collection
+    pub version: i64,
+    pub subscribed_at: i64,
+    pub updated_at: i64,
+}
+
+impl Subscription {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Subscription {
+            user_id: row.get(0)?,
+            shared_deck_id: row.get(1)?,
+            version: row.get(2)?,
+            subscribed_at: row.get(3)?,
+            updated_at: row.get(4)?,
+        })
+    }
+}
+
+pub struct SharingStore<'a> {
+    db: &'a Database,
+}
+
+impl<'a> SharingStore<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    /// Add a deck to the shared library, or bump its version if the owner
+    /// published it before. A missing description keeps the previous one.
+    pub fn publish(
+        &self,
+        owner_id: i64,
+        source_deck_id: i64,
+        name: &str,
+        description: Option<&str>,
+        note_count: i64,
+    ) -> Result<SharedDeck> {
+        let now = current_timestamp();
+        let id = self.db.with_conn(|conn| {
+            conn.query_row(
+                "INSERT INTO shared_decks (owner_id, source_deck_id, name, description, version, note_count, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, COALESCE(?4, ''), 1, ?5, ?6, ?6)
+                 ON CONFLICT (owner_id, source_deck_id) DO UPDATE SET
+                   name = ?3, description = COALESCE(?4, description), version = version + 1,
+                   note_count = ?5, updated_at = ?6
+                 RETURNING id",
+                params![owner_id, source_deck_id, name, description, note_count, now],
+                |row| row.get(0),
+            )
+            .map_err(Into::into)
+        })?;
+
+        self.get(id)?
+            .ok_or_else(|| anyhow::anyhow!("Failed to retrieve published deck"))
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<SharedDeck>> {
+        self.db.with_conn(|conn| {
+            conn.query_row(
+                &format!("SELECT {SHARED_DECK_COLUMNS} FROM shared_decks s JOIN users u ON u.id = s.owner_id WHERE s.id = ?1"),
+                params![id],
+                SharedDeck::from_row,
+            )
+            .optional()
+            .map_err(Into::into)
+        })
+    }
+
+    /// All shared decks, ordered by name
+    pub fn list(&self) -> Result<Vec<SharedDeck>> {
+        self.db.with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {SHARED_DECK_COLUMNS} FROM shared_decks s JOIN users u ON u.id = s.owner_id ORDER BY s.name, s.id"
+            ))?;
+            let decks = stmt
+                .query_map([], SharedDeck::from_row)?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(decks)
+        })
+    }
+
+    /// Remove a deck from the library, ending its subscriptions. Returns
+    /// false if it did not exist.
+    pub fn delete(&self, id: i64) -> Result<bool> {
+        self.db.with_conn(|conn| {
+            let count = conn.execute("DELETE FROM shared_decks WHERE id = ?1", params![id])?;
+            Ok(count > 0)
+        })
+    }
+
+    /// Record that `version` of a shared deck was imported into the user's
+    /// collection, subscribing them if they were not already
+    pub fn set_subscribed_version(
+        &self,
+        user_id: i64,
+        shared_deck_id: i64,
+        version: i64,
+    ) -> Result<()> {
+        let now = current_timestamp();
+        self.db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO deck_subscriptions (user_id, shared_deck_id, version, subscribed_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?4)
+                 ON CONFLICT (user_id, shared_deck_id) DO UPDATE SET version = ?3, updated_at = ?4",
+                params![user_id, shared_deck_id, version, now],
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn get_subscription(
+        &self,
+        user_id: i64,
+        shared_deck_id: i64,
+    ) -> Result<Option<Subscription>> {
+        self.db.with_conn(|conn| {
+            conn.query_row(
+                "SELECT user_id, shared_deck_id, version, subscribed_at, updated_at FROM deck_subscriptions WHERE user_id = ?1 AND shared_deck_id = ?2",
+                params![user_id, shared_deck_id],
+                Subscription::from_row,
+            )
+            .optional()
+            .map_err(Into::into)
+        })
+    }
+
+    /// The user's subscriptions and the decks they refer to, ordered by deck
+    /// name
+    pub fn list_subscriptions(&self, user_id: i64) -> Result<Vec<(Subscription, SharedDeck)>> {
+        self.db.with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT d.user_id, d.shared_deck_id, d.version, d.subscribed_at, d.updated_at, {SHARED_DECK_COLUMNS}
+                 FROM deck_subscriptions d
+                 JOIN shared_decks s ON s.id = d.shared_deck_id
+                 JOIN users u ON u.id = s.owner_id
+                 WHERE d.user_id = ?1 ORDER BY s.name, s.id"
+            ))?;
+            let subscriptions = stmt
+                .query_map(params![user_id], |row| {
+                    let subscription = Subscription::from_row(row)?;
+                    let deck = SharedDeck {
+                        id: row.get(5)?,
+                        owner_id: row.get(6)?,
+                        owner_username: row.get(7)?,
+                        source_deck_id: row.get(8)?,
+                        name: row.get(9)?,
+                        description: row.get(10)?,
+                        version: row.get(11)?,
+                        note_count: row.get(12)?,
+                        created_at: row.get(13)?,
+                        updated_at: row.get(14)?,
+                    };
+                    Ok((subscription, deck))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(subscriptions)
+        })
+    }
+
+    /// Returns false if the user was not subscribed
+    pub fn unsubscribe(&self, user_id: i64, shared_deck_id: i64) -> Result<bool> {
+        self.db.with_conn(|conn| {
+            let count = conn.execute(
+                "DELETE FROM deck_subscriptions WHERE user_id = ?1 AND shared_deck_id = ?2",
+                params![user_id, shared_deck_id],
+            )?;
+            Ok(count > 0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (Database, i64, i64) {
+        let db = Database::open(":memory:").unwrap();
+        db.initialize().unwrap();
+        let teacher = db.users().create("teacher", "hash", None).unwrap().id;
+        let student = db.users().create("student", "hash", None).unwrap().id;
+        (db, teacher, student)
+    }
+
+    #[test]
+    fn test_publish_bumps_version() {
+        let (db, teacher, _) = setup();
+        let store = db.sharing();
+
+        let deck = store
+            .publish(teacher, 10, "Spanish", Some("Weekly vocab"), 5)
+            .unwrap();
+        assert_eq!(deck.version, 1);
+        assert_eq!(deck.owner_username, "teacher");
+
+        let again = store.publish(teacher, 10, "Spanish 1", None, 7).unwrap();
+        assert_eq!(again.id, deck.id);
+        assert_eq!(again.version, 2);
+        assert_eq!(again.name, "Spanish 1");
+        assert_eq!(again.description, "Weekly vocab");
+        assert_eq!(again.note_count, 7);
+
+        // Another source deck is a separate entry
+        let other = store.publish(teacher, 11, "French", None, 1).unwrap();
+        assert_ne!(other.id, deck.id);
+        assert_eq!(
+            store
+                .list()
+                .unwrap()
+                .iter()
+                .map(|deck| deck.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["French", "Spanish 1"]
+        );
+    }
+
+    #[test]
+    fn test_subscriptions() {
+        let (db, teacher, student) = setup();
+        let store = db.sharing();
+        let deck = store.publish(teacher, 10, "Spanish", None, 5).unwrap();
+
+        assert!(store.get_subscription(student, deck.id).unwrap().is_none());
+        store.set_subscribed_version(student, deck.id, 1).unwrap();
+        store.publish(teacher, 10, "Spanish", None, 6).unwrap();
+
+        let subscriptions = store.list_subscriptions(student).unwrap();
+        assert_eq!(subscriptions.len(), 1);
+        let (subscription, shared) = &subscriptions[0];
+        assert_eq!(subscription.version, 1);
+        assert_eq!(shared.version, 2);
+
+        store.set_subscribed_version(student, deck.id, 2).unwrap();
+        assert_eq!(
+            store
+                .get_subscription(student, deck.id)
+                .unwrap()
+                .unwrap()
+                .version,
+            2
+        );
+
+        // Unpublishing ends subscriptions
+        assert!(store.delete(deck.id).unwrap());
+        assert!(store.list_subscriptions(student).unwrap().is_empty());
+        assert!(!store.unsubscribe(student, deck.id).unwrap());
+    }
+}
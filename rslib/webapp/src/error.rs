@@ -2,12 +2,23 @@
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
 use std::fmt;
-
+use std::sync::Arc;
+
+use anki::error::AnkiError;
+use anki::error::DbErrorKind;
+use anki_i18n::I18n;
+use axum::extract::Request;
+use axum::http::header;
+use axum::http::HeaderMap;
 use axum::http::StatusCode;
+use axum::middleware::Next;
 use axum::response::IntoResponse;
 use axum::response::Response;
 use axum::Json;
-use serde_json::json;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::session::CollectionBusy;
 
 pub type Result<T> = std::result::Result<T, WebAppError>;
 
@@ -19,6 +30,11 @@ pub enum WebAppError {
     NotFound(String),
     Conflict(String),
     Forbidden(String),
+    PreconditionFailed(String),
+    Unprocessable(String),
+    /// An error from the collection. The message is localized once the
+    /// request's language is known; see [localize_errors].
+    Anki(AnkiError),
 }
 
 impl WebAppError {
@@ -46,6 +62,14 @@ impl WebAppError {
         WebAppError::Forbidden(msg.to_string())
     }
 
+    pub fn precondition_failed(msg: &str) -> Self {
+        WebAppError::PreconditionFailed(msg.to_string())
+    }
+
+    pub fn unprocessable(msg: &str) -> Self {
+        WebAppError::Unprocessable(msg.to_string())
+    }
+
     pub fn not_implemented(msg: &str) -> Self {
         WebAppError::Internal(format!("Not implemented: {}", msg))
     }
@@ -60,6 +84,9 @@ impl fmt::Display for WebAppError {
             WebAppError::NotFound(msg) => write!(f, "Not found: {}", msg),
             WebAppError::Conflict(msg) => write!(f, "Conflict: {}", msg),
             WebAppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+            WebAppError::PreconditionFailed(msg) => write!(f, "Precondition failed: {}", msg),
+            WebAppError::Unprocessable(msg) => write!(f, "Unprocessable: {}", msg),
+            WebAppError::Anki(err) => write!(f, "{}", err.message(&I18n::template_only())),
         }
     }
 }
@@ -68,7 +95,8 @@ impl std::error::Error for WebAppError {}
 
 impl IntoResponse for WebAppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match &self {
+        let code = self.code();
+        let (status, error_message) = match self {
             WebAppError::Internal(msg) => {
                 // Log internal errors with full context
                 tracing::error!("Internal server error: {}", msg);
@@ -79,39 +107,203 @@ impl IntoResponse for WebAppError {
             }
             WebAppError::BadRequest(msg) => {
                 tracing::warn!("Bad request: {}", msg);
-                (StatusCode::BAD_REQUEST, msg.clone())
+                (StatusCode::BAD_REQUEST, msg)
             }
             WebAppError::Unauthorized(msg) => {
                 tracing::warn!("Unauthorized access attempt: {}", msg);
-                (StatusCode::UNAUTHORIZED, msg.clone())
+                (StatusCode::UNAUTHORIZED, msg)
             }
             WebAppError::NotFound(msg) => {
                 tracing::debug!("Not found: {}", msg);
-                (StatusCode::NOT_FOUND, msg.clone())
+                (StatusCode::NOT_FOUND, msg)
             }
             WebAppError::Conflict(msg) => {
                 tracing::warn!("Conflict: {}", msg);
-                (StatusCode::CONFLICT, msg.clone())
+                (StatusCode::CONFLICT, msg)
             }
             WebAppError::Forbidden(msg) => {
                 tracing::warn!("Forbidden access attempt: {}", msg);
-                (StatusCode::FORBIDDEN, msg.clone())
+                (StatusCode::FORBIDDEN, msg)
+            }
+            WebAppError::PreconditionFailed(msg) => {
+                tracing::debug!("Precondition failed: {}", msg);
+                (StatusCode::PRECONDITION_FAILED, msg)
             }
+            WebAppError::Unprocessable(msg) => {
+                tracing::warn!("Unprocessable request: {}", msg);
+                (StatusCode::UNPROCESSABLE_ENTITY, msg)
+            }
+            WebAppError::Anki(err) => return anki_error_response(err),
         };
 
-        let body = Json(json!({
-            "success": false,
-            "error": {
-                "message": error_message,
-            }
-        }));
+        error_response(status, code, &error_message)
+    }
+}
+
+impl WebAppError {
+    /// A stable, machine-readable identifier for the kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            WebAppError::Internal(_) => "internal",
+            WebAppError::BadRequest(_) => "bad_request",
+            WebAppError::Unauthorized(_) => "unauthorized",
+            WebAppError::NotFound(_) => "not_found",
+            WebAppError::Conflict(_) => "conflict",
+            WebAppError::Forbidden(_) => "forbidden",
+            WebAppError::PreconditionFailed(_) => "precondition_failed",
+            WebAppError::Unprocessable(_) => "unprocessable",
+            WebAppError::Anki(err) => anki_error_status(err).1,
+        }
+    }
+}
+
+/// The body of every error response
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ErrorResponse {
+    pub success: bool,
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ErrorDetail {
+    /// Stable identifier for the kind of error, eg "not_found"
+    pub code: String,
+    /// Human-readable description, localized for collection errors
+    pub message: String,
+}
+
+fn error_response(status: StatusCode, code: &str, message: &str) -> Response {
+    let body = Json(ErrorResponse {
+        success: false,
+        error: ErrorDetail {
+            code: code.to_string(),
+            message: message.to_string(),
+        },
+    });
+
+    (status, body).into_response()
+}
+
+/// The HTTP status and error code for a collection error
+fn anki_error_status(err: &AnkiError) -> (StatusCode, &'static str) {
+    match err {
+        AnkiError::NotFound { .. } => (StatusCode::NOT_FOUND, "not_found"),
+        AnkiError::Deleted => (StatusCode::NOT_FOUND, "deleted"),
+        AnkiError::InvalidInput { .. }
+        | AnkiError::ParseNumError
+        | AnkiError::InvalidId
+        | AnkiError::MultipleNotetypesSelected
+        | AnkiError::FsrsParamsInvalid
+        | AnkiError::FsrsInsufficientData
+        | AnkiError::FsrsInsufficientReviews { .. }
+        | AnkiError::FsrsUnableToDetermineDesiredRetention => {
+            (StatusCode::BAD_REQUEST, "invalid_input")
+        }
+        AnkiError::SearchError { .. } => (StatusCode::BAD_REQUEST, "search_error"),
+        AnkiError::InvalidRegex { .. } => (StatusCode::BAD_REQUEST, "invalid_regex"),
+        AnkiError::TemplateError { .. } => (StatusCode::BAD_REQUEST, "template_error"),
+        AnkiError::CardTypeError { .. } => (StatusCode::BAD_REQUEST, "card_type_error"),
+        AnkiError::FilteredDeckError { .. } => (StatusCode::BAD_REQUEST, "filtered_deck_error"),
+        AnkiError::CustomStudyError { .. } => (StatusCode::BAD_REQUEST, "custom_study_error"),
+        AnkiError::ImportError { .. } => (StatusCode::BAD_REQUEST, "import_error"),
+        AnkiError::Existing => (StatusCode::CONFLICT, "exists"),
+        AnkiError::UndoEmpty => (StatusCode::CONFLICT, "undo_empty"),
+        AnkiError::DatabaseCheckRequired => (StatusCode::CONFLICT, "database_check_required"),
+        AnkiError::MediaCheckRequired => (StatusCode::CONFLICT, "media_check_required"),
+        AnkiError::SchedulerUpgradeRequired => (StatusCode::CONFLICT, "scheduler_upgrade_required"),
+        AnkiError::DbError { source } if matches!(source.kind, DbErrorKind::Locked) => {
+            (StatusCode::SERVICE_UNAVAILABLE, "collection_locked")
+        }
+        AnkiError::CollectionNotOpen | AnkiError::Interrupted => {
+            (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+        }
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+    }
+}
 
-        (status, body).into_response()
+/// Attached to collection error responses so [localize_errors] can render
+/// the message in the client's language.
+#[derive(Clone)]
+struct LocalizableError(Arc<AnkiError>);
+
+fn anki_error_response(err: AnkiError) -> Response {
+    let (status, _) = anki_error_status(&err);
+    if status.is_server_error() {
+        tracing::error!("Collection error: {:?}", err);
+    } else {
+        tracing::debug!("Collection error: {:?}", err);
+    }
+
+    let mut response = render_anki_error(&err, &I18n::template_only());
+    response
+        .extensions_mut()
+        .insert(LocalizableError(Arc::new(err)));
+    response
+}
+
+/// Re-renders collection errors in the languages listed in the request's
+/// `Accept-Language` header. Other responses pass through untouched.
+pub async fn localize_errors(request: Request, next: Next) -> Response {
+    let languages = accepted_languages(request.headers());
+    let response = next.run(request).await;
+
+    if languages.is_empty() {
+        return response;
+    }
+    let Some(LocalizableError(err)) = response.extensions().get::<LocalizableError>().cloned()
+    else {
+        return response;
+    };
+
+    render_anki_error(&err, &I18n::new(&languages))
+}
+
+fn render_anki_error(err: &AnkiError, tr: &I18n) -> Response {
+    let (status, code) = anki_error_status(err);
+    let message = if status == StatusCode::INTERNAL_SERVER_ERROR {
+        format!("Internal error: {}", err.message(tr))
+    } else {
+        err.message(tr)
+    };
+    error_response(status, code, &message)
+}
+
+/// Language tags from an `Accept-Language` header, most preferred first
+pub(crate) fn accepted_languages(headers: &HeaderMap) -> Vec<String> {
+    let Some(value) = headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return vec![];
+    };
+
+    let mut languages: Vec<(f32, String)> = value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && tag != "*" && quality > 0.0).then(|| (quality, tag.to_string()))
+        })
+        .collect();
+    // Stable, so equally weighted tags keep their order
+    languages.sort_by(|a, b| b.0.total_cmp(&a.0));
+    languages.into_iter().map(|(_, tag)| tag).collect()
+}
+
+impl From<AnkiError> for WebAppError {
+    fn from(err: AnkiError) -> Self {
+        WebAppError::Anki(err)
     }
 }
 
 impl From<anyhow::Error> for WebAppError {
     fn from(err: anyhow::Error) -> Self {
+        if err.is::<CollectionBusy>() {
+            return WebAppError::Conflict(err.to_string());
+        }
         WebAppError::Internal(err.to_string())
     }
 }
@@ -204,6 +396,18 @@ mod tests {
         assert_eq!(json["error"]["message"], "Access denied");
     }
 
+    #[tokio::test]
+    async fn test_precondition_failed_response() {
+        let error = WebAppError::precondition_failed("Note was modified");
+        let response = error.into_response();
+
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+        let json = response_to_json(response).await;
+        assert_eq!(json["success"], false);
+        assert_eq!(json["error"]["message"], "Note was modified");
+    }
+
     #[tokio::test]
     async fn test_anyhow_error_conversion() {
         let anyhow_err = anyhow::anyhow!("Something went wrong");
@@ -218,6 +422,81 @@ mod tests {
         assert_eq!(json["error"]["message"], "Internal error: Something went wrong");
     }
 
+    #[tokio::test]
+    async fn test_busy_collection_is_a_conflict() {
+        let webapp_err: WebAppError = anyhow::Error::new(CollectionBusy).into();
+
+        let response = webapp_err.into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_anki_error_conversion() {
+        let cases = [
+            (
+                AnkiError::InvalidRegex {
+                    info: "(".to_string(),
+                },
+                StatusCode::BAD_REQUEST,
+                "invalid_regex",
+            ),
+            (AnkiError::Existing, StatusCode::CONFLICT, "exists"),
+            (
+                AnkiError::DatabaseCheckRequired,
+                StatusCode::CONFLICT,
+                "database_check_required",
+            ),
+            (
+                AnkiError::CollectionNotOpen,
+                StatusCode::SERVICE_UNAVAILABLE,
+                "unavailable",
+            ),
+            (
+                AnkiError::ProtoError {
+                    info: "bad proto".to_string(),
+                },
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal",
+            ),
+        ];
+
+        for (anki_err, status, code) in cases {
+            let webapp_err: WebAppError = anki_err.into();
+            assert_eq!(webapp_err.code(), code);
+
+            let response = webapp_err.into_response();
+            assert_eq!(response.status(), status);
+            assert!(response.extensions().get::<LocalizableError>().is_some());
+
+            let json = response_to_json(response).await;
+            assert_eq!(json["success"], false);
+            assert_eq!(json["error"]["code"], code);
+            assert!(json["error"]["message"].as_str().is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_code_in_body() {
+        let response = WebAppError::not_found("User not found").into_response();
+
+        let json = response_to_json(response).await;
+        assert_eq!(json["error"]["code"], "not_found");
+    }
+
+    #[test]
+    fn test_accepted_languages() {
+        let mut headers = HeaderMap::new();
+        assert!(accepted_languages(&headers).is_empty());
+
+        headers.insert(
+            header::ACCEPT_LANGUAGE,
+            "fr;q=0.5, de-DE, *;q=0.1, ja;q=0, en;q=0.8"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(accepted_languages(&headers), vec!["de-DE", "en", "fr"]);
+    }
+
     #[test]
     fn test_error_display() {
         assert_eq!(
@@ -0,0 +1,244 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! Replays the stored response when a POST request is retried with the same
+//! `Idempotency-Key` header, so that clients retrying after a timeout don't
+//! add a note or answer a card twice.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::body::HttpBody;
+use axum::extract::Request;
+use axum::extract::State;
+use axum::http::header;
+use axum::http::HeaderName;
+use axum::http::HeaderValue;
+use axum::http::Method;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::auth::AuthUser;
+use crate::db::current_timestamp;
+use crate::db::Database;
+use crate::db::IdempotencyClaim;
+use crate::db::StoredResponse;
+use crate::error::WebAppError;
+
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Set on responses that were replayed rather than produced by the handler
+pub const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Responses larger than this are not stored, and retries are handled again
+const MAX_STORED_RESPONSE_BYTES: u64 = 1024 * 1024;
+
+/// Requests with a key are buffered to fingerprint them, so their bodies
+/// are limited to this size. Larger uploads must be sent without a key.
+const MAX_FINGERPRINTED_BODY_BYTES: usize = 1024 * 1024;
+
+/// How often expired keys are pruned
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone)]
+pub struct IdempotencyState {
+    pub database: Arc<Database>,
+    /// How long responses are replayed for; 0 ignores the header
+    pub window_secs: i64,
+}
+
+/// Middleware honouring `Idempotency-Key` on POST requests. Must run inside
+/// `require_auth`, as keys are scoped to the authenticated user. Replays are
+/// answered before the audit log sees them, as they change nothing.
+pub async fn idempotency(
+    State(state): State<IdempotencyState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::POST || state.window_secs <= 0 {
+        return next.run(request).await;
+    }
+    let Some(key) = request.headers().get(&IDEMPOTENCY_KEY).cloned() else {
+        return next.run(request).await;
+    };
+    let Some(auth_user) = request.extensions().get::<AuthUser>().cloned() else {
+        return next.run(request).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if is_valid_key(key) => key.to_string(),
+        _ => {
+            return WebAppError::bad_request(
+                "Idempotency-Key must be 1-255 visible ASCII characters",
+            )
+            .into_response()
+        }
+    };
+
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_FINGERPRINTED_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return WebAppError::bad_request(
+                "Requests with an Idempotency-Key are limited to 1 MiB",
+            )
+            .into_response()
+        }
+    };
+    let fingerprint = request_fingerprint(&parts.method, &parts.uri.to_string(), &body);
+
+    let now = current_timestamp();
+    let claim = state.database.idempotency().claim(
+        auth_user.user_id,
+        &key,
+        &fingerprint,
+        now,
+        now - state.window_secs,
+    );
+    match claim {
+        Ok(IdempotencyClaim::Claimed) => {}
+        Ok(IdempotencyClaim::InProgress) => {
+            return WebAppError::conflict(
+                "A request with this Idempotency-Key is still being processed",
+            )
+            .into_response()
+        }
+        Ok(IdempotencyClaim::Mismatch) => {
+            return WebAppError::unprocessable(
+                "Idempotency-Key was already used for a different request",
+            )
+            .into_response()
+        }
+        Ok(IdempotencyClaim::Completed(stored)) => return replay(stored),
+        Err(e) => {
+            tracing::error!("Failed to claim idempotency key: {}", e);
+            return WebAppError::internal("Failed to check Idempotency-Key").into_response();
+        }
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let store = state.database.idempotency();
+
+    // Server errors may be transient, so the request can be retried
+    let storable = !response.status().is_server_error()
+        && response
+            .body()
+            .size_hint()
+            .upper()
+            .is_some_and(|size| size <= MAX_STORED_RESPONSE_BYTES);
+    if !storable {
+        if let Err(e) = store.release(auth_user.user_id, &key) {
+            tracing::error!("Failed to release idempotency key: {}", e);
+        }
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_STORED_RESPONSE_BYTES as usize).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("Failed to buffer response for idempotency: {}", e);
+            let _ = store.release(auth_user.user_id, &key);
+            return WebAppError::internal("Failed to read response").into_response();
+        }
+    };
+    let stored = StoredResponse {
+        status: parts.status.as_u16(),
+        content_type: parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string),
+        body: body.to_vec(),
+    };
+    if let Err(e) = store.complete(auth_user.user_id, &key, &stored) {
+        tracing::error!("Failed to store idempotent response: {}", e);
+        let _ = store.release(auth_user.user_id, &key);
+    }
+
+    Response::from_parts(parts, Body::from(body))
+}
+
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= MAX_KEY_LENGTH
+        && key.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+/// Identifies a request, so that a key reused for a different one is caught
+fn request_fingerprint(method: &Method, uri: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str());
+    hasher.update([0]);
+    hasher.update(uri);
+    hasher.update([0]);
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let mut response = (status, stored.body).into_response();
+    let headers = response.headers_mut();
+    headers.remove(header::CONTENT_TYPE);
+    if let Some(value) = stored
+        .content_type
+        .and_then(|content_type| HeaderValue::from_str(&content_type).ok())
+    {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+    headers.insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+    response
+}
+
+/// Periodically delete keys older than the replay window
+pub fn spawn_idempotency_pruning(database: Arc<Database>, window_secs: i64) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let cutoff = current_timestamp() - window_secs;
+            match database.idempotency().prune_before(cutoff) {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!("Pruned {} expired idempotency keys", count),
+                Err(e) => tracing::error!("Failed to prune idempotency keys: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_key() {
+        assert!(is_valid_key("3f2a-retry_1"));
+        assert!(!is_valid_key(""));
+        assert!(!is_valid_key("has space"));
+        assert!(!is_valid_key(&"a".repeat(256)));
+    }
+
+    #[test]
+    fn test_request_fingerprint() {
+        let a = request_fingerprint(&Method::POST, "/api/v1/notes", b"{}");
+        assert_eq!(
+            a,
+            request_fingerprint(&Method::POST, "/api/v1/notes", b"{}")
+        );
+        assert_ne!(
+            a,
+            request_fingerprint(&Method::POST, "/api/v1/notes", b"{ }")
+        );
+        assert_ne!(
+            a,
+            request_fingerprint(&Method::POST, "/api/v1/cards", b"{}")
+        );
+    }
+}
@@ -0,0 +1,512 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! JSON representation of image occlusion shapes, and conversion to and from
+//! the cloze syntax stored in an image occlusion note's occlusions field.
+
+use std::fmt::Write;
+
+use anki_proto::image_occlusion::get_image_occlusion_note_response::ImageOcclusion;
+use anki_proto::image_occlusion::get_image_occlusion_note_response::ImageOcclusionShape;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::error::WebAppError;
+
+/// A shape on the image. Positions and sizes are fractions of the image's
+/// width and height, so they don't depend on the size it is displayed at.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Occlusion {
+    /// The cloze number. Shapes with the same number are hidden and revealed
+    /// together on one card. Text labels are always shown, and use 0.
+    pub ordinal: u32,
+    #[serde(flatten)]
+    pub shape: OcclusionShape,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum OcclusionShape {
+    Rect {
+        left: f64,
+        top: f64,
+        width: f64,
+        height: f64,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        angle: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fill: Option<String>,
+    },
+    Ellipse {
+        left: f64,
+        top: f64,
+        rx: f64,
+        ry: f64,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        angle: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fill: Option<String>,
+    },
+    Polygon {
+        /// Defaults to the top left corner of the points' bounding box
+        #[serde(default)]
+        left: Option<f64>,
+        #[serde(default)]
+        top: Option<f64>,
+        /// `[x, y]` pairs
+        points: Vec<[f64; 2]>,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        angle: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fill: Option<String>,
+    },
+    Text {
+        left: f64,
+        top: f64,
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        scale: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        font_size: Option<f64>,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        angle: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fill: Option<String>,
+    },
+}
+
+fn is_zero(value: &f64) -> bool {
+    *value == 0.0
+}
+
+fn push_position(props: &mut Vec<(&'static str, String)>, left: f64, top: f64, angle: f64) {
+    props.push(("left", float_to_display(left)));
+    props.push(("top", float_to_display(top)));
+    if angle != 0.0 {
+        props.push(("angle", angle.to_string()));
+    }
+}
+
+impl OcclusionShape {
+    fn kind(&self) -> &'static str {
+        match self {
+            OcclusionShape::Rect { .. } => "rect",
+            OcclusionShape::Ellipse { .. } => "ellipse",
+            OcclusionShape::Polygon { .. } => "polygon",
+            OcclusionShape::Text { .. } => "text",
+        }
+    }
+
+    /// Properties in the order the editor writes them
+    fn properties(&self) -> Vec<(&'static str, String)> {
+        let mut props = vec![];
+        let fill = match self {
+            OcclusionShape::Rect {
+                left,
+                top,
+                width,
+                height,
+                angle,
+                fill,
+            } => {
+                push_position(&mut props, *left, *top, *angle);
+                props.push(("width", float_to_display(*width)));
+                props.push(("height", float_to_display(*height)));
+                fill
+            }
+            OcclusionShape::Ellipse {
+                left,
+                top,
+                rx,
+                ry,
+                angle,
+                fill,
+            } => {
+                push_position(&mut props, *left, *top, *angle);
+                props.push(("rx", float_to_display(*rx)));
+                props.push(("ry", float_to_display(*ry)));
+                fill
+            }
+            OcclusionShape::Polygon {
+                left,
+                top,
+                points,
+                angle,
+                fill,
+            } => {
+                let min = |axis: usize| {
+                    points
+                        .iter()
+                        .map(|point| point[axis])
+                        .fold(f64::INFINITY, f64::min)
+                };
+                push_position(
+                    &mut props,
+                    left.unwrap_or_else(|| min(0)),
+                    top.unwrap_or_else(|| min(1)),
+                    *angle,
+                );
+                let points = points
+                    .iter()
+                    .map(|[x, y]| format!("{},{}", float_to_display(*x), float_to_display(*y)))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                props.push(("points", points));
+                fill
+            }
+            OcclusionShape::Text {
+                left,
+                top,
+                text,
+                scale,
+                font_size,
+                angle,
+                fill,
+            } => {
+                push_position(&mut props, *left, *top, *angle);
+                props.push(("text", text.clone()));
+                props.push(("scale", float_to_display(scale.unwrap_or(1.0))));
+                if let Some(font_size) = font_size {
+                    props.push(("fs", float_to_display(*font_size)));
+                }
+                fill
+            }
+        };
+        if let Some(fill) = fill {
+            props.push(("fill", fill.clone()));
+        }
+        props
+    }
+
+    fn numbers(&self) -> Vec<f64> {
+        match self {
+            OcclusionShape::Rect {
+                left,
+                top,
+                width,
+                height,
+                angle,
+                ..
+            } => vec![*left, *top, *width, *height, *angle],
+            OcclusionShape::Ellipse {
+                left,
+                top,
+                rx,
+                ry,
+                angle,
+                ..
+            } => vec![*left, *top, *rx, *ry, *angle],
+            OcclusionShape::Polygon {
+                left,
+                top,
+                points,
+                angle,
+                ..
+            } => points
+                .iter()
+                .flatten()
+                .copied()
+                .chain(left.iter().copied())
+                .chain(top.iter().copied())
+                .chain([*angle])
+                .collect(),
+            OcclusionShape::Text {
+                left,
+                top,
+                scale,
+                font_size,
+                angle,
+                ..
+            } => [*left, *top, *angle]
+                .into_iter()
+                .chain(scale.iter().copied())
+                .chain(font_size.iter().copied())
+                .collect(),
+        }
+    }
+}
+
+/// Format a number as the editor does, with at most four decimal places and
+/// no leading zero, e.g. `.25`.
+fn float_to_display(value: f64) -> String {
+    if value == 0.0 || value.is_nan() {
+        return ".0000".to_string();
+    }
+    format!("{:.4}", value)
+        .trim_start_matches('0')
+        .trim_end_matches('0')
+        .to_string()
+}
+
+/// Check shapes supplied by a client before they are written to a note
+pub fn validate_occlusions(occlusions: &[Occlusion]) -> Result<()> {
+    if !occlusions
+        .iter()
+        .any(|occlusion| !matches!(occlusion.shape, OcclusionShape::Text { .. }))
+    {
+        return Err(WebAppError::bad_request(
+            "At least one shape other than text is required",
+        ));
+    }
+    for occlusion in occlusions {
+        let is_text = matches!(occlusion.shape, OcclusionShape::Text { .. });
+        if !is_text && (occlusion.ordinal == 0 || occlusion.ordinal > u16::MAX as u32) {
+            return Err(WebAppError::bad_request(
+                "Shape ordinals must be between 1 and 65535",
+            ));
+        }
+        if occlusion.shape.numbers().iter().any(|n| !n.is_finite()) {
+            return Err(WebAppError::bad_request(
+                "Shape coordinates must be finite numbers",
+            ));
+        }
+        if let OcclusionShape::Polygon { points, .. } = &occlusion.shape {
+            if points.len() < 3 {
+                return Err(WebAppError::bad_request("Polygons need at least 3 points"));
+            }
+        }
+        if occlusion
+            .shape
+            .properties()
+            .iter()
+            .any(|(_, value)| value.contains("}}"))
+        {
+            return Err(WebAppError::bad_request("Shape text must not contain }}"));
+        }
+    }
+    Ok(())
+}
+
+/// Build the contents of the occlusions field, in the format written by the
+/// desktop editor, e.g.
+/// `{{c1::image-occlusion:rect:left=.2:top=.3:width=.2:height=.1}}<br>`
+pub fn occlusions_to_field(occlusions: &[Occlusion], occlude_inactive: bool) -> String {
+    let mut field = String::new();
+    for occlusion in occlusions {
+        let is_text = matches!(occlusion.shape, OcclusionShape::Text { .. });
+        let ordinal = if is_text { 0 } else { occlusion.ordinal };
+        write!(
+            field,
+            "{{{{c{}::image-occlusion:{}",
+            ordinal,
+            occlusion.shape.kind()
+        )
+        .unwrap();
+        for (name, value) in occlusion.shape.properties() {
+            write!(field, ":{}={}", name, value.replace(':', "\\:")).unwrap();
+        }
+        if occlude_inactive {
+            field.push_str(":oi=1");
+        }
+        field.push_str("}}<br>");
+    }
+    field
+}
+
+/// Convert the occlusions parsed from a note, ordered by ordinal. Shapes
+/// that can't be represented are skipped.
+pub fn occlusions_from_note(mut parsed: Vec<ImageOcclusion>) -> Vec<Occlusion> {
+    parsed.sort_by_key(|occlusion| occlusion.ordinal);
+    parsed
+        .iter()
+        .flat_map(|occlusion| {
+            occlusion.shapes.iter().filter_map(|shape| {
+                Some(Occlusion {
+                    ordinal: occlusion.ordinal,
+                    shape: shape_from_note(shape)?,
+                })
+            })
+        })
+        .collect()
+}
+
+fn shape_from_note(shape: &ImageOcclusionShape) -> Option<OcclusionShape> {
+    let text = |name: &str| {
+        shape
+            .properties
+            .iter()
+            .find(|prop| prop.name == name)
+            .map(|prop| prop.value.clone())
+    };
+    let number = |name: &str| text(name).and_then(|value| value.parse::<f64>().ok());
+    let left = number("left")?;
+    let top = number("top")?;
+    let angle = number("angle").unwrap_or_default();
+    let fill = text("fill").filter(|fill| !fill.is_empty());
+
+    Some(match shape.shape.as_str() {
+        "rect" => OcclusionShape::Rect {
+            left,
+            top,
+            width: number("width")?,
+            height: number("height")?,
+            angle,
+            fill,
+        },
+        "ellipse" => OcclusionShape::Ellipse {
+            left,
+            top,
+            rx: number("rx")?,
+            ry: number("ry")?,
+            angle,
+            fill,
+        },
+        "polygon" => OcclusionShape::Polygon {
+            left: Some(left),
+            top: Some(top),
+            points: text("points")?
+                .split(' ')
+                .filter_map(|pair| {
+                    let (x, y) = pair.split_once(',')?;
+                    Some([x.parse().ok()?, y.parse().ok()?])
+                })
+                .collect(),
+            angle,
+            fill,
+        },
+        "text" => OcclusionShape::Text {
+            left,
+            top,
+            text: text("text")?,
+            scale: number("scale"),
+            font_size: number("fs"),
+            angle,
+            fill,
+        },
+        _ => return None,
+    })
+}
+
+/// Whether shapes parsed from a note are shown while other cards' shapes are
+/// being asked
+pub fn occludes_inactive(parsed: &[ImageOcclusion]) -> bool {
+    parsed.iter().any(|occlusion| {
+        occlusion.shapes.iter().any(|shape| {
+            shape
+                .properties
+                .iter()
+                .any(|prop| prop.name == "oi" && prop.value == "1")
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use anki::cloze::parse_image_occlusions;
+
+    use super::*;
+
+    fn rect(ordinal: u32, left: f64) -> Occlusion {
+        Occlusion {
+            ordinal,
+            shape: OcclusionShape::Rect {
+                left,
+                top: 0.5,
+                width: 0.125,
+                height: 0.1,
+                angle: 0.0,
+                fill: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_float_to_display() {
+        assert_eq!(float_to_display(0.25), ".25");
+        assert_eq!(float_to_display(0.123456), ".1235");
+        assert_eq!(float_to_display(0.0), ".0000");
+    }
+
+    #[test]
+    fn test_occlusions_to_field() {
+        assert_eq!(
+            occlusions_to_field(&[rect(1, 0.2)], false),
+            "{{c1::image-occlusion:rect:left=.2:top=.5:width=.125:height=.1}}<br>"
+        );
+        let label = Occlusion {
+            ordinal: 3,
+            shape: OcclusionShape::Text {
+                left: 0.1,
+                top: 0.1,
+                text: "a:b".to_string(),
+                scale: None,
+                font_size: None,
+                angle: 0.0,
+                fill: None,
+            },
+        };
+        assert_eq!(
+            occlusions_to_field(&[label], true),
+            "{{c0::image-occlusion:text:left=.1:top=.1:text=a\\:b:scale=1.:oi=1}}<br>"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let occlusions = vec![
+            rect(1, 0.2),
+            rect(1, 0.4),
+            Occlusion {
+                ordinal: 2,
+                shape: OcclusionShape::Polygon {
+                    left: Some(0.1),
+                    top: Some(0.2),
+                    points: vec![[0.1, 0.2], [0.3, 0.2], [0.2, 0.4]],
+                    angle: 0.0,
+                    fill: Some("#ff0000".to_string()),
+                },
+            },
+            Occlusion {
+                ordinal: 3,
+                shape: OcclusionShape::Ellipse {
+                    left: 0.5,
+                    top: 0.5,
+                    rx: 0.1,
+                    ry: 0.05,
+                    angle: 45.0,
+                    fill: None,
+                },
+            },
+        ];
+        let field = occlusions_to_field(&occlusions, true);
+        let parsed = parse_image_occlusions(&field);
+        assert!(occludes_inactive(&parsed));
+        assert_eq!(occlusions_from_note(parsed), occlusions);
+    }
+
+    #[test]
+    fn test_validate_occlusions() {
+        assert!(validate_occlusions(&[rect(1, 0.2)]).is_ok());
+        assert!(validate_occlusions(&[]).is_err());
+        assert!(validate_occlusions(&[rect(0, 0.2)]).is_err());
+        assert!(validate_occlusions(&[rect(1, f64::NAN)]).is_err());
+        let triangle_without_corner = Occlusion {
+            ordinal: 1,
+            shape: OcclusionShape::Polygon {
+                left: None,
+                top: None,
+                points: vec![[0.1, 0.2], [0.3, 0.2]],
+                angle: 0.0,
+                fill: None,
+            },
+        };
+        assert!(validate_occlusions(&[triangle_without_corner]).is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        let occlusion: Occlusion = serde_json::from_value(serde_json::json!({
+            "ordinal": 1,
+            "shape": "rect",
+            "left": 0.2,
+            "top": 0.5,
+            "width": 0.125,
+            "height": 0.1
+        }))
+        .unwrap();
+        assert_eq!(occlusion, rect(1, 0.2));
+        assert_eq!(serde_json::to_value(&occlusion).unwrap()["shape"], "rect");
+    }
+}
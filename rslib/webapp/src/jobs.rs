@@ -0,0 +1,198 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::db::current_timestamp;
+use crate::error::Result;
+
+/// Finished jobs are kept around for this long so clients can fetch results
+const FINISHED_JOB_RETENTION_SECS: i64 = 3600;
+
+/// How often [JobManager::wait_idle] checks for running jobs
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct JobInfo {
+    pub id: String,
+    #[serde(skip_serializing)]
+    #[schemars(skip)]
+    pub user_id: i64,
+    pub kind: String,
+    pub status: JobStatus,
+    pub created_at: i64,
+    pub finished_at: Option<i64>,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+}
+
+/// Runs slow collection operations on the blocking thread pool and keeps
+/// their results in memory until the client collects them
+#[derive(Default)]
+pub struct JobManager {
+    jobs: Arc<Mutex<HashMap<String, JobInfo>>>,
+}
+
+impl JobManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a job for a user, returning its id
+    pub fn spawn<F>(&self, user_id: i64, kind: &str, task: F) -> String
+    where
+        F: FnOnce() -> Result<Value> + Send + 'static,
+    {
+        self.prune_finished();
+
+        let id = Uuid::new_v4().to_string();
+        let info = JobInfo {
+            id: id.clone(),
+            user_id,
+            kind: kind.to_string(),
+            status: JobStatus::Running,
+            created_at: current_timestamp(),
+            finished_at: None,
+            result: None,
+            error: None,
+        };
+        self.jobs.lock().unwrap().insert(id.clone(), info);
+
+        let jobs = self.jobs.clone();
+        let job_id = id.clone();
+        tokio::task::spawn_blocking(move || {
+            let outcome = task();
+            let mut jobs = jobs.lock().unwrap();
+            if let Some(job) = jobs.get_mut(&job_id) {
+                job.finished_at = Some(current_timestamp());
+                match outcome {
+                    Ok(value) => {
+                        job.status = JobStatus::Completed;
+                        job.result = Some(value);
+                    }
+                    Err(e) => {
+                        tracing::warn!("Job {} ({}) failed: {}", job_id, job.kind, e);
+                        job.status = JobStatus::Failed;
+                        job.error = Some(e.to_string());
+                    }
+                }
+            }
+        });
+
+        id
+    }
+
+    /// Get a job, if it exists and belongs to the given user
+    pub fn get(&self, user_id: i64, id: &str) -> Option<JobInfo> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.get(id).filter(|job| job.user_id == user_id).cloned()
+    }
+
+    /// List all jobs belonging to a user, newest first
+    pub fn list_for_user(&self, user_id: i64) -> Vec<JobInfo> {
+        let jobs = self.jobs.lock().unwrap();
+        let mut list: Vec<JobInfo> = jobs
+            .values()
+            .filter(|job| job.user_id == user_id)
+            .cloned()
+            .collect();
+        list.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        list
+    }
+
+    /// Number of jobs that have not finished yet
+    pub fn running_count(&self) -> usize {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.values()
+            .filter(|job| job.status == JobStatus::Running)
+            .count()
+    }
+
+    /// Wait until no jobs are running, e.g. before shutting down
+    pub async fn wait_idle(&self) {
+        while self.running_count() > 0 {
+            tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+        }
+    }
+
+    fn prune_finished(&self) {
+        let cutoff = current_timestamp() - FINISHED_JOB_RETENTION_SECS;
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, job| job.finished_at.map(|t| t >= cutoff).unwrap_or(true));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::error::WebAppError;
+
+    fn wait_for(manager: &JobManager, user_id: i64, id: &str) -> JobInfo {
+        for _ in 0..100 {
+            let job = manager.get(user_id, id).unwrap();
+            if job.status != JobStatus::Running {
+                return job;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("job did not finish");
+    }
+
+    #[tokio::test]
+    async fn test_job_completes() {
+        let manager = JobManager::new();
+        let id = manager.spawn(1, "test", || Ok(json!({ "answer": 42 })));
+
+        let job = wait_for(&manager, 1, &id);
+        assert_eq!(job.status, JobStatus::Completed);
+        assert_eq!(job.result.unwrap()["answer"], 42);
+        assert_eq!(manager.running_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_job_failure_and_ownership() {
+        let manager = JobManager::new();
+        let id = manager.spawn(1, "test", || Err(WebAppError::bad_request("nope")));
+
+        let job = wait_for(&manager, 1, &id);
+        assert_eq!(job.status, JobStatus::Failed);
+        assert!(job.error.unwrap().contains("nope"));
+
+        // Other users can't see the job
+        assert!(manager.get(2, &id).is_none());
+        assert!(manager.list_for_user(2).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wait_idle() {
+        let manager = JobManager::new();
+        let id = manager.spawn(1, "test", || {
+            std::thread::sleep(Duration::from_millis(100));
+            Ok(json!({}))
+        });
+        assert_eq!(manager.running_count(), 1);
+
+        tokio::time::timeout(Duration::from_secs(5), manager.wait_idle())
+            .await
+            .unwrap();
+        assert_eq!(manager.get(1, &id).unwrap().status, JobStatus::Completed);
+    }
+}
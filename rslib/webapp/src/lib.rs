@@ -3,12 +3,21 @@
 
 #![recursion_limit = "512"]
 
+pub mod admin;
+pub mod audit;
 pub mod auth;
 pub mod config;
 pub mod db;
 pub mod error;
+pub mod idempotency;
+pub mod image_occlusion;
+pub mod jobs;
+pub mod media;
+pub mod metrics;
 pub mod openapi;
+pub mod preconditions;
 pub mod routes;
+pub mod scheduling;
 pub mod server;
 pub mod session;
 pub mod swagger_ui;
@@ -20,6 +29,7 @@ pub use config::WebAppConfig;
 pub use db::Database;
 pub use error::Result;
 pub use error::WebAppError;
+pub use jobs::JobManager;
 pub use routes::AuthRouteState;
 pub use server::WebAppServer;
 pub use session::BackendManager;
@@ -1,23 +1,63 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
+use anki_webapp::admin;
+use anki_webapp::admin::AdminCommand;
+use anki_webapp::config::LogFormat;
+use anki_webapp::WebAppConfig;
 use anki_webapp::WebAppServer;
+use clap::Parser;
+use clap::Subcommand;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+#[derive(Debug, Parser)]
+#[command(name = "anki-webapp", version, about = "Anki web app server")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the server (the default)
+    Serve,
+    #[command(flatten)]
+    Admin(AdminCommand),
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let config = WebAppConfig::from_env()?;
+
+    // Admin commands print their results, so only log problems by default,
+    // and keep the logs out of their output
+    let (default_filter, writer) = match cli.command {
+        Some(Command::Admin(_)) => ("warn", BoxMakeWriter::new(std::io::stderr)),
+        _ => ("info", BoxMakeWriter::new(std::io::stdout)),
+    };
+
     // Initialize tracing
-    tracing_subscriber::fmt()
+    let subscriber = tracing_subscriber::fmt()
         .with_target(false)
+        .with_writer(writer)
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(default_filter)),
+        );
+    match config.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().with_current_span(true).init(),
+    }
+
+    if let Some(Command::Admin(command)) = cli.command {
+        return admin::run(&config, command, &mut std::io::stdout().lock());
+    }
 
     tracing::info!("Starting Anki Web App");
 
     // Create and run server
-    let server = WebAppServer::new()?;
+    let server = WebAppServer::with_config(config);
     server.run().await?;
 
     Ok(())
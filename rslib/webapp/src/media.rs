@@ -0,0 +1,513 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! HTTP caching, byte range and thumbnail support for serving media files,
+//! and rendering of LaTeX images.
+
+use std::fs;
+use std::fs::File;
+use std::io::Cursor;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use anki::latex::MissingLatex;
+use anyhow::bail;
+use anyhow::Context;
+use axum::http::header;
+use axum::http::HeaderMap;
+use chrono::DateTime;
+use chrono::Utc;
+use image::imageops::FilterType;
+use image::ImageFormat;
+
+/// How long clients may reuse a media file before revalidating it
+pub const MEDIA_MAX_AGE_SECS: u64 = 24 * 60 * 60;
+
+/// Upper bound on the width and height of a generated thumbnail
+pub const MAX_THUMBNAIL_SIZE: u32 = 2048;
+
+/// A strong entity tag for a file with the given SHA1, or a weak one derived
+/// from its size and modification time if it isn't in the media database
+/// yet. Thumbnails get their own tag, as their content differs.
+pub fn entity_tag(
+    sha1: Option<[u8; 20]>,
+    len: u64,
+    modified: Option<SystemTime>,
+    thumbnail: Option<(u32, u32)>,
+) -> String {
+    let suffix = thumbnail
+        .map(|(width, height)| format!("-{}x{}", width, height))
+        .unwrap_or_default();
+    match sha1 {
+        Some(sha1) => format!("\"{}{}\"", hex::encode(sha1), suffix),
+        None => {
+            let mtime = modified
+                .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|age| age.as_secs())
+                .unwrap_or_default();
+            format!("W/\"{:x}-{:x}{}\"", len, mtime, suffix)
+        }
+    }
+}
+
+/// Format a time as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// Whether the client's cached copy, as described by its conditional
+/// headers, is still current. If-None-Match takes precedence over
+/// If-Modified-Since.
+pub fn is_not_modified(headers: &HeaderMap, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = header_str(headers, header::IF_NONE_MATCH) {
+        return if_none_match.trim() == "*"
+            || if_none_match
+                .split(',')
+                .any(|tag| weak_eq(tag.trim(), etag));
+    }
+    match (header_str(headers, header::IF_MODIFIED_SINCE), modified) {
+        (Some(since), Some(modified)) => DateTime::parse_from_rfc2822(since)
+            .map(|since| DateTime::<Utc>::from(modified).timestamp() <= since.timestamp())
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Compare entity tags ignoring weakness, as If-None-Match does
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+/// The byte range requested by the client, if any
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// Send the whole file
+    Full,
+    Partial(RangeInclusive<u64>),
+    /// Respond with 416
+    Unsatisfiable,
+}
+
+/// Interpret the Range and If-Range headers for a file of `len` bytes. Only
+/// a single range is supported; requests for several ranges get the whole
+/// file, which the spec allows.
+pub fn requested_range(headers: &HeaderMap, etag: &str, len: u64) -> RangeRequest {
+    let Some(range) = header_str(headers, header::RANGE) else {
+        return RangeRequest::Full;
+    };
+    // A range is only valid against the representation the client has
+    if let Some(if_range) = header_str(headers, header::IF_RANGE) {
+        if etag.starts_with("W/") || if_range.trim() != etag {
+            return RangeRequest::Full;
+        }
+    }
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return RangeRequest::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        // The last N bytes
+        match end.parse::<u64>() {
+            Ok(0) => return RangeRequest::Unsatisfiable,
+            Err(_) => return RangeRequest::Full,
+            Ok(suffix) => len.saturating_sub(suffix)..=len.saturating_sub(1),
+        }
+    } else {
+        let Ok(start) = start.parse::<u64>() else {
+            return RangeRequest::Full;
+        };
+        let end = if end.is_empty() {
+            len.saturating_sub(1)
+        } else {
+            match end.parse::<u64>() {
+                Ok(end) if end >= start => end.min(len.saturating_sub(1)),
+                _ => return RangeRequest::Full,
+            }
+        };
+        start..=end
+    };
+
+    if len == 0 || *range.start() >= len {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(range)
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Whether thumbnails can be generated for a file of this type
+pub fn can_thumbnail(content_type: &str) -> bool {
+    matches!(
+        content_type,
+        "image/jpeg" | "image/png" | "image/gif" | "image/webp"
+    )
+}
+
+/// The width and height of an image, if its format is supported
+pub fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    image::ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Scale an image down to fit within the given size, keeping its aspect
+/// ratio. JPEGs stay JPEGs; everything else becomes a PNG. Returns the
+/// encoded image and its content type, or None if the image is already
+/// small enough.
+pub fn make_thumbnail(
+    data: &[u8],
+    max_width: u32,
+    max_height: u32,
+) -> anyhow::Result<Option<(Vec<u8>, &'static str)>> {
+    let format = image::guess_format(data)?;
+    let image = image::load_from_memory_with_format(data, format)?;
+    if image.width() <= max_width && image.height() <= max_height {
+        return Ok(None);
+    }
+
+    let thumbnail = image.resize(max_width, max_height, FilterType::Triangle);
+    let (format, content_type) = if format == ImageFormat::Jpeg {
+        (ImageFormat::Jpeg, "image/jpeg")
+    } else {
+        (ImageFormat::Png, "image/png")
+    };
+    let thumbnail = if format == ImageFormat::Jpeg {
+        // JPEG has no alpha channel
+        image::DynamicImage::ImageRgb8(thumbnail.to_rgb8())
+    } else {
+        thumbnail
+    };
+
+    let mut out = Cursor::new(Vec::new());
+    thumbnail.write_to(&mut out, format)?;
+    Ok(Some((out.into_inner(), content_type)))
+}
+
+/// How long each of the programs compiling a LaTeX image may run before it
+/// is killed
+pub const LATEX_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Commands that read or write files, run other programs or redefine how
+/// TeX reads its input. Rejecting them is only a first line of defence; what
+/// keeps TeX away from the server's files is the restricted environment it
+/// runs in.
+const FORBIDDEN_LATEX_COMMANDS: &[&str] = &[
+    "\\write18",
+    "\\readline",
+    "\\read",
+    "\\input",
+    "\\include",
+    "\\catcode",
+    "\\openin",
+    "\\openout",
+    "\\write",
+    "\\loop",
+    "\\def",
+    "\\shipout",
+    "\\csname",
+    "\\lstinputlisting",
+    "\\verbatiminput",
+    "\\directlua",
+    // Character codes like ^^5c spell out a backslash
+    "^^",
+];
+
+/// Commands that are fine in a notetype's header, but not in a note
+const FORBIDDEN_NOTE_LATEX_COMMANDS: &[&str] =
+    &["\\usepackage", "\\RequirePackage", "\\documentclass"];
+
+/// The first forbidden command used by the given LaTeX, if any. The whole
+/// source is checked, as the notetype's header and footer are user content
+/// too. `\includegraphics` is allowed, as the desktop does.
+pub fn forbidden_latex_command(missing: &MissingLatex) -> Option<&'static str> {
+    let source = latex_source(missing).replace("\\includegraphics", "");
+    let note_latex = missing.latex.replace("\\includegraphics", "");
+    FORBIDDEN_LATEX_COMMANDS
+        .iter()
+        .find(|command| source.contains(*command))
+        .or_else(|| {
+            FORBIDDEN_NOTE_LATEX_COMMANDS
+                .iter()
+                .find(|command| note_latex.contains(*command))
+        })
+        .copied()
+}
+
+fn latex_source(missing: &MissingLatex) -> String {
+    format!("{}{}{}", missing.header, missing.latex, missing.footer)
+}
+
+/// Compile LaTeX from a note into a PNG or SVG image, as the desktop client
+/// does. Requires `latex`, and `dvipng` or `dvisvgm`, to be installed.
+pub fn render_latex(missing: &MissingLatex) -> anyhow::Result<Vec<u8>> {
+    if let Some(command) = forbidden_latex_command(missing) {
+        bail!("{} is not allowed in LaTeX", command);
+    }
+
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("tmp.tex"), latex_source(missing))?;
+
+    run_in(
+        dir.path(),
+        "latex",
+        &["-interaction=nonstopmode", "-no-shell-escape", "tmp.tex"],
+    )?;
+    let output = if missing.svg {
+        run_in(
+            dir.path(),
+            "dvisvgm",
+            &[
+                "--no-fonts",
+                "--exact",
+                "-Z",
+                "2",
+                "tmp.dvi",
+                "-o",
+                "tmp.svg",
+            ],
+        )?;
+        "tmp.svg"
+    } else {
+        run_in(
+            dir.path(),
+            "dvipng",
+            &[
+                "-D",
+                "200",
+                "-T",
+                "tight",
+                "-bg",
+                "Transparent",
+                "tmp.dvi",
+                "-o",
+                "tmp.png",
+            ],
+        )?;
+        "tmp.png"
+    };
+
+    fs::read(dir.path().join(output)).context("no image was generated")
+}
+
+fn run_in(dir: &Path, program: &str, args: &[&str]) -> anyhow::Result<()> {
+    run_sandboxed(dir, program, args, LATEX_TIMEOUT)
+}
+
+/// Run a TeX program in `dir`, killing it after `timeout`. The environment is
+/// cleared apart from `PATH`, and kpathsea's paranoid mode stops TeX from
+/// opening files outside `dir` and the TeX installation, e.g. the webapp's
+/// database or other users' collections.
+fn run_sandboxed(
+    dir: &Path,
+    program: &str,
+    args: &[&str],
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    // Output goes to a file, so a chatty run can't block on a full pipe
+    let log_path = dir.join(format!("{}.out", program));
+    let log = File::create(&log_path)?;
+
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .current_dir(dir)
+        .env_clear()
+        .env("HOME", dir)
+        .env("TEXMFHOME", dir)
+        .env("TEXMFVAR", dir)
+        .env("TEXMFCONFIG", dir)
+        .env("TEXMFOUTPUT", dir)
+        .env("openin_any", "p")
+        .env("openout_any", "p")
+        .env("shell_escape", "f")
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    if let Some(path) = std::env::var_os("PATH") {
+        command.env("PATH", path);
+    }
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("failed to run {}; is it installed?", program))?;
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            bail!("{} took longer than {}s", program, timeout.as_secs());
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    if !status.success() {
+        let log = fs::read_to_string(&log_path).unwrap_or_default();
+        let tail: Vec<&str> = log.lines().rev().take(10).collect();
+        bail!(
+            "{} failed:\n{}",
+            program,
+            tail.into_iter().rev().collect::<Vec<_>>().join("\n")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_requested_range() {
+        let etag = "\"abc\"";
+        let range = |value: &str| requested_range(&headers(&[(header::RANGE, value)]), etag, 100);
+
+        assert_eq!(
+            requested_range(&HeaderMap::new(), etag, 100),
+            RangeRequest::Full
+        );
+        assert_eq!(range("bytes=0-9"), RangeRequest::Partial(0..=9));
+        assert_eq!(range("bytes=90-"), RangeRequest::Partial(90..=99));
+        assert_eq!(range("bytes=90-500"), RangeRequest::Partial(90..=99));
+        assert_eq!(range("bytes=-10"), RangeRequest::Partial(90..=99));
+        assert_eq!(range("bytes=-500"), RangeRequest::Partial(0..=99));
+        assert_eq!(range("bytes=100-"), RangeRequest::Unsatisfiable);
+        assert_eq!(range("bytes=0-1,5-6"), RangeRequest::Full);
+        assert_eq!(range("items=0-1"), RangeRequest::Full);
+
+        // A stale If-Range gets the whole file
+        let stale = headers(&[(header::RANGE, "bytes=0-9"), (header::IF_RANGE, "\"old\"")]);
+        assert_eq!(requested_range(&stale, etag, 100), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_is_not_modified() {
+        let etag = "\"abc\"";
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(784111777);
+
+        let matching = headers(&[(header::IF_NONE_MATCH, "\"xyz\", W/\"abc\"")]);
+        assert!(is_not_modified(&matching, etag, Some(modified)));
+        let other = headers(&[(header::IF_NONE_MATCH, "\"xyz\"")]);
+        assert!(!is_not_modified(&other, etag, Some(modified)));
+
+        assert_eq!(http_date(modified), "Sun, 06 Nov 1994 08:49:37 GMT");
+        let since = headers(&[(header::IF_MODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:37 GMT")]);
+        assert!(is_not_modified(&since, etag, Some(modified)));
+        let earlier = headers(&[(header::IF_MODIFIED_SINCE, "Sat, 05 Nov 1994 08:49:37 GMT")]);
+        assert!(!is_not_modified(&earlier, etag, Some(modified)));
+    }
+
+    #[test]
+    fn test_entity_tag() {
+        assert_eq!(
+            entity_tag(Some([0xab; 20]), 10, None, Some((64, 64))),
+            format!("\"{}-64x64\"", "ab".repeat(20))
+        );
+        assert!(entity_tag(None, 10, None, None).starts_with("W/"));
+    }
+
+    #[test]
+    fn test_make_thumbnail() {
+        let image = image::RgbaImage::new(400, 200);
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+
+        let (data, content_type) = make_thumbnail(&png, 100, 100).unwrap().unwrap();
+        assert_eq!(content_type, "image/png");
+        let thumbnail = image::load_from_memory(&data).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (100, 50));
+
+        assert!(make_thumbnail(&png, 400, 400).unwrap().is_none());
+        assert_eq!(image_dimensions(&png), Some((400, 200)));
+        assert_eq!(image_dimensions(b"not an image"), None);
+    }
+
+    #[test]
+    fn test_forbidden_latex_command() {
+        let latex = |header: &str, latex: &str| MissingLatex {
+            note_id: Default::default(),
+            fname: "latex-x.png".into(),
+            latex: latex.into(),
+            header: header.into(),
+            footer: "\\end{document}".into(),
+            svg: false,
+        };
+        let header = "\\documentclass{article}\\usepackage{amsmath}\\begin{document}";
+
+        assert_eq!(forbidden_latex_command(&latex(header, "$x^2$")), None);
+        assert_eq!(
+            forbidden_latex_command(&latex(header, "\\includegraphics{a.png}")),
+            None
+        );
+        assert_eq!(
+            forbidden_latex_command(&latex(header, "\\input{/etc/passwd}")),
+            Some("\\input")
+        );
+        assert_eq!(
+            forbidden_latex_command(&latex(header, "\\csname input\\endcsname")),
+            Some("\\csname")
+        );
+        assert_eq!(
+            forbidden_latex_command(&latex(header, "\\openin1=x \\read1 to\\x")),
+            Some("\\read")
+        );
+        assert_eq!(
+            forbidden_latex_command(&latex(header, "\\usepackage{listings}")),
+            Some("\\usepackage")
+        );
+        // The header is checked too
+        assert_eq!(
+            forbidden_latex_command(&latex("\\openin1=/etc/passwd", "$x$")),
+            Some("\\openin")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_sandboxed() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let err =
+            run_sandboxed(dir.path(), "sleep", &["5"], Duration::from_millis(100)).unwrap_err();
+        assert!(err.to_string().contains("took longer"));
+
+        // Only the paranoid file access settings reach the program
+        run_sandboxed(dir.path(), "sh", &["-c", "env > env.txt"], LATEX_TIMEOUT).unwrap();
+        let env = fs::read_to_string(dir.path().join("env.txt")).unwrap();
+        assert!(env.contains("openin_any=p"));
+        assert!(env.contains("openout_any=p"));
+        assert!(!env.contains("ANKI_WEBAPP"));
+    }
+}
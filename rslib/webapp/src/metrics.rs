@@ -0,0 +1,306 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! Process-wide counters and histograms, rendered in the Prometheus text
+//! exposition format by the `/metrics` endpoint.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use axum::extract::FromRequestParts;
+use axum::extract::MatchedPath;
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::Response;
+use axum_client_ip::ClientIp;
+use tracing::Instrument;
+
+/// Upper bounds (in seconds) of the histogram buckets
+const BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// The global metrics registry
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Non-cumulative count per bucket, with a final +Inf bucket
+    buckets: [u64; BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let idx = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[idx] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.buckets.iter()) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}",
+            self.count
+        );
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{labels}}}")
+        };
+        let _ = writeln!(out, "{name}_sum{labels} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{labels} {}", self.count);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RouteKey {
+    method: String,
+    route: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(RouteKey, u16), u64>>,
+    request_durations: Mutex<BTreeMap<RouteKey, Histogram>>,
+    lock_wait: Mutex<Histogram>,
+    reviews_answered: AtomicU64,
+    imports: AtomicU64,
+    auth_failures: AtomicU64,
+}
+
+impl Metrics {
+    /// Record a finished HTTP request. `route` should be the matched route
+    /// pattern rather than the raw path, to keep the number of series bounded.
+    pub fn record_request(&self, method: &str, route: &str, status: StatusCode, elapsed: Duration) {
+        let key = RouteKey {
+            method: method.to_string(),
+            route: route.to_string(),
+        };
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((key.clone(), status.as_u16()))
+            .or_default() += 1;
+        self.request_durations
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+        if status == StatusCode::UNAUTHORIZED {
+            self.auth_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record how long a request waited for a collection lock
+    pub fn record_lock_wait(&self, elapsed: Duration) {
+        self.lock_wait
+            .lock()
+            .unwrap()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn record_review(&self) {
+        self.reviews_answered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_import(&self) {
+        self.imports.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Render all metrics in the Prometheus text format. Open collections are
+    /// passed in, as they're tracked by the backend manager.
+    pub fn render(&self, open_collections: usize) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP anki_webapp_http_requests_total Total HTTP requests handled.\n");
+        out.push_str("# TYPE anki_webapp_http_requests_total counter\n");
+        for ((key, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "anki_webapp_http_requests_total{{{},status=\"{}\"}} {}",
+                route_labels(key),
+                status,
+                count
+            );
+        }
+
+        out.push_str("# HELP anki_webapp_http_request_duration_seconds HTTP request latency.\n");
+        out.push_str("# TYPE anki_webapp_http_request_duration_seconds histogram\n");
+        for (key, histogram) in self.request_durations.lock().unwrap().iter() {
+            histogram.render(
+                &mut out,
+                "anki_webapp_http_request_duration_seconds",
+                &route_labels(key),
+            );
+        }
+
+        out.push_str("# HELP anki_webapp_open_collections Collections currently open.\n");
+        out.push_str("# TYPE anki_webapp_open_collections gauge\n");
+        let _ = writeln!(out, "anki_webapp_open_collections {}", open_collections);
+
+        out.push_str(
+            "# HELP anki_webapp_collection_lock_wait_seconds Time spent waiting for a collection lock.\n",
+        );
+        out.push_str("# TYPE anki_webapp_collection_lock_wait_seconds histogram\n");
+        self.lock_wait.lock().unwrap().render(
+            &mut out,
+            "anki_webapp_collection_lock_wait_seconds",
+            "",
+        );
+
+        for (name, help, value) in [
+            (
+                "anki_webapp_reviews_answered_total",
+                "Cards answered.",
+                &self.reviews_answered,
+            ),
+            (
+                "anki_webapp_imports_total",
+                "Package imports run.",
+                &self.imports,
+            ),
+            (
+                "anki_webapp_auth_failures_total",
+                "Requests rejected as unauthenticated.",
+                &self.auth_failures,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        }
+
+        out
+    }
+}
+
+fn route_labels(key: &RouteKey) -> String {
+    format!(
+        "method=\"{}\",route=\"{}\"",
+        escape_label(&key.method),
+        escape_label(&key.route)
+    )
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Middleware wrapping each request in a tracing span and recording its
+/// count and latency. The span's `user_id` is filled in once the request has
+/// been authenticated; `client_ip` comes from the configured IP source.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let span = tracing::info_span!(
+        "request",
+        method = %method,
+        route = %route,
+        user_id = tracing::field::Empty,
+        client_ip = tracing::field::Empty,
+    );
+
+    let (mut parts, body) = request.into_parts();
+    if let Ok(ClientIp(ip)) = ClientIp::from_request_parts(&mut parts, &()).await {
+        span.record("client_ip", tracing::field::display(ip));
+    }
+    let request = Request::from_parts(parts, body);
+
+    let response = next.run(request).instrument(span.clone()).await;
+
+    let elapsed = start.elapsed();
+    let status = response.status();
+    metrics().record_request(&method, &route, status, elapsed);
+    span.in_scope(|| {
+        tracing::info!(
+            status = status.as_u16(),
+            elapsed_ms = elapsed.as_millis() as u64,
+            "finished request"
+        );
+    });
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.record_request(
+            "GET",
+            "/api/v1/decks/{id}",
+            StatusCode::OK,
+            Duration::from_millis(20),
+        );
+        metrics.record_request(
+            "POST",
+            "/api/v1/auth/login",
+            StatusCode::UNAUTHORIZED,
+            Duration::from_millis(3),
+        );
+        metrics.record_lock_wait(Duration::from_millis(2));
+        metrics.record_review();
+        metrics.record_import();
+
+        let output = metrics.render(2);
+        assert!(output.contains(
+            "anki_webapp_http_requests_total{method=\"GET\",route=\"/api/v1/decks/{id}\",status=\"200\"} 1"
+        ));
+        assert!(output.contains(
+            "anki_webapp_http_request_duration_seconds_bucket{method=\"GET\",route=\"/api/v1/decks/{id}\",le=\"0.025\"} 1"
+        ));
+        assert!(output.contains(
+            "anki_webapp_http_request_duration_seconds_bucket{method=\"GET\",route=\"/api/v1/decks/{id}\",le=\"0.01\"} 0"
+        ));
+        assert!(output.contains("anki_webapp_open_collections 2"));
+        assert!(output.contains("anki_webapp_collection_lock_wait_seconds_count 1"));
+        assert!(output.contains("anki_webapp_reviews_answered_total 1"));
+        assert!(output.contains("anki_webapp_imports_total 1"));
+        assert!(output.contains("anki_webapp_auth_failures_total 1"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
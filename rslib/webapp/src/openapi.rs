@@ -1,16 +1,44 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
+use schemars::gen::SchemaGenerator;
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
 use serde_json::json;
 use serde_json::Value;
 
+use crate::db::SharedDeck;
+use crate::error::ErrorResponse;
+use crate::jobs::JobInfo;
+use crate::routes::audit;
+use crate::routes::auth;
+use crate::routes::backups;
+use crate::routes::browse;
+use crate::routes::cards;
+use crate::routes::collection;
+use crate::routes::decks;
+use crate::routes::fsrs;
+use crate::routes::image_occlusion;
+use crate::routes::import_export;
+use crate::routes::jobs;
+use crate::routes::maintenance;
+use crate::routes::media;
+use crate::routes::notes;
+use crate::routes::notetypes;
+use crate::routes::preferences;
+use crate::routes::scheduler;
+use crate::routes::search;
+use crate::routes::sharing;
+use crate::routes::stats;
+use crate::routes::tags;
+
 pub fn openapi_spec() -> Value {
-    json!({
+    let mut spec = json!({
         "openapi": "3.0.3",
         "info": {
             "title": "Anki Web App API",
             "version": "0.1.0",
-            "description": "REST API for Anki spaced repetition flashcard system.\n\n## Authentication\n\nMost endpoints require a JWT token obtained from `POST /api/v1/auth/login`.\nInclude the token in the `Authorization` header as `Bearer <token>`.",
+            "description": "REST API for Anki spaced repetition flashcard system.\n\n## Authentication\n\nMost endpoints require a JWT token obtained from `POST /api/v1/auth/login`.\nInclude the token in the `Authorization` header as `Bearer <token>`.\n\n## Retries and concurrent edits\n\nPOST requests may carry an `Idempotency-Key` header. Retrying with the same key and body within the replay window (24 hours by default) returns the stored response, marked with `Idempotent-Replayed: true`, instead of repeating the request. Reusing a key for a different request returns 422; retrying while the first attempt is still running returns 409. Bodies of requests with a key are limited to 1 MiB.\n\nNotes and cards are served with an `ETag`. Send it back in `If-Match` when updating them, and the update is rejected with 412 if the object changed in the meantime.\n\n## Errors\n\nError responses carry a machine-readable `error.code` alongside a human-readable `error.message`. Errors raised by the collection (for example `not_found`, `invalid_input`, `search_error`, `exists`, `database_check_required` or `collection_locked`) have their message localized according to the request's `Accept-Language` header.",
             "contact": {
                 "name": "Anki Development",
                 "url": "https://github.com/ankitects/anki"
@@ -32,12 +60,17 @@ pub fn openapi_spec() -> Value {
             { "name": "search", "description": "Search and find-replace operations" },
             { "name": "browse", "description": "Browse-table batch row fetching" },
             { "name": "media", "description": "Media file management" },
+            { "name": "image-occlusion", "description": "Image occlusion note creation and editing" },
             { "name": "tags", "description": "Tag management" },
             { "name": "stats", "description": "Statistics and analytics" },
             { "name": "health", "description": "Health check endpoints" },
             { "name": "scheduler", "description": "Study session and spaced repetition scheduler" },
             { "name": "notetypes", "description": "Note type (model) management" },
-            { "name": "collections", "description": "Collection file management" }
+            { "name": "collections", "description": "Collection file management" },
+            { "name": "jobs", "description": "Background job status" },
+            { "name": "fsrs", "description": "FSRS parameter optimization, evaluation and simulation" },
+            { "name": "preferences", "description": "Collection preferences and config keys" },
+            { "name": "sharing", "description": "Publishing decks to other users of the server, and subscribing to them" }
         ],
         "paths": {
             "/api/v1/auth/register": {
@@ -46,23 +79,21 @@ pub fn openapi_spec() -> Value {
                     "summary": "Register a new user",
                     "operationId": "register",
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/RegisterRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "201": {
-                            "description": "User registered successfully",
+                            "description": "User registered successfully"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "403": {
+                            "description": "Registration is disabled on this server",
                             "content": {
                                 "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/AuthResponse" }
+                                    "schema": { "$ref": "#/components/schemas/ErrorResponse" }
                                 }
                             }
                         },
-                        "400": { "$ref": "#/components/responses/BadRequest" },
                         "409": {
                             "description": "Username already exists",
                             "content": {
@@ -80,21 +111,11 @@ pub fn openapi_spec() -> Value {
                     "summary": "Login and obtain JWT token",
                     "operationId": "login",
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/LoginRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Login successful",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/AuthResponse" }
-                                }
-                            }
+                            "description": "Login successful"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -108,12 +129,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Current user info",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/UserInfo" }
-                                }
-                            }
+                            "description": "Current user info"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -127,12 +143,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Current user info",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/UserInfo" }
-                                }
-                            }
+                            "description": "Current user info"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -146,18 +157,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Logged out successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": { "type": "boolean", "example": true },
-                                            "message": { "type": "string", "example": "Logged out successfully" }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Logged out successfully"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -171,12 +171,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Collection info",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/CollectionInfo" }
-                                }
-                            }
+                            "description": "Collection info"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -190,12 +185,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Collection info",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/CollectionInfo" }
-                                }
-                            }
+                            "description": "Collection info"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -209,18 +199,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Collection closed",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": { "type": "boolean", "example": true },
-                                            "message": { "type": "string", "example": "Collection closed successfully" }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Collection closed"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -234,20 +213,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Deck tree",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "decks": {
-                                                "type": "array",
-                                                "items": { "$ref": "#/components/schemas/DeckTreeNode" }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Deck tree"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -258,28 +224,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "createDeck",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/CreateDeckRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "201": {
-                            "description": "Deck created",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": { "type": "boolean", "example": true },
-                                            "message": { "type": "string", "example": "Deck 'Spanish' created" },
-                                            "id": { "type": "integer", "format": "int64" }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Deck created"
                         },
                         "400": { "$ref": "#/components/responses/BadRequest" },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
@@ -295,12 +244,7 @@ pub fn openapi_spec() -> Value {
                     "parameters": [{ "$ref": "#/components/parameters/DeckId" }],
                     "responses": {
                         "200": {
-                            "description": "Deck details",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/Deck" }
-                                }
-                            }
+                            "description": "Deck details"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -309,26 +253,18 @@ pub fn openapi_spec() -> Value {
                 "put": {
                     "tags": ["decks"],
                     "summary": "Update a deck (rename or set collapsed state)",
+                    "description": "The name uses `::` separators, so renaming can also move the deck. Child decks are renamed with it. The stored name is returned, as it may have been normalized or made unique.",
                     "operationId": "updateDeck",
                     "security": [{ "bearerAuth": [] }],
                     "parameters": [{ "$ref": "#/components/parameters/DeckId" }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/UpdateDeckRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Deck updated",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/MessageResponse" }
-                                }
-                            }
+                            "description": "Deck updated"
                         },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
                     }
@@ -341,18 +277,7 @@ pub fn openapi_spec() -> Value {
                     "parameters": [{ "$ref": "#/components/parameters/DeckId" }],
                     "responses": {
                         "200": {
-                            "description": "Deck deleted",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": { "type": "boolean", "example": true },
-                                            "message": { "type": "string", "example": "Deck deleted successfully" }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Deck deleted"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -366,28 +291,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "createNote",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/CreateNoteRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "201": {
-                            "description": "Note created",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": { "type": "boolean", "example": true },
-                                            "note_id": { "type": "integer", "format": "int64" },
-                                            "message": { "type": "string", "example": "Note created successfully (1 cards generated)" }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Note created"
                         },
                         "400": { "$ref": "#/components/responses/BadRequest" },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
@@ -401,39 +309,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "checkNoteFields",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": {
-                                    "type": "object",
-                                    "required": ["notetype_id", "fields"],
-                                    "properties": {
-                                        "notetype_id": { "type": "integer", "format": "int64" },
-                                        "fields": {
-                                            "type": "array",
-                                            "items": { "type": "string" }
-                                        }
-                                    }
-                                }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Field check result",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "state": {
-                                                "type": "integer",
-                                                "description": "0=Normal, 1=Empty, 2=Duplicate"
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Field check result"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -448,12 +328,7 @@ pub fn openapi_spec() -> Value {
                     "parameters": [{ "$ref": "#/components/parameters/NoteId" }],
                     "responses": {
                         "200": {
-                            "description": "Note details",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/Note" }
-                                }
-                            }
+                            "description": "Note details"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -464,33 +339,21 @@ pub fn openapi_spec() -> Value {
                     "summary": "Update a note",
                     "operationId": "updateNote",
                     "security": [{ "bearerAuth": [] }],
-                    "parameters": [{ "$ref": "#/components/parameters/NoteId" }],
+                    "parameters": [
+                        { "$ref": "#/components/parameters/NoteId" },
+                        { "$ref": "#/components/parameters/IfMatch" }
+                    ],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/UpdateNoteRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Note updated",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": { "type": "boolean", "example": true },
-                                            "message": { "type": "string", "example": "Note updated successfully" }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Note updated"
                         },
                         "400": { "$ref": "#/components/responses/BadRequest" },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
-                        "404": { "$ref": "#/components/responses/NotFound" }
+                        "404": { "$ref": "#/components/responses/NotFound" },
+                        "412": { "$ref": "#/components/responses/PreconditionFailed" }
                     }
                 },
                 "delete": {
@@ -501,18 +364,7 @@ pub fn openapi_spec() -> Value {
                     "parameters": [{ "$ref": "#/components/parameters/NoteId" }],
                     "responses": {
                         "200": {
-                            "description": "Note deleted",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": { "type": "boolean", "example": true },
-                                            "message": { "type": "string", "example": "Note deleted successfully (1 cards removed)" }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Note deleted"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -528,20 +380,7 @@ pub fn openapi_spec() -> Value {
                     "parameters": [{ "$ref": "#/components/parameters/NoteId" }],
                     "responses": {
                         "200": {
-                            "description": "Card IDs for the note",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "card_ids": {
-                                                "type": "array",
-                                                "items": { "type": "integer", "format": "int64" }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Card IDs for the note"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -565,12 +404,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Card information",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/CardInfo" }
-                                }
-                            }
+                            "description": "Card information"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -588,27 +422,19 @@ pub fn openapi_spec() -> Value {
                             "required": true,
                             "schema": { "type": "integer", "format": "int64" },
                             "description": "Card ID"
-                        }
+                        },
+                        { "$ref": "#/components/parameters/IfMatch" }
                     ],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/UpdateCardRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Card updated successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/MessageResponse" }
-                                }
-                            }
+                            "description": "Card updated successfully"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
-                        "404": { "$ref": "#/components/responses/NotFound" }
+                        "404": { "$ref": "#/components/responses/NotFound" },
+                        "412": { "$ref": "#/components/responses/PreconditionFailed" }
                     }
                 },
                 "delete": {
@@ -627,12 +453,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Card deleted successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/MessageResponse" }
-                                }
-                            }
+                            "description": "Card deleted successfully"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -655,21 +476,11 @@ pub fn openapi_spec() -> Value {
                         }
                     ],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/FlagCardRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Card flagged successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/MessageResponse" }
-                                }
-                            }
+                            "description": "Card flagged successfully"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -693,12 +504,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Card suspended successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/MessageResponse" }
-                                }
-                            }
+                            "description": "Card suspended successfully"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -722,12 +528,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Card unsuspended successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/MessageResponse" }
-                                }
-                            }
+                            "description": "Card unsuspended successfully"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -751,12 +552,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Card buried successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/MessageResponse" }
-                                }
-                            }
+                            "description": "Card buried successfully"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -770,29 +566,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "batchGetCards",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/BatchGetCardsRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Cards retrieved successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "cards": {
-                                                "type": "array",
-                                                "items": { "$ref": "#/components/schemas/CardInfo" }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Cards retrieved successfully"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -805,28 +583,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "batchUpdateCards",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/BatchUpdateCardsRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Cards updated successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "success": { "type": "boolean" },
-                                            "message": { "type": "string" },
-                                            "updated_count": { "type": "integer" }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Cards updated successfully"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -839,21 +600,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "searchCards",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/SearchCardsRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Cards matching the search query",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/SearchCardsResponse" }
-                                }
-                            }
+                            "description": "Cards matching the search query"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "400": { "$ref": "#/components/responses/BadRequest" }
@@ -867,21 +618,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "searchNotes",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/SearchNotesRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Notes matching the search query",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/SearchNotesResponse" }
-                                }
-                            }
+                            "description": "Notes matching the search query"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "400": { "$ref": "#/components/responses/BadRequest" }
@@ -895,21 +636,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "findAndReplace",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/FindAndReplaceRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Find and replace completed",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/FindAndReplaceResponse" }
-                                }
-                            }
+                            "description": "Find and replace completed"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "400": { "$ref": "#/components/responses/BadRequest" }
@@ -924,39 +655,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "browseCards",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": {
-                                    "type": "object",
-                                    "required": ["ids"],
-                                    "properties": {
-                                        "ids": {
-                                            "type": "array",
-                                            "items": { "type": "integer", "format": "int64" },
-                                            "description": "Card IDs to fetch browse rows for"
-                                        }
-                                    }
-                                }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Browse rows for the requested cards",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "rows": {
-                                                "type": "array",
-                                                "items": { "$ref": "#/components/schemas/CardBrowseRow" }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Browse rows for the requested cards"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -970,39 +673,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "browseNotes",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": {
-                                    "type": "object",
-                                    "required": ["ids"],
-                                    "properties": {
-                                        "ids": {
-                                            "type": "array",
-                                            "items": { "type": "integer", "format": "int64" },
-                                            "description": "Note IDs to fetch browse rows for"
-                                        }
-                                    }
-                                }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Browse rows for the requested notes",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "rows": {
-                                                "type": "array",
-                                                "items": { "$ref": "#/components/schemas/NoteBrowseRow" }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "Browse rows for the requested notes"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1016,12 +691,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Media check results",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/CheckMediaResponse" }
-                                }
-                            }
+                            "description": "Media check results"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1031,6 +701,7 @@ pub fn openapi_spec() -> Value {
                 "get": {
                     "tags": ["media"],
                     "summary": "Get a media file by filename",
+                    "description": "Streams the file with `ETag`, `Last-Modified` and `Cache-Control` headers. Supports conditional requests (`If-None-Match`, `If-Modified-Since`) and a single byte range (`Range`, `If-Range`). JPEG, PNG, GIF and WebP images can be scaled down with `width` and/or `height`.",
                     "operationId": "getMedia",
                     "security": [{ "bearerAuth": [] }],
                     "parameters": [
@@ -1040,6 +711,20 @@ pub fn openapi_spec() -> Value {
                             "required": true,
                             "schema": { "type": "string" },
                             "description": "Media filename (e.g., image.jpg, audio.mp3)"
+                        },
+                        {
+                            "name": "width",
+                            "in": "query",
+                            "required": false,
+                            "schema": { "type": "integer", "minimum": 1, "maximum": 2048 },
+                            "description": "Scale images down to fit within this width"
+                        },
+                        {
+                            "name": "height",
+                            "in": "query",
+                            "required": false,
+                            "schema": { "type": "integer", "minimum": 1, "maximum": 2048 },
+                            "description": "Scale images down to fit within this height"
                         }
                     ],
                     "responses": {
@@ -1051,6 +736,16 @@ pub fn openapi_spec() -> Value {
                                 }
                             }
                         },
+                        "206": {
+                            "description": "The requested byte range",
+                            "content": {
+                                "application/octet-stream": {
+                                    "schema": { "type": "string", "format": "binary" }
+                                }
+                            }
+                        },
+                        "304": { "description": "The client's cached copy is current" },
+                        "416": { "description": "The requested range is outside the file" },
                         "400": { "$ref": "#/components/responses/BadRequest" },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -1083,12 +778,7 @@ pub fn openapi_spec() -> Value {
                     },
                     "responses": {
                         "200": {
-                            "description": "File uploaded successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/AddMediaResponse" }
-                                }
-                            }
+                            "description": "File uploaded successfully"
                         },
                         "400": { "$ref": "#/components/responses/BadRequest" },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
@@ -1100,21 +790,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "deleteMedia",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/DeleteMediaRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Files moved to trash",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/DeleteMediaResponse" }
-                                }
-                            }
+                            "description": "Files moved to trash"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1146,12 +826,7 @@ pub fn openapi_spec() -> Value {
                     },
                     "responses": {
                         "201": {
-                            "description": "Package imported successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/WebImportResponse" }
-                                }
-                            }
+                            "description": "Package imported successfully"
                         },
                         "400": { "$ref": "#/components/responses/BadRequest" },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
@@ -1166,12 +841,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "List of all tags",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/TagsListResponse" }
-                                }
-                            }
+                            "description": "List of all tags"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1185,12 +855,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Hierarchical tag tree",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/TagTreeResponse" }
-                                }
-                            }
+                            "description": "Hierarchical tag tree"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1203,21 +868,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "renameTag",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/RenameTagRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Tag renamed successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/RenameTagResponse" }
-                                }
-                            }
+                            "description": "Tag renamed successfully"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1240,12 +895,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Tag deleted successfully",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/DeleteTagResponse" }
-                                }
-                            }
+                            "description": "Tag deleted successfully"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1259,12 +909,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Unused tags cleared",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/ClearUnusedTagsResponse" }
-                                }
-                            }
+                            "description": "Unused tags cleared"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1287,12 +932,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Card statistics",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/CardStatsResponse" }
-                                }
-                            }
+                            "description": "Card statistics"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -1323,12 +963,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Collection statistics including today's study stats and card counts",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/CollectionStatsResponse" }
-                                }
-                            }
+                            "description": "Collection statistics including today's study stats and card counts"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1342,12 +977,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Today's study statistics",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/TodayStatsResponse" }
-                                }
-                            }
+                            "description": "Today's study statistics"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1414,20 +1044,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "List of note types",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "notetypes": {
-                                                "type": "array",
-                                                "items": { "$ref": "#/components/schemas/NotetypeSummary" }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "List of note types"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1450,12 +1067,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Note type details including fields",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/NotetypeDetail" }
-                                }
-                            }
+                            "description": "Note type details including fields"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -1470,21 +1082,7 @@ pub fn openapi_spec() -> Value {
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "List of collection paths",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "collections": {
-                                                "type": "array",
-                                                "items": { "type": "string" },
-                                                "description": "Collection file paths relative to the user data directory"
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                            "description": "List of collection paths"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
@@ -1495,21 +1093,11 @@ pub fn openapi_spec() -> Value {
                     "operationId": "createCollection",
                     "security": [{ "bearerAuth": [] }],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/CreateCollectionRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "201": {
-                            "description": "Collection created",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/MessageResponse" }
-                                }
-                            }
+                            "description": "Collection created"
                         },
                         "400": { "$ref": "#/components/responses/BadRequest" },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
@@ -1533,12 +1121,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Collection deleted",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/MessageResponse" }
-                                }
-                            }
+                            "description": "Collection deleted"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -1549,7 +1132,7 @@ pub fn openapi_spec() -> Value {
                 "get": {
                     "tags": ["scheduler"],
                     "summary": "Get the next card due for review in a specific deck",
-                    "description": "Returns the next card to review from the specified deck (and its children). The deck is set as the current deck before fetching cards, ensuring only cards from this deck tree are returned.",
+                    "description": "Returns the next card to review from the specified deck (and its children). The deck is set as the current deck before fetching cards, ensuring only cards from this deck tree are returned. Each card carries the reviewer settings of its deck's preset (auto advance, autoplay, timer and maximum answer seconds). Once the deck is finished, the response includes the information the desktop shows on its congratulations screen.",
                     "operationId": "getNextCard",
                     "security": [{ "bearerAuth": [] }],
                     "parameters": [
@@ -1563,12 +1146,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Next card for review, or null if no cards are due",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/NextCardResponse" }
-                                }
-                            }
+                            "description": "Next card for review, or null if no cards are due"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -1579,6 +1157,7 @@ pub fn openapi_spec() -> Value {
                 "post": {
                     "tags": ["scheduler"],
                     "summary": "Submit an answer (ease rating) for a card",
+                    "description": "The answer time is capped to the preset's maximum answer seconds, and the recorded time is returned. If the card became a leech, the response says so, and whether the preset's leech action suspended it.",
                     "operationId": "answerCard",
                     "security": [{ "bearerAuth": [] }],
                     "parameters": [
@@ -1598,21 +1177,11 @@ pub fn openapi_spec() -> Value {
                         }
                     ],
                     "requestBody": {
-                        "required": true,
-                        "content": {
-                            "application/json": {
-                                "schema": { "$ref": "#/components/schemas/AnswerCardRequest" }
-                            }
-                        }
+                        "required": true
                     },
                     "responses": {
                         "200": {
-                            "description": "Answer recorded, returns updated counts",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/AnswerCardResponse" }
-                                }
-                            }
+                            "description": "Answer recorded, returns updated counts"
                         },
                         "400": { "$ref": "#/components/responses/BadRequest" },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
@@ -1645,12 +1214,7 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Next interval descriptions for each answer button",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/NextStatesResponse" }
-                                }
-                            }
+                            "description": "Next interval descriptions for each answer button"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -1674,12 +1238,32 @@ pub fn openapi_spec() -> Value {
                     ],
                     "responses": {
                         "200": {
-                            "description": "Card counts for the deck",
-                            "content": {
-                                "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/DeckCountsResponse" }
-                                }
-                            }
+                            "description": "Card counts for the deck"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
+                    }
+                }
+            },
+            "/api/v1/scheduler/decks/{deck_id}/congrats": {
+                "get": {
+                    "tags": ["scheduler"],
+                    "summary": "Get what is left to study in a deck today",
+                    "description": "The information the desktop shows on its congratulations screen: learning cards due later today, cards held back by limits or buried, and the deck's description. The user's current deck is left unchanged.",
+                    "operationId": "getCongratsInfo",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [
+                        {
+                            "name": "deck_id",
+                            "in": "path",
+                            "required": true,
+                            "schema": { "type": "integer", "format": "int64" },
+                            "description": "Deck ID"
+                        }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Congratulations screen information for the deck"
                         },
                         "401": { "$ref": "#/components/responses/Unauthorized" },
                         "404": { "$ref": "#/components/responses/NotFound" }
@@ -1689,39 +1273,58 @@ pub fn openapi_spec() -> Value {
             "/api/v1/scheduler/undo": {
                 "post": {
                     "tags": ["scheduler"],
-                    "summary": "Undo the last scheduler action",
+                    "summary": "Undo the last operation",
+                    "description": "Reverts the most recent undoable change to the collection, whichever endpoint made it. Each collection keeps its own history of up to 30 steps, which is cleared when the user logs in again. Labels follow the `Accept-Language` header.",
                     "operationId": "undoScheduler",
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Undo successful",
+                            "description": "Undo successful"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "409": {
+                            "description": "Nothing to undo",
                             "content": {
                                 "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/MessageResponse" }
+                                    "schema": { "$ref": "#/components/schemas/ErrorResponse" }
                                 }
                             }
-                        },
-                        "400": { "$ref": "#/components/responses/BadRequest" },
-                        "401": { "$ref": "#/components/responses/Unauthorized" }
+                        }
                     }
                 }
             },
             "/api/v1/scheduler/redo": {
                 "post": {
                     "tags": ["scheduler"],
-                    "summary": "Redo the last undone scheduler action",
+                    "summary": "Redo the last undone operation",
                     "operationId": "redoScheduler",
                     "security": [{ "bearerAuth": [] }],
                     "responses": {
                         "200": {
-                            "description": "Redo successful",
+                            "description": "Redo successful"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "409": {
+                            "description": "Nothing to redo",
                             "content": {
                                 "application/json": {
-                                    "schema": { "$ref": "#/components/schemas/MessageResponse" }
+                                    "schema": { "$ref": "#/components/schemas/ErrorResponse" }
                                 }
                             }
+                        }
+                    }
+                }
+            },
+            "/api/v1/scheduler/undo-status": {
+                "get": {
+                    "tags": ["scheduler"],
+                    "summary": "Describe what the next undo and redo would do",
+                    "operationId": "getUndoStatus",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "200": {
+                            "description": "Undo and redo labels"
                         },
-                        "400": { "$ref": "#/components/responses/BadRequest" },
                         "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
                 }
@@ -1789,643 +1392,1502 @@ pub fn openapi_spec() -> Value {
                     "required": true,
                     "description": "Note ID",
                     "schema": { "type": "integer", "format": "int64" }
+                },
+                "IfMatch": {
+                    "name": "If-Match",
+                    "in": "header",
+                    "required": false,
+                    "description": "The `ETag` the object was fetched with. The update fails with 412 if the object has changed since.",
+                    "schema": { "type": "string" }
                 }
             },
-            "schemas": {
-                "RegisterRequest": {
-                    "type": "object",
-                    "required": ["username", "password"],
-                    "properties": {
-                        "username": { "type": "string", "maxLength": 50, "example": "alice" },
-                        "password": { "type": "string", "minLength": 8, "example": "password123" },
-                        "email": { "type": "string", "format": "email", "nullable": true, "example": "alice@example.com" }
+            "responses": {
+                "BadRequest": {
+                    "description": "Bad request",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/ErrorResponse" }
+                        }
                     }
                 },
-                "LoginRequest": {
-                    "type": "object",
-                    "required": ["username", "password"],
-                    "properties": {
-                        "username": { "type": "string", "example": "alice" },
-                        "password": { "type": "string", "example": "password123" }
+                "Unauthorized": {
+                    "description": "Missing or invalid authentication token",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/ErrorResponse" }
+                        }
                     }
                 },
-                "AuthResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": { "type": "boolean", "example": true },
-                        "data": {
-                            "type": "object",
-                            "properties": {
-                                "token": { "type": "string", "example": "eyJhbGciOiJIUzI1NiIs..." },
-                                "user": { "$ref": "#/components/schemas/UserInfo" }
-                            }
-                        },
-                        "error": { "type": "string", "nullable": true }
+                "NotFound": {
+                    "description": "Resource not found",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/ErrorResponse" }
+                        }
                     }
                 },
-                "UserInfo": {
-                    "type": "object",
-                    "properties": {
-                        "id": { "type": "integer", "format": "int64" },
-                        "username": { "type": "string" },
-                        "email": { "type": "string", "nullable": true }
-                    }
-                },
-                "CollectionInfo": {
-                    "type": "object",
-                    "properties": {
-                        "user_id": { "type": "integer", "format": "int64" },
-                        "username": { "type": "string" },
-                        "backend_active": { "type": "boolean" },
-                        "message": { "type": "string" }
-                    }
-                },
-                "DeckTreeNode": {
-                    "type": "object",
-                    "properties": {
-                        "id": { "type": "integer", "format": "int64" },
-                        "name": { "type": "string" },
-                        "collapsed": { "type": "boolean" },
-                        "children": {
-                            "type": "array",
-                            "items": { "$ref": "#/components/schemas/DeckTreeNode" }
+                "PreconditionFailed": {
+                    "description": "The object changed since the `If-Match` tag was fetched",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/ErrorResponse" }
                         }
                     }
-                },
-                "Deck": {
-                    "type": "object",
-                    "properties": {
-                        "id": { "type": "integer", "format": "int64" },
-                        "name": { "type": "string" }
+                }
+            }
+        }
+    });
+
+    add_section(&mut spec, maintenance_section());
+    add_section(&mut spec, backups_section());
+    add_section(&mut spec, metrics_section());
+    add_section(&mut spec, audit_section());
+    add_section(&mut spec, scheduling_section());
+    add_section(&mut spec, media_section());
+    add_section(&mut spec, image_occlusion_section());
+    add_section(&mut spec, bulk_notes_section());
+    add_section(&mut spec, tag_ops_section());
+    add_section(&mut spec, deck_ops_section());
+    add_section(&mut spec, fsrs_section());
+    add_section(&mut spec, preferences_section());
+    add_section(&mut spec, sharing_section());
+    add_body_schemas(&mut spec);
+
+    spec
+}
+
+/// Merge the `paths` of a feature section into the main spec. Keeping
+/// sections separate keeps each `json!` invocation small.
+fn add_section(spec: &mut Value, section: Value) {
+    if let Some(paths) = section["paths"].as_object() {
+        for (path, item) in paths {
+            spec["paths"][path] = item.clone();
+        }
+    }
+}
+
+/// Fill in the JSON request and response bodies of each operation from the
+/// types its handler (de)serializes, and collect their definitions under
+/// `components.schemas`. The paths above only describe the rest, so the
+/// documented shapes can't drift from the handlers.
+fn add_body_schemas(spec: &mut Value) {
+    let mut bodies = BodySchemas {
+        spec,
+        generator: SchemaSettings::openapi3().into_generator(),
+    };
+
+    bodies.request::<auth::RegisterRequest>("post", "/api/v1/auth/register");
+    bodies.response::<auth::AuthResponse>("post", "/api/v1/auth/register", "201");
+    bodies.request::<auth::LoginRequest>("post", "/api/v1/auth/login");
+    bodies.response::<auth::AuthResponse>("post", "/api/v1/auth/login", "200");
+    bodies.response::<auth::MessageResponse>("post", "/api/v1/auth/logout", "200");
+    bodies.response::<auth::UserInfo>("get", "/api/v1/auth/me", "200");
+    bodies.response::<auth::UserInfo>("get", "/api/v1/auth/profile", "200");
+
+    bodies.response::<collection::CollectionInfo>("get", "/api/v1/collection", "200");
+    bodies.response::<collection::CollectionInfo>("get", "/api/v1/collection/info", "200");
+    bodies.response::<collection::MessageResponse>("post", "/api/v1/collection/close", "200");
+
+    bodies.response::<collection::CollectionsResponse>("get", "/api/v1/collections", "200");
+    bodies.request::<collection::CreateCollectionRequest>("post", "/api/v1/collections");
+    bodies.response::<collection::CreateCollectionResponse>("post", "/api/v1/collections", "201");
+    bodies.response::<collection::MessageResponse>("delete", "/api/v1/collections/{path}", "200");
+
+    bodies.response::<maintenance::JobStartedResponse>(
+        "post",
+        "/api/v1/collection/check-database",
+        "202",
+    );
+    bodies.response::<maintenance::JobStartedResponse>(
+        "post",
+        "/api/v1/collection/empty-cards",
+        "202",
+    );
+    bodies.request::<maintenance::DeleteEmptyCardsRequest>(
+        "delete",
+        "/api/v1/collection/empty-cards",
+    );
+    bodies.response::<maintenance::DeleteEmptyCardsResponse>(
+        "delete",
+        "/api/v1/collection/empty-cards",
+        "200",
+    );
+    bodies.response::<backups::BackupsListResponse>("get", "/api/v1/collection/backups", "200");
+    bodies.response::<maintenance::JobStartedResponse>("post", "/api/v1/collection/backups", "202");
+    bodies.response::<maintenance::JobStartedResponse>(
+        "post",
+        "/api/v1/collection/backups/{filename}/restore",
+        "202",
+    );
+
+    bodies.response::<audit::AuditLogResponse>("get", "/api/v1/audit", "200");
+
+    bodies.response::<jobs::JobsListResponse>("get", "/api/v1/jobs", "200");
+    bodies.response::<JobInfo>("get", "/api/v1/jobs/{id}", "200");
+
+    bodies.response::<decks::DeckTree>("get", "/api/v1/decks", "200");
+    bodies.request::<decks::CreateDeckRequest>("post", "/api/v1/decks");
+    bodies.response::<decks::MessageResponse>("post", "/api/v1/decks", "201");
+    bodies.response::<decks::DeckInfo>("get", "/api/v1/decks/{id}", "200");
+    bodies.request::<decks::UpdateDeckRequest>("put", "/api/v1/decks/{id}");
+    bodies.response::<decks::UpdateDeckResponse>("put", "/api/v1/decks/{id}", "200");
+    bodies.response::<decks::MessageResponse>("delete", "/api/v1/decks/{id}", "200");
+    bodies.request::<decks::ReparentDecksRequest>("post", "/api/v1/decks/reparent");
+    bodies.response::<decks::ReparentDecksResponse>("post", "/api/v1/decks/reparent", "200");
+    bodies.request::<decks::SetDeckCollapsedRequest>("put", "/api/v1/decks/{id}/collapse");
+    bodies.response::<decks::MessageResponse>("put", "/api/v1/decks/{id}/collapse", "200");
+    bodies.response::<decks::DeckStatsResponse>("get", "/api/v1/decks/{id}/stats", "200");
+
+    bodies.request::<notes::CreateNoteRequest>("post", "/api/v1/notes");
+    bodies.response::<notes::CreateNoteResponse>("post", "/api/v1/notes", "201");
+    bodies.request::<notes::BulkNotesRequest>("post", "/api/v1/notes/bulk");
+    bodies.response::<notes::BulkNotesResponse>("post", "/api/v1/notes/bulk", "200");
+    bodies.request::<notes::CheckNoteFieldsRequest>("post", "/api/v1/notes/check-fields");
+    bodies.response::<notes::CheckNoteFieldsResponse>("post", "/api/v1/notes/check-fields", "200");
+    bodies.request::<maintenance::FindDuplicatesRequest>("post", "/api/v1/notes/find-duplicates");
+    bodies.response::<maintenance::JobStartedResponse>(
+        "post",
+        "/api/v1/notes/find-duplicates",
+        "202",
+    );
+    bodies.response::<notes::NoteInfo>("get", "/api/v1/notes/{id}", "200");
+    bodies.request::<notes::UpdateNoteRequest>("put", "/api/v1/notes/{id}");
+    bodies.response::<notes::MessageResponse>("put", "/api/v1/notes/{id}", "200");
+    bodies.response::<notes::MessageResponse>("delete", "/api/v1/notes/{id}", "200");
+    bodies.response::<notes::NoteCardsResponse>("get", "/api/v1/notes/{id}/cards", "200");
+
+    bodies.response::<notetypes::NotetypesListResponse>("get", "/api/v1/notetypes", "200");
+    bodies.response::<notetypes::NotetypeInfo>("get", "/api/v1/notetypes/{id}", "200");
+
+    bodies.response::<cards::CardInfo>("get", "/api/v1/cards/{id}", "200");
+    bodies.request::<cards::UpdateCardRequest>("put", "/api/v1/cards/{id}");
+    bodies.response::<cards::MessageResponse>("put", "/api/v1/cards/{id}", "200");
+    bodies.response::<cards::MessageResponse>("delete", "/api/v1/cards/{id}", "200");
+    bodies.request::<cards::FlagCardRequest>("post", "/api/v1/cards/{id}/flag");
+    bodies.response::<cards::MessageResponse>("post", "/api/v1/cards/{id}/flag", "200");
+    bodies.response::<cards::MessageResponse>("post", "/api/v1/cards/{id}/suspend", "200");
+    bodies.response::<cards::MessageResponse>("post", "/api/v1/cards/{id}/unsuspend", "200");
+    bodies.response::<cards::MessageResponse>("post", "/api/v1/cards/{id}/bury", "200");
+    bodies.request::<cards::BatchGetCardsRequest>("post", "/api/v1/cards/batch");
+    bodies.response::<cards::BatchGetCardsResponse>("post", "/api/v1/cards/batch", "200");
+    bodies.request::<cards::BatchUpdateCardsRequest>("post", "/api/v1/cards/batch-update");
+    bodies.response::<cards::BatchUpdateCardsResponse>("post", "/api/v1/cards/batch-update", "200");
+
+    bodies.request::<search::SearchCardsRequest>("post", "/api/v1/search/cards");
+    bodies.response::<search::SearchCardsResponse>("post", "/api/v1/search/cards", "200");
+    bodies.request::<search::SearchNotesRequest>("post", "/api/v1/search/notes");
+    bodies.response::<search::SearchNotesResponse>("post", "/api/v1/search/notes", "200");
+
+    bodies.request::<browse::BrowseRequest>("post", "/api/v1/browse/cards");
+    bodies.response::<browse::CardBrowseResponse>("post", "/api/v1/browse/cards", "200");
+    bodies.request::<browse::BrowseRequest>("post", "/api/v1/browse/notes");
+    bodies.response::<browse::NoteBrowseResponse>("post", "/api/v1/browse/notes", "200");
+
+    bodies.request::<search::FindAndReplaceRequest>("post", "/api/v1/search/find-replace");
+    bodies.response::<search::FindAndReplaceResponse>("post", "/api/v1/search/find-replace", "200");
+
+    bodies.response::<media::CheckMediaResponse>("get", "/api/v1/media/check", "200");
+    bodies.response::<media::ListMediaResponse>("get", "/api/v1/media/files", "200");
+    bodies.response::<media::MediaTrashResponse>("post", "/api/v1/media/trash/empty", "200");
+    bodies.response::<media::MediaTrashResponse>("post", "/api/v1/media/trash/restore", "200");
+    bodies.response::<maintenance::JobStartedResponse>("post", "/api/v1/media/latex/render", "202");
+    bodies.response::<media::AddMediaResponse>("post", "/api/v1/media", "200");
+    bodies.request::<media::DeleteMediaRequest>("delete", "/api/v1/media");
+    bodies.response::<media::DeleteMediaResponse>("delete", "/api/v1/media", "200");
+
+    bodies.response::<image_occlusion::OcclusionImageResponse>(
+        "post",
+        "/api/v1/image-occlusion/images",
+        "200",
+    );
+    bodies.request::<image_occlusion::CreateOcclusionNoteRequest>(
+        "post",
+        "/api/v1/image-occlusion/notes",
+    );
+    bodies.response::<image_occlusion::CreateOcclusionNoteResponse>(
+        "post",
+        "/api/v1/image-occlusion/notes",
+        "201",
+    );
+    bodies.response::<image_occlusion::OcclusionNoteResponse>(
+        "get",
+        "/api/v1/image-occlusion/notes/{id}",
+        "200",
+    );
+    bodies.request::<image_occlusion::UpdateOcclusionNoteRequest>(
+        "put",
+        "/api/v1/image-occlusion/notes/{id}",
+    );
+    bodies.response::<notes::MessageResponse>("put", "/api/v1/image-occlusion/notes/{id}", "200");
+
+    bodies.response::<import_export::WebImportResponse>("post", "/api/v1/import/apkg", "201");
+
+    bodies.response::<tags::TagsListResponse>("get", "/api/v1/tags", "200");
+    bodies.response::<tags::TagTreeResponse>("get", "/api/v1/tags/tree", "200");
+    bodies.request::<tags::RenameTagRequest>("put", "/api/v1/tags/rename");
+    bodies.response::<tags::RenameTagResponse>("put", "/api/v1/tags/rename", "200");
+    bodies.response::<tags::TagsListResponse>("get", "/api/v1/tags/complete", "200");
+    bodies.request::<tags::FindReplaceTagsRequest>("post", "/api/v1/tags/find-replace");
+    bodies.response::<tags::TagOpResponse>("post", "/api/v1/tags/find-replace", "200");
+    bodies.request::<tags::ReparentTagsRequest>("post", "/api/v1/tags/reparent");
+    bodies.response::<tags::TagOpResponse>("post", "/api/v1/tags/reparent", "200");
+    bodies.request::<tags::SetTagCollapsedRequest>("put", "/api/v1/tags/collapse");
+    bodies.response::<notes::MessageResponse>("put", "/api/v1/tags/collapse", "200");
+    bodies.request::<tags::BulkTagsRequest>("post", "/api/v1/tags/notes/add");
+    bodies.response::<tags::TagOpResponse>("post", "/api/v1/tags/notes/add", "200");
+    bodies.request::<tags::BulkTagsRequest>("post", "/api/v1/tags/notes/remove");
+    bodies.response::<tags::TagOpResponse>("post", "/api/v1/tags/notes/remove", "200");
+    bodies.response::<tags::DeleteTagResponse>("delete", "/api/v1/tags/{name}", "200");
+    bodies.response::<tags::ClearUnusedTagsResponse>("post", "/api/v1/tags/clear-unused", "200");
+
+    bodies.response::<stats::CardStatsResponse>("get", "/api/v1/stats/card/{id}", "200");
+    bodies.response::<stats::CollectionStatsResponse>("get", "/api/v1/stats/collection", "200");
+    bodies.response::<stats::TodayStatsResponse>("get", "/api/v1/stats/today", "200");
+
+    bodies.response::<scheduler::NextCardResponse>(
+        "get",
+        "/api/v1/scheduler/decks/{deck_id}/next",
+        "200",
+    );
+    bodies.response::<scheduler::PrefetchResponse>(
+        "get",
+        "/api/v1/scheduler/decks/{deck_id}/queue",
+        "200",
+    );
+    bodies.request::<scheduler::BatchAnswerRequest>(
+        "post",
+        "/api/v1/scheduler/decks/{deck_id}/answers",
+    );
+    bodies.response::<scheduler::BatchAnswerResponse>(
+        "post",
+        "/api/v1/scheduler/decks/{deck_id}/answers",
+        "200",
+    );
+    bodies.request::<scheduler::AnswerCardRequest>(
+        "post",
+        "/api/v1/scheduler/decks/{deck_id}/cards/{card_id}/answer",
+    );
+    bodies.response::<scheduler::AnswerCardResponse>(
+        "post",
+        "/api/v1/scheduler/decks/{deck_id}/cards/{card_id}/answer",
+        "200",
+    );
+    bodies.request::<scheduler::AnswerWithStatesRequest>(
+        "post",
+        "/api/v1/scheduler/decks/{deck_id}/cards/{card_id}/answer-with-states",
+    );
+    bodies.response::<scheduler::AnswerCardResponse>(
+        "post",
+        "/api/v1/scheduler/decks/{deck_id}/cards/{card_id}/answer-with-states",
+        "200",
+    );
+    bodies.response::<scheduler::NextStatesResponse>(
+        "get",
+        "/api/v1/scheduler/decks/{deck_id}/cards/{card_id}/next-states",
+        "200",
+    );
+    bodies.response::<scheduler::StudyCounts>(
+        "get",
+        "/api/v1/scheduler/decks/{deck_id}/counts",
+        "200",
+    );
+    bodies.response::<scheduler::CongratsInfo>(
+        "get",
+        "/api/v1/scheduler/decks/{deck_id}/congrats",
+        "200",
+    );
+    bodies.response::<scheduler::UndoResponse>("post", "/api/v1/scheduler/undo", "200");
+    bodies.response::<scheduler::UndoResponse>("post", "/api/v1/scheduler/redo", "200");
+    bodies.response::<scheduler::UndoStatusResponse>("get", "/api/v1/scheduler/undo-status", "200");
+
+    bodies.response::<preferences::PreferencesResponse>("get", "/api/v1/preferences", "200");
+    bodies.request::<preferences::UpdatePreferencesRequest>("put", "/api/v1/preferences");
+    bodies.response::<preferences::PreferencesResponse>("put", "/api/v1/preferences", "200");
+
+    bodies.response::<preferences::ConfigListResponse>("get", "/api/v1/config", "200");
+    bodies.response::<preferences::ConfigEntryResponse>("get", "/api/v1/config/{key}", "200");
+    bodies.request::<preferences::SetConfigRequest>("put", "/api/v1/config/{key}");
+    bodies.response::<preferences::ConfigEntryResponse>("put", "/api/v1/config/{key}", "200");
+
+    bodies.response::<sharing::SharedDecksListResponse>("get", "/api/v1/shared-decks", "200");
+    bodies.request::<sharing::PublishDeckRequest>("post", "/api/v1/shared-decks");
+    bodies.response::<SharedDeck>("post", "/api/v1/shared-decks", "200");
+    bodies.response::<SharedDeck>("post", "/api/v1/shared-decks", "201");
+    bodies.response::<sharing::SubscriptionsListResponse>(
+        "get",
+        "/api/v1/shared-decks/subscriptions",
+        "200",
+    );
+    bodies.response::<sharing::SharingMessageResponse>(
+        "delete",
+        "/api/v1/shared-decks/{id}",
+        "200",
+    );
+    bodies.response::<sharing::SharedDeckImportResponse>(
+        "post",
+        "/api/v1/shared-decks/{id}/subscription",
+        "201",
+    );
+    bodies.response::<sharing::SharingMessageResponse>(
+        "delete",
+        "/api/v1/shared-decks/{id}/subscription",
+        "200",
+    );
+    bodies.response::<sharing::SharedDeckImportResponse>(
+        "post",
+        "/api/v1/shared-decks/{id}/update",
+        "200",
+    );
+
+    bodies.response::<fsrs::FsrsPresetsResponse>("get", "/api/v1/fsrs/presets", "200");
+    bodies.request::<fsrs::OptimizeParamsRequest>("post", "/api/v1/fsrs/presets/{id}/optimize");
+    bodies.response::<maintenance::JobStartedResponse>(
+        "post",
+        "/api/v1/fsrs/presets/{id}/optimize",
+        "202",
+    );
+    bodies.request::<fsrs::EvaluateParamsRequest>("post", "/api/v1/fsrs/presets/{id}/evaluate");
+    bodies.response::<maintenance::JobStartedResponse>(
+        "post",
+        "/api/v1/fsrs/presets/{id}/evaluate",
+        "202",
+    );
+    bodies.request::<fsrs::SimulateWorkloadRequest>("post", "/api/v1/fsrs/presets/{id}/simulate");
+    bodies.response::<maintenance::JobStartedResponse>(
+        "post",
+        "/api/v1/fsrs/presets/{id}/simulate",
+        "202",
+    );
+    bodies
+        .request::<fsrs::SimulationOptions>("post", "/api/v1/fsrs/presets/{id}/optimal-retention");
+    bodies.response::<maintenance::JobStartedResponse>(
+        "post",
+        "/api/v1/fsrs/presets/{id}/optimal-retention",
+        "202",
+    );
+    bodies.request::<fsrs::SaveParamsRequest>("put", "/api/v1/fsrs/presets/{id}/params");
+    bodies.response::<maintenance::JobStartedResponse>(
+        "put",
+        "/api/v1/fsrs/presets/{id}/params",
+        "202",
+    );
+
+    // Referenced by the error responses, and by the descriptions of
+    // operations that start background jobs
+    bodies.schema::<ErrorResponse>();
+    bodies.schema::<maintenance::CheckDatabaseResult>();
+    bodies.schema::<maintenance::EmptyCardsResult>();
+    bodies.schema::<maintenance::FindDuplicatesResult>();
+    bodies.schema::<media::RenderLatexResult>();
+    bodies.schema::<fsrs::OptimizeParamsResult>();
+    bodies.schema::<fsrs::EvaluateParamsResult>();
+    bodies.schema::<fsrs::SimulateWorkloadResult>();
+    bodies.schema::<fsrs::OptimalRetentionResult>();
+    bodies.schema::<fsrs::SaveParamsResult>();
+
+    bodies.finish();
+}
+
+struct BodySchemas<'a> {
+    spec: &'a mut Value,
+    generator: SchemaGenerator,
+}
+
+impl BodySchemas<'_> {
+    fn request<T: JsonSchema>(&mut self, method: &str, path: &str) {
+        let schema = self.generator.subschema_for::<T>();
+        let body = &mut self.operation(method, path)["requestBody"];
+        assert!(
+            body.is_object(),
+            "{method} {path} does not document a request body"
+        );
+        body["content"]["application/json"]["schema"] = json!(schema);
+    }
+
+    fn response<T: JsonSchema>(&mut self, method: &str, path: &str, status: &str) {
+        let schema = self.generator.subschema_for::<T>();
+        let response = &mut self.operation(method, path)["responses"][status];
+        assert!(
+            response.is_object(),
+            "{method} {path} does not document a {status} response"
+        );
+        response["content"]["application/json"]["schema"] = json!(schema);
+    }
+
+    fn schema<T: JsonSchema>(&mut self) {
+        self.generator.subschema_for::<T>();
+    }
+
+    fn operation(&mut self, method: &str, path: &str) -> &mut Value {
+        let operation = &mut self.spec["paths"][path][method];
+        assert!(operation.is_object(), "{method} {path} is not documented");
+        operation
+    }
+
+    fn finish(mut self) {
+        let definitions = self.generator.take_definitions();
+        self.spec["components"]["schemas"] = json!(definitions);
+    }
+}
+
+fn maintenance_section() -> Value {
+    json!({
+        "paths": {
+            "/api/v1/collection/check-database": {
+                "post": {
+                    "tags": ["collection"],
+                    "summary": "Check and repair the collection database",
+                    "description": "Starts a background job. Poll `GET /api/v1/jobs/{id}` for the list of problems found.",
+                    "operationId": "checkDatabase",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "202": {
+                            "description": "Job started"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "CreateDeckRequest": {
-                    "type": "object",
-                    "required": ["name"],
-                    "properties": {
-                        "name": { "type": "string", "example": "Spanish" }
+                }
+            },
+            "/api/v1/collection/empty-cards": {
+                "post": {
+                    "tags": ["collection"],
+                    "summary": "Build an empty cards report",
+                    "description": "Starts a background job whose result is an `EmptyCardsResult`.",
+                    "operationId": "getEmptyCards",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "202": {
+                            "description": "Job started"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
                 },
-                "CreateNoteRequest": {
-                    "type": "object",
-                    "required": ["deck_id", "notetype_id", "fields", "tags"],
-                    "properties": {
-                        "deck_id": { "type": "integer", "format": "int64", "example": 1 },
-                        "notetype_id": { "type": "integer", "format": "int64", "example": 1 },
-                        "fields": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "example": ["hola", "hello"]
-                        },
-                        "tags": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "example": ["vocab"]
-                        }
+                "delete": {
+                    "tags": ["collection"],
+                    "summary": "Delete empty cards",
+                    "description": "Rebuilds the empty cards report and deletes the given cards that it lists. Other cards are skipped and returned in `skipped_card_ids`. Unless `keep_notes` is false, a note whose cards are all empty keeps its first card.",
+                    "operationId": "deleteEmptyCards",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": {
+                        "200": { "description": "Cards deleted" },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "UpdateNoteRequest": {
-                    "type": "object",
-                    "required": ["fields", "tags"],
-                    "properties": {
-                        "fields": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "example": ["hola", "hello (greeting)"]
-                        },
-                        "tags": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "example": ["vocab", "greetings"]
-                        }
+                }
+            },
+            "/api/v1/notes/find-duplicates": {
+                "post": {
+                    "tags": ["notes"],
+                    "summary": "Find notes with duplicate field content",
+                    "description": "Starts a background job whose result is a `FindDuplicatesResult`.",
+                    "operationId": "findDuplicates",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": {
+                        "202": {
+                            "description": "Job started"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "Note": {
-                    "type": "object",
-                    "properties": {
-                        "id": { "type": "integer", "format": "int64" },
-                        "notetype_id": { "type": "integer", "format": "int64" },
-                        "fields": {
-                            "type": "array",
-                            "items": { "type": "string" }
-                        },
-                        "tags": {
-                            "type": "array",
-                            "items": { "type": "string" }
-                        }
+                }
+            },
+            "/api/v1/jobs": {
+                "get": {
+                    "tags": ["jobs"],
+                    "summary": "List background jobs of the current user",
+                    "operationId": "listJobs",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "200": { "description": "Jobs, newest first" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "CardBrowseRow": {
-                    "type": "object",
-                    "properties": {
-                        "card_id": { "type": "integer", "format": "int64" },
-                        "note_id": { "type": "integer", "format": "int64" },
-                        "sort_field": { "type": "string", "description": "First field of the note" },
-                        "card_type": { "type": "string", "description": "Template name (e.g. 'Forward')" },
-                        "due": { "type": "string", "description": "Human-readable due date (e.g. 'Today', 'In 3 days', '#5')" },
-                        "deck": { "type": "string", "description": "Deck human name" }
+                }
+            },
+            "/api/v1/jobs/{id}": {
+                "get": {
+                    "tags": ["jobs"],
+                    "summary": "Get background job status and result",
+                    "operationId": "getJob",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [
+                        { "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Job info"
+                        },
+                        "404": { "$ref": "#/components/responses/NotFound" }
                     }
-                },
-                "NoteBrowseRow": {
-                    "type": "object",
-                    "properties": {
-                        "note_id": { "type": "integer", "format": "int64" },
-                        "sort_field": { "type": "string", "description": "First field of the note" },
-                        "notetype": { "type": "string", "description": "Note type name" },
-                        "cards": { "type": "integer", "description": "Number of cards generated from this note" },
-                        "tags": { "type": "string", "description": "Space-separated tag list" }
+                }
+            }
+        }
+    })
+}
+
+fn backups_section() -> Value {
+    json!({
+        "paths": {
+            "/api/v1/collection/backups": {
+                "get": {
+                    "tags": ["collection"],
+                    "summary": "List collection backups",
+                    "operationId": "listBackups",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "200": {
+                            "description": "Backups, newest first"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
                 },
-                "CardInfo": {
-                    "type": "object",
-                    "properties": {
-                        "id": { "type": "integer", "format": "int64" },
-                        "note_id": { "type": "integer", "format": "int64" },
-                        "deck_id": { "type": "integer", "format": "int64" },
-                        "ordinal": { "type": "integer", "format": "uint16" },
-                        "card_type": { "type": "integer", "format": "uint8", "description": "0=New, 1=Learn, 2=Review, 3=Relearn" },
-                        "queue": { "type": "integer", "format": "int8", "description": "Card queue state" },
-                        "due": { "type": "integer", "format": "int32" },
-                        "interval": { "type": "integer", "format": "uint32" },
-                        "ease_factor": { "type": "integer", "format": "uint16" },
-                        "reps": { "type": "integer", "format": "uint32" },
-                        "lapses": { "type": "integer", "format": "uint32" },
-                        "flags": { "type": "integer", "format": "uint8", "description": "0=None, 1=Red, 2=Orange, 3=Green, 4=Blue" }
+                "post": {
+                    "tags": ["collection"],
+                    "summary": "Create a backup now",
+                    "description": "Starts a background job. No backup is written if the collection is unchanged since the last one.",
+                    "operationId": "createBackup",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "202": {
+                            "description": "Job started"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "UpdateCardRequest": {
-                    "type": "object",
-                    "properties": {
-                        "deck_id": { "type": "integer", "format": "int64", "nullable": true },
-                        "due": { "type": "integer", "format": "int32", "nullable": true },
-                        "flags": { "type": "integer", "format": "uint8", "nullable": true }
+                }
+            },
+            "/api/v1/collection/backups/{filename}/restore": {
+                "post": {
+                    "tags": ["collection"],
+                    "summary": "Restore a backup",
+                    "description": "Starts a background job that backs up the current collection, then replaces it with the given backup.",
+                    "operationId": "restoreBackup",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [
+                        { "name": "filename", "in": "path", "required": true, "schema": { "type": "string" } }
+                    ],
+                    "responses": {
+                        "202": {
+                            "description": "Job started"
+                        },
+                        "404": { "$ref": "#/components/responses/NotFound" }
                     }
-                },
-                "FlagCardRequest": {
-                    "type": "object",
-                    "required": ["flag"],
-                    "properties": {
-                        "flag": {
-                            "type": "integer",
-                            "format": "uint8",
-                            "minimum": 0,
-                            "maximum": 4,
-                            "description": "0=None, 1=Red, 2=Orange, 3=Green, 4=Blue",
-                            "example": 1
+                }
+            }
+        }
+    })
+}
+
+fn metrics_section() -> Value {
+    json!({
+        "paths": {
+            "/metrics": {
+                "get": {
+                    "tags": ["health"],
+                    "summary": "Prometheus metrics",
+                    "description": "Request counts and latency per route, open collections, collection lock wait time, reviews answered, imports and authentication failures, in the Prometheus text format.",
+                    "operationId": "getMetrics",
+                    "responses": {
+                        "200": {
+                            "description": "Metrics in the Prometheus text exposition format",
+                            "content": {
+                                "text/plain": {
+                                    "schema": { "type": "string" }
+                                }
+                            }
                         }
                     }
-                },
-                "BatchGetCardsRequest": {
-                    "type": "object",
-                    "required": ["card_ids"],
-                    "properties": {
-                        "card_ids": {
-                            "type": "array",
-                            "items": { "type": "integer", "format": "int64" },
-                            "example": [1, 2, 3]
-                        }
+                }
+            }
+        }
+    })
+}
+
+fn audit_section() -> Value {
+    let string_filter = |name: &str, description: &str| {
+        json!({
+            "name": name,
+            "in": "query",
+            "required": false,
+            "description": description,
+            "schema": { "type": "string" }
+        })
+    };
+    let integer_filter = |name: &str, description: &str| {
+        json!({
+            "name": name,
+            "in": "query",
+            "required": false,
+            "description": description,
+            "schema": { "type": "integer", "format": "int64" }
+        })
+    };
+
+    json!({
+        "paths": {
+            "/api/v1/audit": {
+                "get": {
+                    "tags": ["auth"],
+                    "summary": "Query the audit log",
+                    "description": "Lists the current user's mutating requests, newest first. Entries older than the configured retention period are pruned.",
+                    "operationId": "getAuditLog",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [
+                        string_filter("session_id", "Only requests made with this session"),
+                        string_filter("client_ip", "Only requests from this IP"),
+                        string_filter("method", "Only requests with this HTTP method"),
+                        string_filter("route", "Only requests to this route pattern, e.g. /api/v1/decks/{id}"),
+                        string_filter("op", "Only requests that performed this collection operation, e.g. RemoveDeck"),
+                        integer_filter("since", "Only entries created at or after this Unix timestamp"),
+                        integer_filter("until", "Only entries created before this Unix timestamp"),
+                        integer_filter("limit", "Maximum entries to return (default 100, max 1000)"),
+                        integer_filter("offset", "Entries to skip")
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Audit entries"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "BatchUpdateCardsRequest": {
-                    "type": "object",
-                    "required": ["updates"],
-                    "properties": {
-                        "updates": {
-                            "type": "array",
-                            "items": { "$ref": "#/components/schemas/BatchCardUpdate" }
+                }
+            }
+        }
+    })
+}
+
+fn media_section() -> Value {
+    let trash_response = json!({ "description": "Trash processed" });
+
+    json!({
+        "paths": {
+            "/api/v1/media/files": {
+                "get": {
+                    "tags": ["media"],
+                    "summary": "List media files",
+                    "description": "Lists the files in the media folder by name, with their size and the notes that reference them. Files with no referencing notes are unused.",
+                    "operationId": "listMedia",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [
+                        {
+                            "name": "limit",
+                            "in": "query",
+                            "required": false,
+                            "description": "Maximum files to return (default 100, max 1000)",
+                            "schema": { "type": "integer" }
+                        },
+                        {
+                            "name": "offset",
+                            "in": "query",
+                            "required": false,
+                            "description": "Files to skip",
+                            "schema": { "type": "integer" }
                         }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "A page of media files"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "BatchCardUpdate": {
-                    "type": "object",
-                    "required": ["card_id"],
-                    "properties": {
-                        "card_id": { "type": "integer", "format": "int64" },
-                        "deck_id": { "type": "integer", "format": "int64", "nullable": true },
-                        "due": { "type": "integer", "format": "int32", "nullable": true },
-                        "flags": { "type": "integer", "format": "uint8", "nullable": true }
+                }
+            },
+            "/api/v1/media/trash/empty": {
+                "post": {
+                    "tags": ["media"],
+                    "summary": "Permanently delete the files in the media trash",
+                    "operationId": "emptyMediaTrash",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "200": trash_response.clone(),
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "MessageResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": { "type": "boolean", "example": true },
-                        "message": { "type": "string", "example": "Operation successful" }
+                }
+            },
+            "/api/v1/media/trash/restore": {
+                "post": {
+                    "tags": ["media"],
+                    "summary": "Move the files in the media trash back into the media folder",
+                    "operationId": "restoreMediaTrash",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "200": trash_response,
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "SearchCardsRequest": {
-                    "type": "object",
-                    "required": ["query"],
-                    "properties": {
-                        "query": {
-                            "type": "string",
-                            "description": "Search query using Anki search syntax",
-                            "example": "deck:Spanish is:due"
-                        },
-                        "sort_column": {
-                            "type": "string",
-                            "nullable": true,
-                            "description": "Column to sort by (e.g., 'noteFld', 'cardDue')",
-                            "example": "cardDue"
-                        },
-                        "reverse": {
-                            "type": "boolean",
-                            "default": false,
-                            "description": "Sort in reverse order"
+                }
+            },
+            "/api/v1/media/latex/render": {
+                "post": {
+                    "tags": ["media"],
+                    "summary": "Generate missing LaTeX images",
+                    "description": "Starts a background job that compiles LaTeX in notes whose image is not in the media folder. Only available if the server sets `latex_rendering`, and requires `latex`, and `dvipng` or `dvisvgm`, on the server. Commands that access files or run programs are rejected, and each run is confined to a temporary folder and killed after 10 seconds. Poll `GET /api/v1/jobs/{id}` for a `RenderLatexResult`.",
+                    "operationId": "renderMissingLatex",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "202": {
+                            "description": "Job started"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "403": {
+                            "description": "LaTeX rendering is not enabled on this server",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/ErrorResponse" }
+                                }
+                            }
                         }
                     }
-                },
-                "SearchCardsResponse": {
-                    "type": "object",
-                    "properties": {
-                        "card_ids": {
-                            "type": "array",
-                            "items": { "type": "integer", "format": "int64" },
-                            "description": "IDs of cards matching the query"
-                        },
-                        "count": {
-                            "type": "integer",
-                            "description": "Number of cards found"
+                }
+            }
+        }
+    })
+}
+
+fn image_occlusion_section() -> Value {
+    let note_id = json!({
+        "name": "id",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "integer", "format": "int64" },
+        "description": "Note ID"
+    });
+
+    json!({
+        "paths": {
+            "/api/v1/image-occlusion/images": {
+                "post": {
+                    "tags": ["image-occlusion"],
+                    "summary": "Upload an image to occlude",
+                    "description": "Adds the image to the media folder and returns the name it was stored under, with its size if the format can be decoded.",
+                    "operationId": "uploadOcclusionImage",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "multipart/form-data": {
+                                "schema": {
+                                    "type": "object",
+                                    "required": ["file"],
+                                    "properties": {
+                                        "file": { "type": "string", "format": "binary" }
+                                    }
+                                }
+                            }
                         }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "Image stored"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "SearchNotesRequest": {
-                    "type": "object",
-                    "required": ["query"],
-                    "properties": {
-                        "query": {
-                            "type": "string",
-                            "description": "Search query using Anki search syntax",
-                            "example": "tag:important added:7"
-                        },
-                        "sort_column": {
-                            "type": "string",
-                            "nullable": true,
-                            "description": "Column to sort by",
-                            "example": "noteCrt"
-                        },
-                        "reverse": {
-                            "type": "boolean",
-                            "default": false,
-                            "description": "Sort in reverse order"
-                        }
+                }
+            },
+            "/api/v1/image-occlusion/notes": {
+                "post": {
+                    "tags": ["image-occlusion"],
+                    "summary": "Create an image occlusion note",
+                    "description": "Creates one card per distinct shape ordinal. If no notetype is given, the first image occlusion notetype is used, and added if needed.",
+                    "operationId": "createOcclusionNote",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": {
+                        "201": {
+                            "description": "Note created"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
                     }
-                },
-                "SearchNotesResponse": {
-                    "type": "object",
-                    "properties": {
-                        "note_ids": {
-                            "type": "array",
-                            "items": { "type": "integer", "format": "int64" },
-                            "description": "IDs of notes matching the query"
-                        },
-                        "count": {
-                            "type": "integer",
-                            "description": "Number of notes found"
-                        }
+                }
+            },
+            "/api/v1/image-occlusion/notes/{id}": {
+                "get": {
+                    "tags": ["image-occlusion"],
+                    "summary": "Get an image occlusion note's shapes for editing",
+                    "operationId": "getOcclusionNote",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [note_id.clone()],
+                    "responses": {
+                        "200": {
+                            "description": "The note's image, shapes and text fields"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
                     }
                 },
-                "FindAndReplaceRequest": {
-                    "type": "object",
-                    "required": ["note_ids", "search", "replacement"],
-                    "properties": {
-                        "note_ids": {
-                            "type": "array",
-                            "items": { "type": "integer", "format": "int64" },
-                            "description": "Note IDs to search in (empty array = all notes)"
-                        },
-                        "search": {
-                            "type": "string",
-                            "description": "Text to search for",
-                            "example": "color"
-                        },
-                        "replacement": {
-                            "type": "string",
-                            "description": "Replacement text",
-                            "example": "colour"
-                        },
-                        "regex": {
-                            "type": "boolean",
-                            "default": false,
-                            "description": "Treat search as regex pattern"
-                        },
-                        "match_case": {
-                            "type": "boolean",
-                            "default": false,
-                            "description": "Case-sensitive matching"
-                        },
-                        "field_name": {
-                            "type": "string",
-                            "nullable": true,
-                            "description": "Limit to specific field (empty = all fields)",
-                            "example": "Front"
-                        }
+                "put": {
+                    "tags": ["image-occlusion"],
+                    "summary": "Replace an image occlusion note's shapes",
+                    "description": "The header, back extra and tags are left unchanged if omitted. The image can't be changed.",
+                    "operationId": "updateOcclusionNote",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [note_id],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "Note updated"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
                     }
-                },
-                "FindAndReplaceResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": { "type": "boolean" },
-                        "message": { "type": "string" },
-                        "replaced_count": {
-                            "type": "integer",
-                            "description": "Number of notes modified"
+                }
+            }
+        }
+    })
+}
+
+fn bulk_notes_section() -> Value {
+    json!({
+        "paths": {
+            "/api/v1/notes/bulk": {
+                "post": {
+                    "tags": ["notes"],
+                    "summary": "Create many notes at once",
+                    "description": "Adds up to 10000 notes in a single transaction and undo step. A note whose first field matches an existing note of the same notetype, or one earlier in the request, is handled according to `duplicate_policy`. Notes with an unknown notetype or deck, or too many fields, are reported as invalid and not added.",
+                    "operationId": "createNotesBulk",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "One result per note, in request order"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
+                    }
+                }
+            }
+        }
+    })
+}
+
+fn tag_ops_section() -> Value {
+    let tag_op_responses = json!({
+        "200": { "description": "Number of notes changed" },
+        "400": { "$ref": "#/components/responses/BadRequest" },
+        "401": { "$ref": "#/components/responses/Unauthorized" }
+    });
+    let bulk_tags_body = json!({ "required": true });
+
+    json!({
+        "paths": {
+            "/api/v1/tags/notes/add": {
+                "post": {
+                    "tags": ["tags"],
+                    "summary": "Add tags to notes",
+                    "operationId": "addTagsToNotes",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": bulk_tags_body,
+                    "responses": tag_op_responses
+                }
+            },
+            "/api/v1/tags/notes/remove": {
+                "post": {
+                    "tags": ["tags"],
+                    "summary": "Remove tags from notes",
+                    "operationId": "removeTagsFromNotes",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": bulk_tags_body,
+                    "responses": tag_op_responses
+                }
+            },
+            "/api/v1/tags/find-replace": {
+                "post": {
+                    "tags": ["tags"],
+                    "summary": "Find and replace within the tags of notes",
+                    "description": "Returns 400 if the regex is invalid or the replacement contains a space.",
+                    "operationId": "findAndReplaceTags",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": tag_op_responses
+                }
+            },
+            "/api/v1/tags/reparent": {
+                "post": {
+                    "tags": ["tags"],
+                    "summary": "Move tags under a new parent",
+                    "description": "Child tags move with their parents. Omit `new_parent` to move the tags to the top level.",
+                    "operationId": "reparentTags",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": tag_op_responses
+                }
+            },
+            "/api/v1/tags/complete": {
+                "get": {
+                    "tags": ["tags"],
+                    "summary": "Autocomplete tags",
+                    "description": "Each `::` separated component of the input matches tags containing it, with prefix matches listed first.",
+                    "operationId": "completeTags",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [
+                        {
+                            "name": "input",
+                            "in": "query",
+                            "required": true,
+                            "schema": { "type": "string" }
+                        },
+                        {
+                            "name": "limit",
+                            "in": "query",
+                            "schema": { "type": "integer", "default": 20, "minimum": 1, "maximum": 100 }
                         }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Matching tags"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "CheckMediaResponse": {
-                    "type": "object",
-                    "properties": {
-                        "unused": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "description": "Files in media folder not referenced by any note"
-                        },
-                        "missing": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "description": "Files referenced by notes but not in media folder"
-                        },
-                        "missing_media_notes": {
-                            "type": "array",
-                            "items": { "type": "integer", "format": "int64" },
-                            "description": "Note IDs that reference missing media"
-                        },
-                        "report": { "type": "string", "description": "Human-readable summary report" },
-                        "have_trash": { "type": "boolean", "description": "Whether the trash folder contains files" }
+                }
+            },
+            "/api/v1/tags/collapse": {
+                "put": {
+                    "tags": ["tags"],
+                    "summary": "Collapse or expand a tag in the tag tree",
+                    "operationId": "setTagCollapsed",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "State saved"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
                     }
-                },
-                "AddMediaResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": { "type": "boolean", "example": true },
-                        "filename": { "type": "string", "description": "Actual filename used (may differ from requested if conflict)", "example": "image.jpg" }
+                }
+            }
+        }
+    })
+}
+
+fn deck_ops_section() -> Value {
+    json!({
+        "paths": {
+            "/api/v1/decks/reparent": {
+                "post": {
+                    "tags": ["decks"],
+                    "summary": "Move decks under another deck",
+                    "description": "Child decks move with their parents. Omit `new_parent_id` to move the decks to the top level. Dropping a deck onto itself or one of its children is ignored.",
+                    "operationId": "reparentDecks",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "Number of decks moved"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
+                    }
+                }
+            },
+            "/api/v1/decks/{id}/collapse": {
+                "put": {
+                    "tags": ["decks"],
+                    "summary": "Collapse or expand a deck",
+                    "operationId": "setDeckCollapsed",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [{ "$ref": "#/components/parameters/DeckId" }],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "State saved"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
+                    }
+                }
+            },
+            "/api/v1/decks/{id}/stats": {
+                "get": {
+                    "tags": ["decks"],
+                    "summary": "Get due counts and today's progress for a deck",
+                    "operationId": "getDeckStats",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [{ "$ref": "#/components/parameters/DeckId" }],
+                    "responses": {
+                        "200": {
+                            "description": "Deck statistics"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
+                    }
+                }
+            }
+        }
+    })
+}
+
+fn scheduling_section() -> Value {
+    let deck_id_param = json!({
+        "name": "deck_id",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "integer", "format": "int64" },
+        "description": "Deck ID"
+    });
+
+    json!({
+        "paths": {
+            "/api/v1/scheduler/decks/{deck_id}/queue": {
+                "get": {
+                    "tags": ["scheduler"],
+                    "summary": "Prefetch the next cards in a deck's queue",
+                    "description": "Returns up to `limit` queued cards, rendered and with their scheduling states, so they can be studied without a round trip per card. Answer them with answer-with-states, or upload answers made offline to the answers endpoint.",
+                    "operationId": "prefetchCards",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [
+                        deck_id_param,
+                        {
+                            "name": "limit",
+                            "in": "query",
+                            "required": false,
+                            "schema": { "type": "integer", "default": 20, "maximum": 200 },
+                            "description": "Maximum number of cards to return"
+                        }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Queued cards"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
+                    }
+                }
+            },
+            "/api/v1/scheduler/decks/{deck_id}/answers": {
+                "post": {
+                    "tags": ["scheduler"],
+                    "summary": "Upload answers recorded offline",
+                    "description": "Applies up to 1000 answers in the order given. Each answer is applied on its own and reported in `results`; an answer whose card was modified since its states were fetched is reported as a conflict.",
+                    "operationId": "answerCardsBatch",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [deck_id_param],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "Per-card results"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "DeleteMediaRequest": {
-                    "type": "object",
-                    "required": ["filenames"],
-                    "properties": {
-                        "filenames": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "description": "List of filenames to move to trash",
-                            "example": ["image.jpg", "audio.mp3"]
+                }
+            },
+            "/api/v1/scheduler/decks/{deck_id}/cards/{card_id}/answer-with-states": {
+                "post": {
+                    "tags": ["scheduler"],
+                    "summary": "Answer a card with client-held scheduling states",
+                    "description": "Answers any card in the deck or its children, not just the head of the queue, using the `states` returned by next-states. The states may be adjusted by a custom scheduler, but `current` must still match the card, or 409 is returned.",
+                    "operationId": "answerCardWithStates",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [
+                        {
+                            "name": "deck_id",
+                            "in": "path",
+                            "required": true,
+                            "schema": { "type": "integer", "format": "int64" },
+                            "description": "Deck ID"
+                        },
+                        {
+                            "name": "card_id",
+                            "in": "path",
+                            "required": true,
+                            "schema": { "type": "integer", "format": "int64" },
+                            "description": "Card ID to answer"
                         }
-                    }
-                },
-                "DeleteMediaResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": { "type": "boolean", "example": true },
-                        "message": { "type": "string", "example": "Moved 2 file(s) to trash" }
-                    }
-                },
-                "TagsListResponse": {
-                    "type": "object",
-                    "properties": {
-                        "tags": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "description": "All tags in the collection",
-                            "example": ["vocab", "grammar", "languages::spanish"]
+                    ],
+                    "requestBody": {
+                        "required": true
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "Answer recorded"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" },
+                        "409": {
+                            "description": "The card was modified since its states were fetched",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/ErrorResponse" }
+                                }
+                            }
                         }
                     }
-                },
-                "TagTreeResponse": {
-                    "type": "object",
-                    "properties": {
-                        "root": { "$ref": "#/components/schemas/TagTreeNode" }
-                    }
-                },
-                "TagTreeNode": {
-                    "type": "object",
-                    "properties": {
-                        "name": { "type": "string" },
-                        "children": {
-                            "type": "array",
-                            "items": { "$ref": "#/components/schemas/TagTreeNode" }
+                }
+            }
+        }
+    })
+}
+
+fn fsrs_section() -> Value {
+    let preset_id = json!({
+        "name": "id",
+        "in": "path",
+        "required": true,
+        "description": "Deck options preset ID",
+        "schema": { "type": "integer", "format": "int64" }
+    });
+    let job_started = json!({
+        "202": { "description": "Job started" },
+        "400": { "$ref": "#/components/responses/BadRequest" },
+        "401": { "$ref": "#/components/responses/Unauthorized" },
+        "404": { "$ref": "#/components/responses/NotFound" }
+    });
+    let request_body = json!({ "required": true });
+
+    json!({
+        "paths": {
+            "/api/v1/fsrs/presets": {
+                "get": {
+                    "tags": ["fsrs"],
+                    "summary": "List deck options presets with their FSRS settings",
+                    "operationId": "listFsrsPresets",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "200": {
+                            "description": "Presets, and whether FSRS is enabled"
                         },
-                        "level": { "type": "integer" },
-                        "collapsed": { "type": "boolean" }
-                    }
-                },
-                "RenameTagRequest": {
-                    "type": "object",
-                    "required": ["old_name", "new_name"],
-                    "properties": {
-                        "old_name": { "type": "string", "example": "vocab" },
-                        "new_name": { "type": "string", "example": "vocabulary" }
-                    }
-                },
-                "RenameTagResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": { "type": "boolean", "example": true },
-                        "message": { "type": "string", "example": "Tag renamed successfully" },
-                        "count": { "type": "integer", "description": "Number of notes affected" }
-                    }
-                },
-                "DeleteTagResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": { "type": "boolean", "example": true },
-                        "message": { "type": "string", "example": "Tag deleted successfully" },
-                        "count": { "type": "integer", "description": "Number of notes affected" }
-                    }
-                },
-                "CardStatsResponse": {
-                    "type": "object",
-                    "properties": {
-                        "card_id": { "type": "integer", "format": "int64" },
-                        "note_id": { "type": "integer", "format": "int64" },
-                        "deck": { "type": "string", "description": "Deck name" },
-                        "added": { "type": "integer", "format": "int64", "description": "Timestamp when card was added" },
-                        "first_review": { "type": "integer", "format": "int64", "nullable": true },
-                        "latest_review": { "type": "integer", "format": "int64", "nullable": true },
-                        "due_date": { "type": "integer", "format": "int64", "nullable": true },
-                        "interval": { "type": "integer", "format": "uint32", "description": "Current interval in days" },
-                        "ease": { "type": "integer", "format": "uint32", "description": "Ease factor (e.g., 2500 = 250%)" },
-                        "reviews": { "type": "integer", "format": "uint32", "description": "Total review count" },
-                        "lapses": { "type": "integer", "format": "uint32", "description": "Number of lapses" },
-                        "average_secs": { "type": "number", "format": "float", "description": "Average seconds per review" },
-                        "total_secs": { "type": "number", "format": "float", "description": "Total seconds spent reviewing" },
-                        "card_type": { "type": "string", "description": "Card type (e.g., New, Learn, Review)" },
-                        "notetype": { "type": "string", "description": "Note type name" }
-                    }
-                },
-                "TodayStatsResponse": {
-                    "type": "object",
-                    "properties": {
-                        "answer_count": { "type": "integer", "format": "uint32", "description": "Total answers today" },
-                        "answer_millis": { "type": "integer", "format": "uint32", "description": "Total time spent answering (ms)" },
-                        "correct_count": { "type": "integer", "format": "uint32" },
-                        "mature_correct": { "type": "integer", "format": "uint32" },
-                        "mature_count": { "type": "integer", "format": "uint32" },
-                        "learn_count": { "type": "integer", "format": "uint32" },
-                        "review_count": { "type": "integer", "format": "uint32" },
-                        "relearn_count": { "type": "integer", "format": "uint32" },
-                        "early_review_count": { "type": "integer", "format": "uint32" }
-                    }
-                },
-                "CardCountsResponse": {
-                    "type": "object",
-                    "properties": {
-                        "new_cards": { "type": "integer", "format": "uint32" },
-                        "learn": { "type": "integer", "format": "uint32" },
-                        "relearn": { "type": "integer", "format": "uint32" },
-                        "young": { "type": "integer", "format": "uint32" },
-                        "mature": { "type": "integer", "format": "uint32" },
-                        "suspended": { "type": "integer", "format": "uint32" },
-                        "buried": { "type": "integer", "format": "uint32" }
-                    }
-                },
-                "CollectionStatsResponse": {
-                    "type": "object",
-                    "properties": {
-                        "today": { "$ref": "#/components/schemas/TodayStatsResponse" },
-                        "card_counts": { "$ref": "#/components/schemas/CardCountsResponse" }
-                    }
-                },
-                "ClearUnusedTagsResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": { "type": "boolean", "example": true },
-                        "message": { "type": "string", "example": "Cleared 3 unused tags" },
-                        "removed_count": { "type": "integer", "description": "Number of tags removed" }
-                    }
-                },
-                "UpdateDeckRequest": {
-                    "type": "object",
-                    "properties": {
-                        "name": { "type": "string", "nullable": true, "example": "Spanish Vocabulary" },
-                        "collapsed": { "type": "boolean", "nullable": true }
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "NotetypeSummary": {
-                    "type": "object",
-                    "properties": {
-                        "id": { "type": "integer", "format": "int64" },
-                        "name": { "type": "string", "example": "Basic" }
+                }
+            },
+            "/api/v1/fsrs/presets/{id}/optimize": {
+                "post": {
+                    "tags": ["fsrs"],
+                    "summary": "Optimize a preset's FSRS parameters",
+                    "description": "Trains parameters on the review history of the preset's cards. Starts a background job whose result is an `OptimizeParamsResult`. Nothing is saved; send the parameters to `PUT /api/v1/fsrs/presets/{id}/params` to use them. Send `{}` to use the preset's search.",
+                    "operationId": "optimizeFsrsParams",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [preset_id],
+                    "requestBody": request_body,
+                    "responses": job_started
+                }
+            },
+            "/api/v1/fsrs/presets/{id}/evaluate": {
+                "post": {
+                    "tags": ["fsrs"],
+                    "summary": "Evaluate FSRS parameters against the review history",
+                    "description": "Starts a background job whose result is an `EvaluateParamsResult` with the log loss and RMSE (bins); lower is better.",
+                    "operationId": "evaluateFsrsParams",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [preset_id],
+                    "requestBody": request_body,
+                    "responses": job_started
+                }
+            },
+            "/api/v1/fsrs/presets/{id}/simulate": {
+                "post": {
+                    "tags": ["fsrs"],
+                    "summary": "Simulate future workload for candidate desired retentions",
+                    "description": "Simulates the preset's cards using its daily limits and steps. Starts a background job whose result is a `SimulateWorkloadResult` with one set of daily curves per retention.",
+                    "operationId": "simulateFsrsWorkload",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [preset_id],
+                    "requestBody": request_body,
+                    "responses": job_started
+                }
+            },
+            "/api/v1/fsrs/presets/{id}/optimal-retention": {
+                "post": {
+                    "tags": ["fsrs"],
+                    "summary": "Find the desired retention with the least work per card memorized",
+                    "description": "Starts a background job whose result has an `optimal_retention` between 0 and 1.",
+                    "operationId": "computeOptimalRetention",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [preset_id],
+                    "requestBody": request_body,
+                    "responses": job_started
+                }
+            },
+            "/api/v1/fsrs/presets/{id}/params": {
+                "put": {
+                    "tags": ["fsrs"],
+                    "summary": "Save FSRS parameters to a preset",
+                    "description": "Starts a background job whose result is a `SaveParamsResult`. As when saving the desktop deck options, memory states of the preset's cards are recomputed if FSRS is enabled, and cards are rescheduled if `reschedule` is set. Only FSRS 6 parameters (21 values), or an empty list for the defaults, can be saved. The change can be undone.",
+                    "operationId": "saveFsrsParams",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [preset_id],
+                    "requestBody": request_body,
+                    "responses": job_started
+                }
+            }
+        }
+    })
+}
+
+fn preferences_section() -> Value {
+    let config_key = json!({
+        "name": "key",
+        "in": "path",
+        "required": true,
+        "description": "Config key, as stored in the collection",
+        "schema": { "type": "string", "example": "applyAllParentLimits" }
+    });
+    let preferences_response = json!({ "description": "Current preferences" });
+    let config_entry_response = json!({ "description": "Config key and its value" });
+
+    json!({
+        "paths": {
+            "/api/v1/preferences": {
+                "get": {
+                    "tags": ["preferences"],
+                    "summary": "Get scheduling, reviewing and editing preferences",
+                    "operationId": "getPreferences",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "200": preferences_response,
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
                 },
-                "NotetypeDetail": {
-                    "type": "object",
-                    "properties": {
-                        "id": { "type": "integer", "format": "int64" },
-                        "name": { "type": "string", "example": "Basic" },
-                        "fields": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "example": ["Front", "Back"]
+                "put": {
+                    "tags": ["preferences"],
+                    "summary": "Update preferences",
+                    "description": "Fields that are left out keep their current value. The change is a single step that can be undone with `POST /api/v1/scheduler/undo`.",
+                    "operationId": "updatePreferences",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": {
+                                "example": { "scheduling": { "rollover": 2 } }
+                            }
                         }
+                    },
+                    "responses": {
+                        "200": preferences_response,
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "CreateCollectionRequest": {
-                    "type": "object",
-                    "required": ["name"],
-                    "properties": {
-                        "name": { "type": "string", "example": "Work" }
+                }
+            },
+            "/api/v1/config": {
+                "get": {
+                    "tags": ["preferences"],
+                    "summary": "List the config keys clients may change",
+                    "description": "Only a whitelisted set of keys not covered by the preferences is exposed.",
+                    "operationId": "listConfig",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "200": {
+                            "description": "Config keys and their values"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
-                },
-                "NextCardResponse": {
-                    "type": "object",
-                    "properties": {
-                        "card_id": { "type": "integer", "format": "int64", "nullable": true },
-                        "question": { "type": "string", "nullable": true, "description": "Rendered HTML for the question side" },
-                        "answer": { "type": "string", "nullable": true, "description": "Rendered HTML for the answer side" },
-                        "deck_id": { "type": "integer", "format": "int64", "nullable": true },
-                        "card_type": { "type": "integer", "nullable": true, "description": "0=New, 1=Learn, 2=Review, 3=Relearn" }
+                }
+            },
+            "/api/v1/config/{key}": {
+                "get": {
+                    "tags": ["preferences"],
+                    "summary": "Get a config key",
+                    "operationId": "getConfig",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [config_key],
+                    "responses": {
+                        "200": config_entry_response,
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
                     }
                 },
-                "AnswerCardRequest": {
-                    "type": "object",
-                    "required": ["rating"],
-                    "properties": {
-                        "rating": {
-                            "type": "integer",
-                            "minimum": 0,
-                            "maximum": 3,
-                            "description": "Rating: 0=Again, 1=Hard, 2=Good, 3=Easy",
-                            "example": 2
-                        },
-                        "milliseconds_taken": {
-                            "type": "integer",
-                            "format": "uint32",
-                            "minimum": 0,
-                            "description": "Time spent reviewing the card in milliseconds (for statistics)",
-                            "example": 5230,
-                            "default": 0
+                "put": {
+                    "tags": ["preferences"],
+                    "summary": "Set a config key",
+                    "description": "The value must have the key's type. The change can be undone.",
+                    "operationId": "setConfig",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [config_key],
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": {
+                                "example": { "value": true }
+                            }
                         }
+                    },
+                    "responses": {
+                        "200": config_entry_response,
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
                     }
-                },
-                "AnswerCardResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": { "type": "boolean", "example": true },
-                        "counts": { "$ref": "#/components/schemas/DeckCountsResponse" }
-                    }
-                },
-                "DeckCountsResponse": {
-                    "type": "object",
-                    "properties": {
-                        "new": { "type": "integer", "format": "uint32", "description": "New cards remaining" },
-                        "learn": { "type": "integer", "format": "uint32", "description": "Learning/relearning cards remaining" },
-                        "review": { "type": "integer", "format": "uint32", "description": "Review cards remaining" }
+                }
+            }
+        }
+    })
+}
+
+fn sharing_section() -> Value {
+    let shared_deck_id = json!({
+        "name": "id",
+        "in": "path",
+        "required": true,
+        "description": "Shared deck ID",
+        "schema": { "type": "integer", "format": "int64" }
+    });
+
+    json!({
+        "paths": {
+            "/api/v1/shared-decks": {
+                "get": {
+                    "tags": ["sharing"],
+                    "summary": "List the decks in the shared library",
+                    "operationId": "listSharedDecks",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "200": {
+                            "description": "Shared decks of all users, ordered by name"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
                 },
-                "NextStatesResponse": {
-                    "type": "object",
-                    "properties": {
-                        "again": { "type": "string", "description": "Interval if answered Again (e.g., '<1m', '10m')", "example": "<1m" },
-                        "hard": { "type": "string", "description": "Interval if answered Hard (e.g., '10m', '1d')", "example": "10m" },
-                        "good": { "type": "string", "description": "Interval if answered Good (e.g., '1d', '4d')", "example": "1d" },
-                        "easy": { "type": "string", "description": "Interval if answered Easy (e.g., '4d', '2mo')", "example": "4d" }
+                "post": {
+                    "tags": ["sharing"],
+                    "summary": "Publish a deck to the shared library",
+                    "description": "Snapshots the deck and its subdecks, with media but without scheduling. Publishing the same deck again replaces the snapshot and increases its version, so subscribers see an update.",
+                    "operationId": "publishDeck",
+                    "security": [{ "bearerAuth": [] }],
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": {
+                                "example": { "deck_id": 1, "description": "Week 1 vocabulary" }
+                            }
+                        }
                     },
-                    "required": ["again", "hard", "good", "easy"],
-                    "description": "Human-readable descriptions of next review intervals for each answer button"
-                },
-                "WebImportResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": { "type": "boolean", "example": true },
-                        "message": { "type": "string", "example": "Successfully imported 'deck.apkg'" },
-                        "notes_new": { "type": "integer", "format": "uint32" },
-                        "notes_updated": { "type": "integer", "format": "uint32" }
+                    "responses": {
+                        "200": {
+                            "description": "New version published"
+                        },
+                        "201": {
+                            "description": "Deck published for the first time"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
                     }
-                },
-                "ErrorResponse": {
-                    "type": "object",
-                    "properties": {
-                        "success": { "type": "boolean", "example": false },
-                        "data": { "nullable": true },
-                        "error": { "type": "string", "example": "Error description" }
+                }
+            },
+            "/api/v1/shared-decks/subscriptions": {
+                "get": {
+                    "tags": ["sharing"],
+                    "summary": "List the current user's subscriptions",
+                    "description": "`update_available` is set when a newer version was published than the one in the collection.",
+                    "operationId": "listSubscriptions",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "200": {
+                            "description": "Subscriptions, ordered by deck name"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" }
                     }
                 }
             },
-            "responses": {
-                "BadRequest": {
-                    "description": "Bad request",
-                    "content": {
-                        "application/json": {
-                            "schema": { "$ref": "#/components/schemas/ErrorResponse" }
-                        }
+            "/api/v1/shared-decks/{id}": {
+                "delete": {
+                    "tags": ["sharing"],
+                    "summary": "Unpublish a shared deck",
+                    "description": "Only the owner may unpublish a deck. Subscribers keep the notes they imported.",
+                    "operationId": "unpublishDeck",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [shared_deck_id],
+                    "responses": {
+                        "200": {
+                            "description": "Deck unpublished"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "403": {
+                            "description": "The deck belongs to another user",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/ErrorResponse" }
+                                }
+                            }
+                        },
+                        "404": { "$ref": "#/components/responses/NotFound" }
                     }
-                },
-                "Unauthorized": {
-                    "description": "Missing or invalid authentication token",
-                    "content": {
-                        "application/json": {
-                            "schema": { "$ref": "#/components/schemas/ErrorResponse" }
+                }
+            },
+            "/api/v1/shared-decks/{id}/subscription": {
+                "post": {
+                    "tags": ["sharing"],
+                    "summary": "Subscribe to a shared deck",
+                    "description": "Imports the latest version into the collection. Notes are matched by GUID and notetypes are merged.",
+                    "operationId": "subscribeDeck",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [shared_deck_id],
+                    "responses": {
+                        "201": {
+                            "description": "Subscribed and imported"
+                        },
+                        "400": { "$ref": "#/components/responses/BadRequest" },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" },
+                        "409": {
+                            "description": "Already subscribed",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/ErrorResponse" }
+                                }
+                            }
                         }
                     }
                 },
-                "NotFound": {
-                    "description": "Resource not found",
-                    "content": {
-                        "application/json": {
-                            "schema": { "$ref": "#/components/schemas/ErrorResponse" }
-                        }
+                "delete": {
+                    "tags": ["sharing"],
+                    "summary": "Unsubscribe from a shared deck",
+                    "description": "Imported notes stay in the collection, but no longer receive updates.",
+                    "operationId": "unsubscribeDeck",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [shared_deck_id],
+                    "responses": {
+                        "200": {
+                            "description": "Unsubscribed"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
+                    }
+                }
+            },
+            "/api/v1/shared-decks/{id}/update": {
+                "post": {
+                    "tags": ["sharing"],
+                    "summary": "Import the latest version of a subscribed deck",
+                    "description": "Changed notes are overwritten with the published version and new notes added. Existing cards keep their scheduling. Nothing is imported if the collection is up to date.",
+                    "operationId": "updateSubscription",
+                    "security": [{ "bearerAuth": [] }],
+                    "parameters": [shared_deck_id],
+                    "responses": {
+                        "200": {
+                            "description": "Collection is up to date"
+                        },
+                        "401": { "$ref": "#/components/responses/Unauthorized" },
+                        "404": { "$ref": "#/components/responses/NotFound" }
                     }
                 }
             }
@@ -0,0 +1,88 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! Optimistic concurrency for updates. Objects are served with an `ETag`,
+//! and updates sent with a matching `If-Match` header are rejected with 412
+//! if the object changed in the meantime.
+
+use axum::http::header;
+use axum::http::HeaderMap;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::error::Result;
+use crate::error::WebAppError;
+
+/// A strong entity tag for an object. mtime only has second resolution and
+/// usn doesn't change for local edits, so the content is hashed in as well.
+pub fn object_etag(mtime: i64, usn: i32, content: &[u8]) -> String {
+    let digest = Sha256::digest(content);
+    format!("\"{}-{}-{}\"", mtime, usn, hex::encode(&digest[..8]))
+}
+
+/// Fail with 412 if the request has an `If-Match` header that doesn't match
+/// the object's current entity tag. Requests without one always pass.
+pub fn check_if_match(headers: &HeaderMap, etag: &str) -> Result<()> {
+    let mut values = headers.get_all(header::IF_MATCH).iter().peekable();
+    if values.peek().is_none() {
+        return Ok(());
+    }
+    for value in values {
+        let value = value
+            .to_str()
+            .map_err(|_| WebAppError::bad_request("Invalid If-Match header"))?;
+        // Weak tags never match, as If-Match uses strong comparison
+        if value
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag == etag)
+        {
+            return Ok(());
+        }
+    }
+    Err(WebAppError::precondition_failed(
+        "The object was modified since it was fetched",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_object_etag() {
+        let etag = object_etag(1700000000, -1, b"front\x1fback");
+        assert!(etag.starts_with("\"1700000000--1-"));
+        assert_ne!(etag, object_etag(1700000000, -1, b"front\x1fedited"));
+    }
+
+    #[test]
+    fn test_check_if_match() {
+        let etag = object_etag(1, 0, b"a");
+        let mut headers = HeaderMap::new();
+        assert!(check_if_match(&headers, &etag).is_ok());
+
+        headers.insert(header::IF_MATCH, HeaderValue::from_static("\"stale\""));
+        assert!(matches!(
+            check_if_match(&headers, &etag),
+            Err(WebAppError::PreconditionFailed(_))
+        ));
+
+        headers.insert(
+            header::IF_MATCH,
+            HeaderValue::from_str(&format!("\"stale\", {}", etag)).unwrap(),
+        );
+        assert!(check_if_match(&headers, &etag).is_ok());
+
+        headers.insert(
+            header::IF_MATCH,
+            HeaderValue::from_str(&format!("W/{}", etag)).unwrap(),
+        );
+        assert!(check_if_match(&headers, &etag).is_err());
+
+        headers.insert(header::IF_MATCH, HeaderValue::from_static("*"));
+        assert!(check_if_match(&headers, &etag).is_ok());
+    }
+}
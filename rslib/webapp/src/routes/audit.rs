@@ -0,0 +1,37 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use axum::extract::Query;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Extension;
+use axum::Json;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::auth::AuthUser;
+use crate::db::AuditEntry;
+use crate::db::AuditQuery;
+use crate::error::Result;
+use crate::error::WebAppError;
+use crate::routes::AuthRouteState;
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AuditLogResponse {
+    pub entries: Vec<AuditEntry>,
+}
+
+/// Query the current user's audit log, newest first
+pub async fn get_audit_log(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(query): Query<AuditQuery>,
+) -> Result<impl IntoResponse> {
+    let entries = state
+        .database
+        .audit()
+        .query(auth_user.user_id, &query)
+        .map_err(|e| WebAppError::internal(&format!("Database error: {}", e)))?;
+
+    Ok(Json(AuditLogResponse { entries }))
+}
@@ -5,6 +5,7 @@ use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 use uuid::Uuid;
@@ -14,9 +15,12 @@ use crate::auth::verify_password;
 use crate::auth::AuthUser;
 use crate::auth::Claims;
 use crate::auth::JwtManager;
+use crate::auth::MIN_PASSWORD_LEN;
 use crate::db::Database;
 use crate::error::Result;
 use crate::error::WebAppError;
+use crate::jobs::JobManager;
+use crate::session::lock_collection;
 use crate::session::BackendManager;
 
 #[derive(Clone)]
@@ -24,43 +28,46 @@ pub struct AuthRouteState {
     pub database: Arc<Database>,
     pub jwt_manager: Arc<JwtManager>,
     pub backend_manager: Arc<BackendManager>,
+    pub job_manager: Arc<JobManager>,
     pub session_timeout_hours: i64,
+    /// Whether LaTeX in notes may be compiled on the server
+    pub latex_rendering: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct RegisterRequest {
     pub username: String,
     pub password: String,
     pub email: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct AuthResponse {
     pub success: bool,
     pub data: Option<AuthData>,
     pub error: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct AuthData {
     pub token: String,
     pub user: UserInfo,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct UserInfo {
     pub id: i64,
     pub username: String,
     pub email: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct MessageResponse {
     pub success: bool,
     pub message: String,
@@ -71,14 +78,19 @@ pub async fn register(
     State(state): State<AuthRouteState>,
     Json(payload): Json<RegisterRequest>,
 ) -> Result<impl IntoResponse> {
+    if !state.database.settings().registration_enabled()? {
+        return Err(WebAppError::forbidden("Registration is disabled"));
+    }
+
     // Validate input
     if payload.username.trim().is_empty() {
         return Err(WebAppError::bad_request("Username cannot be empty"));
     }
-    if payload.password.len() < 8 {
-        return Err(WebAppError::bad_request(
-            "Password must be at least 8 characters",
-        ));
+    if payload.password.len() < MIN_PASSWORD_LEN {
+        return Err(WebAppError::bad_request(&format!(
+            "Password must be at least {} characters",
+            MIN_PASSWORD_LEN
+        )));
     }
     if payload.username.len() > 50 {
         return Err(WebAppError::bad_request(
@@ -169,6 +181,12 @@ pub async fn login(
         return Err(WebAppError::unauthorized("Invalid username or password"));
     }
 
+    // The collection may still be open from an earlier session, which must
+    // not be undone from this one
+    if let Some(backend) = state.backend_manager.get_backend(user.id) {
+        lock_collection(&backend).clear_undo();
+    }
+
     // Create session
     let session_id = Uuid::new_v4().to_string();
     let _session = state.database.sessions().create(
@@ -208,12 +226,31 @@ pub async fn logout(
     State(state): State<AuthRouteState>,
     Extension(auth_user): Extension<AuthUser>,
 ) -> Result<impl IntoResponse> {
-    // Close backend instance for this user
-    let _ = state.backend_manager.close_backend(auth_user.user_id);
-
     // Delete the session
     state.database.sessions().delete(&auth_user.session_id)?;
 
+    // Close the collection once the user's last session has ended. Other
+    // sessions keep it open, along with their undo history. Closing waits
+    // for in-flight requests, so it runs on the blocking pool; if one holds
+    // on too long, the collection stays open and the next login clears its
+    // undo history instead.
+    let signed_in = state
+        .database
+        .sessions()
+        .get_user_sessions(auth_user.user_id)?
+        .iter()
+        .any(|session| !session.is_expired());
+    if !signed_in {
+        let backend_manager = state.backend_manager.clone();
+        let user_id = auth_user.user_id;
+        let closed = tokio::task::spawn_blocking(move || backend_manager.close_backend(user_id))
+            .await
+            .map_err(|e| WebAppError::internal(&e.to_string()))?;
+        if let Err(e) = closed {
+            tracing::warn!("Failed to close collection for user {}: {}", user_id, e);
+        }
+    }
+
     Ok(Json(MessageResponse {
         success: true,
         message: "Logged out successfully".to_string(),
@@ -258,18 +295,22 @@ mod tests {
 
         let jwt_manager = Arc::new(JwtManager::new("test_secret"));
         let backend_manager = Arc::new(BackendManager::new(std::env::temp_dir()));
+        let job_manager = Arc::new(JobManager::new());
 
         let auth_state = AuthRouteState {
             database: db.clone(),
             jwt_manager: jwt_manager.clone(),
             backend_manager: backend_manager.clone(),
+            job_manager: job_manager.clone(),
             session_timeout_hours: 24,
+            latex_rendering: false,
         };
 
         let middleware_state = AuthState {
             database: db.clone(),
             jwt_manager,
             backend_manager,
+            job_manager,
         };
 
         let app = Router::new()
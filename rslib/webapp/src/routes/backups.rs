@@ -0,0 +1,93 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Extension;
+use axum::Json;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::auth::AuthUser;
+use crate::error::Result;
+use crate::error::WebAppError;
+use crate::routes::maintenance::JobStartedResponse;
+use crate::routes::AuthRouteState;
+use crate::session::BackupInfo;
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct BackupsListResponse {
+    pub backups: Vec<BackupInfo>,
+}
+
+/// List the current user's collection backups, newest first
+pub async fn list_backups(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    let backups = state.backend_manager.list_backups(auth_user.user_id)?;
+
+    Ok(Json(BackupsListResponse { backups }))
+}
+
+/// Back up the current user's collection now, regardless of when the last
+/// backup was made
+pub async fn create_backup(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let manager = state.backend_manager.clone();
+    let user_id = auth_user.user_id;
+    let job_id = state.job_manager.spawn(user_id, "create_backup", move || {
+        let created = manager.backup_collection(user_id, &backend, true)?;
+        Ok(json!({ "created": created }))
+    });
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobStartedResponse {
+            success: true,
+            job_id,
+        }),
+    ))
+}
+
+/// Replace the current user's collection with a backup. The collection is
+/// closed while the backup is imported, then reopened.
+pub async fn restore_backup(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(filename): Path<String>,
+) -> Result<impl IntoResponse> {
+    let exists = state
+        .backend_manager
+        .list_backups(auth_user.user_id)?
+        .iter()
+        .any(|backup| backup.filename == filename);
+    if !exists {
+        return Err(WebAppError::not_found("Backup not found"));
+    }
+
+    let manager = state.backend_manager.clone();
+    let user_id = auth_user.user_id;
+    let username = auth_user.username.clone();
+    let job_id = state.job_manager.spawn(user_id, "restore_backup", move || {
+        manager.restore_backup(user_id, &username, &filename)?;
+        Ok(json!({ "restored": filename }))
+    });
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobStartedResponse {
+            success: true,
+            job_id,
+        }),
+    ))
+}
@@ -11,20 +11,21 @@ use axum::extract::State;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 
 use crate::auth::AuthUser;
 use crate::error::Result;
-use crate::error::WebAppError;
 use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct BrowseRequest {
     pub ids: Vec<i64>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct CardBrowseRow {
     pub card_id: i64,
     pub note_id: i64,
@@ -34,7 +35,7 @@ pub struct CardBrowseRow {
     pub deck: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct NoteBrowseRow {
     pub note_id: i64,
     pub sort_field: String,
@@ -43,6 +44,16 @@ pub struct NoteBrowseRow {
     pub tags: String,
 }
 
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CardBrowseResponse {
+    pub rows: Vec<CardBrowseRow>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NoteBrowseResponse {
+    pub rows: Vec<NoteBrowseRow>,
+}
+
 /// Batch endpoint: given a list of card IDs, return browse-table rows with
 /// Sort Field, Card Type, Due, and Deck — matching the desktop app's card browser.
 pub async fn browse_cards(
@@ -54,11 +65,9 @@ pub async fn browse_cards(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
-    let timing = col
-        .timing_today()
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let timing = col.timing_today()?;
     let days_elapsed = timing.days_elapsed as i32;
 
     // Phase 1: collect raw data for each card using the proto-based public API.
@@ -147,7 +156,7 @@ pub async fn browse_cards(
         })
         .collect();
 
-    Ok(Json(serde_json::json!({ "rows": rows })))
+    Ok(Json(CardBrowseResponse { rows }))
 }
 
 /// Batch endpoint: given a list of note IDs, return browse-table rows with
@@ -161,7 +170,7 @@ pub async fn browse_notes(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Phase 1: collect raw data.
     struct RawNote {
@@ -226,7 +235,7 @@ pub async fn browse_notes(
         })
         .collect();
 
-    Ok(Json(serde_json::json!({ "rows": rows })))
+    Ok(Json(NoteBrowseResponse { rows }))
 }
 
 /// Format the due date into a human-readable string matching the desktop browser.
@@ -2,18 +2,25 @@ use anki::services::CardsService;
 use anki::services::SchedulerService;
 use axum::extract::Path;
 use axum::extract::State;
+use axum::http::header;
+use axum::http::HeaderMap;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use prost::Message;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 
 use crate::auth::AuthUser;
 use crate::error::Result;
 use crate::error::WebAppError;
+use crate::preconditions::check_if_match;
+use crate::preconditions::object_etag;
 use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct CardInfo {
     pub id: i64,
     pub note_id: i64,
@@ -29,35 +36,41 @@ pub struct CardInfo {
     pub flags: u8,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct UpdateCardRequest {
     pub deck_id: Option<i64>,
     pub due: Option<i32>,
     pub flags: Option<u8>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct MessageResponse {
     pub success: bool,
     pub message: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct FlagCardRequest {
     pub flag: u8,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct BatchGetCardsRequest {
     pub card_ids: Vec<i64>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct BatchGetCardsResponse {
+    /// Cards that were not found are left out
+    pub cards: Vec<CardInfo>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct BatchUpdateCardsRequest {
     pub updates: Vec<BatchCardUpdate>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct BatchCardUpdate {
     pub card_id: i64,
     pub deck_id: Option<i64>,
@@ -65,8 +78,19 @@ pub struct BatchCardUpdate {
     pub flags: Option<u8>,
 }
 
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct BatchUpdateCardsResponse {
+    pub success: bool,
+    pub message: String,
+    pub updated_count: usize,
+}
+
+fn card_etag(card: &anki_proto::cards::Card) -> String {
+    object_etag(card.mtime_secs, card.usn, &card.encode_to_vec())
+}
+
 /// Convert Anki Card (protobuf) to CardInfo
-fn card_to_info(card: &anki_proto::cards::Card) -> CardInfo {
+pub(crate) fn card_to_info(card: &anki_proto::cards::Card) -> CardInfo {
     CardInfo {
         id: card.id,
         note_id: card.note_id,
@@ -93,23 +117,15 @@ pub async fn get_card(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
-    let card = col
-        .get_card(anki_proto::cards::CardId { cid: card_id })
-        .map_err(|e: anki::error::AnkiError| {
-            if e.to_string().contains("NotFound") {
-                WebAppError::not_found("Card not found")
-            } else {
-                WebAppError::internal(&e.to_string())
-            }
-        })?;
+    let card = col.get_card(anki_proto::cards::CardId { cid: card_id })?;
 
     let info = card_to_info(&card);
 
     drop(col);
 
-    Ok(Json(info))
+    Ok(([(header::ETAG, card_etag(&card))], Json(info)))
 }
 
 /// Update a card
@@ -117,18 +133,18 @@ pub async fn update_card(
     State(state): State<AuthRouteState>,
     Extension(auth_user): Extension<AuthUser>,
     Path(card_id): Path<i64>,
+    headers: HeaderMap,
     Json(request): Json<UpdateCardRequest>,
 ) -> Result<impl IntoResponse> {
     let backend = state
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Get the existing card
-    let mut card = col
-        .get_card(anki_proto::cards::CardId { cid: card_id })
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let mut card = col.get_card(anki_proto::cards::CardId { cid: card_id })?;
+    check_if_match(&headers, &card_etag(&card))?;
 
     // Update fields if provided
     if let Some(deck_id) = request.deck_id {
@@ -142,19 +158,22 @@ pub async fn update_card(
     }
 
     // Update the card
-    let _ = col
-        .update_cards(anki_proto::cards::UpdateCardsRequest {
-            cards: vec![card],
-            skip_undo_entry: false,
-        })
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let _ = col.update_cards(anki_proto::cards::UpdateCardsRequest {
+        cards: vec![card],
+        skip_undo_entry: false,
+    })?;
+
+    let card = col.get_card(anki_proto::cards::CardId { cid: card_id })?;
 
     drop(col);
 
-    Ok(Json(MessageResponse {
-        success: true,
-        message: "Card updated successfully".to_string(),
-    }))
+    Ok((
+        [(header::ETAG, card_etag(&card))],
+        Json(MessageResponse {
+            success: true,
+            message: "Card updated successfully".to_string(),
+        }),
+    ))
 }
 
 /// Delete a card
@@ -167,7 +186,7 @@ pub async fn delete_card(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Check if card exists
     if col.get_card(anki_proto::cards::CardId { cid: card_id }).is_err() {
@@ -209,15 +228,13 @@ pub async fn flag_card(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Set the flag
-    let _ = col
-        .set_flag(anki_proto::cards::SetFlagRequest {
-            card_ids: vec![card_id],
-            flag: request.flag as u32,
-        })
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let _ = col.set_flag(anki_proto::cards::SetFlagRequest {
+        card_ids: vec![card_id],
+        flag: request.flag as u32,
+    })?;
 
     drop(col);
 
@@ -237,7 +254,7 @@ pub async fn suspend_card(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Suspend the card
     let _ = <anki::collection::Collection as SchedulerService>::bury_or_suspend_cards(
@@ -247,8 +264,7 @@ pub async fn suspend_card(
             note_ids: vec![],
             mode: anki_proto::scheduler::bury_or_suspend_cards_request::Mode::Suspend as i32,
         },
-    )
-    .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    )?;
 
     drop(col);
 
@@ -268,14 +284,12 @@ pub async fn unsuspend_card(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Unsuspend the card (restore from buried/suspended)
-    let _ = col
-        .restore_buried_and_suspended_cards(anki_proto::cards::CardIds {
-            cids: vec![card_id],
-        })
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let _ = col.restore_buried_and_suspended_cards(anki_proto::cards::CardIds {
+        cids: vec![card_id],
+    })?;
 
     drop(col);
 
@@ -295,7 +309,7 @@ pub async fn bury_card(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Bury the card (bury until next day)
     let _ = <anki::collection::Collection as SchedulerService>::bury_or_suspend_cards(
@@ -305,8 +319,7 @@ pub async fn bury_card(
             note_ids: vec![],
             mode: anki_proto::scheduler::bury_or_suspend_cards_request::Mode::BuryUser as i32,
         },
-    )
-    .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    )?;
 
     drop(col);
 
@@ -326,7 +339,7 @@ pub async fn batch_get_cards(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     let mut cards = Vec::new();
     for card_id in request.card_ids {
@@ -337,9 +350,7 @@ pub async fn batch_get_cards(
 
     drop(col);
 
-    Ok(Json(serde_json::json!({
-        "cards": cards,
-    })))
+    Ok(Json(BatchGetCardsResponse { cards }))
 }
 
 /// Update multiple cards (batch update)
@@ -352,7 +363,7 @@ pub async fn batch_update_cards(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     let mut cards_to_update = Vec::new();
     for update in request.updates {
@@ -377,19 +388,17 @@ pub async fn batch_update_cards(
 
     // Update all cards
     if !cards_to_update.is_empty() {
-        let _ = col
-            .update_cards(anki_proto::cards::UpdateCardsRequest {
-                cards: cards_to_update,
-                skip_undo_entry: false,
-            })
-            .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+        let _ = col.update_cards(anki_proto::cards::UpdateCardsRequest {
+            cards: cards_to_update,
+            skip_undo_entry: false,
+        })?;
     }
 
     drop(col);
 
-    Ok(Json(serde_json::json!({
-        "success": true,
-        "message": format!("Updated {} cards successfully", updated_count),
-        "updated_count": updated_count,
-    })))
+    Ok(Json(BatchUpdateCardsResponse {
+        success: true,
+        message: format!("Updated {} cards successfully", updated_count),
+        updated_count,
+    }))
 }
@@ -2,14 +2,16 @@ use axum::extract::State;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 
 use crate::auth::AuthUser;
 use crate::error::Result;
+use crate::error::WebAppError;
 use crate::routes::AuthRouteState;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct CollectionInfo {
     pub user_id: i64,
     pub username: String,
@@ -17,29 +19,29 @@ pub struct CollectionInfo {
     pub message: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct MessageResponse {
     pub success: bool,
     pub message: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct Collection {
     pub name: String,
     pub path: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct CollectionsResponse {
     pub collections: Vec<Collection>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CreateCollectionRequest {
     pub name: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct CreateCollectionResponse {
     pub path: String,
     pub message: String,
@@ -68,8 +70,12 @@ pub async fn close_collection(
     State(state): State<AuthRouteState>,
     Extension(auth_user): Extension<AuthUser>,
 ) -> Result<impl IntoResponse> {
-    // Close backend for this user
-    state.backend_manager.close_backend(auth_user.user_id)?;
+    // Close backend for this user, on the blocking pool as it waits for
+    // in-flight requests
+    let backend_manager = state.backend_manager.clone();
+    tokio::task::spawn_blocking(move || backend_manager.close_backend(auth_user.user_id))
+        .await
+        .map_err(|e| WebAppError::internal(&e.to_string()))??;
 
     Ok(Json(MessageResponse {
         success: true,
@@ -1,9 +1,14 @@
+use anki::decks::tree::get_deck_in_tree;
+use anki::decks::tree::DeckCollapseScope;
+use anki::decks::DeckId;
+use anki::decks::NativeDeckName;
 use anki::timestamp::TimestampSecs;
 use axum::extract::Path;
 use axum::extract::State;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -11,41 +16,112 @@ use crate::auth::AuthUser;
 use crate::error::Result;
 use crate::error::WebAppError;
 use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DeckTree {
     pub decks: Vec<DeckNode>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DeckNode {
     pub id: i64,
     pub name: String,
+    pub level: u32,
     pub collapsed: bool,
+    pub filtered: bool,
+    /// Due counts including children, with deck limits applied
     pub new_count: u32,
     pub learn_count: u32,
     pub review_count: u32,
+    /// Cards in this deck and its children, ignoring limits
+    pub total_cards: u32,
     pub children: Vec<DeckNode>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct CreateDeckRequest {
     pub name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct UpdateDeckRequest {
+    /// Full name using `::` separators; child decks move with the deck
     pub name: Option<String>,
     pub collapsed: Option<bool>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct UpdateDeckResponse {
+    pub success: bool,
+    pub message: String,
+    pub id: i64,
+    /// Stored name, which may have been normalized or made unique
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct DeckInfo {
     pub id: i64,
     pub name: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReparentDecksRequest {
+    pub deck_ids: Vec<i64>,
+    /// Moves the decks to the top level if not provided
+    pub new_parent_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReparentDecksResponse {
+    pub success: bool,
+    pub message: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CollapseScopeJson {
+    #[default]
+    Reviewer,
+    Browser,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetDeckCollapsedRequest {
+    pub collapsed: bool,
+    #[serde(default)]
+    pub scope: CollapseScopeJson,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DeckStudiedToday {
+    pub new: i32,
+    pub review: i32,
+    pub milliseconds: i32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DeckStatsResponse {
+    pub id: i64,
+    pub name: String,
+    pub filtered: bool,
+    /// Due counts including children, with deck limits applied
+    pub new_count: u32,
+    pub learn_count: u32,
+    pub review_count: u32,
+    /// Due counts in this deck alone, ignoring limits
+    pub new_uncapped: u32,
+    pub learn_uncapped: u32,
+    pub review_uncapped: u32,
+    pub cards_in_deck: u32,
+    pub cards_including_children: u32,
+    pub studied_today: DeckStudiedToday,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(rename = "DeckMessageResponse")]
 pub struct MessageResponse {
     pub success: bool,
     pub message: String,
@@ -61,12 +137,10 @@ pub async fn get_deck_tree(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Pass current timestamp so due-card counts are populated
-    let tree = col
-        .deck_tree(Some(TimestampSecs::now()))
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let tree = col.deck_tree(Some(TimestampSecs::now()))?;
 
     drop(col);
 
@@ -86,12 +160,10 @@ pub async fn create_deck(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Create the deck
-    let deck = col
-        .get_or_create_normal_deck(&request.name)
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let deck = col.get_or_create_normal_deck(&request.name)?;
     let deck_id = deck.id.0;
 
     drop(col);
@@ -116,12 +188,11 @@ pub async fn get_deck(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     let deck_id = anki::decks::DeckId(deck_id);
     let deck = col
-        .get_deck(deck_id)
-        .map_err(|e| WebAppError::internal(&e.to_string()))?
+        .get_deck(deck_id)?
         .ok_or_else(|| WebAppError::not_found("Deck not found"))?;
 
     drop(col);
@@ -139,40 +210,43 @@ pub async fn update_deck(
     Path(deck_id): Path<i64>,
     Json(request): Json<UpdateDeckRequest>,
 ) -> Result<impl IntoResponse> {
+    if request
+        .name
+        .as_ref()
+        .is_some_and(|name| name.trim().is_empty())
+    {
+        return Err(WebAppError::bad_request("Deck name cannot be empty"));
+    }
+
     let backend = state
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
-    let deck_id = anki::decks::DeckId(deck_id);
     let deck = col
-        .get_deck(deck_id)
-        .map_err(|e| WebAppError::internal(&e.to_string()))?
+        .get_deck(DeckId(deck_id))?
         .ok_or_else(|| WebAppError::not_found("Deck not found"))?;
+    let mut deck = (*deck).clone();
 
-    // Clone the deck to get a mutable copy
-    let mut deck_mut = (*deck).clone();
-
-    // Update name if provided
+    // Renaming by full path can also move the deck, taking its children along
     if let Some(new_name) = request.name {
-        deck_mut.name = anki::decks::NativeDeckName::from_human_name(&new_name);
+        deck.name = NativeDeckName::from_human_name(&new_name);
     }
-
-    // Update collapsed state if provided
     if let Some(collapsed) = request.collapsed {
-        deck_mut.common.study_collapsed = collapsed;
+        deck.common.study_collapsed = collapsed;
     }
 
-    col.update_deck(&mut deck_mut)
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    // A single undoable step; the name may be normalized or made unique
+    col.update_deck(&mut deck)?;
 
     drop(col);
 
-    Ok(Json(MessageResponse {
+    Ok(Json(UpdateDeckResponse {
         success: true,
         message: "Deck updated successfully".to_string(),
-        id: Some(deck_id.0),
+        id: deck.id.0,
+        name: deck.human_name(),
     }))
 }
 
@@ -186,11 +260,10 @@ pub async fn delete_deck(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     let deck_ids = vec![anki::decks::DeckId(deck_id)];
-    col.remove_decks_and_child_decks(&deck_ids)
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    col.remove_decks_and_child_decks(&deck_ids)?;
 
     drop(col);
 
@@ -201,16 +274,147 @@ pub async fn delete_deck(
     }))
 }
 
+/// Move decks and their children under another deck, or to the top level
+pub async fn reparent_decks(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<ReparentDecksRequest>,
+) -> Result<impl IntoResponse> {
+    if request.deck_ids.is_empty() {
+        return Err(WebAppError::bad_request("No decks provided"));
+    }
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+
+    // The core treats a missing target as the top level
+    let new_parent = request.new_parent_id.map(DeckId);
+    if let Some(parent_id) = new_parent {
+        if col.get_deck(parent_id)?.is_none() {
+            return Err(WebAppError::not_found("Parent deck not found"));
+        }
+    }
+
+    let deck_ids: Vec<DeckId> = request.deck_ids.into_iter().map(DeckId).collect();
+    let count = col.reparent_decks(&deck_ids, new_parent)?.output;
+
+    drop(col);
+
+    Ok(Json(ReparentDecksResponse {
+        success: true,
+        message: format!("Moved {} deck(s)", count),
+        count,
+    }))
+}
+
+/// Persist whether a deck is collapsed in the deck list or browser sidebar
+pub async fn set_deck_collapsed(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(deck_id): Path<i64>,
+    Json(request): Json<SetDeckCollapsedRequest>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+
+    let deck_id = DeckId(deck_id);
+    if col.get_deck(deck_id)?.is_none() {
+        return Err(WebAppError::not_found("Deck not found"));
+    }
+
+    let scope = match request.scope {
+        CollapseScopeJson::Reviewer => DeckCollapseScope::Reviewer,
+        CollapseScopeJson::Browser => DeckCollapseScope::Browser,
+    };
+    col.set_deck_collapsed(deck_id, request.collapsed, scope)?;
+
+    drop(col);
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: if request.collapsed {
+            "Deck collapsed".to_string()
+        } else {
+            "Deck expanded".to_string()
+        },
+        id: Some(deck_id.0),
+    }))
+}
+
+/// Get due counts, card totals and today's study progress for a deck
+pub async fn get_deck_stats(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(deck_id): Path<i64>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+
+    let deck_id = DeckId(deck_id);
+    let deck = col
+        .get_deck(deck_id)?
+        .ok_or_else(|| WebAppError::not_found("Deck not found"))?;
+    let tree = col.deck_tree(Some(TimestampSecs::now()))?;
+    let node =
+        get_deck_in_tree(tree, deck_id).ok_or_else(|| WebAppError::not_found("Deck not found"))?;
+    let today = col.timing_today()?.days_elapsed;
+
+    drop(col);
+
+    // The stored counts are only reset when the deck is next studied
+    let common = &deck.common;
+    let studied_today = if common.last_day_studied == today {
+        DeckStudiedToday {
+            new: common.new_studied,
+            review: common.review_studied,
+            milliseconds: common.milliseconds_studied,
+        }
+    } else {
+        DeckStudiedToday {
+            new: 0,
+            review: 0,
+            milliseconds: 0,
+        }
+    };
+
+    Ok(Json(DeckStatsResponse {
+        id: deck_id.0,
+        name: deck.human_name(),
+        filtered: node.filtered,
+        new_count: node.new_count,
+        learn_count: node.learn_count,
+        review_count: node.review_count,
+        new_uncapped: node.new_uncapped,
+        learn_uncapped: node.intraday_learning + node.interday_learning_uncapped,
+        review_uncapped: node.review_uncapped,
+        cards_in_deck: node.total_in_deck,
+        cards_including_children: node.total_including_children,
+        studied_today,
+    }))
+}
+
 // Helper function to convert protobuf tree to our format
 fn convert_deck_tree(tree: anki_proto::decks::DeckTreeNode) -> DeckTree {
     fn convert_node(node: anki_proto::decks::DeckTreeNode) -> DeckNode {
         DeckNode {
             id: node.deck_id,
             name: node.name,
+            level: node.level,
             collapsed: node.collapsed,
+            filtered: node.filtered,
             new_count: node.new_count,
             learn_count: node.learn_count,
             review_count: node.review_count,
+            total_cards: node.total_including_children,
             children: node.children.into_iter().map(convert_node).collect(),
         }
     }
@@ -0,0 +1,723 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! FSRS tools for deck option presets, matching the desktop deck options
+//! screen: optimizing parameters from a preset's review history, evaluating
+//! them, simulating future workload, and saving new parameters. Everything
+//! that trains, simulates or rewrites cards runs as a background job.
+
+use anki::collection::Collection;
+use anki::config::BoolKey;
+use anki::deckconfig::DeckConfSchema11;
+use anki::deckconfig::DeckConfig;
+use anki::deckconfig::DeckConfigId;
+use anki::decks::DeckId;
+use anki::decks::DeckKind;
+use anki::services::DeckConfigService;
+use anki::services::SchedulerService;
+use anki_proto::deck_config::UpdateDeckConfigsMode;
+use anki_proto::deck_config::UpdateDeckConfigsRequest;
+use anki_proto::generic;
+use anki_proto::scheduler::ComputeFsrsParamsRequest;
+use anki_proto::scheduler::EvaluateParamsLegacyRequest;
+use anki_proto::scheduler::SimulateFsrsReviewRequest;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Extension;
+use axum::Json;
+use chrono::NaiveDate;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::auth::AuthUser;
+use crate::error::Result;
+use crate::error::WebAppError;
+use crate::routes::maintenance::JobStartedResponse;
+use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
+
+/// Parameter counts accepted by FSRS 4.5, 5 and 6. An empty list selects the
+/// default parameters.
+const VALID_PARAM_COUNTS: [usize; 4] = [0, 17, 19, 21];
+/// Presets are saved with FSRS 6 parameters only, as the desktop does; older
+/// sets can still be evaluated and simulated.
+const SAVED_PARAM_COUNTS: [usize; 2] = [0, 21];
+const MAX_SIMULATED_RETENTIONS: usize = 20;
+const MAX_DAYS_TO_SIMULATE: u32 = 3650;
+const MAX_SIMULATED_NEW_CARDS: u32 = 100_000;
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FsrsPreset {
+    pub id: i64,
+    pub name: String,
+    pub params: Vec<f32>,
+    pub desired_retention: f32,
+    /// The search optimization and evaluation use by default
+    pub search: String,
+    pub ignore_revlogs_before_date: String,
+    /// Number of decks using the preset
+    pub use_count: u32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FsrsPresetsResponse {
+    pub fsrs_enabled: bool,
+    pub presets: Vec<FsrsPreset>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct OptimizeParamsRequest {
+    /// Defaults to the preset's own search
+    #[serde(default)]
+    pub search: Option<String>,
+    /// Also check how well the optimized parameters fit the history
+    #[serde(default)]
+    pub health_check: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OptimizeParamsResult {
+    pub preset_id: i64,
+    pub search: String,
+    pub current_params: Vec<f32>,
+    pub params: Vec<f32>,
+    /// Number of reviews trained on
+    pub fsrs_items: u32,
+    /// True if the current parameters are already the best fit, or there was
+    /// no history to train on
+    pub already_optimal: bool,
+    pub health_check_passed: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct EvaluateParamsRequest {
+    #[serde(default)]
+    pub search: Option<String>,
+    /// Defaults to the preset's saved parameters
+    #[serde(default)]
+    pub params: Option<Vec<f32>>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EvaluateParamsResult {
+    pub preset_id: i64,
+    pub search: String,
+    pub log_loss: f32,
+    pub rmse_bins: f32,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SimulationOptions {
+    #[serde(default)]
+    pub search: Option<String>,
+    #[serde(default)]
+    pub params: Option<Vec<f32>>,
+    #[serde(default = "default_days_to_simulate")]
+    pub days_to_simulate: u32,
+    /// New cards to add on top of the preset's existing new cards
+    #[serde(default)]
+    pub additional_new_cards: u32,
+}
+
+fn default_days_to_simulate() -> u32 {
+    365
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SimulateWorkloadRequest {
+    /// Candidate desired retentions, each between 0 and 1
+    pub retentions: Vec<f32>,
+    #[serde(flatten)]
+    pub options: SimulationOptions,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RetentionCurve {
+    pub desired_retention: f32,
+    pub daily_review_count: Vec<u32>,
+    pub daily_new_count: Vec<u32>,
+    /// Seconds spent studying each day
+    pub daily_time_cost: Vec<f32>,
+    /// Cards memorized by the end of each day
+    pub accumulated_knowledge_acquisition: Vec<f32>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SimulateWorkloadResult {
+    pub preset_id: i64,
+    pub curves: Vec<RetentionCurve>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OptimalRetentionResult {
+    pub preset_id: i64,
+    pub optimal_retention: f32,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SaveParamsRequest {
+    pub params: Vec<f32>,
+    #[serde(default)]
+    pub desired_retention: Option<f32>,
+    /// Reschedule cards to match their new memory states
+    #[serde(default)]
+    pub reschedule: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SaveParamsResult {
+    pub preset_id: i64,
+    pub params: Vec<f32>,
+    pub desired_retention: f32,
+    /// Memory states are only kept while FSRS is enabled, and only for cards
+    /// in decks using the preset
+    pub memory_states_updated: bool,
+    pub rescheduled: bool,
+}
+
+/// List deck option presets with their FSRS settings
+pub async fn list_fsrs_presets(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+
+    // The default deck always exists, and the result lists every preset
+    let for_update = col.get_deck_configs_for_update(DeckId(1))?;
+    let mut presets = vec![];
+    for entry in for_update.all_config {
+        let Some(config) = entry.config else {
+            continue;
+        };
+        let config = DeckConfig::from(config);
+        presets.push(FsrsPreset {
+            id: config.id.0,
+            params: config.fsrs_params().clone(),
+            desired_retention: config.inner.desired_retention,
+            search: preset_search(&config, None),
+            ignore_revlogs_before_date: config.inner.ignore_revlogs_before_date.clone(),
+            name: config.name,
+            use_count: entry.use_count,
+        });
+    }
+    drop(col);
+
+    Ok(Json(FsrsPresetsResponse {
+        fsrs_enabled: for_update.fsrs,
+        presets,
+    }))
+}
+
+/// Start optimizing a preset's parameters from its review history. The
+/// result is not saved; pass the parameters to the save endpoint to use them.
+pub async fn optimize_fsrs_params(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(preset_id): Path<i64>,
+    Json(request): Json<OptimizeParamsRequest>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+    let config = get_preset(&mut col, preset_id)?;
+    drop(col);
+
+    let current_params = config.fsrs_params().clone();
+    let search = preset_search(&config, request.search);
+    let input = ComputeFsrsParamsRequest {
+        search: search.clone(),
+        current_params: current_params.clone(),
+        ignore_revlogs_before_ms: ignore_revlogs_before_ms(&config)?,
+        num_of_relearning_steps: relearning_steps_in_day(&config),
+        health_check: request.health_check,
+    };
+
+    let job_id = state
+        .job_manager
+        .spawn(auth_user.user_id, "fsrs_optimize", move || {
+            let mut col = lock_collection(&backend);
+            let output = SchedulerService::compute_fsrs_params(&mut *col, input)?;
+            drop(col);
+
+            // The optimizer hands back the current parameters unchanged if
+            // it can't improve on them
+            let already_optimal = output.params.is_empty() || output.params == current_params;
+            let result = OptimizeParamsResult {
+                preset_id,
+                search,
+                current_params,
+                params: output.params,
+                fsrs_items: output.fsrs_items,
+                already_optimal,
+                health_check_passed: output.health_check_passed,
+            };
+            serde_json::to_value(result).map_err(|e| WebAppError::internal(&e.to_string()))
+        });
+
+    Ok(job_started(job_id))
+}
+
+/// Start measuring how well parameters predict a preset's review history
+pub async fn evaluate_fsrs_params(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(preset_id): Path<i64>,
+    Json(request): Json<EvaluateParamsRequest>,
+) -> Result<impl IntoResponse> {
+    if let Some(params) = &request.params {
+        validate_params(params)?;
+    }
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+    let config = get_preset(&mut col, preset_id)?;
+    drop(col);
+
+    let search = preset_search(&config, request.search);
+    let input = EvaluateParamsLegacyRequest {
+        params: request
+            .params
+            .unwrap_or_else(|| config.fsrs_params().clone()),
+        search: search.clone(),
+        ignore_revlogs_before_ms: ignore_revlogs_before_ms(&config)?,
+    };
+
+    let job_id = state
+        .job_manager
+        .spawn(auth_user.user_id, "fsrs_evaluate", move || {
+            let mut col = lock_collection(&backend);
+            let output = SchedulerService::evaluate_params_legacy(&mut *col, input)?;
+            drop(col);
+
+            let result = EvaluateParamsResult {
+                preset_id,
+                search,
+                log_loss: output.log_loss,
+                rmse_bins: output.rmse_bins,
+            };
+            serde_json::to_value(result).map_err(|e| WebAppError::internal(&e.to_string()))
+        });
+
+    Ok(job_started(job_id))
+}
+
+/// Start simulating the daily workload of a preset's cards for each of the
+/// candidate desired retentions
+pub async fn simulate_fsrs_workload(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(preset_id): Path<i64>,
+    Json(request): Json<SimulateWorkloadRequest>,
+) -> Result<impl IntoResponse> {
+    if request.retentions.is_empty() || request.retentions.len() > MAX_SIMULATED_RETENTIONS {
+        return Err(WebAppError::bad_request(&format!(
+            "Between 1 and {} retentions must be provided",
+            MAX_SIMULATED_RETENTIONS
+        )));
+    }
+    if let Some(retention) = request
+        .retentions
+        .iter()
+        .find(|retention| !is_retention(**retention))
+    {
+        return Err(WebAppError::bad_request(&format!(
+            "Invalid retention {}: must be between 0 and 1",
+            retention
+        )));
+    }
+    validate_simulation(&request.options)?;
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+    let config = get_preset(&mut col, preset_id)?;
+    let input = simulation_request(&col, &config, request.options);
+    drop(col);
+
+    let retentions = request.retentions;
+    let job_id = state
+        .job_manager
+        .spawn(auth_user.user_id, "fsrs_simulate", move || {
+            let mut curves = Vec::with_capacity(retentions.len());
+            for desired_retention in retentions {
+                let input = SimulateFsrsReviewRequest {
+                    desired_retention,
+                    ..input.clone()
+                };
+                // Lock per simulation, so long runs don't starve other requests
+                let mut col = lock_collection(&backend);
+                let output = SchedulerService::simulate_fsrs_review(&mut *col, input)?;
+                drop(col);
+
+                curves.push(RetentionCurve {
+                    desired_retention,
+                    daily_review_count: output.daily_review_count,
+                    daily_new_count: output.daily_new_count,
+                    daily_time_cost: output.daily_time_cost,
+                    accumulated_knowledge_acquisition: output.accumulated_knowledge_acquisition,
+                });
+            }
+
+            serde_json::to_value(SimulateWorkloadResult { preset_id, curves })
+                .map_err(|e| WebAppError::internal(&e.to_string()))
+        });
+
+    Ok(job_started(job_id))
+}
+
+/// Start searching for the desired retention that minimizes study time per
+/// card memorized
+pub async fn compute_optimal_retention(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(preset_id): Path<i64>,
+    Json(request): Json<SimulationOptions>,
+) -> Result<impl IntoResponse> {
+    validate_simulation(&request)?;
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+    let config = get_preset(&mut col, preset_id)?;
+    let input = simulation_request(&col, &config, request);
+    drop(col);
+
+    let job_id = state
+        .job_manager
+        .spawn(auth_user.user_id, "fsrs_optimal_retention", move || {
+            let mut col = lock_collection(&backend);
+            let output = SchedulerService::compute_optimal_retention(&mut *col, input)?;
+            drop(col);
+
+            serde_json::to_value(OptimalRetentionResult {
+                preset_id,
+                optimal_retention: output.optimal_retention,
+            })
+            .map_err(|e| WebAppError::internal(&e.to_string()))
+        });
+
+    Ok(job_started(job_id))
+}
+
+/// Start saving new parameters (and optionally desired retention) to a
+/// preset. Like saving the desktop deck options, this recomputes the memory
+/// states of the preset's cards when FSRS is enabled, and can reschedule them.
+pub async fn save_fsrs_params(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(preset_id): Path<i64>,
+    Json(request): Json<SaveParamsRequest>,
+) -> Result<impl IntoResponse> {
+    validate_params(&request.params)?;
+    if !SAVED_PARAM_COUNTS.contains(&request.params.len()) {
+        return Err(WebAppError::bad_request(&format!(
+            "Only FSRS 6 parameters can be saved: expected 21, got {}",
+            request.params.len()
+        )));
+    }
+    if let Some(retention) = request.desired_retention {
+        if !is_retention(retention) {
+            return Err(WebAppError::bad_request(
+                "Desired retention must be between 0 and 1",
+            ));
+        }
+    }
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+    get_preset(&mut col, preset_id)?;
+    drop(col);
+
+    let job_id = state
+        .job_manager
+        .spawn(auth_user.user_id, "fsrs_save_params", move || {
+            let mut col = lock_collection(&backend);
+            let result = save_params(&mut col, preset_id, request)?;
+            drop(col);
+
+            serde_json::to_value(result).map_err(|e| WebAppError::internal(&e.to_string()))
+        });
+
+    Ok(job_started(job_id))
+}
+
+fn save_params(
+    col: &mut Collection,
+    preset_id: i64,
+    request: SaveParamsRequest,
+) -> Result<SaveParamsResult> {
+    let mut config = get_preset(col, preset_id)?;
+    config.inner.fsrs_params_6 = request.params;
+    if let Some(retention) = request.desired_retention {
+        config.inner.desired_retention = retention;
+    }
+
+    let Some(deck_id) = first_deck_using(col, config.id)? else {
+        // No cards use the preset, so there is nothing to recompute
+        if config.inner.fsrs_params_6.is_empty() {
+            config.inner.fsrs_params_5.clear();
+            config.inner.fsrs_params_4.clear();
+        }
+        let result = unchanged_deck_result(&config);
+        let json = serde_json::to_vec(&DeckConfSchema11::from(config))
+            .map_err(|e| WebAppError::internal(&e.to_string()))?;
+        DeckConfigService::add_or_update_deck_config_legacy(col, generic::Json { json })?;
+        return Ok(result);
+    };
+
+    // Save through the deck options code path, keeping the deck's limits and
+    // the collection-wide options as they are
+    let current = col.get_deck_configs_for_update(deck_id)?;
+    let mut result = unchanged_deck_result(&config);
+    result.memory_states_updated = current.fsrs;
+    result.rescheduled = current.fsrs && request.reschedule;
+    DeckConfigService::update_deck_configs(
+        col,
+        UpdateDeckConfigsRequest {
+            target_deck_id: deck_id.0,
+            configs: vec![config.into()],
+            removed_config_ids: vec![],
+            mode: UpdateDeckConfigsMode::Normal as i32,
+            card_state_customizer: current.card_state_customizer,
+            limits: current.current_deck.and_then(|deck| deck.limits),
+            new_cards_ignore_review_limit: current.new_cards_ignore_review_limit,
+            fsrs: current.fsrs,
+            apply_all_parent_limits: current.apply_all_parent_limits,
+            fsrs_reschedule: request.reschedule,
+            fsrs_health_check: current.fsrs_health_check,
+        },
+    )?;
+
+    Ok(result)
+}
+
+fn unchanged_deck_result(config: &DeckConfig) -> SaveParamsResult {
+    SaveParamsResult {
+        preset_id: config.id.0,
+        params: config.fsrs_params().clone(),
+        desired_retention: config.inner.desired_retention,
+        memory_states_updated: false,
+        rescheduled: false,
+    }
+}
+
+fn job_started(job_id: String) -> impl IntoResponse {
+    (
+        StatusCode::ACCEPTED,
+        Json(JobStartedResponse {
+            success: true,
+            job_id,
+        }),
+    )
+}
+
+fn get_preset(col: &mut Collection, preset_id: i64) -> Result<DeckConfig> {
+    col.get_deck_config(DeckConfigId(preset_id), false)?
+        .ok_or_else(|| WebAppError::not_found("Preset not found"))
+}
+
+fn first_deck_using(col: &mut Collection, preset_id: DeckConfigId) -> Result<Option<DeckId>> {
+    for (deck_id, _) in col.get_all_deck_names(false)? {
+        let Some(deck) = col.get_deck(deck_id)? else {
+            continue;
+        };
+        if let DeckKind::Normal(normal) = &deck.kind {
+            if normal.config_id == preset_id.0 {
+                return Ok(Some(deck_id));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The search used to gather a preset's cards: the one given, else the
+/// preset's own, else its unsuspended cards, as in the desktop deck options
+fn preset_search(config: &DeckConfig, search: Option<String>) -> String {
+    search
+        .filter(|search| !search.trim().is_empty())
+        .or_else(|| Some(config.inner.param_search.clone()).filter(|s| !s.trim().is_empty()))
+        .unwrap_or_else(|| {
+            let name = config.name.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("preset:\"{}\" -is:suspended", name)
+        })
+}
+
+/// Relearning steps that complete within a day, which FSRS treats as
+/// short-term reviews
+fn relearning_steps_in_day(config: &DeckConfig) -> u32 {
+    let mut elapsed_mins = 0.0;
+    let mut count = 0;
+    for step in &config.inner.relearn_steps {
+        elapsed_mins += step;
+        if elapsed_mins >= 1440.0 {
+            break;
+        }
+        count += 1;
+    }
+    count
+}
+
+fn ignore_revlogs_before_ms(config: &DeckConfig) -> Result<i64> {
+    let date = &config.inner.ignore_revlogs_before_date;
+    if date.is_empty() {
+        return Ok(0);
+    }
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+        WebAppError::bad_request(&format!(
+            "Preset has an invalid ignore-before date: {}",
+            date
+        ))
+    })?;
+    Ok(date
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp_millis())
+}
+
+/// Strictly between 0 and 1, and not NaN
+fn is_retention(value: f32) -> bool {
+    value > 0.0 && value < 1.0
+}
+
+fn validate_params(params: &[f32]) -> Result<()> {
+    if !VALID_PARAM_COUNTS.contains(&params.len()) {
+        return Err(WebAppError::bad_request(&format!(
+            "Expected 17, 19 or 21 parameters, got {}",
+            params.len()
+        )));
+    }
+    if params.iter().any(|param| !param.is_finite()) {
+        return Err(WebAppError::bad_request(
+            "Parameters must be finite numbers",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_simulation(options: &SimulationOptions) -> Result<()> {
+    if let Some(params) = &options.params {
+        validate_params(params)?;
+    }
+    if options.days_to_simulate == 0 || options.days_to_simulate > MAX_DAYS_TO_SIMULATE {
+        return Err(WebAppError::bad_request(&format!(
+            "days_to_simulate must be between 1 and {}",
+            MAX_DAYS_TO_SIMULATE
+        )));
+    }
+    if options.additional_new_cards > MAX_SIMULATED_NEW_CARDS {
+        return Err(WebAppError::bad_request(&format!(
+            "additional_new_cards must be at most {}",
+            MAX_SIMULATED_NEW_CARDS
+        )));
+    }
+    Ok(())
+}
+
+/// Simulate with the preset's limits and steps, as the desktop simulator does
+fn simulation_request(
+    col: &Collection,
+    config: &DeckConfig,
+    options: SimulationOptions,
+) -> SimulateFsrsReviewRequest {
+    let inner = &config.inner;
+    SimulateFsrsReviewRequest {
+        params: options
+            .params
+            .unwrap_or_else(|| config.fsrs_params().clone()),
+        desired_retention: inner.desired_retention,
+        deck_size: options.additional_new_cards,
+        days_to_simulate: options.days_to_simulate,
+        new_limit: inner.new_per_day,
+        review_limit: inner.reviews_per_day,
+        max_interval: inner.maximum_review_interval,
+        search: preset_search(config, options.search),
+        new_cards_ignore_review_limit: col.get_config_bool(BoolKey::NewCardsIgnoreReviewLimit),
+        easy_days_percentages: inner.easy_days_percentages.clone(),
+        review_order: inner.review_order,
+        suspend_after_lapse_count: None,
+        historical_retention: inner.historical_retention,
+        learning_step_count: inner.learn_steps.len() as u32,
+        relearning_step_count: inner.relearn_steps.len() as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_search() {
+        let mut config = DeckConfig {
+            name: r#"My "Hard" \ Cards"#.to_string(),
+            ..DeckConfig::default()
+        };
+        assert_eq!(
+            preset_search(&config, None),
+            r#"preset:"My \"Hard\" \\ Cards" -is:suspended"#
+        );
+
+        config.inner.param_search = "deck:Spanish".to_string();
+        assert_eq!(preset_search(&config, None), "deck:Spanish");
+        assert_eq!(
+            preset_search(&config, Some("tag:verbs".to_string())),
+            "tag:verbs"
+        );
+        assert_eq!(
+            preset_search(&config, Some(" ".to_string())),
+            "deck:Spanish"
+        );
+    }
+
+    #[test]
+    fn test_relearning_steps_in_day() {
+        let mut config = DeckConfig::default();
+        config.inner.relearn_steps = vec![10.0, 60.0];
+        assert_eq!(relearning_steps_in_day(&config), 2);
+        config.inner.relearn_steps = vec![10.0, 1440.0, 60.0];
+        assert_eq!(relearning_steps_in_day(&config), 1);
+        config.inner.relearn_steps = vec![];
+        assert_eq!(relearning_steps_in_day(&config), 0);
+    }
+
+    #[test]
+    fn test_ignore_revlogs_before() {
+        let mut config = DeckConfig::default();
+        assert_eq!(ignore_revlogs_before_ms(&config).unwrap(), 0);
+        config.inner.ignore_revlogs_before_date = "2024-01-02".to_string();
+        assert_eq!(
+            ignore_revlogs_before_ms(&config).unwrap(),
+            1_704_153_600_000
+        );
+        config.inner.ignore_revlogs_before_date = "yesterday".to_string();
+        assert!(ignore_revlogs_before_ms(&config).is_err());
+    }
+
+    #[test]
+    fn test_validate_params() {
+        assert!(validate_params(&[]).is_ok());
+        assert!(validate_params(&[0.5; 21]).is_ok());
+        assert!(validate_params(&[0.5; 20]).is_err());
+        let mut params = [0.5; 19];
+        params[3] = f32::NAN;
+        assert!(validate_params(&params).is_err());
+    }
+}
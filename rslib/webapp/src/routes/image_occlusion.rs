@@ -0,0 +1,251 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use anki::collection::Collection;
+use anki::decks::DeckId;
+use anki::notes::NoteId;
+use anki::services::MediaService;
+use anki_proto::image_occlusion::AddImageOcclusionNoteRequest;
+use anki_proto::notetypes::stock_notetype::OriginalStockKind;
+use axum::extract::Multipart;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Extension;
+use axum::Json;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::auth::AuthUser;
+use crate::error::Result;
+use crate::error::WebAppError;
+use crate::image_occlusion::occludes_inactive;
+use crate::image_occlusion::occlusions_from_note;
+use crate::image_occlusion::occlusions_to_field;
+use crate::image_occlusion::validate_occlusions;
+use crate::image_occlusion::Occlusion;
+use crate::media::image_dimensions;
+use crate::routes::media::extract_file_from_multipart;
+use crate::routes::notes::MessageResponse;
+use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OcclusionImageResponse {
+    pub success: bool,
+    pub filename: String,
+    /// Unknown for formats the server can't decode, such as SVG
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateOcclusionNoteRequest {
+    /// An image previously added to the media folder
+    pub image: String,
+    pub deck_id: i64,
+    /// Defaults to the first image occlusion notetype, which is added if the
+    /// collection doesn't have one
+    pub notetype_id: Option<i64>,
+    pub occlusions: Vec<Occlusion>,
+    #[serde(default)]
+    pub occlude_inactive: bool,
+    #[serde(default)]
+    pub header: String,
+    #[serde(default)]
+    pub back_extra: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CreateOcclusionNoteResponse {
+    pub success: bool,
+    pub note_id: i64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct UpdateOcclusionNoteRequest {
+    pub occlusions: Vec<Occlusion>,
+    #[serde(default)]
+    pub occlude_inactive: bool,
+    /// Left unchanged if not provided
+    pub header: Option<String>,
+    pub back_extra: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OcclusionNoteResponse {
+    pub note_id: i64,
+    /// The occluded image, which can be fetched from the media endpoint
+    pub image: String,
+    pub occlusions: Vec<Occlusion>,
+    pub occlude_inactive: bool,
+    pub header: String,
+    pub back_extra: String,
+    pub tags: Vec<String>,
+}
+
+/// Upload an image to occlude. It is added to the media folder like any
+/// other file; its size is returned so clients can position shapes.
+pub async fn upload_occlusion_image(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    multipart: Multipart,
+) -> Result<impl IntoResponse> {
+    let (desired_name, data) = extract_file_from_multipart(multipart).await?;
+    let content_type = mime_guess::from_path(&desired_name).first_or_octet_stream();
+    if content_type.type_() != mime_guess::mime::IMAGE {
+        return Err(WebAppError::bad_request("Only images can be occluded"));
+    }
+    let dimensions = image_dimensions(&data);
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+    let filename = col
+        .add_media_file(anki_proto::media::AddMediaFileRequest {
+            desired_name,
+            data: data.to_vec(),
+        })?
+        .val;
+    drop(col);
+
+    Ok(Json(OcclusionImageResponse {
+        success: true,
+        filename,
+        width: dimensions.map(|(width, _)| width),
+        height: dimensions.map(|(_, height)| height),
+    }))
+}
+
+/// Create an image occlusion note from a list of shapes
+pub async fn create_occlusion_note(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<CreateOcclusionNoteRequest>,
+) -> Result<impl IntoResponse> {
+    validate_occlusions(&request.occlusions)?;
+    if request.image.contains('/') || request.image.contains('\\') || request.image.contains("..") {
+        return Err(WebAppError::bad_request("Invalid image filename"));
+    }
+    let image_path = state
+        .backend_manager
+        .get_media_folder_path(auth_user.user_id, &auth_user.username)
+        .join(&request.image);
+    if !image_path.is_file() {
+        return Err(WebAppError::bad_request("Image not found in media folder"));
+    }
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+
+    let deck_id = DeckId(request.deck_id);
+    if col.get_deck(deck_id)?.is_none() {
+        return Err(WebAppError::not_found("Deck not found"));
+    }
+
+    let output = col.add_image_occlusion_note_to_deck(
+        AddImageOcclusionNoteRequest {
+            image_path: image_path.to_string_lossy().into_owned(),
+            occlusions: occlusions_to_field(&request.occlusions, request.occlude_inactive),
+            header: request.header,
+            back_extra: request.back_extra,
+            tags: request.tags,
+            notetype_id: request.notetype_id.unwrap_or(0),
+        },
+        deck_id,
+    )?;
+    drop(col);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateOcclusionNoteResponse {
+            success: true,
+            note_id: output.output.0,
+        }),
+    ))
+}
+
+/// Get an image occlusion note's shapes for editing
+pub async fn get_occlusion_note(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(note_id): Path<i64>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+
+    let note_id = NoteId(note_id);
+    ensure_occlusion_note(&mut col, note_id)?;
+    let note = col.get_image_occlusion_note_inner(note_id)?;
+    drop(col);
+
+    Ok(Json(OcclusionNoteResponse {
+        note_id: note_id.0,
+        image: note.image_file_name,
+        occlude_inactive: occludes_inactive(&note.occlusions),
+        occlusions: occlusions_from_note(note.occlusions),
+        header: note.header,
+        back_extra: note.back_extra,
+        tags: note.tags,
+    }))
+}
+
+/// Replace an image occlusion note's shapes. The image can't be changed.
+pub async fn update_occlusion_note(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(note_id): Path<i64>,
+    Json(request): Json<UpdateOcclusionNoteRequest>,
+) -> Result<impl IntoResponse> {
+    validate_occlusions(&request.occlusions)?;
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+
+    let note_id = NoteId(note_id);
+    ensure_occlusion_note(&mut col, note_id)?;
+    let existing = col.get_image_occlusion_note_inner(note_id)?;
+    col.update_image_occlusion_note(
+        note_id,
+        &occlusions_to_field(&request.occlusions, request.occlude_inactive),
+        request.header.as_deref().unwrap_or(&existing.header),
+        request
+            .back_extra
+            .as_deref()
+            .unwrap_or(&existing.back_extra),
+        request.tags.unwrap_or(existing.tags),
+    )?;
+    drop(col);
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: "Note updated successfully".to_string(),
+    }))
+}
+
+/// Ensure the note exists and uses an image occlusion notetype
+fn ensure_occlusion_note(col: &mut Collection, note_id: NoteId) -> Result<()> {
+    let note = col
+        .storage
+        .get_note(note_id)?
+        .ok_or_else(|| WebAppError::not_found("Note not found"))?;
+    let notetype = col
+        .get_notetype(note.notetype_id)?
+        .ok_or_else(|| WebAppError::not_found("Notetype not found"))?;
+    if notetype.config.original_stock_kind() != OriginalStockKind::ImageOcclusion {
+        return Err(WebAppError::bad_request("Not an image occlusion note"));
+    }
+    Ok(())
+}
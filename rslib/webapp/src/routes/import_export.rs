@@ -10,15 +10,18 @@ use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Serialize;
 use tempfile::NamedTempFile;
 
 use crate::auth::AuthUser;
 use crate::error::Result;
 use crate::error::WebAppError;
+use crate::metrics::metrics;
 use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct WebImportResponse {
     pub success: bool,
     pub message: String,
@@ -65,7 +68,7 @@ pub async fn import_apkg(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     let path = temp_file.path().to_string_lossy().to_string();
 
@@ -80,15 +83,14 @@ pub async fn import_apkg(
         }),
     };
 
-    let response = col
-        .import_anki_package(request)
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let response = col.import_anki_package(request)?;
 
     let log = response.log.unwrap_or_default();
     let new_count = log.new.len() as u32;
     let updated_count = log.updated.len() as u32;
 
     drop(col);
+    metrics().record_import();
 
     Ok((
         StatusCode::CREATED,
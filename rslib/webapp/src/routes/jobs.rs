@@ -0,0 +1,45 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Extension;
+use axum::Json;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::auth::AuthUser;
+use crate::error::Result;
+use crate::error::WebAppError;
+use crate::jobs::JobInfo;
+use crate::routes::AuthRouteState;
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct JobsListResponse {
+    pub jobs: Vec<JobInfo>,
+}
+
+/// List the current user's background jobs
+pub async fn list_jobs(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    Ok(Json(JobsListResponse {
+        jobs: state.job_manager.list_for_user(auth_user.user_id),
+    }))
+}
+
+/// Get the status (and result, once finished) of a background job
+pub async fn get_job(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(job_id): Path<String>,
+) -> Result<impl IntoResponse> {
+    let job = state
+        .job_manager
+        .get(auth_user.user_id, &job_id)
+        .ok_or_else(|| WebAppError::not_found("Job not found"))?;
+
+    Ok(Json(job))
+}
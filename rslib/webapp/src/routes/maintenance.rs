@@ -0,0 +1,310 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use anki::notes::NoteId;
+use anki::notetype::NotetypeId;
+use anki::search::SortMode;
+use anki::services::CardRenderingService;
+use anki::services::CardsService;
+use anki::services::CollectionService;
+use anki::text::strip_html_preserving_media_filenames;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Extension;
+use axum::Json;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::auth::AuthUser;
+use crate::error::Result;
+use crate::error::WebAppError;
+use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct JobStartedResponse {
+    pub success: bool,
+    pub job_id: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CheckDatabaseResult {
+    pub problems: Vec<String>,
+    pub ok: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EmptyCardsNote {
+    pub note_id: i64,
+    pub card_ids: Vec<i64>,
+    pub will_delete_note: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EmptyCardsResult {
+    pub report: String,
+    pub notes: Vec<EmptyCardsNote>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DeleteEmptyCardsRequest {
+    pub card_ids: Vec<i64>,
+    /// Keep the first card of notes whose cards are all empty, so the notes
+    /// aren't deleted, as the desktop does by default
+    #[serde(default = "default_keep_notes")]
+    pub keep_notes: bool,
+}
+
+fn default_keep_notes() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DeleteEmptyCardsResponse {
+    pub success: bool,
+    pub message: String,
+    pub removed_count: usize,
+    /// Requested cards that were left alone, because they are not empty or
+    /// are the card kept for their note
+    pub skipped_card_ids: Vec<i64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FindDuplicatesRequest {
+    pub notetype_id: i64,
+    pub field_name: String,
+    /// Optional extra search to narrow down the notes being compared
+    #[serde(default)]
+    pub search: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DuplicateGroup {
+    pub value: String,
+    pub note_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FindDuplicatesResult {
+    pub duplicates: Vec<DuplicateGroup>,
+}
+
+/// Start a database check in the background. The problems found (and fixed)
+/// are available from the job once it completes.
+pub async fn check_database(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let job_id = state
+        .job_manager
+        .spawn(auth_user.user_id, "check_database", move || {
+            let mut col = lock_collection(&backend);
+            let output = CollectionService::check_database(&mut *col)?;
+            drop(col);
+
+            let result = CheckDatabaseResult {
+                ok: output.problems.is_empty(),
+                problems: output.problems,
+            };
+            serde_json::to_value(result).map_err(|e| WebAppError::internal(&e.to_string()))
+        });
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobStartedResponse {
+            success: true,
+            job_id,
+        }),
+    ))
+}
+
+/// Start building an empty cards report in the background
+pub async fn get_empty_cards(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let job_id = state
+        .job_manager
+        .spawn(auth_user.user_id, "empty_cards", move || {
+            let mut col = lock_collection(&backend);
+            let report = col.get_empty_cards()?;
+            drop(col);
+
+            let result = EmptyCardsResult {
+                report: report.report,
+                notes: report
+                    .notes
+                    .into_iter()
+                    .map(|note| EmptyCardsNote {
+                        note_id: note.note_id,
+                        card_ids: note.card_ids,
+                        will_delete_note: note.will_delete_note,
+                    })
+                    .collect(),
+            };
+            serde_json::to_value(result).map_err(|e| WebAppError::internal(&e.to_string()))
+        });
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobStartedResponse {
+            success: true,
+            job_id,
+        }),
+    ))
+}
+
+/// Delete the selected empty cards. The empty cards report is rebuilt first,
+/// and only cards it lists are deleted.
+pub async fn delete_empty_cards(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<DeleteEmptyCardsRequest>,
+) -> Result<impl IntoResponse> {
+    if request.card_ids.is_empty() {
+        return Err(WebAppError::bad_request("No card IDs provided"));
+    }
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+
+    let report = col.get_empty_cards()?;
+    let mut deletable = HashSet::new();
+    for note in report.notes {
+        if request.keep_notes && note.will_delete_note {
+            deletable.extend(note.card_ids.into_iter().skip(1));
+        } else {
+            deletable.extend(note.card_ids);
+        }
+    }
+    let (card_ids, skipped_card_ids): (Vec<i64>, Vec<i64>) = request
+        .card_ids
+        .into_iter()
+        .partition(|card_id| deletable.contains(card_id));
+
+    let removed_count = if card_ids.is_empty() {
+        0
+    } else {
+        col.remove_cards(anki_proto::cards::RemoveCardsRequest { card_ids })?
+            .count as usize
+    };
+
+    drop(col);
+
+    Ok(Json(DeleteEmptyCardsResponse {
+        success: true,
+        message: format!("Deleted {} empty card(s)", removed_count),
+        removed_count,
+        skipped_card_ids,
+    }))
+}
+
+/// Start a search for notes of a notetype sharing the same field content
+pub async fn find_duplicates(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<FindDuplicatesRequest>,
+) -> Result<impl IntoResponse> {
+    if request.field_name.trim().is_empty() {
+        return Err(WebAppError::bad_request("Field name cannot be empty"));
+    }
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let job_id = state
+        .job_manager
+        .spawn(auth_user.user_id, "find_duplicates", move || {
+            let mut col = lock_collection(&backend);
+            let duplicates = find_duplicates_in_collection(&mut col, &request)?;
+            drop(col);
+
+            serde_json::to_value(FindDuplicatesResult { duplicates })
+                .map_err(|e| WebAppError::internal(&e.to_string()))
+        });
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobStartedResponse {
+            success: true,
+            job_id,
+        }),
+    ))
+}
+
+/// Group notes by the stripped content of the given field, like the desktop
+/// browser's "Find Duplicates". Empty fields never count as duplicates.
+fn find_duplicates_in_collection(
+    col: &mut anki::collection::Collection,
+    request: &FindDuplicatesRequest,
+) -> Result<Vec<DuplicateGroup>> {
+    let notetype = col
+        .get_notetype(NotetypeId(request.notetype_id))?
+        .ok_or_else(|| WebAppError::not_found("Notetype not found"))?;
+
+    let field_idx = notetype
+        .fields
+        .iter()
+        .position(|f| f.name.eq_ignore_ascii_case(&request.field_name))
+        .ok_or_else(|| WebAppError::bad_request("Field not found in notetype"))?;
+
+    let mut query = format!("mid:{}", notetype.id.0);
+    if let Some(search) = request.search.as_deref().filter(|s| !s.trim().is_empty()) {
+        query = format!("{} ({})", query, search);
+    }
+
+    let note_ids = col
+        .search_notes(&query, SortMode::NoOrder)
+        .map_err(|e| WebAppError::bad_request(&e.to_string()))?;
+
+    let mut groups: HashMap<String, Vec<NoteId>> = HashMap::new();
+    for nid in note_ids {
+        let Some(note) = col.storage.get_note(nid)? else {
+            continue;
+        };
+        let Some(field) = note.fields().get(field_idx) else {
+            continue;
+        };
+        let value = strip_html_preserving_media_filenames(field)
+            .trim()
+            .to_string();
+        if value.is_empty() {
+            continue;
+        }
+        groups.entry(value).or_default().push(nid);
+    }
+
+    let mut duplicates: Vec<DuplicateGroup> = groups
+        .into_iter()
+        .filter(|(_, nids)| nids.len() > 1)
+        .map(|(value, nids)| DuplicateGroup {
+            value,
+            note_ids: nids.into_iter().map(|nid| nid.0).collect(),
+        })
+        .collect();
+    duplicates.sort_by(|a, b| {
+        b.note_ids
+            .len()
+            .cmp(&a.note_ids.len())
+            .then_with(|| a.value.cmp(&b.value))
+    });
+
+    Ok(duplicates)
+}
@@ -1,26 +1,48 @@
-use std::path::PathBuf;
+use std::io::SeekFrom;
 
 use anki::services::MediaService;
 use axum::body::Body;
 use axum::body::Bytes;
 use axum::extract::Multipart;
 use axum::extract::Path;
+use axum::extract::Query;
 use axum::extract::State;
 use axum::http::header;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::response::Response;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 use tokio::fs;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncSeekExt;
+use tokio_util::io::ReaderStream;
 
 use crate::auth::AuthUser;
 use crate::error::Result;
 use crate::error::WebAppError;
+use crate::media::can_thumbnail;
+use crate::media::entity_tag;
+use crate::media::http_date;
+use crate::media::is_not_modified;
+use crate::media::make_thumbnail;
+use crate::media::render_latex;
+use crate::media::requested_range;
+use crate::media::RangeRequest;
+use crate::media::MAX_THUMBNAIL_SIZE;
+use crate::media::MEDIA_MAX_AGE_SECS;
+use crate::routes::maintenance::JobStartedResponse;
 use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
 
-#[derive(Debug, Serialize)]
+/// Upper bound on the number of files returned by a single listing
+pub const MAX_MEDIA_LIST_LIMIT: usize = 1000;
+
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct CheckMediaResponse {
     pub unused: Vec<String>,
     pub missing: Vec<String>,
@@ -29,18 +51,18 @@ pub struct CheckMediaResponse {
     pub have_trash: bool,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct AddMediaResponse {
     pub success: bool,
     pub filename: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DeleteMediaRequest {
     pub filenames: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct DeleteMediaResponse {
     pub success: bool,
     pub message: String,
@@ -55,12 +77,10 @@ pub async fn check_media(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Check media
-    let result = col
-        .check_media()
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let result = col.check_media()?;
 
     drop(col);
 
@@ -73,11 +93,23 @@ pub async fn check_media(
     }))
 }
 
-/// Get a media file by filename
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MediaQuery {
+    /// Scale images down to fit within this width
+    pub width: Option<u32>,
+    /// Scale images down to fit within this height
+    pub height: Option<u32>,
+}
+
+/// Get a media file by filename. Responses are streamed, support byte
+/// ranges and conditional requests, and images can be scaled down with the
+/// `width` and `height` parameters.
 pub async fn get_media(
     State(state): State<AuthRouteState>,
     Extension(auth_user): Extension<AuthUser>,
     Path(filename): Path<String>,
+    Query(query): Query<MediaQuery>,
+    headers: HeaderMap,
 ) -> Result<Response> {
     // Reject path traversal attempts
     if filename.contains('/') || filename.contains('\\') || filename.contains("..") {
@@ -99,59 +131,117 @@ pub async fn get_media(
         return Err(WebAppError::bad_request("Invalid filename"));
     }
 
-    let data = fs::read(&canonical_file)
+    let metadata = fs::metadata(&canonical_file)
         .await
-        .map_err(|_| WebAppError::not_found("Media file not found"))?;
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .ok_or_else(|| WebAppError::not_found("Media file not found"))?;
+    let len = metadata.len();
+    let modified = metadata.modified().ok();
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let sha1 = {
+        let col = lock_collection(&backend);
+        col.media().and_then(|media| {
+            let mut checksum = media.checksum_getter();
+            checksum(&filename)
+        })?
+    };
+
+    let content_type = mime_guess::from_path(&filename)
+        .first_or_octet_stream()
+        .to_string();
+    let thumbnail_size = match (query.width, query.height) {
+        (None, None) => None,
+        (width, height) => Some((
+            width
+                .unwrap_or(MAX_THUMBNAIL_SIZE)
+                .clamp(1, MAX_THUMBNAIL_SIZE),
+            height
+                .unwrap_or(MAX_THUMBNAIL_SIZE)
+                .clamp(1, MAX_THUMBNAIL_SIZE),
+        )),
+    }
+    .filter(|_| can_thumbnail(&content_type));
+
+    let etag = entity_tag(sha1, len, modified, thumbnail_size);
+    let mut builder = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(
+            header::CACHE_CONTROL,
+            format!("private, max-age={}", MEDIA_MAX_AGE_SECS),
+        )
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+    if let Some(modified) = modified {
+        builder = builder.header(header::LAST_MODIFIED, http_date(modified));
+    }
 
-    let content_type = mime_type_for_filename(&filename);
+    if is_not_modified(&headers, &etag, modified) {
+        return Ok(builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap());
+    }
 
-    Ok(Response::builder()
-        .header(header::CONTENT_TYPE, content_type)
-        .body(Body::from(data))
-        .unwrap())
-}
-
-/// Determine MIME type from file extension
-fn mime_type_for_filename(filename: &str) -> &'static str {
-    let ext = PathBuf::from(filename)
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("")
-        .to_lowercase();
-    // Use a leaked string so we can match on &str
-    let ext = ext.as_str();
-    match ext {
-        // Images
-        "jpg" | "jpeg" => "image/jpeg",
-        "png" => "image/png",
-        "gif" => "image/gif",
-        "svg" => "image/svg+xml",
-        "webp" => "image/webp",
-        "ico" => "image/x-icon",
-        "bmp" => "image/bmp",
-        "tiff" | "tif" => "image/tiff",
-        // Audio
-        "mp3" => "audio/mpeg",
-        "ogg" | "oga" => "audio/ogg",
-        "wav" => "audio/wav",
-        "flac" => "audio/flac",
-        "m4a" | "aac" => "audio/aac",
-        "opus" => "audio/opus",
-        // Video
-        "mp4" => "video/mp4",
-        "webm" => "video/webm",
-        "ogv" => "video/ogg",
-        "mkv" => "video/x-matroska",
-        "avi" => "video/x-msvideo",
-        // Text/other
-        "txt" => "text/plain",
-        "html" | "htm" => "text/html",
-        "css" => "text/css",
-        "js" => "application/javascript",
-        "json" => "application/json",
-        "pdf" => "application/pdf",
-        _ => "application/octet-stream",
+    if let Some((width, height)) = thumbnail_size {
+        let data = fs::read(&canonical_file)
+            .await
+            .map_err(|_| WebAppError::not_found("Media file not found"))?;
+        let (data, content_type) =
+            tokio::task::spawn_blocking(move || match make_thumbnail(&data, width, height) {
+                Ok(Some((thumbnail, content_type))) => (thumbnail, content_type.to_string()),
+                Ok(None) => (data, content_type),
+                Err(e) => {
+                    tracing::debug!("Failed to create thumbnail, sending original: {}", e);
+                    (data, content_type)
+                }
+            })
+            .await
+            .map_err(|e| WebAppError::internal(&e.to_string()))?;
+
+        return Ok(builder
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, data.len())
+            .body(Body::from(data))
+            .unwrap());
     }
+
+    let mut file = fs::File::open(&canonical_file)
+        .await
+        .map_err(|_| WebAppError::not_found("Media file not found"))?;
+    let builder = builder
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    let response = match requested_range(&headers, &etag, len) {
+        RangeRequest::Full => builder
+            .header(header::CONTENT_LENGTH, len)
+            .body(Body::from_stream(ReaderStream::new(file))),
+        RangeRequest::Partial(range) => {
+            let (start, end) = (*range.start(), *range.end());
+            file.seek(SeekFrom::Start(start))
+                .await
+                .map_err(|e| WebAppError::internal(&e.to_string()))?;
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, end, len),
+                )
+                .header(header::CONTENT_LENGTH, end - start + 1)
+                .body(Body::from_stream(ReaderStream::new(
+                    file.take(end - start + 1),
+                )))
+        }
+        RangeRequest::Unsatisfiable => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .body(Body::empty()),
+    };
+
+    Ok(response.unwrap())
 }
 
 /// Upload a media file
@@ -169,15 +259,13 @@ pub async fn add_media(
         .backend_manager
         .get_or_create_backend(user.user_id, &user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Add file using the service
-    let chosen_name = col
-        .add_media_file(anki_proto::media::AddMediaFileRequest {
-            desired_name: desired_name.clone(),
-            data: file_data.to_vec(),
-        })
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let chosen_name = col.add_media_file(anki_proto::media::AddMediaFileRequest {
+        desired_name: desired_name.clone(),
+        data: file_data.to_vec(),
+    })?;
 
     drop(col);
 
@@ -187,7 +275,9 @@ pub async fn add_media(
     }))
 }
 
-async fn extract_file_from_multipart(mut multipart: Multipart) -> Result<(String, Bytes)> {
+pub(crate) async fn extract_file_from_multipart(
+    mut multipart: Multipart,
+) -> Result<(String, Bytes)> {
     let mut filename: Option<String> = None;
     let mut file_data: Option<Bytes> = None;
 
@@ -223,13 +313,12 @@ pub async fn delete_media(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Trash files
     col.trash_media_files(anki_proto::media::TrashMediaFilesRequest {
         fnames: request.filenames.clone(),
-    })
-    .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    })?;
 
     let count = request.filenames.len();
 
@@ -240,3 +329,225 @@ pub async fn delete_media(
         message: format!("Moved {} file(s) to trash", count),
     }))
 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListMediaQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MediaFileInfo {
+    pub filename: String,
+    pub size: u64,
+    /// Modification time in seconds
+    pub modified: Option<i64>,
+    /// Notes that reference the file; empty if it is unused
+    pub note_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListMediaResponse {
+    pub files: Vec<MediaFileInfo>,
+    pub total: usize,
+}
+
+/// List the files in the media folder by name, with their size and the notes
+/// that reference them
+pub async fn list_media(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(query): Query<ListMediaQuery>,
+) -> Result<impl IntoResponse> {
+    let media_folder = state
+        .backend_manager
+        .get_media_folder_path(auth_user.user_id, &auth_user.username);
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut references = {
+        let mut col = lock_collection(&backend);
+        col.media_references()?
+    };
+
+    let mut files = vec![];
+    let mut entries = fs::read_dir(&media_folder)
+        .await
+        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| WebAppError::internal(&e.to_string()))?
+    {
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+        let Ok(filename) = entry.file_name().into_string() else {
+            continue;
+        };
+        if metadata.is_file() {
+            files.push((filename, metadata));
+        }
+    }
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let total = files.len();
+    let files = files
+        .into_iter()
+        .skip(query.offset.unwrap_or(0))
+        .take(query.limit.unwrap_or(100).clamp(1, MAX_MEDIA_LIST_LIMIT))
+        .map(|(filename, metadata)| MediaFileInfo {
+            note_ids: references
+                .remove(&filename)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|nid| nid.0)
+                .collect(),
+            size: metadata.len(),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|age| age.as_secs() as i64),
+            filename,
+        })
+        .collect();
+
+    Ok(Json(ListMediaResponse { files, total }))
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MediaTrashResponse {
+    pub success: bool,
+    /// Number of files that were in the trash
+    pub count: usize,
+}
+
+/// Permanently delete the files in the media trash
+pub async fn empty_media_trash(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    let count = trash_count(&state, &auth_user).await;
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+    MediaService::empty_trash(&mut *col)?;
+    drop(col);
+
+    Ok(Json(MediaTrashResponse {
+        success: true,
+        count,
+    }))
+}
+
+/// Move the files in the media trash back into the media folder. Files that
+/// have since been re-added with different content are restored under a new
+/// name.
+pub async fn restore_media_trash(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    let count = trash_count(&state, &auth_user).await;
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+    MediaService::restore_trash(&mut *col)?;
+    drop(col);
+
+    Ok(Json(MediaTrashResponse {
+        success: true,
+        count,
+    }))
+}
+
+/// Number of files in the user's media trash, which the core keeps next to
+/// the media folder
+async fn trash_count(state: &AuthRouteState, auth_user: &AuthUser) -> usize {
+    let trash = state
+        .backend_manager
+        .get_media_folder_path(auth_user.user_id, &auth_user.username)
+        .with_file_name("media.trash");
+    let Ok(mut entries) = fs::read_dir(&trash).await else {
+        return 0;
+    };
+    let mut count = 0;
+    while let Ok(Some(_)) = entries.next_entry().await {
+        count += 1;
+    }
+    count
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct LatexRenderError {
+    pub filename: String,
+    pub note_id: i64,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RenderLatexResult {
+    pub rendered: Vec<String>,
+    pub errors: Vec<LatexRenderError>,
+}
+
+/// Start generating the images for LaTeX in notes that don't have one yet.
+/// The files rendered, and any that failed, are available from the job once
+/// it completes. Only available if the server enables LaTeX rendering.
+pub async fn render_missing_latex(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    if !state.latex_rendering {
+        return Err(WebAppError::forbidden(
+            "LaTeX rendering is not enabled on this server",
+        ));
+    }
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let job_id = state
+        .job_manager
+        .spawn(auth_user.user_id, "render_latex", move || {
+            let missing = lock_collection(&backend).missing_latex()?;
+
+            let mut result = RenderLatexResult {
+                rendered: vec![],
+                errors: vec![],
+            };
+            // The collection is only locked to store each image, as
+            // compiling can be slow
+            for item in missing {
+                let added = render_latex(&item)
+                    .map_err(|e| format!("{:#}", e))
+                    .and_then(|data| {
+                        lock_collection(&backend)
+                            .media()
+                            .and_then(|media| media.add_file(&item.fname, &data).map(|_| ()))
+                            .map_err(|e| e.to_string())
+                    });
+                match added {
+                    Ok(_) => result.rendered.push(item.fname),
+                    Err(error) => result.errors.push(LatexRenderError {
+                        filename: item.fname,
+                        note_id: item.note_id.0,
+                        error,
+                    }),
+                }
+            }
+            serde_json::to_value(result).map_err(|e| WebAppError::internal(&e.to_string()))
+        });
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobStartedResponse {
+            success: true,
+            job_id,
+        }),
+    ))
+}
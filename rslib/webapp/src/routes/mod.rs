@@ -1,17 +1,26 @@
+pub mod audit;
 pub mod auth;
+pub mod backups;
 pub mod browse;
 pub mod cards;
 pub mod collection;
 pub mod decks;
+pub mod fsrs;
+pub mod image_occlusion;
 pub mod import_export;
+pub mod jobs;
+pub mod maintenance;
 pub mod media;
 pub mod notes;
 pub mod notetypes;
+pub mod preferences;
 pub mod scheduler;
 pub mod search;
+pub mod sharing;
 pub mod stats;
 pub mod tags;
 
+pub use audit::get_audit_log;
 pub use auth::login;
 pub use browse::browse_cards;
 pub use browse::browse_notes;
@@ -19,6 +28,9 @@ pub use auth::logout;
 pub use auth::me;
 pub use auth::register;
 pub use auth::AuthRouteState;
+pub use backups::create_backup;
+pub use backups::list_backups;
+pub use backups::restore_backup;
 pub use cards::batch_get_cards;
 pub use cards::batch_update_cards;
 pub use cards::bury_card;
@@ -36,36 +48,83 @@ pub use collection::list_collections;
 pub use decks::create_deck;
 pub use decks::delete_deck;
 pub use decks::get_deck;
+pub use decks::get_deck_stats;
 pub use decks::get_deck_tree;
+pub use decks::reparent_decks;
+pub use decks::set_deck_collapsed;
 pub use decks::update_deck;
+pub use fsrs::compute_optimal_retention;
+pub use fsrs::evaluate_fsrs_params;
+pub use fsrs::list_fsrs_presets;
+pub use fsrs::optimize_fsrs_params;
+pub use fsrs::save_fsrs_params;
+pub use fsrs::simulate_fsrs_workload;
+pub use image_occlusion::create_occlusion_note;
+pub use image_occlusion::get_occlusion_note;
+pub use image_occlusion::update_occlusion_note;
+pub use image_occlusion::upload_occlusion_image;
 pub use import_export::import_apkg;
+pub use jobs::get_job;
+pub use jobs::list_jobs;
+pub use maintenance::check_database;
+pub use maintenance::delete_empty_cards;
+pub use maintenance::find_duplicates;
+pub use maintenance::get_empty_cards;
 pub use media::add_media;
 pub use media::check_media;
 pub use media::delete_media;
+pub use media::empty_media_trash;
 pub use media::get_media;
+pub use media::list_media;
+pub use media::render_missing_latex;
+pub use media::restore_media_trash;
 pub use notes::check_note_fields;
 pub use notes::create_note;
+pub use notes::create_notes_bulk;
 pub use notes::delete_note;
 pub use notes::get_note;
 pub use notes::get_note_cards;
 pub use notes::update_note;
 pub use notetypes::get_notetype;
 pub use notetypes::list_notetypes;
+pub use preferences::get_config;
+pub use preferences::get_preferences;
+pub use preferences::list_config;
+pub use preferences::set_config;
+pub use preferences::update_preferences;
 pub use scheduler::answer_card;
+pub use scheduler::answer_card_with_states;
+pub use scheduler::answer_cards_batch;
+pub use scheduler::get_congrats_info;
 pub use scheduler::get_deck_counts;
 pub use scheduler::get_next_card;
 pub use scheduler::get_next_states;
+pub use scheduler::get_undo_status;
+pub use scheduler::prefetch_cards;
 pub use scheduler::redo;
 pub use scheduler::undo;
 pub use search::find_and_replace;
 pub use search::search_cards;
 pub use search::search_notes;
+pub use sharing::list_shared_decks;
+pub use sharing::list_subscriptions;
+pub use sharing::publish_deck;
+pub use sharing::subscribe_deck;
+pub use sharing::unpublish_deck;
+pub use sharing::unsubscribe_deck;
+pub use sharing::update_subscription;
 pub use stats::get_card_stats;
 pub use stats::get_collection_stats;
 pub use stats::get_graphs;
 pub use stats::get_today_stats;
+pub use tags::add_tags_to_notes;
 pub use tags::clear_unused_tags;
+pub use tags::complete_tags;
 pub use tags::delete_tag;
+pub use tags::find_and_replace_tags;
 pub use tags::get_tag_tree;
 pub use tags::get_tags;
+pub use tags::remove_tags_from_notes;
 pub use tags::rename_tag;
+pub use tags::reparent_tags;
+pub use tags::set_tag_collapsed;
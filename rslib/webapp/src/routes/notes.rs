@@ -1,17 +1,31 @@
+use std::collections::HashMap;
+
+use anki::decks::DeckId;
+use anki::decks::DeckKind;
+use anki::notes::AddNoteOutcome;
+use anki::notes::AddNoteRequest;
+use anki::notes::DuplicatePolicy;
+use anki::notetype::NotetypeId;
 use axum::extract::Path;
 use axum::extract::State;
+use axum::http::header;
+use axum::http::HeaderMap;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 
 use crate::auth::AuthUser;
 use crate::error::Result;
 use crate::error::WebAppError;
+use crate::preconditions::check_if_match;
+use crate::preconditions::object_etag;
 use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct NoteInfo {
     pub id: i64,
     pub notetype_id: i64,
@@ -19,7 +33,7 @@ pub struct NoteInfo {
     pub tags: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CreateNoteRequest {
     pub deck_id: i64,
     pub notetype_id: i64,
@@ -27,36 +41,101 @@ pub struct CreateNoteRequest {
     pub tags: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct CreateNoteResponse {
     pub success: bool,
     pub note_id: i64,
     pub message: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct UpdateNoteRequest {
     pub fields: Vec<String>,
     pub tags: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+/// Upper bound on the number of notes in a single bulk request
+pub const MAX_BULK_NOTES: usize = 10_000;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BulkNotesRequest {
+    pub notes: Vec<CreateNoteRequest>,
+    /// How notes whose first field matches an existing note are handled
+    #[serde(default)]
+    pub duplicate_policy: DuplicatePolicyJson,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicyJson {
+    #[default]
+    Skip,
+    Allow,
+    UpdateExisting,
+}
+
+impl From<DuplicatePolicyJson> for DuplicatePolicy {
+    fn from(policy: DuplicatePolicyJson) -> Self {
+        match policy {
+            DuplicatePolicyJson::Skip => DuplicatePolicy::Skip,
+            DuplicatePolicyJson::Allow => DuplicatePolicy::Allow,
+            DuplicatePolicyJson::UpdateExisting => DuplicatePolicy::UpdateExisting,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkNoteStatus {
+    Added,
+    /// A duplicate exists and was left unchanged
+    Skipped,
+    /// A duplicate exists and its fields and tags were replaced
+    Updated,
+    /// The note's notetype, deck or fields are invalid; it was not added
+    Invalid,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct BulkNoteResult {
+    pub status: BulkNoteStatus,
+    /// The new note, or the existing duplicate
+    pub note_id: Option<i64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct BulkNotesResponse {
+    pub added: usize,
+    pub skipped: usize,
+    pub updated: usize,
+    pub invalid: usize,
+    /// One result per requested note, in order
+    pub results: Vec<BulkNoteResult>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct MessageResponse {
     pub success: bool,
     pub message: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CheckNoteFieldsRequest {
     pub notetype_id: i64,
     pub fields: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct CheckNoteFieldsResponse {
     pub state: i32, // 0=NORMAL, 1=EMPTY, 2=DUPLICATE, etc.
 }
 
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NoteCardsResponse {
+    pub card_ids: Vec<i64>,
+}
+
 /// Check note fields for duplicates or errors
 pub async fn check_note_fields(
     State(state): State<AuthRouteState>,
@@ -67,12 +146,11 @@ pub async fn check_note_fields(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Get the notetype
     let notetype = col
-        .get_notetype(anki::notetype::NotetypeId(request.notetype_id))
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?
+        .get_notetype(anki::notetype::NotetypeId(request.notetype_id))?
         .ok_or_else(|| WebAppError::bad_request("Notetype not found"))?;
 
     // Create a temporary note
@@ -81,15 +159,12 @@ pub async fn check_note_fields(
     // Set fields
     for (idx, field_value) in request.fields.iter().enumerate() {
         if idx < note.fields().len() {
-            note.set_field(idx, field_value.clone())
-                .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+            note.set_field(idx, field_value.clone())?;
         }
     }
 
     // Run the check
-    let state = col
-        .note_fields_check(&note)
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let state = col.note_fields_check(&note)?;
 
     drop(col);
 
@@ -106,12 +181,11 @@ pub async fn get_note(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let col = backend.lock().unwrap();
+    let col = lock_collection(&backend);
 
     let note = col
         .storage
-        .get_note(anki::notes::NoteId(note_id))
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?
+        .get_note(anki::notes::NoteId(note_id))?
         .ok_or_else(|| WebAppError::not_found("Note not found"))?;
 
     let notetype_id = note.notetype_id.0;
@@ -120,12 +194,15 @@ pub async fn get_note(
 
     drop(col);
 
-    Ok(Json(NoteInfo {
-        id: note.id.0,
-        notetype_id,
-        fields,
-        tags,
-    }))
+    Ok((
+        [(header::ETAG, note_etag(&note))],
+        Json(NoteInfo {
+            id: note.id.0,
+            notetype_id,
+            fields,
+            tags,
+        }),
+    ))
 }
 
 /// Create a new note
@@ -138,12 +215,11 @@ pub async fn create_note(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Get the notetype
     let notetype = col
-        .get_notetype(anki::notetype::NotetypeId(request.notetype_id))
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?
+        .get_notetype(anki::notetype::NotetypeId(request.notetype_id))?
         .ok_or_else(|| WebAppError::bad_request("Notetype not found"))?;
 
     // Create a new note
@@ -152,8 +228,7 @@ pub async fn create_note(
     // Set fields
     for (idx, field_value) in request.fields.iter().enumerate() {
         if idx < note.fields().len() {
-            note.set_field(idx, field_value.clone())
-                .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+            note.set_field(idx, field_value.clone())?;
         }
     }
 
@@ -161,9 +236,7 @@ pub async fn create_note(
     note.tags = request.tags;
 
     // Add the note to the collection
-    let output = col
-        .add_note(&mut note, anki::decks::DeckId(request.deck_id))
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let output = col.add_note(&mut note, anki::decks::DeckId(request.deck_id))?;
 
     let note_id = note.id.0;
 
@@ -187,26 +260,26 @@ pub async fn update_note(
     State(state): State<AuthRouteState>,
     Extension(auth_user): Extension<AuthUser>,
     Path(note_id): Path<i64>,
+    headers: HeaderMap,
     Json(request): Json<UpdateNoteRequest>,
 ) -> Result<impl IntoResponse> {
     let backend = state
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Get the existing note
     let mut note = col
         .storage
-        .get_note(anki::notes::NoteId(note_id))
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?
+        .get_note(anki::notes::NoteId(note_id))?
         .ok_or_else(|| WebAppError::not_found("Note not found"))?;
+    check_if_match(&headers, &note_etag(&note))?;
 
     // Update fields
     for (idx, field_value) in request.fields.iter().enumerate() {
         if idx < note.fields().len() {
-            note.set_field(idx, field_value.clone())
-                .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+            note.set_field(idx, field_value.clone())?;
         }
     }
 
@@ -214,15 +287,22 @@ pub async fn update_note(
     note.tags = request.tags;
 
     // Update the note
-    col.update_note(&mut note)
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    col.update_note(&mut note)?;
 
     drop(col);
 
-    Ok(Json(MessageResponse {
-        success: true,
-        message: "Note updated successfully".to_string(),
-    }))
+    Ok((
+        [(header::ETAG, note_etag(&note))],
+        Json(MessageResponse {
+            success: true,
+            message: "Note updated successfully".to_string(),
+        }),
+    ))
+}
+
+fn note_etag(note: &anki::notes::Note) -> String {
+    let content = format!("{}\x1f{}", note.fields().join("\x1f"), note.tags.join(" "));
+    object_etag(note.mtime.0, note.usn.0, content.as_bytes())
 }
 
 /// Delete a note
@@ -235,12 +315,10 @@ pub async fn delete_note(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Remove the note
-    let output = col
-        .remove_notes(&[anki::notes::NoteId(note_id)])
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let output = col.remove_notes(&[anki::notes::NoteId(note_id)])?;
 
     drop(col);
 
@@ -263,16 +341,132 @@ pub async fn get_note_cards(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let col = backend.lock().unwrap();
+    let col = lock_collection(&backend);
 
     let cards = col
         .storage
-        .all_cards_of_note(anki::notes::NoteId(note_id))
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+        .all_cards_of_note(anki::notes::NoteId(note_id))?;
+
+    drop(col);
+
+    Ok(Json(NoteCardsResponse {
+        card_ids: cards.into_iter().map(|card| card.id().0).collect(),
+    }))
+}
+
+/// Create many notes at once. Valid notes are added in a single transaction
+/// and undo step; invalid ones are reported and skipped.
+pub async fn create_notes_bulk(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<BulkNotesRequest>,
+) -> Result<impl IntoResponse> {
+    if request.notes.is_empty() {
+        return Err(WebAppError::bad_request("No notes provided"));
+    }
+    if request.notes.len() > MAX_BULK_NOTES {
+        return Err(WebAppError::bad_request(&format!(
+            "At most {} notes can be added at once",
+            MAX_BULK_NOTES
+        )));
+    }
 
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+
+    // Build the notes up front, so invalid rows don't abort the transaction
+    let mut notetypes = HashMap::new();
+    let mut decks = HashMap::new();
+    let mut results: Vec<Option<BulkNoteResult>> = Vec::with_capacity(request.notes.len());
+    let mut requests = vec![];
+    for item in request.notes {
+        let notetype_id = NotetypeId(item.notetype_id);
+        if !notetypes.contains_key(&notetype_id) {
+            let notetype = col.get_notetype(notetype_id)?;
+            notetypes.insert(notetype_id, notetype);
+        }
+        let deck_id = DeckId(item.deck_id);
+        if !decks.contains_key(&deck_id) {
+            let exists = col
+                .get_deck(deck_id)?
+                .is_some_and(|deck| matches!(deck.kind, DeckKind::Normal(_)));
+            decks.insert(deck_id, exists);
+        }
+
+        let error = match &notetypes[&notetype_id] {
+            None => Some("Notetype not found".to_string()),
+            Some(_) if !decks[&deck_id] => Some("Deck not found or filtered".to_string()),
+            Some(notetype) if item.fields.len() > notetype.fields.len() => Some(format!(
+                "Notetype has {} fields, but {} were provided",
+                notetype.fields.len(),
+                item.fields.len()
+            )),
+            Some(notetype) => {
+                let mut note = anki::notes::Note::new(notetype);
+                for (idx, field) in item.fields.into_iter().enumerate() {
+                    note.set_field(idx, field)?;
+                }
+                note.tags = item.tags;
+                requests.push(AddNoteRequest { note, deck_id });
+                None
+            }
+        };
+        results.push(error.map(|error| BulkNoteResult {
+            status: BulkNoteStatus::Invalid,
+            note_id: None,
+            error: Some(error),
+        }));
+    }
+
+    let outcomes = if requests.is_empty() {
+        vec![]
+    } else {
+        col.add_notes_with_duplicate_policy(&mut requests, request.duplicate_policy.into())?
+            .output
+    };
     drop(col);
 
-    Ok(Json(serde_json::json!({
-        "card_ids": cards.into_iter().map(|card| card.id().0).collect::<Vec<_>>(),
-    })))
+    if outcomes.len() != requests.len() {
+        return Err(WebAppError::internal(&format!(
+            "Expected {} outcomes for added notes, got {}",
+            requests.len(),
+            outcomes.len()
+        )));
+    }
+    // Fill the gaps left by valid notes with their outcomes, in order
+    let mut outcomes = outcomes.into_iter();
+    let mut bulk_results = Vec::with_capacity(results.len());
+    for result in results {
+        let result = match result {
+            Some(invalid) => invalid,
+            None => {
+                let (status, note_id) = match outcomes.next() {
+                    Some(AddNoteOutcome::Added(nid)) => (BulkNoteStatus::Added, nid),
+                    Some(AddNoteOutcome::Skipped(nid)) => (BulkNoteStatus::Skipped, nid),
+                    Some(AddNoteOutcome::Updated(nid)) => (BulkNoteStatus::Updated, nid),
+                    None => {
+                        return Err(WebAppError::internal("Missing outcome for an added note"));
+                    }
+                };
+                BulkNoteResult {
+                    status,
+                    note_id: Some(note_id.0),
+                    error: None,
+                }
+            }
+        };
+        bulk_results.push(result);
+    }
+    let results = bulk_results;
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+
+    Ok(Json(BulkNotesResponse {
+        added: count(BulkNoteStatus::Added),
+        skipped: count(BulkNoteStatus::Skipped),
+        updated: count(BulkNoteStatus::Updated),
+        invalid: count(BulkNoteStatus::Invalid),
+        results,
+    }))
 }
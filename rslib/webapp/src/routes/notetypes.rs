@@ -2,14 +2,16 @@ use axum::extract::State;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Serialize;
 
 use crate::auth::AuthUser;
 use crate::error::Result;
 use crate::error::WebAppError;
 use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct NotetypeInfo {
     pub id: i64,
     pub name: String,
@@ -18,24 +20,30 @@ pub struct NotetypeInfo {
     pub is_cloze: bool,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct NotetypeField {
     pub name: String,
     pub ord: u32,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct NotetypeTemplate {
     pub name: String,
     pub ord: u32,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct NotetypeListItem {
     pub id: i64,
     pub name: String,
 }
 
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NotetypesListResponse {
+    /// Sorted by name
+    pub notetypes: Vec<NotetypeListItem>,
+}
+
 /// List all notetypes
 pub async fn list_notetypes(
     State(state): State<AuthRouteState>,
@@ -45,11 +53,9 @@ pub async fn list_notetypes(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
-    let notetypes = col
-        .get_all_notetypes()
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let notetypes = col.get_all_notetypes()?;
 
     let mut notetype_list: Vec<NotetypeListItem> = notetypes
         .into_iter()
@@ -64,9 +70,9 @@ pub async fn list_notetypes(
 
     drop(col);
 
-    Ok(Json(serde_json::json!({
-        "notetypes": notetype_list
-    })))
+    Ok(Json(NotetypesListResponse {
+        notetypes: notetype_list,
+    }))
 }
 
 /// Get a specific notetype by ID
@@ -79,11 +85,10 @@ pub async fn get_notetype(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     let notetype = col
-        .get_notetype(anki::notetype::NotetypeId(notetype_id))
-        .map_err(|e| WebAppError::internal(&e.to_string()))?
+        .get_notetype(anki::notetype::NotetypeId(notetype_id))?
         .ok_or_else(|| WebAppError::not_found("Notetype not found"))?;
 
     let fields: Vec<NotetypeField> = notetype
@@ -0,0 +1,524 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! Collection preferences, and a whitelisted set of other config keys. Both
+//! are stored in the collection, so they sync, and changes can be undone like
+//! on the desktop.
+
+use anki::collection::Collection;
+use anki::config::BoolKey;
+use anki::config::StringKey;
+use anki_proto::config::preferences::scheduling::NewReviewMix;
+use anki_proto::config::preferences::Editing;
+use anki_proto::config::preferences::Reviewing;
+use anki_proto::config::preferences::Scheduling;
+use anki_proto::config::Preferences;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Extension;
+use axum::Json;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::auth::AuthUser;
+use crate::error::Result;
+use crate::error::WebAppError;
+use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
+
+/// The desktop limits learn ahead to a day
+const MAX_LEARN_AHEAD_SECS: u32 = 24 * 60 * 60;
+/// The desktop limits the answer time limit to 9999 minutes
+const MAX_TIME_LIMIT_SECS: u32 = 9999 * 60;
+const MAX_CONFIG_STRING_LEN: usize = 1000;
+
+/// Config keys that clients may read and change, beyond those covered by the
+/// preferences. Names are the keys the collection stores them under.
+const BOOL_CONFIG_KEYS: [BoolKey; 12] = [
+    BoolKey::ApplyAllParentLimits,
+    BoolKey::NewCardsIgnoreReviewLimit,
+    BoolKey::CardCountsSeparateInactive,
+    BoolKey::FutureDueShowBacklog,
+    BoolKey::BrowserTableShowNotesMode,
+    BoolKey::PreviewBothSides,
+    BoolKey::RandomOrderReposition,
+    BoolKey::ShiftPositionOfExistingCards,
+    BoolKey::RestorePositionBrowser,
+    BoolKey::RestorePositionReviewer,
+    BoolKey::ResetCountsBrowser,
+    BoolKey::ResetCountsReviewer,
+];
+const STRING_CONFIG_KEYS: [StringKey; 2] = [StringKey::SetDueBrowser, StringKey::SetDueReviewer];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NewReviewMixJson {
+    Distribute,
+    ReviewsFirst,
+    NewFirst,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SchedulingPreferences {
+    /// Hour of the day (0-23) at which the next day starts
+    pub rollover: u32,
+    pub learn_ahead_secs: u32,
+    pub new_review_mix: NewReviewMixJson,
+    pub new_timezone: bool,
+    pub day_learn_first: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReviewingPreferences {
+    pub hide_audio_play_buttons: bool,
+    pub interrupt_audio_when_answering: bool,
+    pub show_remaining_due_counts: bool,
+    pub show_intervals_on_buttons: bool,
+    /// 0 when there is no limit
+    pub time_limit_secs: u32,
+    pub load_balancer_enabled: bool,
+    pub fsrs_short_term_with_steps_enabled: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EditingPreferences {
+    pub adding_defaults_to_current_deck: bool,
+    pub paste_images_as_png: bool,
+    pub paste_strips_formatting: bool,
+    pub default_search_text: String,
+    pub ignore_accents_in_search: bool,
+    pub render_latex: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PreferencesResponse {
+    pub scheduling: SchedulingPreferences,
+    pub reviewing: ReviewingPreferences,
+    pub editing: EditingPreferences,
+}
+
+#[derive(Debug, Deserialize, Default, JsonSchema)]
+pub struct SchedulingPreferencesUpdate {
+    pub rollover: Option<u32>,
+    pub learn_ahead_secs: Option<u32>,
+    pub new_review_mix: Option<NewReviewMixJson>,
+    pub new_timezone: Option<bool>,
+    pub day_learn_first: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Default, JsonSchema)]
+pub struct ReviewingPreferencesUpdate {
+    pub hide_audio_play_buttons: Option<bool>,
+    pub interrupt_audio_when_answering: Option<bool>,
+    pub show_remaining_due_counts: Option<bool>,
+    pub show_intervals_on_buttons: Option<bool>,
+    pub time_limit_secs: Option<u32>,
+    pub load_balancer_enabled: Option<bool>,
+    pub fsrs_short_term_with_steps_enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Default, JsonSchema)]
+pub struct EditingPreferencesUpdate {
+    pub adding_defaults_to_current_deck: Option<bool>,
+    pub paste_images_as_png: Option<bool>,
+    pub paste_strips_formatting: Option<bool>,
+    pub default_search_text: Option<String>,
+    pub ignore_accents_in_search: Option<bool>,
+    pub render_latex: Option<bool>,
+}
+
+/// Fields that are left out keep their current value
+#[derive(Debug, Deserialize, Default, JsonSchema)]
+pub struct UpdatePreferencesRequest {
+    pub scheduling: Option<SchedulingPreferencesUpdate>,
+    pub reviewing: Option<ReviewingPreferencesUpdate>,
+    pub editing: Option<EditingPreferencesUpdate>,
+}
+
+#[derive(Debug, Serialize, PartialEq, JsonSchema)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum ConfigValue {
+    Bool(bool),
+    String(String),
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ConfigEntryResponse {
+    pub key: String,
+    #[serde(flatten)]
+    pub value: ConfigValue,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ConfigListResponse {
+    pub config: Vec<ConfigEntryResponse>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetConfigRequest {
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum WhitelistedKey {
+    Bool(BoolKey),
+    String(StringKey),
+}
+
+impl WhitelistedKey {
+    fn all() -> impl Iterator<Item = WhitelistedKey> {
+        BOOL_CONFIG_KEYS
+            .into_iter()
+            .map(WhitelistedKey::Bool)
+            .chain(STRING_CONFIG_KEYS.into_iter().map(WhitelistedKey::String))
+    }
+
+    fn from_name(name: &str) -> Option<WhitelistedKey> {
+        Self::all().find(|key| key.name() == name)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            WhitelistedKey::Bool(key) => key.into(),
+            WhitelistedKey::String(key) => key.into(),
+        }
+    }
+
+    fn get(self, col: &Collection) -> ConfigEntryResponse {
+        let value = match self {
+            WhitelistedKey::Bool(key) => ConfigValue::Bool(col.get_config_bool(key)),
+            WhitelistedKey::String(key) => ConfigValue::String(col.get_config_string(key)),
+        };
+        ConfigEntryResponse {
+            key: self.name().to_string(),
+            value,
+        }
+    }
+
+    fn set(self, col: &mut Collection, value: &serde_json::Value) -> Result<()> {
+        match self {
+            WhitelistedKey::Bool(key) => {
+                let value = value
+                    .as_bool()
+                    .ok_or_else(|| WebAppError::bad_request("Expected a boolean value"))?;
+                col.set_config_bool(key, value, true)?;
+            }
+            WhitelistedKey::String(key) => {
+                let value = value
+                    .as_str()
+                    .ok_or_else(|| WebAppError::bad_request("Expected a string value"))?;
+                if value.len() > MAX_CONFIG_STRING_LEN {
+                    return Err(WebAppError::bad_request(&format!(
+                        "Value must be at most {} bytes",
+                        MAX_CONFIG_STRING_LEN
+                    )));
+                }
+                col.set_config_string(key, value, true)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Get the collection's scheduling, reviewing and editing preferences
+pub async fn get_preferences(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let col = lock_collection(&backend);
+    let prefs = col.get_preferences()?;
+    drop(col);
+
+    Ok(Json(preferences_response(prefs)))
+}
+
+/// Change some or all preferences as a single undoable step
+pub async fn update_preferences(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<UpdatePreferencesRequest>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+
+    let mut prefs = col.get_preferences()?;
+    // Backup limits only apply to the desktop app
+    prefs.backups = None;
+    if let (Some(update), Some(current)) = (request.scheduling, prefs.scheduling.as_mut()) {
+        apply_scheduling_update(current, update)?;
+    }
+    if let (Some(update), Some(current)) = (request.reviewing, prefs.reviewing.as_mut()) {
+        apply_reviewing_update(current, update)?;
+    }
+    if let (Some(update), Some(current)) = (request.editing, prefs.editing.as_mut()) {
+        apply_editing_update(current, update);
+    }
+    col.set_preferences(prefs)?;
+
+    let prefs = col.get_preferences()?;
+    drop(col);
+
+    Ok(Json(preferences_response(prefs)))
+}
+
+/// List the config keys clients may change, with their current values
+pub async fn list_config(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let col = lock_collection(&backend);
+    let config = WhitelistedKey::all().map(|key| key.get(&col)).collect();
+    drop(col);
+
+    Ok(Json(ConfigListResponse { config }))
+}
+
+/// Get a single whitelisted config key
+pub async fn get_config(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse> {
+    let key = config_key(&name)?;
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let col = lock_collection(&backend);
+    let entry = key.get(&col);
+    drop(col);
+
+    Ok(Json(entry))
+}
+
+/// Change a single whitelisted config key as an undoable step. The value must
+/// have the key's type.
+pub async fn set_config(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(name): Path<String>,
+    Json(request): Json<SetConfigRequest>,
+) -> Result<impl IntoResponse> {
+    let key = config_key(&name)?;
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+    key.set(&mut col, &request.value)?;
+    let entry = key.get(&col);
+    drop(col);
+
+    Ok(Json(entry))
+}
+
+fn config_key(name: &str) -> Result<WhitelistedKey> {
+    WhitelistedKey::from_name(name).ok_or_else(|| WebAppError::not_found("Unknown config key"))
+}
+
+fn apply_scheduling_update(
+    current: &mut Scheduling,
+    update: SchedulingPreferencesUpdate,
+) -> Result<()> {
+    if let Some(rollover) = update.rollover {
+        if rollover > 23 {
+            return Err(WebAppError::bad_request(
+                "rollover must be an hour between 0 and 23",
+            ));
+        }
+        current.rollover = rollover;
+    }
+    if let Some(secs) = update.learn_ahead_secs {
+        if secs > MAX_LEARN_AHEAD_SECS {
+            return Err(WebAppError::bad_request(&format!(
+                "learn_ahead_secs must be at most {}",
+                MAX_LEARN_AHEAD_SECS
+            )));
+        }
+        current.learn_ahead_secs = secs;
+    }
+    if let Some(mix) = update.new_review_mix {
+        current.set_new_review_mix(match mix {
+            NewReviewMixJson::Distribute => NewReviewMix::Distribute,
+            NewReviewMixJson::ReviewsFirst => NewReviewMix::ReviewsFirst,
+            NewReviewMixJson::NewFirst => NewReviewMix::NewFirst,
+        });
+    }
+    if let Some(new_timezone) = update.new_timezone {
+        current.new_timezone = new_timezone;
+    }
+    if let Some(day_learn_first) = update.day_learn_first {
+        current.day_learn_first = day_learn_first;
+    }
+    Ok(())
+}
+
+fn apply_reviewing_update(
+    current: &mut Reviewing,
+    update: ReviewingPreferencesUpdate,
+) -> Result<()> {
+    if let Some(secs) = update.time_limit_secs {
+        if secs > MAX_TIME_LIMIT_SECS {
+            return Err(WebAppError::bad_request(&format!(
+                "time_limit_secs must be at most {}",
+                MAX_TIME_LIMIT_SECS
+            )));
+        }
+        current.time_limit_secs = secs;
+    }
+    let flags = [
+        (
+            update.hide_audio_play_buttons,
+            &mut current.hide_audio_play_buttons,
+        ),
+        (
+            update.interrupt_audio_when_answering,
+            &mut current.interrupt_audio_when_answering,
+        ),
+        (
+            update.show_remaining_due_counts,
+            &mut current.show_remaining_due_counts,
+        ),
+        (
+            update.show_intervals_on_buttons,
+            &mut current.show_intervals_on_buttons,
+        ),
+        (
+            update.load_balancer_enabled,
+            &mut current.load_balancer_enabled,
+        ),
+        (
+            update.fsrs_short_term_with_steps_enabled,
+            &mut current.fsrs_short_term_with_steps_enabled,
+        ),
+    ];
+    for (value, field) in flags {
+        if let Some(value) = value {
+            *field = value;
+        }
+    }
+    Ok(())
+}
+
+fn apply_editing_update(current: &mut Editing, update: EditingPreferencesUpdate) {
+    if let Some(text) = update.default_search_text {
+        current.default_search_text = text;
+    }
+    let flags = [
+        (
+            update.adding_defaults_to_current_deck,
+            &mut current.adding_defaults_to_current_deck,
+        ),
+        (update.paste_images_as_png, &mut current.paste_images_as_png),
+        (
+            update.paste_strips_formatting,
+            &mut current.paste_strips_formatting,
+        ),
+        (
+            update.ignore_accents_in_search,
+            &mut current.ignore_accents_in_search,
+        ),
+        (update.render_latex, &mut current.render_latex),
+    ];
+    for (value, field) in flags {
+        if let Some(value) = value {
+            *field = value;
+        }
+    }
+}
+
+fn preferences_response(prefs: Preferences) -> PreferencesResponse {
+    let scheduling = prefs.scheduling.unwrap_or_default();
+    let reviewing = prefs.reviewing.unwrap_or_default();
+    let editing = prefs.editing.unwrap_or_default();
+
+    PreferencesResponse {
+        scheduling: SchedulingPreferences {
+            rollover: scheduling.rollover,
+            learn_ahead_secs: scheduling.learn_ahead_secs,
+            new_review_mix: match scheduling.new_review_mix() {
+                NewReviewMix::Distribute => NewReviewMixJson::Distribute,
+                NewReviewMix::ReviewsFirst => NewReviewMixJson::ReviewsFirst,
+                NewReviewMix::NewFirst => NewReviewMixJson::NewFirst,
+            },
+            new_timezone: scheduling.new_timezone,
+            day_learn_first: scheduling.day_learn_first,
+        },
+        reviewing: ReviewingPreferences {
+            hide_audio_play_buttons: reviewing.hide_audio_play_buttons,
+            interrupt_audio_when_answering: reviewing.interrupt_audio_when_answering,
+            show_remaining_due_counts: reviewing.show_remaining_due_counts,
+            show_intervals_on_buttons: reviewing.show_intervals_on_buttons,
+            time_limit_secs: reviewing.time_limit_secs,
+            load_balancer_enabled: reviewing.load_balancer_enabled,
+            fsrs_short_term_with_steps_enabled: reviewing.fsrs_short_term_with_steps_enabled,
+        },
+        editing: EditingPreferences {
+            adding_defaults_to_current_deck: editing.adding_defaults_to_current_deck,
+            paste_images_as_png: editing.paste_images_as_png,
+            paste_strips_formatting: editing.paste_strips_formatting,
+            default_search_text: editing.default_search_text,
+            ignore_accents_in_search: editing.ignore_accents_in_search,
+            render_latex: editing.render_latex,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_key_names() {
+        assert!(matches!(
+            WhitelistedKey::from_name("applyAllParentLimits"),
+            Some(WhitelistedKey::Bool(BoolKey::ApplyAllParentLimits))
+        ));
+        assert!(matches!(
+            WhitelistedKey::from_name("setDueReviewer"),
+            Some(WhitelistedKey::String(StringKey::SetDueReviewer))
+        ));
+        // Keys outside the whitelist, including preferences, are not exposed
+        assert!(WhitelistedKey::from_name("sched2021").is_none());
+        assert!(WhitelistedKey::from_name("cardStateCustomizer").is_none());
+        assert!(WhitelistedKey::from_name("estTimes").is_none());
+    }
+
+    #[test]
+    fn test_apply_scheduling_update() {
+        let mut current = Scheduling {
+            rollover: 4,
+            learn_ahead_secs: 1200,
+            ..Default::default()
+        };
+        apply_scheduling_update(
+            &mut current,
+            SchedulingPreferencesUpdate {
+                rollover: Some(2),
+                new_review_mix: Some(NewReviewMixJson::NewFirst),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(current.rollover, 2);
+        assert_eq!(current.learn_ahead_secs, 1200);
+        assert_eq!(current.new_review_mix(), NewReviewMix::NewFirst);
+
+        let invalid = SchedulingPreferencesUpdate {
+            rollover: Some(24),
+            ..Default::default()
+        };
+        assert!(apply_scheduling_update(&mut current, invalid).is_err());
+        assert_eq!(current.rollover, 2);
+    }
+}
@@ -1,21 +1,49 @@
+use std::collections::HashMap;
+
+use anki::collection::Collection;
+use anki::deckconfig::AnswerAction;
+use anki::deckconfig::DeckConfigId;
+use anki::deckconfig::QuestionAction;
+use anki::decks::Deck;
+use anki::decks::DeckId;
+use anki::error::AnkiError;
+use anki::ops::OpChanges;
 use anki::scheduler::answering::CardAnswer;
 use anki::scheduler::answering::Rating;
+use anki::scheduler::states::SchedulingStates;
 use anki::services::CardsService;
 use anki::timestamp::TimestampMillis;
+use anki::timestamp::TimestampSecs;
+use anki::undo::UndoStatus;
+use anki_i18n::I18n;
 use axum::extract::Path;
+use axum::extract::Query;
 use axum::extract::State;
+use axum::http::HeaderMap;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 
 use crate::auth::AuthUser;
+use crate::error::accepted_languages;
 use crate::error::Result;
 use crate::error::WebAppError;
+use crate::metrics::metrics;
 use crate::routes::AuthRouteState;
-
-#[derive(Debug, Serialize)]
+use crate::scheduling::answer_with_states;
+use crate::scheduling::ensure_card_in_deck;
+use crate::scheduling::learning_due_at;
+use crate::scheduling::parse_rating;
+use crate::scheduling::record_answer;
+use crate::scheduling::AnswerOutcome;
+use crate::scheduling::ClientAnswer;
+use crate::scheduling::SchedulingStatesJson;
+use crate::session::lock_collection;
+
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct QueuedCardResponse {
     pub card_id: i64,
     pub question_html: String,
@@ -23,26 +51,278 @@ pub struct QueuedCardResponse {
     pub css: String,
     pub counts: StudyCounts,
     pub flags: u8,
+    pub settings: ReviewerSettings,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct StudyCounts {
     pub new: usize,
     pub learning: usize,
     pub review: usize,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestionActionJson {
+    ShowAnswer,
+    ShowReminder,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerActionJson {
+    BuryCard,
+    AnswerAgain,
+    AnswerGood,
+    AnswerHard,
+    ShowReminder,
+}
+
+/// The settings of a card's deck preset that control how the desktop
+/// reviewer shows it
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+pub struct ReviewerSettings {
+    /// Seconds after which `question_action` is taken, or 0 if auto advance
+    /// is off
+    pub seconds_to_show_question: f32,
+    /// Seconds after which `answer_action` is taken, or 0 if auto advance is
+    /// off
+    pub seconds_to_show_answer: f32,
+    pub question_action: QuestionActionJson,
+    pub answer_action: AnswerActionJson,
+    /// Start the auto advance timers once audio has finished playing
+    pub wait_for_audio: bool,
+    /// Play audio when the question and answer are shown
+    pub autoplay: bool,
+    /// When replaying the answer, leave out the question's audio
+    pub skip_question_when_replaying_answer: bool,
+    pub show_timer: bool,
+    pub stop_timer_on_answer: bool,
+    /// Longer answer times are recorded as this many seconds
+    pub max_answer_secs: u32,
+}
+
+/// What the desktop shows once a deck has nothing left to study today
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CongratsInfo {
+    /// Learning cards that become due later today
+    pub learn_remaining: u32,
+    /// Seconds until the next of them can be shown, counting learn ahead.
+    /// None if there are none.
+    pub secs_until_next_learn: Option<u32>,
+    /// Reviews held back by the deck's daily limit
+    pub review_remaining: bool,
+    /// New cards held back by the deck's daily limit
+    pub new_remaining: bool,
+    pub have_sched_buried: bool,
+    pub have_user_buried: bool,
+    pub is_filtered_deck: bool,
+    /// Rendered as HTML
+    pub deck_description: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct AnswerCardRequest {
     pub rating: u8, // 0=Again, 1=Hard, 2=Good, 3=Easy
     #[serde(default)]
     pub milliseconds_taken: u32,
 }
 
-#[derive(Debug, Serialize)]
-pub struct MessageResponse {
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AnswerWithStatesRequest {
+    pub rating: u8, // 0=Again, 1=Hard, 2=Good, 3=Easy
+    /// The states returned by next-states, possibly adjusted by a custom
+    /// scheduler
+    pub states: SchedulingStatesJson,
+    /// Stored on the card; must be a JSON object
+    #[serde(default)]
+    pub custom_data: Option<serde_json::Value>,
+    #[serde(default)]
+    pub milliseconds_taken: u32,
+}
+
+/// Upper bound on the number of cards returned by a single prefetch
+pub const MAX_PREFETCH_CARDS: usize = 200;
+
+/// Upper bound on the number of answers accepted in a single upload
+pub const MAX_BATCH_ANSWERS: usize = 1000;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PrefetchQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PrefetchedCard {
+    pub card_id: i64,
+    pub question_html: String,
+    pub answer_html: String,
+    pub css: String,
+    pub flags: u8,
+    /// Interval descriptions for [Again, Hard, Good, Easy]
+    pub next_states: Vec<String>,
+    pub states: SchedulingStatesJson,
+    /// For cards in an intraday learning step, when the step is due
+    pub due_at: Option<i64>,
+    pub settings: ReviewerSettings,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PrefetchResponse {
+    pub cards: Vec<PrefetchedCard>,
+    pub counts: StudyCounts,
+    pub fetched_at: i64,
+    /// Learning cards due before this time may be shown early, as the
+    /// desktop reviewer does
+    pub learn_ahead_cutoff: i64,
+    /// After the day rolls over, the prefetched cards should be refetched
+    pub next_day_at: i64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BatchAnswer {
+    pub card_id: i64,
+    pub rating: u8, // 0=Again, 1=Hard, 2=Good, 3=Easy
+    pub states: SchedulingStatesJson,
+    #[serde(default)]
+    pub custom_data: Option<serde_json::Value>,
+    /// When the card was answered, in milliseconds since the epoch. Defaults
+    /// to now; times in the future are treated as now.
+    #[serde(default)]
+    pub answered_at: Option<i64>,
+    #[serde(default)]
+    pub milliseconds_taken: u32,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BatchAnswerRequest {
+    pub answers: Vec<BatchAnswer>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchAnswerStatus {
+    Answered,
+    /// The card was modified since its states were fetched
+    Conflict,
+    NotFound,
+    Invalid,
+    Failed,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct BatchAnswerResult {
+    pub card_id: i64,
+    pub status: BatchAnswerStatus,
+    pub error: Option<String>,
+    /// The card became a leech
+    pub leech: bool,
+    /// The card was suspended as a leech
+    pub suspended: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct BatchAnswerResponse {
+    pub answered: usize,
+    pub results: Vec<BatchAnswerResult>,
+}
+
+/// Interval descriptions for each answer button
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NextStatesResponse {
+    pub again: String,
+    pub hard: String,
+    pub good: String,
+    pub easy: String,
+    /// Pass back unchanged (or as adjusted by a custom scheduler) when
+    /// answering with answer-with-states
+    pub states: SchedulingStatesJson,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NextCardResponse {
+    /// None once the deck has nothing left to study today
+    pub card: Option<QueuedCardResponse>,
+    pub finished: bool,
+    /// Set once finished
+    pub congrats: Option<CongratsInfo>,
+    /// Learning cards due before this time are shown early, as the desktop
+    /// reviewer does
+    pub learn_ahead_cutoff: i64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AnswerCardResponse {
+    pub success: bool,
+    pub message: String,
+    /// The answer time as recorded, capped to the preset's maximum answer
+    /// seconds
+    pub milliseconds_taken: u32,
+    /// The card became a leech, and its note was tagged "leech"
+    pub leech: bool,
+    /// The card was suspended as a leech
+    pub suspended: bool,
+    /// What the desktop would show in a tooltip, in the client's language,
+    /// eg "Card was a leech. It has been suspended."
+    pub notice: Option<String>,
+}
+
+/// What the next undo and redo would revert, labelled in the client's
+/// language
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct UndoStatusResponse {
+    /// eg "Undo Answer Card", or None if there is nothing to undo
+    pub undo: Option<String>,
+    pub redo: Option<String>,
+    /// Increases with every undoable step
+    pub last_step: usize,
+}
+
+/// Which parts of the collection an operation touched, and what a client
+/// should refresh as a result
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OpChangesJson {
+    pub card: bool,
+    pub note: bool,
+    pub deck: bool,
+    pub tag: bool,
+    pub notetype: bool,
+    pub config: bool,
+    pub deck_config: bool,
+    pub study_queues: bool,
+    pub browser_table: bool,
+    pub browser_sidebar: bool,
+    pub note_text: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct UndoResponse {
     pub success: bool,
+    /// eg "Answer Card undone"
     pub message: String,
+    /// Label of the operation that was undone or redone
+    pub op: String,
+    pub changes: OpChangesJson,
+    pub undo_status: UndoStatusResponse,
+}
+
+impl From<&OpChanges> for OpChangesJson {
+    fn from(op: &OpChanges) -> Self {
+        let c = &op.changes;
+        Self {
+            card: c.card,
+            note: c.note,
+            deck: c.deck,
+            tag: c.tag,
+            notetype: c.notetype,
+            config: c.config,
+            deck_config: c.deck_config,
+            study_queues: op.requires_study_queue_rebuild(),
+            browser_table: op.requires_browser_table_redraw(),
+            browser_sidebar: op.requires_browser_sidebar_redraw(),
+            note_text: op.requires_note_text_redraw(),
+        }
+    }
 }
 
 /// Get the next card to study from a specific deck
@@ -55,15 +335,12 @@ pub async fn get_next_card(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Set the current deck to scope the scheduler to this deck
-    col.set_current_deck(deck_id.into())
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    col.set_current_deck(deck_id.into())?;
 
-    let queued_cards = col
-        .get_queued_cards(1, false)
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let queued_cards = col.get_queued_cards(1, false)?;
 
     if let Some(queued_card) = queued_cards.cards.first() {
         let card_id = queued_card.card.id();
@@ -72,13 +349,12 @@ pub async fn get_next_card(
         let full_card = <anki::collection::Collection as CardsService>::get_card(
             &mut *col,
             anki_proto::cards::CardId { cid: card_id.0 },
-        )
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+        )?;
 
         // Render the card HTML
-        let rendered = col
-            .render_existing_card(card_id, false, false)
-            .map_err(|e| WebAppError::internal(&e.to_string()))?;
+        let rendered = col.render_existing_card(card_id, false, false)?;
+        let settings = reviewer_settings(&mut col, &full_card)?;
+        let learn_ahead_cutoff = learn_ahead_cutoff(&mut col)?;
 
         let response = QueuedCardResponse {
             card_id: card_id.0,
@@ -91,40 +367,49 @@ pub async fn get_next_card(
                 review: queued_cards.review_count,
             },
             flags: full_card.flags as u8,
+            settings,
         };
 
         drop(col);
-        Ok(Json(serde_json::json!({
-            "card": response,
-            "finished": false,
-        })))
+        Ok(Json(NextCardResponse {
+            card: Some(response),
+            finished: false,
+            congrats: None,
+            learn_ahead_cutoff,
+        }))
     } else {
         // No more cards
+        let deck = col.get_current_deck()?;
+        let congrats = congrats_info(&mut col, &deck)?;
+        let learn_ahead_cutoff = learn_ahead_cutoff(&mut col)?;
         drop(col);
-        Ok(Json(serde_json::json!({
-            "card": null,
-            "finished": true,
-        })))
+        Ok(Json(NextCardResponse {
+            card: None,
+            finished: true,
+            congrats: Some(congrats),
+            learn_ahead_cutoff,
+        }))
     }
 }
 
-/// Answer a card and get the next one
+/// Answer the card at the head of a deck's queue
 pub async fn answer_card(
     State(state): State<AuthRouteState>,
     Extension(auth_user): Extension<AuthUser>,
-    Path((_deck_id, card_id)): Path<(i64, i64)>,
+    Path((deck_id, card_id)): Path<(i64, i64)>,
+    headers: HeaderMap,
     Json(request): Json<AnswerCardRequest>,
 ) -> Result<impl IntoResponse> {
     let backend = state
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
+
+    col.set_current_deck(deck_id.into())?;
 
     // Get the queued card to get its states
-    let queued_cards = col
-        .get_queued_cards(1, false)
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let queued_cards = col.get_queued_cards(1, false)?;
 
     if let Some(queued) = queued_cards.cards.first() {
         let queued_card_id = queued.card.id();
@@ -136,18 +421,7 @@ pub async fn answer_card(
             ));
         }
 
-        let rating = match request.rating {
-            0 => Rating::Again,
-            1 => Rating::Hard,
-            2 => Rating::Good,
-            3 => Rating::Easy,
-            _ => {
-                drop(col);
-                return Err(WebAppError::bad_request(
-                    "Invalid rating value. Must be 0-3.",
-                ));
-            }
-        };
+        let rating = parse_rating(request.rating)?;
 
         // Pick the new state based on the rating
         let new_state = match rating {
@@ -168,59 +442,244 @@ pub async fn answer_card(
             from_queue: true,
         };
 
-        col.answer_card(&mut answer)
-            .map_err(|e| WebAppError::internal(&e.to_string()))?;
+        let outcome = record_answer(&mut col, &mut answer)?;
 
         drop(col);
+        metrics().record_review();
 
-        Ok(Json(MessageResponse {
-            success: true,
-            message: "Card answered successfully".to_string(),
-        }))
+        Ok(Json(answer_response(outcome, &headers)))
     } else {
         drop(col);
         Err(WebAppError::not_found("No card in queue"))
     }
 }
 
-/// Get next interval descriptions for a card's answer buttons
+/// Answer any card in a deck with the states the client fetched from
+/// next-states, such as a card studied ahead of time or out of order
+pub async fn answer_card_with_states(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path((deck_id, card_id)): Path<(i64, i64)>,
+    headers: HeaderMap,
+    Json(request): Json<AnswerWithStatesRequest>,
+) -> Result<impl IntoResponse> {
+    let answer = ClientAnswer {
+        card_id: card_id.into(),
+        rating: parse_rating(request.rating)?,
+        states: SchedulingStates::try_from(request.states)?,
+        custom_data: request.custom_data,
+        answered_at: TimestampMillis::now(),
+        milliseconds_taken: request.milliseconds_taken,
+    };
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+    let outcome = answer_with_states(&mut col, deck_id.into(), answer)?;
+    drop(col);
+    metrics().record_review();
+
+    Ok(Json(answer_response(outcome, &headers)))
+}
+
+/// Get the next cards in a deck's queue, with everything needed to study
+/// them without further round trips
+pub async fn prefetch_cards(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(deck_id): Path<i64>,
+    Query(query): Query<PrefetchQuery>,
+) -> Result<impl IntoResponse> {
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_PREFETCH_CARDS);
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+
+    col.set_current_deck(deck_id.into())?;
+
+    let queued_cards = col.get_queued_cards(limit, false)?;
+
+    let now = TimestampSecs::now();
+    let mut cards = Vec::with_capacity(queued_cards.cards.len());
+    // Cards mostly come from a few decks, so look up each deck's preset once
+    let mut settings_by_deck = HashMap::new();
+    for queued in &queued_cards.cards {
+        let card_id = queued.card.id();
+        let rendered = col.render_existing_card(card_id, false, false)?;
+        let next_states = col.describe_next_states(&queued.states)?;
+        let full_card = <anki::collection::Collection as CardsService>::get_card(
+            &mut *col,
+            anki_proto::cards::CardId { cid: card_id.0 },
+        )?;
+        let settings = match settings_by_deck.get(&home_deck_id(&full_card)) {
+            Some(settings) => *settings,
+            None => {
+                let settings = reviewer_settings(&mut col, &full_card)?;
+                settings_by_deck.insert(home_deck_id(&full_card), settings);
+                settings
+            }
+        };
+
+        cards.push(PrefetchedCard {
+            card_id: card_id.0,
+            question_html: rendered.question().into_owned(),
+            answer_html: rendered.answer().into_owned(),
+            css: rendered.css.clone(),
+            flags: full_card.flags as u8,
+            next_states,
+            states: SchedulingStatesJson::from(&queued.states),
+            due_at: learning_due_at(&queued.states.current, now),
+            settings,
+        });
+    }
+
+    let learn_ahead_secs = col.get_scheduling_preferences()?.learn_ahead_secs;
+    let timing = col.timing_today()?;
+
+    drop(col);
+
+    Ok(Json(PrefetchResponse {
+        cards,
+        counts: StudyCounts {
+            new: queued_cards.new_count,
+            learning: queued_cards.learning_count,
+            review: queued_cards.review_count,
+        },
+        fetched_at: now.0,
+        learn_ahead_cutoff: now.0 + learn_ahead_secs as i64,
+        next_day_at: timing.next_day_at.0,
+    }))
+}
+
+/// Apply answers recorded while offline, in the order given. Each answer is
+/// applied on its own, so a conflict doesn't prevent later answers from
+/// being recorded.
+pub async fn answer_cards_batch(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(deck_id): Path<i64>,
+    Json(request): Json<BatchAnswerRequest>,
+) -> Result<impl IntoResponse> {
+    if request.answers.len() > MAX_BATCH_ANSWERS {
+        return Err(WebAppError::bad_request(&format!(
+            "At most {} answers can be uploaded at once",
+            MAX_BATCH_ANSWERS
+        )));
+    }
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+
+    let now = TimestampMillis::now();
+    let mut results = Vec::with_capacity(request.answers.len());
+    for answer in request.answers {
+        let card_id = answer.card_id;
+        let outcome = parse_rating(answer.rating)
+            .and_then(|rating| {
+                Ok(ClientAnswer {
+                    card_id: card_id.into(),
+                    rating,
+                    states: SchedulingStates::try_from(answer.states)?,
+                    custom_data: answer.custom_data,
+                    answered_at: answer
+                        .answered_at
+                        .map(|millis| TimestampMillis(millis.min(now.0)))
+                        .unwrap_or(now),
+                    milliseconds_taken: answer.milliseconds_taken,
+                })
+            })
+            .and_then(|answer| answer_with_states(&mut col, deck_id.into(), answer));
+
+        let (status, error, outcome) = match outcome {
+            Ok(outcome) => (BatchAnswerStatus::Answered, None, outcome),
+            Err(WebAppError::Conflict(msg)) => {
+                (BatchAnswerStatus::Conflict, Some(msg), Default::default())
+            }
+            Err(WebAppError::NotFound(msg)) => {
+                (BatchAnswerStatus::NotFound, Some(msg), Default::default())
+            }
+            Err(WebAppError::BadRequest(msg)) => {
+                (BatchAnswerStatus::Invalid, Some(msg), Default::default())
+            }
+            Err(e @ WebAppError::Anki(AnkiError::NotFound { .. })) => (
+                BatchAnswerStatus::NotFound,
+                Some(e.to_string()),
+                Default::default(),
+            ),
+            Err(e @ WebAppError::Anki(AnkiError::InvalidInput { .. })) => (
+                BatchAnswerStatus::Invalid,
+                Some(e.to_string()),
+                Default::default(),
+            ),
+            Err(e) => {
+                tracing::error!("Failed to apply answer for card {}: {}", card_id, e);
+                (
+                    BatchAnswerStatus::Failed,
+                    Some(e.to_string()),
+                    Default::default(),
+                )
+            }
+        };
+        results.push(BatchAnswerResult {
+            card_id,
+            status,
+            error,
+            leech: outcome.leech,
+            suspended: outcome.suspended,
+        });
+    }
+
+    drop(col);
+
+    let answered = results
+        .iter()
+        .filter(|result| result.status == BatchAnswerStatus::Answered)
+        .count();
+    for _ in 0..answered {
+        metrics().record_review();
+    }
+
+    Ok(Json(BatchAnswerResponse { answered, results }))
+}
+
+/// Get a card's scheduling states and the interval descriptions for its
+/// answer buttons
 pub async fn get_next_states(
     State(state): State<AuthRouteState>,
     Extension(auth_user): Extension<AuthUser>,
-    Path((_deck_id, card_id)): Path<(i64, i64)>,
+    Path((deck_id, card_id)): Path<(i64, i64)>,
 ) -> Result<impl IntoResponse> {
     let backend = state
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
+
+    ensure_card_in_deck(&mut col, deck_id.into(), card_id.into())?;
 
     // Get scheduling states for the card
-    let states = col
-        .get_scheduling_states(card_id.into())
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let states = col.get_scheduling_states(card_id.into())?;
 
     // Get human-readable interval descriptions
-    let descriptions = col
-        .describe_next_states(&states)
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let descriptions = col.describe_next_states(&states)?;
 
     drop(col);
 
     // descriptions is a Vec<String> with 4 entries: [Again, Hard, Good, Easy]
-    #[derive(Serialize)]
-    struct NextStatesResponse {
-        again: String,
-        hard: String,
-        good: String,
-        easy: String,
-    }
-
     let response = NextStatesResponse {
         again: descriptions.get(0).cloned().unwrap_or_default(),
         hard: descriptions.get(1).cloned().unwrap_or_default(),
         good: descriptions.get(2).cloned().unwrap_or_default(),
         easy: descriptions.get(3).cloned().unwrap_or_default(),
+        states: SchedulingStatesJson::from(&states),
     };
 
     Ok(Json(response))
@@ -236,14 +695,11 @@ pub async fn get_deck_counts(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
-    col.set_current_deck(deck_id.into())
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    col.set_current_deck(deck_id.into())?;
 
-    let queued_cards = col
-        .get_queued_cards(0, false)
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let queued_cards = col.get_queued_cards(0, false)?;
 
     drop(col);
 
@@ -254,62 +710,194 @@ pub async fn get_deck_counts(
     }))
 }
 
+/// Get what the desktop shows when a deck has nothing left to study today
+pub async fn get_congrats_info(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(deck_id): Path<i64>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+
+    // Read only, so the current deck and the undo queue are left alone
+    let deck = col
+        .get_deck(deck_id.into())?
+        .ok_or_else(|| WebAppError::not_found("Deck not found"))?;
+    let congrats = congrats_info(&mut col, &deck)?;
+
+    drop(col);
+
+    Ok(Json(congrats))
+}
+
+/// Report what the next undo and redo would do
+pub async fn get_undo_status(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let col = lock_collection(&backend);
+    let status = col.undo_status();
+    drop(col);
+
+    let tr = I18n::new(&accepted_languages(&headers));
+    Ok(Json(undo_status_response(&status, &tr)))
+}
+
 /// Undo the last operation
 pub async fn undo(
     State(state): State<AuthRouteState>,
     Extension(auth_user): Extension<AuthUser>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse> {
     let backend = state
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
+    let output = col.undo()?;
+    drop(col);
 
-    match col.undo() {
-        Ok(_) => {
-            drop(col);
-            Ok(Json(MessageResponse {
-                success: true,
-                message: "Action undone successfully".to_string(),
-            }))
-        }
-        Err(e) => {
-            drop(col);
-            if e.to_string().is_empty() || matches!(e, anki::error::AnkiError::UndoEmpty) {
-                Err(WebAppError::bad_request("Nothing to undo"))
-            } else {
-                Err(WebAppError::internal(&e.to_string()))
-            }
-        }
-    }
+    let tr = I18n::new(&accepted_languages(&headers));
+    let op = output.output.undone_op.describe(&tr);
+    Ok(Json(UndoResponse {
+        success: true,
+        message: tr.undo_action_undone(op.as_str()).into(),
+        changes: OpChangesJson::from(&output.changes),
+        undo_status: undo_status_response(&output.output.new_undo_status, &tr),
+        op,
+    }))
 }
 
 /// Redo the last undone operation
 pub async fn redo(
     State(state): State<AuthRouteState>,
     Extension(auth_user): Extension<AuthUser>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse> {
     let backend = state
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
+    let output = col.redo()?;
+    drop(col);
 
-    match col.redo() {
-        Ok(_) => {
-            drop(col);
-            Ok(Json(MessageResponse {
-                success: true,
-                message: "Action redone successfully".to_string(),
-            }))
-        }
-        Err(e) => {
-            drop(col);
-            if e.to_string().is_empty() || matches!(e, anki::error::AnkiError::UndoEmpty) {
-                Err(WebAppError::bad_request("Nothing to redo"))
-            } else {
-                Err(WebAppError::internal(&e.to_string()))
-            }
+    let tr = I18n::new(&accepted_languages(&headers));
+    let op = output.output.undone_op.describe(&tr);
+    Ok(Json(UndoResponse {
+        success: true,
+        message: tr.undo_action_redone(op.as_str()).into(),
+        changes: OpChangesJson::from(&output.changes),
+        undo_status: undo_status_response(&output.output.new_undo_status, &tr),
+        op,
+    }))
+}
+
+fn undo_status_response(status: &UndoStatus, tr: &I18n) -> UndoStatusResponse {
+    UndoStatusResponse {
+        undo: status
+            .undo
+            .as_ref()
+            .map(|op| tr.undo_undo_action(op.describe(tr)).into()),
+        redo: status
+            .redo
+            .as_ref()
+            .map(|op| tr.undo_redo_action(op.describe(tr)).into()),
+        last_step: status.last_step,
+    }
+}
+
+/// The deck a card's preset comes from: its home deck if it is in a
+/// filtered deck
+fn home_deck_id(card: &anki_proto::cards::Card) -> DeckId {
+    if card.original_deck_id != 0 {
+        DeckId(card.original_deck_id)
+    } else {
+        DeckId(card.deck_id)
+    }
+}
+
+fn reviewer_settings(
+    col: &mut Collection,
+    card: &anki_proto::cards::Card,
+) -> Result<ReviewerSettings> {
+    let config_id = col
+        .get_deck(home_deck_id(card))?
+        .and_then(|deck| deck.config_id())
+        .unwrap_or(DeckConfigId(1));
+    let config = col.get_deck_config(config_id, true)?.unwrap_or_default();
+    let inner = &config.inner;
+
+    Ok(ReviewerSettings {
+        seconds_to_show_question: inner.seconds_to_show_question,
+        seconds_to_show_answer: inner.seconds_to_show_answer,
+        question_action: match inner.question_action() {
+            QuestionAction::ShowAnswer => QuestionActionJson::ShowAnswer,
+            QuestionAction::ShowReminder => QuestionActionJson::ShowReminder,
+        },
+        answer_action: match inner.answer_action() {
+            AnswerAction::BuryCard => AnswerActionJson::BuryCard,
+            AnswerAction::AnswerAgain => AnswerActionJson::AnswerAgain,
+            AnswerAction::AnswerGood => AnswerActionJson::AnswerGood,
+            AnswerAction::AnswerHard => AnswerActionJson::AnswerHard,
+            AnswerAction::ShowReminder => AnswerActionJson::ShowReminder,
+        },
+        wait_for_audio: inner.wait_for_audio,
+        autoplay: !inner.disable_autoplay,
+        skip_question_when_replaying_answer: inner.skip_question_when_replaying_answer,
+        show_timer: inner.show_timer,
+        stop_timer_on_answer: inner.stop_timer_on_answer,
+        max_answer_secs: inner.cap_answer_time_to_secs,
+    })
+}
+
+fn congrats_info(col: &mut Collection, deck: &Deck) -> Result<CongratsInfo> {
+    let info = col.congrats_info_for_deck(deck)?;
+    Ok(CongratsInfo {
+        learn_remaining: info.learn_remaining,
+        secs_until_next_learn: (info.learn_remaining > 0).then_some(info.secs_until_next_learn),
+        review_remaining: info.review_remaining,
+        new_remaining: info.new_remaining,
+        have_sched_buried: info.have_sched_buried,
+        have_user_buried: info.have_user_buried,
+        is_filtered_deck: info.is_filtered_deck,
+        deck_description: info.deck_description,
+    })
+}
+
+fn learn_ahead_cutoff(col: &mut Collection) -> Result<i64> {
+    let learn_ahead_secs = col.get_scheduling_preferences()?.learn_ahead_secs;
+    Ok(TimestampSecs::now().0 + learn_ahead_secs as i64)
+}
+
+fn answer_response(outcome: AnswerOutcome, headers: &HeaderMap) -> AnswerCardResponse {
+    let notice = outcome.leech.then(|| {
+        let tr = I18n::new(&accepted_languages(headers));
+        if outcome.suspended {
+            format!(
+                "{} {}",
+                tr.studying_card_was_a_leech(),
+                tr.studying_it_has_been_suspended()
+            )
+        } else {
+            tr.studying_card_was_a_leech().into()
         }
+    });
+
+    AnswerCardResponse {
+        success: true,
+        message: "Card answered successfully".to_string(),
+        milliseconds_taken: outcome.milliseconds_taken,
+        leech: outcome.leech,
+        suspended: outcome.suspended,
+        notice,
     }
 }
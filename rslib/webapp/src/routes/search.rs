@@ -1,46 +1,169 @@
+use anki::browser_table::Column;
+use anki::search::SortMode;
+use anki::services::CardsService;
 use axum::extract::State;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::Map;
+use serde_json::Value;
 
 use crate::auth::AuthUser;
 use crate::error::Result;
 use crate::error::WebAppError;
+use crate::routes::cards::card_to_info;
+use crate::routes::notes::NoteInfo;
 use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Largest page returned when results are projected inline
+const MAX_PROJECTED_LIMIT: usize = 1000;
+
+/// Page size used when projected results are requested without a limit
+const DEFAULT_PROJECTED_LIMIT: usize = 100;
+
+/// Properties of `CardInfo` that can be projected into search results
+const CARD_PROJECTION_FIELDS: &[&str] = &[
+    "note_id",
+    "deck_id",
+    "ordinal",
+    "card_type",
+    "queue",
+    "due",
+    "interval",
+    "ease_factor",
+    "reps",
+    "lapses",
+    "flags",
+];
+
+/// Properties of `NoteInfo` that can be projected into search results
+const NOTE_PROJECTION_FIELDS: &[&str] = &["notetype_id", "fields", "tags"];
+
+/// A browser column to sort by, named as in the collection config. Notes
+/// can't be sorted by `stability`, `difficulty` or `retrievability`, and are
+/// left unordered.
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SortColumn {
+    CardMod,
+    #[serde(rename = "template")]
+    Cards,
+    Deck,
+    #[serde(rename = "cardDue")]
+    Due,
+    #[serde(rename = "cardEase")]
+    Ease,
+    #[serde(rename = "cardLapses")]
+    Lapses,
+    #[serde(rename = "cardIvl")]
+    Interval,
+    #[serde(rename = "noteCrt")]
+    NoteCreation,
+    NoteMod,
+    #[serde(rename = "note")]
+    Notetype,
+    OriginalPosition,
+    #[serde(rename = "cardReps")]
+    Reps,
+    #[serde(rename = "noteFld")]
+    SortField,
+    #[serde(rename = "noteTags")]
+    Tags,
+    Stability,
+    Difficulty,
+    Retrievability,
+}
+
+impl From<SortColumn> for Column {
+    fn from(column: SortColumn) -> Self {
+        match column {
+            SortColumn::CardMod => Column::CardMod,
+            SortColumn::Cards => Column::Cards,
+            SortColumn::Deck => Column::Deck,
+            SortColumn::Due => Column::Due,
+            SortColumn::Ease => Column::Ease,
+            SortColumn::Lapses => Column::Lapses,
+            SortColumn::Interval => Column::Interval,
+            SortColumn::NoteCreation => Column::NoteCreation,
+            SortColumn::NoteMod => Column::NoteMod,
+            SortColumn::Notetype => Column::Notetype,
+            SortColumn::OriginalPosition => Column::OriginalPosition,
+            SortColumn::Reps => Column::Reps,
+            SortColumn::SortField => Column::SortField,
+            SortColumn::Tags => Column::Tags,
+            SortColumn::Stability => Column::Stability,
+            SortColumn::Difficulty => Column::Difficulty,
+            SortColumn::Retrievability => Column::Retrievability,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct SearchCardsRequest {
     pub query: String,
+    /// Results are unordered if not provided
     #[serde(default)]
-    pub sort_column: Option<String>,
+    pub sort_column: Option<SortColumn>,
     #[serde(default)]
     pub reverse: bool,
+    #[serde(flatten)]
+    pub page: PageRequest,
+    /// Card properties to return inline for each result on the page
+    #[serde(default)]
+    pub fields: Option<Vec<String>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct SearchCardsResponse {
     pub card_ids: Vec<i64>,
+    /// Total number of matches, including those outside the page
     pub count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cards: Option<Vec<Value>>,
+    /// Pass back as `offset` to fetch the next page; null on the last page
+    pub next_offset: Option<usize>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct SearchNotesRequest {
     pub query: String,
+    /// Results are unordered if not provided
     #[serde(default)]
-    pub sort_column: Option<String>,
+    pub sort_column: Option<SortColumn>,
     #[serde(default)]
     pub reverse: bool,
+    #[serde(flatten)]
+    pub page: PageRequest,
+    /// Note properties to return inline for each result on the page
+    #[serde(default)]
+    pub fields: Option<Vec<String>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct SearchNotesResponse {
     pub note_ids: Vec<i64>,
+    /// Total number of matches, including those outside the page
     pub count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<Vec<Value>>,
+    /// Pass back as `offset` to fetch the next page; null on the last page
+    pub next_offset: Option<usize>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Paging for search results. Without a limit, all matches are returned
+/// unless fields are projected. Pages are positions in the sorted results,
+/// so they may shift if the collection changes between requests.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct PageRequest {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct FindAndReplaceRequest {
     pub note_ids: Vec<i64>,
     pub search: String,
@@ -53,7 +176,7 @@ pub struct FindAndReplaceRequest {
     pub field_name: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct FindAndReplaceResponse {
     pub success: bool,
     pub message: String,
@@ -67,36 +190,41 @@ pub async fn search_cards(
     Json(request): Json<SearchCardsRequest>,
 ) -> Result<impl IntoResponse> {
     tracing::debug!(user_id = auth_user.user_id, query = %request.query, "search_cards request");
+    let projection = validate_projection(request.fields.as_deref(), CARD_PROJECTION_FIELDS)?;
+    let (offset, limit) = page_bounds(&request.page, projection.is_some())?;
+    let mode = sort_mode(request.sort_column, request.reverse);
 
     let backend = state
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
-
-    // Build sort mode - use NoOrder for simplicity
-    let sort_mode = anki::search::SortMode::NoOrder;
-
-    // Search for cards
-    let card_ids = col
-        .search_cards(&request.query, sort_mode);
-
-    match card_ids {
-        Ok(ids) => {
-            tracing::debug!(count = ids.len(), "search_cards succeeded");
-            let count = ids.len();
-            let ids: Vec<i64> = ids.into_iter().map(|cid| cid.0).collect();
-            drop(col);
-            Ok(Json(SearchCardsResponse {
-                card_ids: ids,
-                count,
-            }))
-        }
-        Err(e) => {
-            tracing::error!("Search cards failed for query '{}': {:?}", request.query, e);
-            Err(WebAppError::internal(&format!("{:?}", e)))
+    let mut col = lock_collection(&backend);
+
+    let ids = col.search_cards(request.query.as_str(), mode)?;
+    tracing::debug!(count = ids.len(), "search_cards succeeded");
+    let count = ids.len();
+    let page = paginate(&ids, offset, limit);
+
+    let cards = match &projection {
+        Some(fields) => {
+            let mut cards = Vec::with_capacity(page.len());
+            for card_id in page {
+                let card = col.get_card(anki_proto::cards::CardId { cid: card_id.0 })?;
+                cards.push(project(card_id.0, &card_to_info(&card), fields)?);
+            }
+            Some(cards)
         }
-    }
+        None => None,
+    };
+
+    drop(col);
+
+    Ok(Json(SearchCardsResponse {
+        card_ids: page.iter().map(|cid| cid.0).collect(),
+        count,
+        cards,
+        next_offset: next_offset(offset, page.len(), count),
+    }))
 }
 
 /// Search for notes matching a query
@@ -106,36 +234,122 @@ pub async fn search_notes(
     Json(request): Json<SearchNotesRequest>,
 ) -> Result<impl IntoResponse> {
     tracing::debug!(user_id = auth_user.user_id, query = %request.query, "search_notes request");
+    let projection = validate_projection(request.fields.as_deref(), NOTE_PROJECTION_FIELDS)?;
+    let (offset, limit) = page_bounds(&request.page, projection.is_some())?;
+    let mode = sort_mode(request.sort_column, request.reverse);
 
     let backend = state
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
-
-    // Build sort mode - use NoOrder for simplicity
-    let sort_mode = anki::search::SortMode::NoOrder;
-
-    // Search for notes
-    let note_ids = col
-        .search_notes(&request.query, sort_mode);
-
-    match note_ids {
-        Ok(ids) => {
-            tracing::debug!(count = ids.len(), "search_notes succeeded");
-            let count = ids.len();
-            let ids: Vec<i64> = ids.into_iter().map(|nid| nid.0).collect();
-            drop(col);
-            Ok(Json(SearchNotesResponse {
-                note_ids: ids,
-                count,
-            }))
+    let mut col = lock_collection(&backend);
+
+    let ids = col.search_notes(request.query.as_str(), mode)?;
+    tracing::debug!(count = ids.len(), "search_notes succeeded");
+    let count = ids.len();
+    let page = paginate(&ids, offset, limit);
+
+    let notes = match &projection {
+        Some(fields) => {
+            let mut notes = Vec::with_capacity(page.len());
+            for note_id in page {
+                let note = col
+                    .storage
+                    .get_note(*note_id)?
+                    .ok_or_else(|| WebAppError::not_found("Note not found"))?;
+                let info = NoteInfo {
+                    id: note.id.0,
+                    notetype_id: note.notetype_id.0,
+                    fields: note.fields().clone(),
+                    tags: note.tags.clone(),
+                };
+                notes.push(project(note_id.0, &info, fields)?);
+            }
+            Some(notes)
         }
-        Err(e) => {
-            tracing::error!("Search cards failed for query '{}': {:?}", request.query, e);
-            Err(WebAppError::internal(&format!("{:?}", e)))
+        None => None,
+    };
+
+    drop(col);
+
+    Ok(Json(SearchNotesResponse {
+        note_ids: page.iter().map(|nid| nid.0).collect(),
+        count,
+        notes,
+        next_offset: next_offset(offset, page.len(), count),
+    }))
+}
+
+fn sort_mode(sort_column: Option<SortColumn>, reverse: bool) -> SortMode {
+    match sort_column {
+        Some(column) => SortMode::Builtin {
+            column: column.into(),
+            reverse,
+        },
+        None => SortMode::NoOrder,
+    }
+}
+
+/// The requested projection, with `id` always included. Unknown fields are
+/// rejected so that typos don't silently return nothing.
+fn validate_projection(fields: Option<&[String]>, allowed: &[&str]) -> Result<Option<Vec<String>>> {
+    let Some(fields) = fields else {
+        return Ok(None);
+    };
+    if let Some(unknown) = fields
+        .iter()
+        .find(|field| !allowed.contains(&field.as_str()))
+    {
+        return Err(WebAppError::bad_request(&format!(
+            "Unknown field '{}', expected one of: {}",
+            unknown,
+            allowed.join(", ")
+        )));
+    }
+    Ok(Some(fields.to_vec()))
+}
+
+/// The offset and limit for a page; a limit of None returns all matches
+fn page_bounds(page: &PageRequest, projected: bool) -> Result<(usize, Option<usize>)> {
+    let offset = page.offset.unwrap_or(0);
+    let limit = match page.limit {
+        Some(0) => return Err(WebAppError::bad_request("limit must be at least 1")),
+        Some(limit) if projected => Some(limit.min(MAX_PROJECTED_LIMIT)),
+        Some(limit) => Some(limit),
+        None if projected => Some(DEFAULT_PROJECTED_LIMIT),
+        None => None,
+    };
+    Ok((offset, limit))
+}
+
+fn paginate<T>(ids: &[T], offset: usize, limit: Option<usize>) -> &[T] {
+    let start = offset.min(ids.len());
+    let end = limit.map_or(ids.len(), |limit| {
+        start.saturating_add(limit).min(ids.len())
+    });
+    &ids[start..end]
+}
+
+fn next_offset(offset: usize, returned: usize, total: usize) -> Option<usize> {
+    let next = offset + returned;
+    (returned > 0 && next < total).then_some(next)
+}
+
+/// Serialize an object, keeping only its id and the requested fields
+fn project<T: Serialize>(id: i64, item: &T, fields: &[String]) -> Result<Value> {
+    let Value::Object(mut all) =
+        serde_json::to_value(item).map_err(|e| WebAppError::internal(&e.to_string()))?
+    else {
+        return Err(WebAppError::internal("Expected an object"));
+    };
+    let mut projected = Map::new();
+    projected.insert("id".to_string(), Value::from(id));
+    for field in fields {
+        if let Some(value) = all.remove(field) {
+            projected.insert(field.clone(), value);
         }
     }
+    Ok(Value::Object(projected))
 }
 
 /// Find and replace text in note fields
@@ -148,7 +362,7 @@ pub async fn find_and_replace(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Convert note IDs
     let nids: Vec<anki::notes::NoteId> = request
@@ -170,14 +384,12 @@ pub async fn find_and_replace(
     }
 
     // Perform find and replace
-    let result = col
-        .find_and_replace(
-            nids,
-            &search_pattern,
-            &request.replacement,
-            request.field_name,
-        )
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let result = col.find_and_replace(
+        nids,
+        &search_pattern,
+        &request.replacement,
+        request.field_name,
+    )?;
 
     let replaced_count = result.output;
 
@@ -0,0 +1,345 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! Sharing decks between users of the same server.
+//!
+//! Publishing exports a deck, without scheduling, into the server's shared
+//! library. Subscribers import it into their own collection, and pull later
+//! versions in the same way: notes are matched by GUID and notetypes merged,
+//! so changed notes are updated while the subscriber's cards keep their
+//! scheduling.
+
+use anki::decks::DeckId;
+use anki::decks::DeckKind;
+use anki::import_export::package::ExportAnkiPackageOptions;
+use anki::import_export::package::ImportAnkiPackageOptions;
+use anki::import_export::package::UpdateCondition;
+use anki::import_export::NoteLog;
+use anki::search::SearchNode;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Extension;
+use axum::Json;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::auth::AuthUser;
+use crate::db::SharedDeck;
+use crate::error::Result;
+use crate::error::WebAppError;
+use crate::metrics::metrics;
+use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PublishDeckRequest {
+    /// The deck to publish, including its subdecks
+    pub deck_id: i64,
+    /// Shown in the library. Omit to keep the description of an earlier
+    /// version.
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SharedDecksListResponse {
+    pub decks: Vec<SharedDeck>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SubscriptionInfo {
+    pub shared_deck: SharedDeck,
+    /// The version last imported into the collection
+    pub subscribed_version: i64,
+    /// True if a newer version has been published since
+    pub update_available: bool,
+    pub subscribed_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SubscriptionsListResponse {
+    pub subscriptions: Vec<SubscriptionInfo>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SharedDeckImportResponse {
+    pub success: bool,
+    pub message: String,
+    /// The version now in the collection
+    pub version: i64,
+    pub notes_new: u32,
+    pub notes_updated: u32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SharingMessageResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+/// Publish a deck to the shared library, or publish a new version of it.
+/// Responds with 201 the first time a deck is published.
+pub async fn publish_deck(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<PublishDeckRequest>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+
+    let deck = col
+        .get_deck(DeckId(request.deck_id))?
+        .ok_or_else(|| WebAppError::not_found("Deck not found"))?;
+    if matches!(deck.kind, DeckKind::Filtered(_)) {
+        return Err(WebAppError::bad_request("Filtered decks cannot be shared"));
+    }
+
+    let path = state
+        .backend_manager
+        .get_shared_deck_path(auth_user.user_id, deck.id.0);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            WebAppError::internal(&format!("Failed to create shared deck folder: {}", e))
+        })?;
+    }
+    let note_count = col.export_apkg(
+        &path,
+        ExportAnkiPackageOptions {
+            with_scheduling: false,
+            with_deck_configs: false,
+            with_media: true,
+            legacy: false,
+        },
+        SearchNode::from_deck_id(deck.id, true),
+        None,
+    )?;
+
+    drop(col);
+
+    let shared = state.database.sharing().publish(
+        auth_user.user_id,
+        deck.id.0,
+        &deck.human_name(),
+        request.description.as_deref(),
+        note_count as i64,
+    )?;
+    tracing::info!(
+        "User {} published deck {} as shared deck {} version {}",
+        auth_user.username,
+        deck.id.0,
+        shared.id,
+        shared.version
+    );
+
+    let status = if shared.version == 1 {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(shared)))
+}
+
+/// List the decks in the shared library
+pub async fn list_shared_decks(
+    State(state): State<AuthRouteState>,
+    Extension(_auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    let decks = state.database.sharing().list()?;
+
+    Ok(Json(SharedDecksListResponse { decks }))
+}
+
+/// Remove one of the current user's decks from the shared library.
+/// Subscribers keep the notes they already imported.
+pub async fn unpublish_deck(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse> {
+    let sharing = state.database.sharing();
+    let shared = sharing
+        .get(id)?
+        .ok_or_else(|| WebAppError::not_found("Shared deck not found"))?;
+    if shared.owner_id != auth_user.user_id {
+        return Err(WebAppError::forbidden(
+            "Only the owner can unpublish a shared deck",
+        ));
+    }
+
+    sharing.delete(id)?;
+    let path = state
+        .backend_manager
+        .get_shared_deck_path(shared.owner_id, shared.source_deck_id);
+    if let Err(e) = std::fs::remove_file(&path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("Failed to remove shared deck file {:?}: {}", path, e);
+        }
+    }
+
+    Ok(Json(SharingMessageResponse {
+        success: true,
+        message: format!("Deck '{}' unpublished", shared.name),
+    }))
+}
+
+/// Subscribe to a shared deck, importing its latest version into the
+/// current user's collection
+pub async fn subscribe_deck(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse> {
+    let sharing = state.database.sharing();
+    let shared = sharing
+        .get(id)?
+        .ok_or_else(|| WebAppError::not_found("Shared deck not found"))?;
+    if shared.owner_id == auth_user.user_id {
+        return Err(WebAppError::bad_request(
+            "You cannot subscribe to your own deck",
+        ));
+    }
+    if sharing.get_subscription(auth_user.user_id, id)?.is_some() {
+        return Err(WebAppError::conflict("Already subscribed to this deck"));
+    }
+
+    let log = import_shared_deck(&state, &auth_user, &shared)?;
+    sharing.set_subscribed_version(auth_user.user_id, id, shared.version)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(SharedDeckImportResponse {
+            success: true,
+            message: format!("Subscribed to '{}'", shared.name),
+            version: shared.version,
+            notes_new: log.new.len() as u32,
+            notes_updated: log.updated.len() as u32,
+        }),
+    ))
+}
+
+/// Stop receiving updates to a shared deck. Notes already imported stay in
+/// the collection.
+pub async fn unsubscribe_deck(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse> {
+    if !state
+        .database
+        .sharing()
+        .unsubscribe(auth_user.user_id, id)?
+    {
+        return Err(WebAppError::not_found("Not subscribed to this deck"));
+    }
+
+    Ok(Json(SharingMessageResponse {
+        success: true,
+        message: "Unsubscribed".to_string(),
+    }))
+}
+
+/// List the current user's subscriptions, and whether each has an update
+pub async fn list_subscriptions(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<impl IntoResponse> {
+    let subscriptions = state
+        .database
+        .sharing()
+        .list_subscriptions(auth_user.user_id)?
+        .into_iter()
+        .map(|(subscription, shared_deck)| SubscriptionInfo {
+            update_available: shared_deck.version > subscription.version,
+            shared_deck,
+            subscribed_version: subscription.version,
+            subscribed_at: subscription.subscribed_at,
+            updated_at: subscription.updated_at,
+        })
+        .collect();
+
+    Ok(Json(SubscriptionsListResponse { subscriptions }))
+}
+
+/// Import the latest version of a subscribed deck. Changed notes are
+/// updated and new ones added; existing cards keep their scheduling. Does
+/// nothing if the collection already has the latest version.
+pub async fn update_subscription(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse> {
+    let sharing = state.database.sharing();
+    let subscription = sharing
+        .get_subscription(auth_user.user_id, id)?
+        .ok_or_else(|| WebAppError::not_found("Not subscribed to this deck"))?;
+    let shared = sharing
+        .get(id)?
+        .ok_or_else(|| WebAppError::not_found("Shared deck not found"))?;
+
+    if subscription.version >= shared.version {
+        return Ok(Json(SharedDeckImportResponse {
+            success: true,
+            message: format!("'{}' is up to date", shared.name),
+            version: subscription.version,
+            notes_new: 0,
+            notes_updated: 0,
+        }));
+    }
+
+    let log = import_shared_deck(&state, &auth_user, &shared)?;
+    sharing.set_subscribed_version(auth_user.user_id, id, shared.version)?;
+
+    Ok(Json(SharedDeckImportResponse {
+        success: true,
+        message: format!("Updated '{}' to version {}", shared.name, shared.version),
+        version: shared.version,
+        notes_new: log.new.len() as u32,
+        notes_updated: log.updated.len() as u32,
+    }))
+}
+
+/// Import the published snapshot of a shared deck into the user's
+/// collection. Subscriptions are read-only, so the publisher's notes and
+/// notetypes always win over local edits.
+fn import_shared_deck(
+    state: &AuthRouteState,
+    auth_user: &AuthUser,
+    shared: &SharedDeck,
+) -> Result<NoteLog> {
+    let path = state
+        .backend_manager
+        .get_shared_deck_path(shared.owner_id, shared.source_deck_id);
+    if !path.exists() {
+        return Err(WebAppError::not_found("Shared deck file is missing"));
+    }
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+
+    let mut col = lock_collection(&backend);
+
+    let log = col
+        .import_apkg(
+            &path,
+            ImportAnkiPackageOptions {
+                merge_notetypes: true,
+                update_notes: UpdateCondition::Always as i32,
+                update_notetypes: UpdateCondition::Always as i32,
+                with_scheduling: false,
+                with_deck_configs: false,
+            },
+        )?
+        .output;
+
+    drop(col);
+    metrics().record_import();
+
+    Ok(log)
+}
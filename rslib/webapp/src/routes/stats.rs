@@ -6,6 +6,7 @@ use axum::extract::State;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -13,14 +14,15 @@ use crate::auth::AuthUser;
 use crate::error::Result;
 use crate::error::WebAppError;
 use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct GraphsQuery {
     pub search: Option<String>,
     pub days: Option<u32>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct CardStatsResponse {
     pub card_id: i64,
     pub note_id: i64,
@@ -39,7 +41,7 @@ pub struct CardStatsResponse {
     pub notetype: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct TodayStatsResponse {
     pub answer_count: u32,
     pub answer_millis: u32,
@@ -52,21 +54,17 @@ pub struct TodayStatsResponse {
     pub early_review_count: u32,
 }
 
-#[derive(Debug, Serialize)]
-pub struct CardCountsResponse {
-    pub new_cards: u32,
-    pub learn: u32,
-    pub relearn: u32,
-    pub young: u32,
-    pub mature: u32,
-    pub suspended: u32,
-    pub buried: u32,
-}
-
-#[derive(Debug, Serialize)]
+/// Card counts across the whole collection. New, young and mature exclude
+/// suspended and buried cards.
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct CollectionStatsResponse {
-    pub today: TodayStatsResponse,
-    pub card_counts: CardCountsResponse,
+    pub total_cards: u32,
+    pub new_cards: u32,
+    pub young_cards: u32,
+    pub mature_cards: u32,
+    pub suspended_cards: u32,
+    pub buried_cards: u32,
+    pub total_notes: u32,
 }
 
 /// Get statistics for a specific card
@@ -79,12 +77,10 @@ pub async fn get_card_stats(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Get card stats
-    let result = col
-        .card_stats(anki::card::CardId(card_id))
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let result = col.card_stats(anki::card::CardId(card_id))?;
 
     drop(col);
 
@@ -162,7 +158,7 @@ pub async fn get_collection_stats(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Each search is independent — no heavyweight graphs() call or temp-table
     // interactions that could silently return zeros.
@@ -175,40 +171,31 @@ pub async fn get_collection_stats(
     //   prop:ivl<21          → interval < 21 days  (young)
     //   prop:ivl>=21         → interval ≥ 21 days  (mature)
 
-    let total_cards = count_cards(&mut *col, "")
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let total_cards = count_cards(&mut *col, "")?;
 
-    let total_notes = count_notes(&mut *col, "")
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let total_notes = count_notes(&mut *col, "")?;
 
-    let new_cards = count_cards(&mut *col, "is:new -is:suspended -is:buried")
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let new_cards = count_cards(&mut *col, "is:new -is:suspended -is:buried")?;
 
-    let young_cards =
-        count_cards(&mut *col, "is:review prop:ivl<21 -is:suspended -is:buried")
-            .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let young_cards = count_cards(&mut *col, "is:review prop:ivl<21 -is:suspended -is:buried")?;
 
-    let mature_cards =
-        count_cards(&mut *col, "is:review prop:ivl>=21 -is:suspended -is:buried")
-            .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let mature_cards = count_cards(&mut *col, "is:review prop:ivl>=21 -is:suspended -is:buried")?;
 
-    let suspended_cards = count_cards(&mut *col, "is:suspended")
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let suspended_cards = count_cards(&mut *col, "is:suspended")?;
 
-    let buried_cards = count_cards(&mut *col, "is:buried")
-        .map_err(|e| WebAppError::internal(&e.to_string()))?;
+    let buried_cards = count_cards(&mut *col, "is:buried")?;
 
     drop(col);
 
-    Ok(Json(serde_json::json!({
-        "total_cards": total_cards,
-        "new_cards": new_cards,
-        "young_cards": young_cards,
-        "mature_cards": mature_cards,
-        "suspended_cards": suspended_cards,
-        "buried_cards": buried_cards,
-        "total_notes": total_notes,
-    })))
+    Ok(Json(CollectionStatsResponse {
+        total_cards,
+        new_cards,
+        young_cards,
+        mature_cards,
+        suspended_cards,
+        buried_cards,
+        total_notes,
+    }))
 }
 
 /// Get today's study statistics
@@ -220,15 +207,13 @@ pub async fn get_today_stats(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Get today's stats via graphs endpoint
-    let result = col
-        .graphs(anki_proto::stats::GraphsRequest {
-            search: String::new(),
-            days: 1,
-        })
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let result = col.graphs(anki_proto::stats::GraphsRequest {
+        search: String::new(),
+        days: 1,
+    })?;
 
     drop(col);
 
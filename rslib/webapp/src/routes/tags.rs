@@ -1,23 +1,30 @@
+use anki::collection::Collection;
+use anki::notes::NoteId;
 use anki::services::TagsService;
 use axum::extract::Path;
+use axum::extract::Query;
 use axum::extract::State;
 use axum::response::IntoResponse;
 use axum::Extension;
 use axum::Json;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
+use unicase::UniCase;
 
 use crate::auth::AuthUser;
 use crate::error::Result;
 use crate::error::WebAppError;
+use crate::routes::notes::MessageResponse;
 use crate::routes::AuthRouteState;
+use crate::session::lock_collection;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct TagsListResponse {
     pub tags: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct TagTreeNode {
     pub name: String,
     pub children: Vec<TagTreeNode>,
@@ -25,37 +32,37 @@ pub struct TagTreeNode {
     pub collapsed: bool,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct TagTreeResponse {
     pub root: Option<TagTreeNode>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct RenameTagRequest {
     pub old_name: String,
     pub new_name: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct RenameTagResponse {
     pub success: bool,
     pub message: String,
     pub count: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DeleteTagRequest {
     pub tag: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct DeleteTagResponse {
     pub success: bool,
     pub message: String,
     pub count: usize,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct ClearUnusedTagsResponse {
     pub success: bool,
     pub message: String,
@@ -71,12 +78,10 @@ pub async fn get_tags(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Get all tags
-    let result = col
-        .all_tags()
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let result = col.all_tags()?;
 
     drop(col);
 
@@ -92,12 +97,10 @@ pub async fn get_tag_tree(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Get tag tree
-    let result = col
-        .tag_tree()
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let result = col.tag_tree()?;
 
     drop(col);
 
@@ -130,15 +133,13 @@ pub async fn rename_tag(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Rename tag
-    let result = col
-        .rename_tags(anki_proto::tags::RenameTagsRequest {
-            current_prefix: request.old_name.clone(),
-            new_prefix: request.new_name.clone(),
-        })
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let result = col.rename_tags(anki_proto::tags::RenameTagsRequest {
+        current_prefix: request.old_name.clone(),
+        new_prefix: request.new_name.clone(),
+    })?;
 
     let count = result.count as usize;
 
@@ -161,12 +162,10 @@ pub async fn delete_tag(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Remove tag
-    let result = col
-        .remove_tags(&tag_name)
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let result = col.remove_tags(&tag_name)?;
 
     let count = result.output;
 
@@ -188,12 +187,10 @@ pub async fn clear_unused_tags(
         .backend_manager
         .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
 
-    let mut col = backend.lock().unwrap();
+    let mut col = lock_collection(&backend);
 
     // Clear unused tags
-    let result = col
-        .clear_unused_tags()
-        .map_err(|e: anki::error::AnkiError| WebAppError::internal(&e.to_string()))?;
+    let result = col.clear_unused_tags()?;
 
     let removed_count = result.output;
 
@@ -205,3 +202,230 @@ pub async fn clear_unused_tags(
         removed_count,
     }))
 }
+
+/// Maximum number of suggestions returned by tag completion
+const MAX_TAG_COMPLETIONS: usize = 100;
+
+/// Notes to operate on, either listed explicitly or matched by a search.
+/// An empty search matches every note.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NoteSelection {
+    pub note_ids: Option<Vec<i64>>,
+    pub search: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BulkTagsRequest {
+    #[serde(flatten)]
+    pub notes: NoteSelection,
+    /// Space-separated tags
+    pub tags: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FindReplaceTagsRequest {
+    #[serde(flatten)]
+    pub notes: NoteSelection,
+    pub find: String,
+    /// May not contain spaces
+    pub replacement: String,
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub match_case: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReparentTagsRequest {
+    pub tags: Vec<String>,
+    /// Moves the tags to the top level if not provided
+    pub new_parent: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CompleteTagQuery {
+    pub input: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetTagCollapsedRequest {
+    pub tag: String,
+    pub collapsed: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TagOpResponse {
+    pub success: bool,
+    pub message: String,
+    pub count: usize,
+}
+
+/// Add tags to the selected notes
+pub async fn add_tags_to_notes(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<BulkTagsRequest>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+
+    let note_ids = resolve_note_selection(&mut col, request.notes)?;
+    let count = col.add_tags_to_notes(&note_ids, &request.tags)?.output;
+    drop(col);
+
+    Ok(Json(TagOpResponse {
+        success: true,
+        message: format!("Added tags to {} note(s)", count),
+        count,
+    }))
+}
+
+/// Remove tags from the selected notes
+pub async fn remove_tags_from_notes(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<BulkTagsRequest>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+
+    let note_ids = resolve_note_selection(&mut col, request.notes)?;
+    let count = col.remove_tags_from_notes(&note_ids, &request.tags)?.output;
+    drop(col);
+
+    Ok(Json(TagOpResponse {
+        success: true,
+        message: format!("Removed tags from {} note(s)", count),
+        count,
+    }))
+}
+
+/// Find and replace text within the tags of the selected notes
+pub async fn find_and_replace_tags(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<FindReplaceTagsRequest>,
+) -> Result<impl IntoResponse> {
+    if request.find.is_empty() {
+        return Err(WebAppError::bad_request("Search text cannot be empty"));
+    }
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+
+    let note_ids = resolve_note_selection(&mut col, request.notes)?;
+    let count = col
+        .find_and_replace_tag(
+            &note_ids,
+            &request.find,
+            &request.replacement,
+            request.regex,
+            request.match_case,
+        )?
+        .output;
+    drop(col);
+
+    Ok(Json(TagOpResponse {
+        success: true,
+        message: format!("Updated tags on {} note(s)", count),
+        count,
+    }))
+}
+
+/// Move tags and their children under a new parent tag
+pub async fn reparent_tags(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<ReparentTagsRequest>,
+) -> Result<impl IntoResponse> {
+    if request.tags.is_empty() {
+        return Err(WebAppError::bad_request("No tags provided"));
+    }
+    let new_parent = request
+        .new_parent
+        .filter(|parent| !parent.trim().is_empty());
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+
+    let count = col.reparent_tags(&request.tags, new_parent)?.output;
+    drop(col);
+
+    Ok(Json(TagOpResponse {
+        success: true,
+        message: format!("Reparented tags on {} note(s)", count),
+        count,
+    }))
+}
+
+/// Suggest existing tags matching the typed input, for editor autocomplete
+pub async fn complete_tags(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(query): Query<CompleteTagQuery>,
+) -> Result<impl IntoResponse> {
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_TAG_COMPLETIONS);
+
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let col = lock_collection(&backend);
+
+    let tags = col.complete_tag(&query.input, limit)?;
+    drop(col);
+
+    Ok(Json(TagsListResponse { tags }))
+}
+
+/// Persist whether a tag is collapsed in the tag tree
+pub async fn set_tag_collapsed(
+    State(state): State<AuthRouteState>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<SetTagCollapsedRequest>,
+) -> Result<impl IntoResponse> {
+    let backend = state
+        .backend_manager
+        .get_or_create_backend(auth_user.user_id, &auth_user.username)?;
+    let mut col = lock_collection(&backend);
+
+    // The core would register a missing tag, which we don't want here
+    let exists = col
+        .all_tags()?
+        .vals
+        .iter()
+        .any(|tag| UniCase::new(tag.as_str()) == UniCase::new(request.tag.as_str()));
+    if !exists {
+        return Err(WebAppError::not_found("Tag not found"));
+    }
+
+    col.set_tag_collapsed(&request.tag, request.collapsed)?;
+    drop(col);
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: if request.collapsed {
+            "Tag collapsed".to_string()
+        } else {
+            "Tag expanded".to_string()
+        },
+    }))
+}
+
+fn resolve_note_selection(col: &mut Collection, selection: NoteSelection) -> Result<Vec<NoteId>> {
+    match (selection.note_ids, selection.search) {
+        (Some(ids), None) => Ok(ids.into_iter().map(NoteId).collect()),
+        (None, Some(search)) => Ok(col.search_notes_unordered(search.as_str())?),
+        _ => Err(WebAppError::bad_request(
+            "Provide either note_ids or search",
+        )),
+    }
+}
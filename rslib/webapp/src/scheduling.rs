@@ -0,0 +1,522 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! JSON form of the scheduler's card states, so clients can hold on to the
+//! states of a card between fetching and answering it.
+
+use anki::card::CardQueue;
+use anki::card::FsrsMemoryState;
+use anki::collection::Collection;
+use anki::decks::DeckId;
+use anki::prelude::CardId;
+use anki::scheduler::answering::CardAnswer;
+use anki::scheduler::answering::Rating;
+use anki::scheduler::states::CardState;
+use anki::scheduler::states::FilteredState;
+use anki::scheduler::states::LearnState;
+use anki::scheduler::states::NewState;
+use anki::scheduler::states::NormalState;
+use anki::scheduler::states::PreviewState;
+use anki::scheduler::states::RelearnState;
+use anki::scheduler::states::ReschedulingFilterState;
+use anki::scheduler::states::ReviewState;
+use anki::scheduler::states::SchedulingStates;
+use anki::services::CardsService;
+use anki::timestamp::TimestampMillis;
+use anki::timestamp::TimestampSecs;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::Result;
+use crate::error::WebAppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct MemoryStateJson {
+    pub stability: f32,
+    pub difficulty: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct LearnStateJson {
+    pub remaining_steps: u32,
+    pub scheduled_secs: u32,
+    pub elapsed_secs: u32,
+    #[serde(default)]
+    pub memory_state: Option<MemoryStateJson>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ReviewStateJson {
+    pub scheduled_days: u32,
+    pub elapsed_days: u32,
+    pub ease_factor: f32,
+    pub lapses: u32,
+    pub leeched: bool,
+    #[serde(default)]
+    pub memory_state: Option<MemoryStateJson>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CardStateJson {
+    New {
+        position: u32,
+    },
+    Learning(LearnStateJson),
+    Review(ReviewStateJson),
+    Relearning {
+        learning: LearnStateJson,
+        review: ReviewStateJson,
+    },
+    Preview {
+        scheduled_secs: u32,
+        finished: bool,
+    },
+    /// The original state must be one of the normal kinds
+    Rescheduling {
+        original_state: Box<CardStateJson>,
+    },
+}
+
+/// A card's current state and the state each answer button leads to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SchedulingStatesJson {
+    pub current: CardStateJson,
+    pub again: CardStateJson,
+    pub hard: CardStateJson,
+    pub good: CardStateJson,
+    pub easy: CardStateJson,
+}
+
+impl From<FsrsMemoryState> for MemoryStateJson {
+    fn from(state: FsrsMemoryState) -> Self {
+        MemoryStateJson {
+            stability: state.stability,
+            difficulty: state.difficulty,
+        }
+    }
+}
+
+impl From<LearnState> for LearnStateJson {
+    fn from(state: LearnState) -> Self {
+        LearnStateJson {
+            remaining_steps: state.remaining_steps,
+            scheduled_secs: state.scheduled_secs,
+            elapsed_secs: state.elapsed_secs,
+            memory_state: state.memory_state.map(Into::into),
+        }
+    }
+}
+
+impl From<ReviewState> for ReviewStateJson {
+    fn from(state: ReviewState) -> Self {
+        ReviewStateJson {
+            scheduled_days: state.scheduled_days,
+            elapsed_days: state.elapsed_days,
+            ease_factor: state.ease_factor,
+            lapses: state.lapses,
+            leeched: state.leeched,
+            memory_state: state.memory_state.map(Into::into),
+        }
+    }
+}
+
+impl From<NormalState> for CardStateJson {
+    fn from(state: NormalState) -> Self {
+        match state {
+            NormalState::New(state) => CardStateJson::New {
+                position: state.position,
+            },
+            NormalState::Learning(state) => CardStateJson::Learning(state.into()),
+            NormalState::Review(state) => CardStateJson::Review(state.into()),
+            NormalState::Relearning(state) => CardStateJson::Relearning {
+                learning: state.learning.into(),
+                review: state.review.into(),
+            },
+        }
+    }
+}
+
+impl From<CardState> for CardStateJson {
+    fn from(state: CardState) -> Self {
+        match state {
+            CardState::Normal(state) => state.into(),
+            CardState::Filtered(FilteredState::Preview(state)) => CardStateJson::Preview {
+                scheduled_secs: state.scheduled_secs,
+                finished: state.finished,
+            },
+            CardState::Filtered(FilteredState::Rescheduling(state)) => {
+                CardStateJson::Rescheduling {
+                    original_state: Box::new(state.original_state.into()),
+                }
+            }
+        }
+    }
+}
+
+impl From<&SchedulingStates> for SchedulingStatesJson {
+    fn from(states: &SchedulingStates) -> Self {
+        SchedulingStatesJson {
+            current: states.current.into(),
+            again: states.again.into(),
+            hard: states.hard.into(),
+            good: states.good.into(),
+            easy: states.easy.into(),
+        }
+    }
+}
+
+impl TryFrom<MemoryStateJson> for FsrsMemoryState {
+    type Error = WebAppError;
+
+    fn try_from(state: MemoryStateJson) -> Result<Self> {
+        if !state.stability.is_finite() || !state.difficulty.is_finite() {
+            return Err(WebAppError::bad_request("Invalid memory state"));
+        }
+        Ok(FsrsMemoryState {
+            stability: state.stability,
+            difficulty: state.difficulty,
+        })
+    }
+}
+
+impl TryFrom<LearnStateJson> for LearnState {
+    type Error = WebAppError;
+
+    fn try_from(state: LearnStateJson) -> Result<Self> {
+        Ok(LearnState {
+            remaining_steps: state.remaining_steps,
+            scheduled_secs: state.scheduled_secs,
+            elapsed_secs: state.elapsed_secs,
+            memory_state: state.memory_state.map(TryInto::try_into).transpose()?,
+        })
+    }
+}
+
+impl TryFrom<ReviewStateJson> for ReviewState {
+    type Error = WebAppError;
+
+    fn try_from(state: ReviewStateJson) -> Result<Self> {
+        if !state.ease_factor.is_finite() {
+            return Err(WebAppError::bad_request("Invalid ease factor"));
+        }
+        Ok(ReviewState {
+            scheduled_days: state.scheduled_days,
+            elapsed_days: state.elapsed_days,
+            ease_factor: state.ease_factor,
+            lapses: state.lapses,
+            leeched: state.leeched,
+            memory_state: state.memory_state.map(TryInto::try_into).transpose()?,
+        })
+    }
+}
+
+impl TryFrom<CardStateJson> for CardState {
+    type Error = WebAppError;
+
+    fn try_from(state: CardStateJson) -> Result<Self> {
+        Ok(match state {
+            CardStateJson::New { position } => NewState { position }.into(),
+            CardStateJson::Learning(state) => LearnState::try_from(state)?.into(),
+            CardStateJson::Review(state) => ReviewState::try_from(state)?.into(),
+            CardStateJson::Relearning { learning, review } => RelearnState {
+                learning: learning.try_into()?,
+                review: review.try_into()?,
+            }
+            .into(),
+            CardStateJson::Preview {
+                scheduled_secs,
+                finished,
+            } => PreviewState {
+                scheduled_secs,
+                finished,
+            }
+            .into(),
+            CardStateJson::Rescheduling { original_state } => {
+                match CardState::try_from(*original_state)? {
+                    CardState::Normal(original_state) => {
+                        ReschedulingFilterState { original_state }.into()
+                    }
+                    CardState::Filtered(_) => {
+                        return Err(WebAppError::bad_request(
+                            "A rescheduling state must wrap a normal state",
+                        ))
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl TryFrom<SchedulingStatesJson> for SchedulingStates {
+    type Error = WebAppError;
+
+    fn try_from(states: SchedulingStatesJson) -> Result<Self> {
+        Ok(SchedulingStates {
+            current: states.current.try_into()?,
+            again: states.again.try_into()?,
+            hard: states.hard.try_into()?,
+            good: states.good.try_into()?,
+            easy: states.easy.try_into()?,
+        })
+    }
+}
+
+/// Parse an answer button number (0=Again, 1=Hard, 2=Good, 3=Easy)
+pub fn parse_rating(rating: u8) -> Result<Rating> {
+    match rating {
+        0 => Ok(Rating::Again),
+        1 => Ok(Rating::Hard),
+        2 => Ok(Rating::Good),
+        3 => Ok(Rating::Easy),
+        _ => Err(WebAppError::bad_request(
+            "Invalid rating value. Must be 0-3.",
+        )),
+    }
+}
+
+/// An answer to a card, made with states the client fetched earlier
+pub struct ClientAnswer {
+    pub card_id: CardId,
+    pub rating: Rating,
+    pub states: SchedulingStates,
+    /// Must be a JSON object if provided
+    pub custom_data: Option<Value>,
+    pub answered_at: TimestampMillis,
+    pub milliseconds_taken: u32,
+}
+
+/// What happened to a card when it was answered, which the desktop reviewer
+/// reports to the user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AnswerOutcome {
+    /// The answer time as recorded, capped to the preset's maximum answer
+    /// seconds
+    pub milliseconds_taken: u32,
+    /// The card became a leech, and its note was tagged as one
+    pub leech: bool,
+    /// The preset's leech action suspended the card
+    pub suspended: bool,
+}
+
+/// Answer a card, reporting the time recorded and whether it became a leech
+pub fn record_answer(
+    col: &mut Collection,
+    answer: &mut CardAnswer,
+) -> anki::error::Result<AnswerOutcome> {
+    col.answer_card(answer)?;
+
+    let leech = normal_state(&answer.new_state).is_some_and(|state| match state {
+        NormalState::Review(review) => review.leeched,
+        NormalState::Relearning(relearning) => relearning.review.leeched,
+        _ => false,
+    });
+    let suspended = leech
+        && <Collection as CardsService>::get_card(
+            col,
+            anki_proto::cards::CardId {
+                cid: answer.card_id.0,
+            },
+        )?
+        .queue
+            == CardQueue::Suspended as i32;
+
+    Ok(AnswerOutcome {
+        milliseconds_taken: answer.milliseconds_taken,
+        leech,
+        suspended,
+    })
+}
+
+/// Answer a card with client-held states, which need not be at the head of
+/// the queue. The card must belong to `deck_id` or one of its children, and
+/// its current state must still match the one the client was given, or a
+/// conflict is returned.
+pub fn answer_with_states(
+    col: &mut Collection,
+    deck_id: DeckId,
+    answer: ClientAnswer,
+) -> Result<AnswerOutcome> {
+    ensure_card_in_deck(col, deck_id, answer.card_id)?;
+
+    let current = col.get_scheduling_states(answer.card_id)?.current;
+    if !same_state(&current, &answer.states.current) {
+        return Err(WebAppError::conflict(
+            "Card was modified since its states were fetched",
+        ));
+    }
+
+    let custom_data = match answer.custom_data {
+        None => None,
+        Some(Value::Object(data)) => Some(Value::Object(data).to_string()),
+        Some(_) => {
+            return Err(WebAppError::bad_request(
+                "custom_data must be a JSON object",
+            ))
+        }
+    };
+
+    let states = answer.states;
+    let new_state = match answer.rating {
+        Rating::Again => states.again,
+        Rating::Hard => states.hard,
+        Rating::Good => states.good,
+        Rating::Easy => states.easy,
+    };
+
+    let mut answer = CardAnswer {
+        card_id: answer.card_id,
+        current_state: states.current,
+        new_state,
+        rating: answer.rating,
+        answered_at: answer.answered_at,
+        milliseconds_taken: answer.milliseconds_taken,
+        custom_data,
+        from_queue: false,
+    };
+    Ok(record_answer(col, &mut answer)?)
+}
+
+/// Fail with not found unless the card exists in the deck or its children
+pub fn ensure_card_in_deck(col: &mut Collection, deck_id: DeckId, card_id: CardId) -> Result<()> {
+    let card = col
+        .storage
+        .get_card(card_id)?
+        .ok_or_else(|| WebAppError::not_found("Card not found"))?;
+    let decks = col.get_deck_and_child_names(deck_id)?;
+    if !decks.iter().any(|(id, _)| *id == card.deck_id()) {
+        return Err(WebAppError::not_found("Card not found in deck"));
+    }
+    Ok(())
+}
+
+/// Compare states the way the collection does when answering. Time spent in
+/// a learning step since the states were fetched is not a modification.
+fn same_state(current: &CardState, client: &CardState) -> bool {
+    let mut client = *client;
+    let client_normal = match &mut client {
+        CardState::Normal(state) => Some(state),
+        CardState::Filtered(FilteredState::Rescheduling(state)) => Some(&mut state.original_state),
+        CardState::Filtered(FilteredState::Preview(_)) => None,
+    };
+    match (normal_state(current), client_normal) {
+        (Some(NormalState::Learning(current)), Some(NormalState::Learning(answer))) => {
+            answer.elapsed_secs = current.elapsed_secs;
+        }
+        (Some(NormalState::Relearning(current)), Some(NormalState::Relearning(answer))) => {
+            answer.learning.elapsed_secs = current.learning.elapsed_secs;
+        }
+        _ => {}
+    }
+    *current == client
+}
+
+/// The normal state of a card, or the one a rescheduling filtered deck will
+/// return it to
+fn normal_state(state: &CardState) -> Option<&NormalState> {
+    match state {
+        CardState::Normal(state) => Some(state),
+        CardState::Filtered(FilteredState::Rescheduling(state)) => Some(&state.original_state),
+        CardState::Filtered(FilteredState::Preview(_)) => None,
+    }
+}
+
+/// When a card in an intraday learning step becomes due, as a Unix timestamp
+pub fn learning_due_at(state: &CardState, now: TimestampSecs) -> Option<i64> {
+    let learning = match normal_state(state)? {
+        NormalState::Learning(learning) => learning,
+        NormalState::Relearning(relearning) => &relearning.learning,
+        _ => return None,
+    };
+    // Steps of a day or more are scheduled by day, not intraday
+    if learning.scheduled_secs >= 86_400 {
+        return None;
+    }
+    Some(now.0 - learning.elapsed_secs as i64 + learning.scheduled_secs as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn learning(elapsed_secs: u32) -> CardState {
+        LearnState {
+            remaining_steps: 2,
+            scheduled_secs: 600,
+            elapsed_secs,
+            memory_state: Some(FsrsMemoryState {
+                stability: 1.234_567,
+                difficulty: 5.5,
+            }),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_card_state_round_trip() {
+        let states = [
+            NewState { position: 3 }.into(),
+            learning(30),
+            RelearnState {
+                learning: LearnState {
+                    remaining_steps: 1,
+                    scheduled_secs: 60,
+                    elapsed_secs: 0,
+                    memory_state: None,
+                },
+                review: ReviewState {
+                    scheduled_days: 1,
+                    elapsed_days: 12,
+                    ease_factor: 2.35,
+                    lapses: 1,
+                    leeched: false,
+                    memory_state: None,
+                },
+            }
+            .into(),
+            CardState::Filtered(FilteredState::Rescheduling(ReschedulingFilterState {
+                original_state: NewState { position: 1 }.into(),
+            })),
+        ];
+        for state in states {
+            let json = serde_json::to_value(CardStateJson::from(state)).unwrap();
+            let parsed: CardStateJson = serde_json::from_value(json).unwrap();
+            assert_eq!(CardState::try_from(parsed).unwrap(), state);
+        }
+
+        let json = serde_json::to_value(CardStateJson::from(learning(30))).unwrap();
+        assert_eq!(json["kind"], "learning");
+        assert_eq!(json["remaining_steps"], 2);
+    }
+
+    #[test]
+    fn test_nested_filtered_state_rejected() {
+        let state: CardStateJson = serde_json::from_value(json!({
+            "kind": "rescheduling",
+            "original_state": { "kind": "preview", "scheduled_secs": 60, "finished": false }
+        }))
+        .unwrap();
+        assert!(CardState::try_from(state).is_err());
+    }
+
+    #[test]
+    fn test_learning_due_at() {
+        let now = TimestampSecs(1_000_000);
+        assert_eq!(learning_due_at(&learning(30), now), Some(1_000_570));
+        assert_eq!(learning_due_at(&NewState { position: 1 }.into(), now), None);
+    }
+
+    #[test]
+    fn test_same_state_ignores_learning_elapsed_time() {
+        assert!(same_state(&learning(90), &learning(30)));
+        assert!(!same_state(&learning(90), &NewState { position: 1 }.into()));
+        assert!(!same_state(
+            &NewState { position: 1 }.into(),
+            &NewState { position: 2 }.into()
+        ));
+    }
+}
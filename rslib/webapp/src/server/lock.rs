@@ -0,0 +1,61 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! An exclusive lock on the data directory. The server holds it while it
+//! runs, and admin commands that open collections directly take it too, so
+//! the two never change a collection at the same time. The lock belongs to
+//! the process, so the OS releases it if the holder dies.
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::fs::TryLockError;
+use std::path::Path;
+
+use anyhow::Context;
+use anyhow::Result;
+
+const LOCK_FILE: &str = "server.lock";
+
+pub struct DataDirLock {
+    _file: File,
+}
+
+impl DataDirLock {
+    /// Take the lock on `data_dir`, or return None if another process holds
+    /// it
+    pub fn try_acquire(data_dir: &Path) -> Result<Option<Self>> {
+        let path = data_dir.join(LOCK_FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => {
+                Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive_until_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let lock = DataDirLock::try_acquire(temp_dir.path()).unwrap();
+        assert!(lock.is_some());
+        assert!(DataDirLock::try_acquire(temp_dir.path()).unwrap().is_none());
+
+        drop(lock);
+        assert!(DataDirLock::try_acquire(temp_dir.path()).unwrap().is_some());
+    }
+}
@@ -1,18 +1,29 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
+pub mod lock;
 pub mod router;
+pub mod shutdown;
+pub mod tls;
 
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
 use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
 
+use crate::audit::spawn_audit_pruning;
 use crate::auth::AuthState;
 use crate::auth::JwtManager;
 use crate::config::WebAppConfig;
 use crate::db::Database;
 use crate::error::Result;
+use crate::idempotency::spawn_idempotency_pruning;
+use crate::jobs::JobManager;
+use crate::server::lock::DataDirLock;
+use crate::session::backup::spawn_periodic_backups;
 use crate::session::BackendManager;
 
 pub struct WebAppServer {
@@ -21,8 +32,10 @@ pub struct WebAppServer {
 
 impl WebAppServer {
     pub fn new() -> anyhow::Result<Self> {
-        let config = WebAppConfig::from_env()?;
+        Ok(Self::with_config(WebAppConfig::from_env()?))
+    }
 
+    pub fn with_config(config: WebAppConfig) -> Self {
         // Validate configuration
         if config.jwt_secret == "change-this-secret-in-production" {
             tracing::warn!(
@@ -30,10 +43,21 @@ impl WebAppServer {
             );
         }
 
-        Ok(Self { config })
+        Self { config }
     }
 
+    /// Run until the process receives SIGTERM or Ctrl-C, then shut down
+    /// gracefully
     pub async fn run(self) -> Result<()> {
+        let shutdown = CancellationToken::new();
+        shutdown::spawn_shutdown_on_signal(shutdown.clone());
+        self.run_until(shutdown).await
+    }
+
+    /// Run until `shutdown` is cancelled. New connections are then refused,
+    /// and in-flight requests and background jobs get up to the configured
+    /// shutdown timeout to finish before every collection is closed.
+    pub async fn run_until(self, shutdown: CancellationToken) -> Result<()> {
         let addr = SocketAddr::new(self.config.host, self.config.port);
 
         tracing::info!("Server configuration:");
@@ -45,12 +69,45 @@ impl WebAppServer {
         std::fs::create_dir_all(&self.config.data_dir)
             .map_err(|e| anyhow::anyhow!("Failed to create data directory: {}", e))?;
 
+        // Held until the server stops, so admin commands know not to open
+        // collections behind its back
+        let _lock = DataDirLock::try_acquire(&self.config.data_dir)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Another process is using the data directory {}",
+                self.config.data_dir.display()
+            )
+        })?;
+
         // Initialize database
         let db_path = self.config.data_dir.join("webapp.db");
         let database = Arc::new(Database::open(&db_path)?);
         database.initialize()?;
         tracing::info!("📦 Database initialized at {}", db_path.display());
 
+        // Requests left unfinished by a previous run will never complete, so
+        // retries of them may run again. No other server can be running, as
+        // we hold the lock.
+        let released = database.idempotency().release_unfinished()?;
+        if released > 0 {
+            tracing::warn!(
+                "Released {} idempotency keys of requests interrupted by a restart",
+                released
+            );
+        }
+
+        // Prune old audit entries
+        if self.config.audit_retention_days > 0 {
+            spawn_audit_pruning(database.clone(), self.config.audit_retention_days);
+        }
+
+        // Forget idempotency keys once they can no longer be replayed
+        if self.config.idempotency_window_hours > 0 {
+            spawn_idempotency_pruning(
+                database.clone(),
+                (self.config.idempotency_window_hours * 60 * 60) as i64,
+            );
+        }
+
         // Initialize JWT manager
         let jwt_manager = Arc::new(JwtManager::new(&self.config.jwt_secret));
 
@@ -58,11 +115,27 @@ impl WebAppServer {
         let backend_manager = Arc::new(BackendManager::new(self.config.data_dir.clone()));
         tracing::info!("📋 Backend manager initialized");
 
+        // Start automatic backups
+        if self.config.backup_interval_mins > 0 {
+            spawn_periodic_backups(
+                backend_manager.clone(),
+                Duration::from_secs(self.config.backup_interval_mins * 60),
+            );
+            tracing::info!(
+                "💾 Automatic backups checked every {} minute(s)",
+                self.config.backup_interval_mins
+            );
+        }
+
+        // Initialize background job manager
+        let job_manager = Arc::new(JobManager::new());
+
         // Create auth state
         let auth_state = AuthState {
             database,
             jwt_manager,
-            backend_manager,
+            backend_manager: backend_manager.clone(),
+            job_manager: job_manager.clone(),
         };
 
         // Build router
@@ -73,13 +146,73 @@ impl WebAppServer {
             .await
             .map_err(|e| anyhow::anyhow!("Failed to bind to {}: {}", addr, e))?;
 
-        tracing::info!("🚀 Anki Web App listening on http://{}", addr);
+        // Run server
+        let mut server = if let Some((cert_path, key_path)) = self.config.tls_paths() {
+            tracing::info!("🚀 Anki Web App listening on https://{}", addr);
+            tokio::spawn(tls::serve_tls(
+                listener,
+                app,
+                cert_path.clone(),
+                key_path.clone(),
+                shutdown.clone(),
+            ))
+        } else {
+            tracing::info!("🚀 Anki Web App listening on http://{}", addr);
+            let serve = axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+            tokio::spawn(async move {
+                serve
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Server error: {}", e))
+            })
+        };
         tracing::info!("📚 Ready to serve!");
 
-        // Run server
-        axum::serve(listener, app)
+        let stopped = tokio::select! {
+            result = &mut server => {
+                // The server only stops by itself if it failed
+                result.map_err(|e| anyhow::anyhow!("Server task failed: {}", e))??;
+                true
+            }
+            _ = shutdown.cancelled() => false,
+        };
+
+        let timeout = Duration::from_secs(self.config.shutdown_timeout_secs);
+        let deadline = Instant::now() + timeout;
+        if !stopped {
+            // Drain requests, then let jobs they started finish
+            tracing::info!(
+                "Waiting up to {}s for in-flight requests and background jobs",
+                timeout.as_secs()
+            );
+            let drained = tokio::time::timeout(timeout, async {
+                match (&mut server).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => tracing::error!("Error while draining requests: {}", e),
+                    Err(e) => tracing::error!("Server task failed: {}", e),
+                }
+                job_manager.wait_idle().await;
+            })
+            .await;
+            if drained.is_err() {
+                server.abort();
+                tracing::warn!(
+                    "Shutdown timeout reached with {} job(s) running, closing collections anyway",
+                    job_manager.running_count()
+                );
+            }
+        }
+
+        // Close collections on the blocking pool, as it may wait for the
+        // last requests to release them within what is left of the timeout
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        tokio::task::spawn_blocking(move || backend_manager.close_all(remaining))
             .await
-            .map_err(|e| anyhow::anyhow!("Server error: {}", e))?;
+            .map_err(|e| anyhow::anyhow!("Closing collections failed: {}", e))??;
+        tracing::info!("👋 Shut down cleanly");
 
         Ok(())
     }
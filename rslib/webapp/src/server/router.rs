@@ -1,6 +1,9 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
+use axum::extract::DefaultBodyLimit;
+use axum::extract::State;
+use axum::http::header;
 use axum::http::HeaderValue;
 use axum::http::Method;
 use axum::http::StatusCode;
@@ -14,72 +17,135 @@ use axum::routing::post;
 use axum::routing::put;
 use axum::Router;
 use serde_json::json;
+use tower_http::cors::AllowOrigin;
 use tower_http::cors::CorsLayer;
 
+use crate::audit::record_audit;
 use crate::auth::require_auth;
 use crate::auth::AuthState;
+use crate::error::localize_errors;
+use crate::idempotency::idempotency;
+use crate::idempotency::IdempotencyState;
+use crate::idempotency::IDEMPOTENCY_KEY;
+use crate::metrics::metrics;
+use crate::metrics::track_requests;
 use crate::openapi;
 use crate::routes::add_media;
+use crate::routes::add_tags_to_notes;
 use crate::routes::answer_card;
+use crate::routes::answer_card_with_states;
+use crate::routes::answer_cards_batch;
 use crate::routes::batch_get_cards;
 use crate::routes::batch_update_cards;
+use crate::routes::browse_cards;
+use crate::routes::browse_notes;
 use crate::routes::bury_card;
+use crate::routes::check_database;
 use crate::routes::check_media;
 use crate::routes::check_note_fields;
 use crate::routes::clear_unused_tags;
 use crate::routes::close_collection;
+use crate::routes::complete_tags;
+use crate::routes::compute_optimal_retention;
+use crate::routes::create_backup;
 use crate::routes::create_collection;
 use crate::routes::create_deck;
 use crate::routes::create_note;
+use crate::routes::create_notes_bulk;
+use crate::routes::create_occlusion_note;
 use crate::routes::delete_card;
 use crate::routes::delete_collection;
 use crate::routes::delete_deck;
+use crate::routes::delete_empty_cards;
 use crate::routes::delete_media;
 use crate::routes::delete_note;
 use crate::routes::delete_tag;
+use crate::routes::empty_media_trash;
+use crate::routes::evaluate_fsrs_params;
 use crate::routes::find_and_replace;
+use crate::routes::find_and_replace_tags;
+use crate::routes::find_duplicates;
 use crate::routes::flag_card;
+use crate::routes::get_audit_log;
 use crate::routes::get_card;
 use crate::routes::get_card_stats;
 use crate::routes::get_collection_info;
 use crate::routes::get_collection_stats;
+use crate::routes::get_config;
+use crate::routes::get_congrats_info;
 use crate::routes::get_deck;
 use crate::routes::get_deck_counts;
+use crate::routes::get_deck_stats;
 use crate::routes::get_deck_tree;
+use crate::routes::get_empty_cards;
 use crate::routes::get_graphs;
+use crate::routes::get_job;
 use crate::routes::get_media;
 use crate::routes::get_next_card;
 use crate::routes::get_next_states;
 use crate::routes::get_note;
 use crate::routes::get_note_cards;
 use crate::routes::get_notetype;
+use crate::routes::get_occlusion_note;
+use crate::routes::get_preferences;
 use crate::routes::get_tag_tree;
 use crate::routes::get_tags;
 use crate::routes::get_today_stats;
+use crate::routes::get_undo_status;
 use crate::routes::import_apkg;
+use crate::routes::list_backups;
 use crate::routes::list_collections;
+use crate::routes::list_config;
+use crate::routes::list_fsrs_presets;
+use crate::routes::list_jobs;
+use crate::routes::list_media;
 use crate::routes::list_notetypes;
+use crate::routes::list_shared_decks;
+use crate::routes::list_subscriptions;
 use crate::routes::login;
 use crate::routes::logout;
 use crate::routes::me;
+use crate::routes::optimize_fsrs_params;
+use crate::routes::prefetch_cards;
+use crate::routes::publish_deck;
 use crate::routes::redo;
 use crate::routes::register;
+use crate::routes::remove_tags_from_notes;
 use crate::routes::rename_tag;
-use crate::routes::browse_cards;
-use crate::routes::browse_notes;
+use crate::routes::render_missing_latex;
+use crate::routes::reparent_decks;
+use crate::routes::reparent_tags;
+use crate::routes::restore_backup;
+use crate::routes::restore_media_trash;
+use crate::routes::save_fsrs_params;
 use crate::routes::search_cards;
 use crate::routes::search_notes;
+use crate::routes::set_config;
+use crate::routes::set_deck_collapsed;
+use crate::routes::set_tag_collapsed;
+use crate::routes::simulate_fsrs_workload;
+use crate::routes::subscribe_deck;
 use crate::routes::suspend_card;
 use crate::routes::undo;
+use crate::routes::unpublish_deck;
+use crate::routes::unsubscribe_deck;
 use crate::routes::unsuspend_card;
 use crate::routes::update_card;
 use crate::routes::update_deck;
 use crate::routes::update_note;
+use crate::routes::update_occlusion_note;
+use crate::routes::update_preferences;
+use crate::routes::update_subscription;
+use crate::routes::upload_occlusion_image;
 use crate::routes::AuthRouteState;
 use crate::swagger_ui;
 use crate::WebAppConfig;
 
 pub fn create_router(config: &WebAppConfig, auth_state: AuthState) -> Router {
+    // Uploads get their own body limit; everything else uses the JSON limit
+    // applied to the whole router below
+    let upload_limit = DefaultBodyLimit::max(config.max_upload_body_bytes);
+
     // Routes that require authentication
     let protected_routes = Router::new()
         .route("/api/v1/auth/logout", post(logout))
@@ -91,13 +157,30 @@ pub fn create_router(config: &WebAppConfig, auth_state: AuthState) -> Router {
         .route("/api/v1/collections", get(list_collections))
         .route("/api/v1/collections", post(create_collection))
         .route("/api/v1/collections/{path}", delete(delete_collection))
+        .route("/api/v1/collection/check-database", post(check_database))
+        .route("/api/v1/collection/empty-cards", post(get_empty_cards))
+        .route("/api/v1/collection/empty-cards", delete(delete_empty_cards))
+        .route("/api/v1/collection/backups", get(list_backups))
+        .route("/api/v1/collection/backups", post(create_backup))
+        .route(
+            "/api/v1/collection/backups/{filename}/restore",
+            post(restore_backup),
+        )
+        .route("/api/v1/audit", get(get_audit_log))
+        .route("/api/v1/jobs", get(list_jobs))
+        .route("/api/v1/jobs/{id}", get(get_job))
         .route("/api/v1/decks", get(get_deck_tree))
         .route("/api/v1/decks", post(create_deck))
         .route("/api/v1/decks/{id}", get(get_deck))
         .route("/api/v1/decks/{id}", put(update_deck))
         .route("/api/v1/decks/{id}", delete(delete_deck))
+        .route("/api/v1/decks/reparent", post(reparent_decks))
+        .route("/api/v1/decks/{id}/collapse", put(set_deck_collapsed))
+        .route("/api/v1/decks/{id}/stats", get(get_deck_stats))
         .route("/api/v1/notes", post(create_note))
+        .route("/api/v1/notes/bulk", post(create_notes_bulk))
         .route("/api/v1/notes/check-fields", post(check_note_fields))
+        .route("/api/v1/notes/find-duplicates", post(find_duplicates))
         .route("/api/v1/notes/{id}", get(get_note))
         .route("/api/v1/notes/{id}", put(update_note))
         .route("/api/v1/notes/{id}", delete(delete_note))
@@ -119,13 +202,36 @@ pub fn create_router(config: &WebAppConfig, auth_state: AuthState) -> Router {
         .route("/api/v1/browse/notes", post(browse_notes))
         .route("/api/v1/search/find-replace", post(find_and_replace))
         .route("/api/v1/media/check", get(check_media))
+        .route("/api/v1/media/files", get(list_media))
+        .route("/api/v1/media/trash/empty", post(empty_media_trash))
+        .route("/api/v1/media/trash/restore", post(restore_media_trash))
+        .route("/api/v1/media/latex/render", post(render_missing_latex))
         .route("/api/v1/media/{filename}", get(get_media))
-        .route("/api/v1/media", post(add_media))
+        .route("/api/v1/media", post(add_media).layer(upload_limit))
         .route("/api/v1/media", delete(delete_media))
-        .route("/api/v1/import/apkg", post(import_apkg))
+        .route(
+            "/api/v1/image-occlusion/images",
+            post(upload_occlusion_image).layer(upload_limit),
+        )
+        .route("/api/v1/image-occlusion/notes", post(create_occlusion_note))
+        .route(
+            "/api/v1/image-occlusion/notes/{id}",
+            get(get_occlusion_note),
+        )
+        .route(
+            "/api/v1/image-occlusion/notes/{id}",
+            put(update_occlusion_note),
+        )
+        .route("/api/v1/import/apkg", post(import_apkg).layer(upload_limit))
         .route("/api/v1/tags", get(get_tags))
         .route("/api/v1/tags/tree", get(get_tag_tree))
         .route("/api/v1/tags/rename", put(rename_tag))
+        .route("/api/v1/tags/complete", get(complete_tags))
+        .route("/api/v1/tags/find-replace", post(find_and_replace_tags))
+        .route("/api/v1/tags/reparent", post(reparent_tags))
+        .route("/api/v1/tags/collapse", put(set_tag_collapsed))
+        .route("/api/v1/tags/notes/add", post(add_tags_to_notes))
+        .route("/api/v1/tags/notes/remove", post(remove_tags_from_notes))
         .route("/api/v1/tags/{name}", delete(delete_tag))
         .route("/api/v1/tags/clear-unused", post(clear_unused_tags))
         .route("/api/v1/stats/card/{id}", get(get_card_stats))
@@ -133,10 +239,22 @@ pub fn create_router(config: &WebAppConfig, auth_state: AuthState) -> Router {
         .route("/api/v1/stats/graphs", get(get_graphs))
         .route("/api/v1/stats/today", get(get_today_stats))
         .route("/api/v1/scheduler/decks/{deck_id}/next", get(get_next_card))
+        .route(
+            "/api/v1/scheduler/decks/{deck_id}/queue",
+            get(prefetch_cards),
+        )
+        .route(
+            "/api/v1/scheduler/decks/{deck_id}/answers",
+            post(answer_cards_batch),
+        )
         .route(
             "/api/v1/scheduler/decks/{deck_id}/cards/{card_id}/answer",
             post(answer_card),
         )
+        .route(
+            "/api/v1/scheduler/decks/{deck_id}/cards/{card_id}/answer-with-states",
+            post(answer_card_with_states),
+        )
         .route(
             "/api/v1/scheduler/decks/{deck_id}/cards/{card_id}/next-states",
             get(get_next_states),
@@ -145,8 +263,65 @@ pub fn create_router(config: &WebAppConfig, auth_state: AuthState) -> Router {
             "/api/v1/scheduler/decks/{deck_id}/counts",
             get(get_deck_counts),
         )
+        .route(
+            "/api/v1/scheduler/decks/{deck_id}/congrats",
+            get(get_congrats_info),
+        )
         .route("/api/v1/scheduler/undo", post(undo))
         .route("/api/v1/scheduler/redo", post(redo))
+        .route("/api/v1/scheduler/undo-status", get(get_undo_status))
+        .route(
+            "/api/v1/preferences",
+            get(get_preferences).put(update_preferences),
+        )
+        .route("/api/v1/config", get(list_config))
+        .route("/api/v1/config/{key}", get(get_config).put(set_config))
+        .route("/api/v1/fsrs/presets", get(list_fsrs_presets))
+        .route(
+            "/api/v1/fsrs/presets/{id}/optimize",
+            post(optimize_fsrs_params),
+        )
+        .route(
+            "/api/v1/fsrs/presets/{id}/evaluate",
+            post(evaluate_fsrs_params),
+        )
+        .route(
+            "/api/v1/fsrs/presets/{id}/simulate",
+            post(simulate_fsrs_workload),
+        )
+        .route(
+            "/api/v1/fsrs/presets/{id}/optimal-retention",
+            post(compute_optimal_retention),
+        )
+        .route("/api/v1/fsrs/presets/{id}/params", put(save_fsrs_params))
+        .route(
+            "/api/v1/shared-decks",
+            get(list_shared_decks).post(publish_deck),
+        )
+        .route(
+            "/api/v1/shared-decks/subscriptions",
+            get(list_subscriptions),
+        )
+        .route("/api/v1/shared-decks/{id}", delete(unpublish_deck))
+        .route(
+            "/api/v1/shared-decks/{id}/subscription",
+            post(subscribe_deck).delete(unsubscribe_deck),
+        )
+        .route(
+            "/api/v1/shared-decks/{id}/update",
+            post(update_subscription),
+        )
+        .layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            record_audit,
+        ))
+        .layer(middleware::from_fn_with_state(
+            IdempotencyState {
+                database: auth_state.database.clone(),
+                window_secs: (config.idempotency_window_hours * 60 * 60) as i64,
+            },
+            idempotency,
+        ))
         .layer(middleware::from_fn_with_state(
             auth_state.clone(),
             require_auth,
@@ -157,12 +332,15 @@ pub fn create_router(config: &WebAppConfig, auth_state: AuthState) -> Router {
         database: auth_state.database.clone(),
         jwt_manager: auth_state.jwt_manager.clone(),
         backend_manager: auth_state.backend_manager.clone(),
+        job_manager: auth_state.job_manager.clone(),
         session_timeout_hours: config.session_timeout_hours as i64,
+        latex_rendering: config.latex_rendering,
     };
 
     let public_routes = Router::new()
         .route("/", get(root_handler))
         .route("/health", get(health_handler))
+        .route("/metrics", get(metrics_handler))
         .route("/api/v1/info", get(info_handler))
         .route("/api-docs/openapi.json", get(openapi_spec_handler))
         .route("/swagger-ui", get(swagger_ui::swagger_ui_handler))
@@ -170,12 +348,10 @@ pub fn create_router(config: &WebAppConfig, auth_state: AuthState) -> Router {
         .route("/api/v1/auth/register", post(register))
         .route("/api/v1/auth/login", post(login));
 
-    // CORS layer for development (SvelteKit dev server on different port)
+    // CORS layer for frontends served from a different origin (e.g. the
+    // SvelteKit dev server)
     let cors = CorsLayer::new()
-        .allow_origin([
-            "http://localhost:5173".parse::<HeaderValue>().unwrap(),
-            "http://127.0.0.1:5173".parse::<HeaderValue>().unwrap(),
-        ])
+        .allow_origin(cors_allowed_origins(&config.cors_allowed_origins))
         .allow_methods([
             Method::GET,
             Method::POST,
@@ -186,15 +362,41 @@ pub fn create_router(config: &WebAppConfig, auth_state: AuthState) -> Router {
         .allow_headers([
             axum::http::header::CONTENT_TYPE,
             axum::http::header::AUTHORIZATION,
-        ]);
+            axum::http::header::IF_MATCH,
+            axum::http::header::ACCEPT_LANGUAGE,
+            IDEMPOTENCY_KEY,
+        ])
+        .expose_headers([axum::http::header::ETAG]);
 
     // Combine all routes with state
     public_routes
         .merge(protected_routes)
         .with_state(auth_route_state)
+        .layer(DefaultBodyLimit::max(config.max_json_body_bytes))
+        .layer(middleware::from_fn(localize_errors))
+        .layer(middleware::from_fn(track_requests))
+        .layer(config.ip_header.clone().into_extension())
         .layer(cors)
 }
 
+fn cors_allowed_origins(origins: &[String]) -> AllowOrigin {
+    if origins.iter().any(|origin| origin == "*") {
+        return AllowOrigin::any();
+    }
+
+    let origins: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|origin| match origin.parse::<HeaderValue>() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                tracing::warn!("Ignoring invalid CORS origin: {}", origin);
+                None
+            }
+        })
+        .collect();
+    AllowOrigin::list(origins)
+}
+
 async fn openapi_spec_handler() -> Json<serde_json::Value> {
     Json(openapi::openapi_spec())
 }
@@ -256,6 +458,7 @@ async fn root_handler() -> Html<&'static str> {
     <ul>
         <li><code>GET /</code> - This page</li>
         <li><code>GET /health</code> - Health check</li>
+        <li><code>GET /metrics</code> - Prometheus metrics</li>
         <li><code>GET /api/v1/info</code> - Server info (JSON)</li>
         <li><code>POST /api/v1/auth/register</code> - Register new user</li>
         <li><code>POST /api/v1/auth/login</code> - Login user</li>
@@ -268,6 +471,21 @@ async fn root_handler() -> Html<&'static str> {
         <li><code>POST /api/v1/auth/logout</code> - Logout user</li>
         <li><code>GET /api/v1/collection</code> - Get collection info</li>
         <li><code>POST /api/v1/collection/close</code> - Close collection</li>
+        <li><code>POST /api/v1/collection/check-database</code> - Check database (background job)</li>
+        <li><code>POST /api/v1/collection/empty-cards</code> - Empty cards report (background job)</li>
+        <li><code>DELETE /api/v1/collection/empty-cards</code> - Delete empty cards</li>
+        <li><code>GET /api/v1/collection/backups</code> - List backups</li>
+        <li><code>POST /api/v1/collection/backups</code> - Create backup now (background job)</li>
+        <li><code>POST /api/v1/collection/backups/{filename}/restore</code> - Restore backup (background job)</li>
+        <li><code>GET /api/v1/audit</code> - Audit log of changes made through the API</li>
+        <li><code>GET /api/v1/jobs/{id}</code> - Background job status and result</li>
+        <li><code>GET /api/v1/preferences</code> - Scheduling, reviewing and editing preferences</li>
+        <li><code>PUT /api/v1/preferences</code> - Update preferences (undoable)</li>
+        <li><code>GET /api/v1/config</code> - Config keys clients may change</li>
+        <li><code>PUT /api/v1/config/{key}</code> - Set a config key (undoable)</li>
+        <li><code>GET /api/v1/fsrs/presets</code> - Deck option presets and their FSRS parameters</li>
+        <li><code>POST /api/v1/fsrs/presets/{id}/optimize</code> - Optimize FSRS parameters (background job)</li>
+        <li><code>PUT /api/v1/fsrs/presets/{id}/params</code> - Save FSRS parameters and update memory states (background job)</li>
         <li><code>GET /api/v1/decks</code> - Get deck tree</li>
         <li><code>POST /api/v1/decks</code> - Create deck</li>
         <li><code>GET /api/v1/decks/{id}</code> - Get deck by ID</li>
@@ -308,6 +526,13 @@ async fn root_handler() -> Html<&'static str> {
         <li><code>ANKI_WEBAPP_PORT</code> - Port (default: 8080)</li>
         <li><code>ANKI_WEBAPP_DATA_DIR</code> - Data directory (default: ./data)</li>
         <li><code>ANKI_WEBAPP_JWT_SECRET</code> - JWT secret (⚠️ required for production)</li>
+        <li><code>ANKI_WEBAPP_BACKUP_INTERVAL_MINS</code> - How often to check for due backups, 0 to disable (default: 15)</li>
+        <li><code>ANKI_WEBAPP_AUDIT_RETENTION_DAYS</code> - Days to keep audit log entries, 0 to keep forever (default: 90)</li>
+        <li><code>ANKI_WEBAPP_TLS_CERT</code> / <code>ANKI_WEBAPP_TLS_KEY</code> - PEM certificate and key to serve HTTPS, reloaded on SIGHUP</li>
+        <li><code>ANKI_WEBAPP_CORS_ORIGINS</code> - Comma-separated allowed origins, or * (default: localhost:5173)</li>
+        <li><code>ANKI_WEBAPP_IP_HEADER</code> - Client IP source, e.g. RightmostXForwardedFor behind a trusted proxy (default: ConnectInfo)</li>
+        <li><code>ANKI_WEBAPP_MAX_JSON_BODY_BYTES</code> / <code>ANKI_WEBAPP_MAX_UPLOAD_BODY_BYTES</code> - Request body limits (default: 2 MiB / 100 MiB)</li>
+        <li><code>ANKI_WEBAPP_LOG_FORMAT</code> - Log output format, text or json (default: text)</li>
         <li><code>RUST_LOG</code> - Log level (default: info)</li>
     </ul>
 </body>
@@ -320,6 +545,13 @@ async fn health_handler() -> impl IntoResponse {
     (StatusCode::OK, "OK")
 }
 
+async fn metrics_handler(State(state): State<AuthRouteState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics().render(state.backend_manager.active_backend_count()),
+    )
+}
+
 async fn info_handler() -> impl IntoResponse {
     Json(json!({
         "success": true,
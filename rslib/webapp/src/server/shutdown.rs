@@ -0,0 +1,49 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use std::future::pending;
+
+use tokio_util::sync::CancellationToken;
+
+/// Cancel `shutdown` when the process receives Ctrl-C or, on Unix, SIGTERM
+/// (which is what `docker stop` and systemd send)
+pub fn spawn_shutdown_on_signal(shutdown: CancellationToken) {
+    tokio::spawn(async move {
+        let interrupt = async {
+            if let Err(e) = tokio::signal::ctrl_c().await {
+                tracing::error!("Failed to listen for Ctrl-C: {}", e);
+                pending::<()>().await;
+            }
+        };
+
+        tokio::select! {
+            _ = interrupt => {}
+            _ = terminate() => {}
+            _ = shutdown.cancelled() => return,
+        }
+
+        tracing::info!("🛑 Received shutdown signal");
+        shutdown.cancel();
+    });
+}
+
+#[cfg(unix)]
+async fn terminate() {
+    use tokio::signal::unix::signal;
+    use tokio::signal::unix::SignalKind;
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            terminate.recv().await;
+        }
+        Err(e) => {
+            tracing::error!("Failed to listen for SIGTERM: {}", e);
+            pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminate() {
+    pending::<()>().await;
+}
@@ -0,0 +1,190 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use axum::extract::ConnectInfo;
+use axum::extract::Request;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::TokioExecutor;
+use hyper_util::rt::TokioIo;
+use hyper_util::server::conn::auto::Builder;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use tower::Service;
+
+/// Load a PEM certificate chain and private key into a rustls server config
+pub fn load_tls_config(cert_path: &Path, key_path: &Path) -> anyhow::Result<Arc<ServerConfig>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(open(cert_path)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {}", cert_path.display());
+    }
+
+    let key = rustls_pemfile::private_key(&mut BufReader::new(open(key_path)?))?
+        .ok_or_else(|| anyhow::anyhow!("No private key found in {}", key_path.display()))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+fn open(path: &Path) -> anyhow::Result<File> {
+    File::open(path).map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))
+}
+
+/// Serve the app over HTTPS. Connections are accepted with whichever
+/// certificate is current, so a reload only affects new connections.
+///
+/// Once `shutdown` is cancelled, no new connections are accepted, open
+/// connections finish their in-flight requests and are closed, and this
+/// returns when the last one has gone.
+pub async fn serve_tls(
+    listener: TcpListener,
+    app: Router,
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
+    let acceptor = Arc::new(RwLock::new(TlsAcceptor::from(load_tls_config(
+        &cert_path, &key_path,
+    )?)));
+    spawn_reload_on_sighup(acceptor.clone(), cert_path, key_path);
+
+    // Each connection holds a receiver; the sender is closed once all are gone
+    let (open_tx, open_rx) = watch::channel(());
+
+    loop {
+        let (stream, addr) = tokio::select! {
+            conn = listener.accept() => match conn {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::warn!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            },
+            _ = shutdown.cancelled() => break,
+        };
+
+        let acceptor = acceptor.read().unwrap().clone();
+        let app = app.clone();
+        let shutdown = shutdown.clone();
+        let open_rx = open_rx.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("TLS handshake with {} failed: {}", addr, e);
+                    return;
+                }
+            };
+
+            let service = hyper::service::service_fn(move |mut request: Request<Incoming>| {
+                // Make the peer address available to the ConnectInfo IP source
+                request
+                    .extensions_mut()
+                    .insert(ConnectInfo::<SocketAddr>(addr));
+                app.clone().call(request)
+            });
+
+            let builder = Builder::new(TokioExecutor::new());
+            let conn = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            tokio::pin!(conn);
+            let result = tokio::select! {
+                result = conn.as_mut() => result,
+                _ = shutdown.cancelled() => {
+                    conn.as_mut().graceful_shutdown();
+                    conn.await
+                }
+            };
+            if let Err(e) = result {
+                tracing::debug!("Connection from {} closed with error: {}", addr, e);
+            }
+            drop(open_rx);
+        });
+    }
+
+    drop(open_rx);
+    open_tx.closed().await;
+
+    Ok(())
+}
+
+/// Reload the certificate and key when the process receives SIGHUP, keeping
+/// the current ones if the new files can't be loaded
+#[cfg(unix)]
+fn spawn_reload_on_sighup(
+    acceptor: Arc<RwLock<TlsAcceptor>>,
+    cert_path: PathBuf,
+    key_path: PathBuf,
+) {
+    use tokio::signal::unix::signal;
+    use tokio::signal::unix::SignalKind;
+
+    tokio::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGHUP, TLS reload disabled: {}", e);
+                return;
+            }
+        };
+
+        while hangup.recv().await.is_some() {
+            match load_tls_config(&cert_path, &key_path) {
+                Ok(config) => {
+                    *acceptor.write().unwrap() = TlsAcceptor::from(config);
+                    tracing::info!("🔐 Reloaded TLS certificate");
+                }
+                Err(e) => tracing::error!(
+                    "Failed to reload TLS certificate, keeping the current one: {}",
+                    e
+                ),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_reload_on_sighup(
+    _acceptor: Arc<RwLock<TlsAcceptor>>,
+    _cert_path: PathBuf,
+    _key_path: PathBuf,
+) {
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    #[test]
+    fn test_load_tls_config_errors() {
+        let missing = Path::new("/nonexistent/cert.pem");
+        let err = load_tls_config(missing, missing).unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/cert.pem"));
+
+        let mut empty = NamedTempFile::new().unwrap();
+        writeln!(empty, "not a certificate").unwrap();
+        let err = load_tls_config(empty.path(), empty.path()).unwrap_err();
+        assert!(err.to_string().contains("No certificates found"));
+    }
+}
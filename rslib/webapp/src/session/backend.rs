@@ -1,16 +1,50 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::time::Duration;
+use std::time::Instant;
 
 use anki::collection::Collection;
 use anki::collection::CollectionBuilder;
 use anyhow::Result;
 
+use crate::metrics::metrics;
+
+/// How long to wait for in-flight requests to release a collection before
+/// giving up on closing it
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+const CLOSE_WAIT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Returned when a user's collection can't be opened because it is being
+/// closed or replaced, e.g. while a backup is restored
+#[derive(Debug)]
+pub struct CollectionBusy;
+
+impl fmt::Display for CollectionBusy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Collection is being closed, try again shortly")
+    }
+}
+
+impl std::error::Error for CollectionBusy {}
+
+/// A user's entry in the backend map
+enum Slot {
+    Open(Arc<Mutex<Collection>>),
+    /// Another thread has taken the collection out to close or replace it,
+    /// without holding the map lock. It can't be opened until that thread
+    /// is done.
+    Closing,
+}
+
 /// Manages per-user Anki Backend (Collection) instances
 pub struct BackendManager {
     /// Map of user_id -> Collection instance
-    backends: Arc<Mutex<HashMap<i64, Arc<Mutex<Collection>>>>>,
+    backends: Arc<Mutex<HashMap<i64, Slot>>>,
     /// Base directory for user collections
     data_dir: PathBuf,
 }
@@ -32,8 +66,10 @@ impl BackendManager {
         let mut backends = self.backends.lock().unwrap();
 
         // Return existing backend if available
-        if let Some(backend) = backends.get(&user_id) {
-            return Ok(backend.clone());
+        match backends.get(&user_id) {
+            Some(Slot::Open(backend)) => return Ok(backend.clone()),
+            Some(Slot::Closing) => return Err(CollectionBusy.into()),
+            None => {}
         }
 
         // Create new backend
@@ -50,13 +86,10 @@ impl BackendManager {
             collection_path
         );
 
-        // Open or create collection
-        let col = CollectionBuilder::new(collection_path)
-            .with_desktop_media_paths()
-            .build()?;
+        let col = open_collection(&collection_path)?;
 
         let backend = Arc::new(Mutex::new(col));
-        backends.insert(user_id, backend.clone());
+        backends.insert(user_id, Slot::Open(backend.clone()));
 
         Ok(backend)
     }
@@ -64,28 +97,131 @@ impl BackendManager {
     /// Get existing backend for a user (without creating)
     pub fn get_backend(&self, user_id: i64) -> Option<Arc<Mutex<Collection>>> {
         let backends = self.backends.lock().unwrap();
-        backends.get(&user_id).cloned()
+        match backends.get(&user_id) {
+            Some(Slot::Open(backend)) => Some(backend.clone()),
+            _ => None,
+        }
     }
 
-    /// Close and remove backend for a user
+    /// Close and remove backend for a user, once in-flight requests have
+    /// released it. This blocks while waiting, but other users' collections
+    /// stay available meanwhile.
     pub fn close_backend(&self, user_id: i64) -> Result<()> {
-        let mut backends = self.backends.lock().unwrap();
-
-        if let Some(backend) = backends.remove(&user_id) {
-            // Drop the backend to close the collection
-            // The Collection's Drop implementation will handle cleanup
-            drop(backend);
-            tracing::info!("Closed collection for user {}", user_id);
+        let Some(backend) = self.begin_closing(user_id)? else {
+            self.finish_closing(user_id, None);
+            return Ok(());
+        };
+
+        match take_when_unused(backend, Instant::now() + CLOSE_TIMEOUT) {
+            Ok(col) => {
+                let result = col.close(None);
+                self.finish_closing(user_id, None);
+                result?;
+            }
+            Err(backend) => {
+                self.finish_closing(user_id, Some(backend));
+                anyhow::bail!("Collection is still in use");
+            }
         }
+        tracing::info!("Closed collection for user {}", user_id);
 
         Ok(())
     }
 
-    /// Get the collection path for a user
-    fn get_collection_path(&self, user_id: i64, username: &str) -> PathBuf {
+    /// Close a user's collection, run `f` with the collection path while
+    /// nothing has the file open, then reopen the collection. Meanwhile the
+    /// user's entry is marked as closing, so no request can reopen it, but
+    /// other users' collections stay available.
+    pub fn with_collection_closed<F, R>(&self, user_id: i64, username: &str, f: F) -> Result<R>
+    where
+        F: FnOnce(&Path) -> Result<R>,
+    {
+        if let Some(backend) = self.begin_closing(user_id)? {
+            match take_when_unused(backend, Instant::now() + CLOSE_TIMEOUT) {
+                Ok(col) => {
+                    if let Err(e) = col.close(None) {
+                        self.finish_closing(user_id, None);
+                        return Err(e.into());
+                    }
+                }
+                Err(backend) => {
+                    self.finish_closing(user_id, Some(backend));
+                    anyhow::bail!("Collection is still in use");
+                }
+            }
+        }
+
+        let collection_path = self.get_collection_path(user_id, username);
+        let result = f(&collection_path);
+
+        // Reopen even if `f` failed, so the user isn't left without a collection
+        match open_collection(&collection_path) {
+            Ok(col) => self.finish_closing(user_id, Some(Arc::new(Mutex::new(col)))),
+            Err(e) => {
+                self.finish_closing(user_id, None);
+                return Err(e);
+            }
+        }
+
+        result
+    }
+
+    /// Take a user's collection out of the map, leaving a closing marker in
+    /// its place. Fails if another thread is already closing it.
+    fn begin_closing(&self, user_id: i64) -> Result<Option<Arc<Mutex<Collection>>>> {
+        let mut backends = self.backends.lock().unwrap();
+        match backends.insert(user_id, Slot::Closing) {
+            Some(Slot::Open(backend)) => Ok(Some(backend)),
+            Some(Slot::Closing) => Err(CollectionBusy.into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Clear a user's closing marker, putting back the collection if it is
+    /// still (or again) open
+    fn finish_closing(&self, user_id: i64, backend: Option<Arc<Mutex<Collection>>>) {
+        let mut backends = self.backends.lock().unwrap();
+        match backend {
+            Some(backend) => backends.insert(user_id, Slot::Open(backend)),
+            None => backends.remove(&user_id),
+        };
+    }
+
+    /// Get a snapshot of all open collections, keyed by user id
+    pub fn open_backends(&self) -> Vec<(i64, Arc<Mutex<Collection>>)> {
+        let backends = self.backends.lock().unwrap();
+        backends
+            .iter()
+            .filter_map(|(user_id, slot)| match slot {
+                Slot::Open(backend) => Some((*user_id, backend.clone())),
+                Slot::Closing => None,
+            })
+            .collect()
+    }
+
+    /// Get the folder holding all of a user's files
+    pub fn get_user_folder_path(&self, user_id: i64) -> PathBuf {
         self.data_dir
             .join("users")
             .join(format!("user_{}", user_id))
+    }
+
+    /// Get the folder automatic and manual backups are written to
+    pub fn get_backup_folder_path(&self, user_id: i64) -> PathBuf {
+        self.get_user_folder_path(user_id).join("backups")
+    }
+
+    /// Get the file holding the latest published version of a user's shared
+    /// deck
+    pub fn get_shared_deck_path(&self, owner_id: i64, source_deck_id: i64) -> PathBuf {
+        self.data_dir
+            .join("shared")
+            .join(format!("user_{}_deck_{}.apkg", owner_id, source_deck_id))
+    }
+
+    /// Get the collection path for a user
+    fn get_collection_path(&self, user_id: i64, username: &str) -> PathBuf {
+        self.get_user_folder_path(user_id)
             .join(format!("{}.anki2", username))
     }
 
@@ -99,27 +235,118 @@ impl BackendManager {
     /// Get count of active backends
     pub fn active_backend_count(&self) -> usize {
         let backends = self.backends.lock().unwrap();
-        backends.len()
+        backends
+            .values()
+            .filter(|slot| matches!(slot, Slot::Open(_)))
+            .count()
     }
 
-    /// Close all backends (for shutdown)
-    pub fn close_all(&self) -> Result<()> {
-        let mut backends = self.backends.lock().unwrap();
-        let count = backends.len();
+    /// Close all backends (for shutdown). The collections are taken out of
+    /// the map first, then closed in parallel once in-flight requests have
+    /// released them or `timeout` runs out; closing the last connection
+    /// checkpoints the WAL into the collection file. Collections that stay in
+    /// use or fail to close are logged and reported as an error.
+    pub fn close_all(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        let open: Vec<_> = {
+            let mut backends = self.backends.lock().unwrap();
+            backends
+                .iter_mut()
+                .filter_map(|(user_id, slot)| {
+                    match std::mem::replace(slot, Slot::Closing) {
+                        Slot::Open(backend) => Some((*user_id, backend)),
+                        // Left for the thread closing it to clear
+                        Slot::Closing => None,
+                    }
+                })
+                .collect()
+        };
+
+        let failed = std::thread::scope(|scope| {
+            let handles: Vec<_> = open
+                .into_iter()
+                .map(|(user_id, backend)| {
+                    scope.spawn(move || {
+                        let closed = close_by(user_id, backend, deadline);
+                        self.finish_closing(user_id, None);
+                        closed
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .filter(|handle| !matches!(handle.join(), Ok(true)))
+                .count()
+        });
+
+        if failed > 0 {
+            anyhow::bail!("Failed to close {} collection(s)", failed);
+        }
 
-        backends.clear();
+        Ok(())
+    }
+}
 
-        if count > 0 {
-            tracing::info!("Closed {} backend instances", count);
+/// Close a collection once it is unused, giving up at `deadline`. Returns
+/// whether it was closed.
+fn close_by(user_id: i64, backend: Arc<Mutex<Collection>>, deadline: Instant) -> bool {
+    match take_when_unused(backend, deadline) {
+        Ok(col) => match col.close(None) {
+            Ok(()) => {
+                tracing::info!("Closed collection for user {}", user_id);
+                true
+            }
+            Err(e) => {
+                tracing::error!("Failed to close collection for user {}: {}", user_id, e);
+                false
+            }
+        },
+        Err(_) => {
+            tracing::error!(
+                "Collection for user {} is still in use, leaving it unclosed",
+                user_id
+            );
+            false
         }
+    }
+}
 
-        Ok(())
+/// Lock a collection, recording how long the caller had to wait for it
+pub fn lock_collection(backend: &Mutex<Collection>) -> MutexGuard<'_, Collection> {
+    let start = Instant::now();
+    let col = backend.lock().unwrap();
+    metrics().record_lock_wait(start.elapsed());
+    col
+}
+
+pub(super) fn open_collection(collection_path: &Path) -> Result<Collection> {
+    Ok(CollectionBuilder::new(collection_path)
+        .with_desktop_media_paths()
+        .build()?)
+}
+
+/// Take ownership of a collection once no in-flight request holds a
+/// reference to it. If it is still in use at `deadline`, the reference is
+/// handed back.
+fn take_when_unused(
+    mut backend: Arc<Mutex<Collection>>,
+    deadline: Instant,
+) -> std::result::Result<Collection, Arc<Mutex<Collection>>> {
+    loop {
+        match Arc::try_unwrap(backend) {
+            Ok(mutex) => return Ok(mutex.into_inner().unwrap_or_else(|e| e.into_inner())),
+            Err(shared) if Instant::now() < deadline => {
+                backend = shared;
+                std::thread::sleep(CLOSE_WAIT_INTERVAL);
+            }
+            Err(shared) => return Err(shared),
+        }
     }
 }
 
 impl Drop for BackendManager {
     fn drop(&mut self) {
-        let _ = self.close_all();
+        let _ = self.close_all(CLOSE_TIMEOUT);
     }
 }
 
@@ -147,10 +374,12 @@ mod tests {
         assert!(Arc::ptr_eq(&backend1, &backend1_again));
 
         // Get backend for user 2
-        let _backend2 = manager.get_or_create_backend(2, "bob").unwrap();
+        let backend2 = manager.get_or_create_backend(2, "bob").unwrap();
         assert_eq!(manager.active_backend_count(), 2);
 
-        // Close backend for user 1
+        // Close backend for user 1, once nothing holds it
+        drop(backend1);
+        drop(backend1_again);
         manager.close_backend(1).unwrap();
         assert_eq!(manager.active_backend_count(), 1);
 
@@ -158,8 +387,87 @@ mod tests {
         assert!(manager.get_backend(1).is_none());
 
         // Close all
-        manager.close_all().unwrap();
+        drop(backend2);
+        manager.close_all(CLOSE_TIMEOUT).unwrap();
+        assert_eq!(manager.active_backend_count(), 0);
+    }
+
+    #[test]
+    fn test_close_all_checkpoints_and_reports_busy_collections() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = BackendManager::new(temp_dir.path().to_path_buf());
+
+        manager.get_or_create_backend(1, "alice").unwrap();
+        let wal_path = manager
+            .get_collection_path(1, "alice")
+            .with_extension("anki2-wal");
+        assert!(wal_path.exists());
+
+        // A collection still held by a request can't be closed
+        let busy = manager.get_or_create_backend(2, "bob").unwrap();
+        assert!(manager.close_backend(2).is_err());
+        assert!(manager.get_backend(2).is_some());
+
+        let err = manager.close_all(Duration::ZERO).unwrap_err();
+        assert!(err.to_string().contains("1 collection(s)"));
         assert_eq!(manager.active_backend_count(), 0);
+
+        // The idle collection was closed and its WAL folded into the file
+        assert!(!wal_path.exists());
+        drop(busy);
+    }
+
+    #[test]
+    fn test_close_all_waits_without_holding_the_map() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = BackendManager::new(temp_dir.path().to_path_buf());
+
+        let alice = manager.get_or_create_backend(1, "alice").unwrap();
+        let bob = manager.get_or_create_backend(2, "bob").unwrap();
+        std::thread::scope(|scope| {
+            let closing = scope.spawn(|| manager.close_all(CLOSE_TIMEOUT));
+            while manager.active_backend_count() > 0 {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            // Collections being closed can't be reopened, but others can
+            let err = manager.get_or_create_backend(1, "alice").unwrap_err();
+            assert!(err.is::<CollectionBusy>());
+            manager.get_or_create_backend(3, "carol").unwrap();
+
+            drop(alice);
+            drop(bob);
+            closing.join().unwrap().unwrap();
+        });
+
+        assert!(manager.get_backend(1).is_none());
+        assert!(manager.get_backend(2).is_none());
+        assert_eq!(manager.active_backend_count(), 1);
+    }
+
+    #[test]
+    fn test_closed_collection_blocks_only_its_user() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = BackendManager::new(temp_dir.path().to_path_buf());
+
+        manager.get_or_create_backend(1, "alice").unwrap();
+        manager
+            .with_collection_closed(1, "alice", |_| {
+                // Other users' collections open as usual
+                manager.get_or_create_backend(2, "bob")?;
+                // While the user's own collection can't be reopened
+                let err = manager.get_or_create_backend(1, "alice").unwrap_err();
+                assert!(err.is::<CollectionBusy>());
+                assert!(manager
+                    .with_collection_closed(1, "alice", |_| Ok(()))
+                    .is_err());
+                Ok(())
+            })
+            .unwrap();
+
+        // Reopened afterwards
+        assert!(manager.get_backend(1).is_some());
+        assert_eq!(manager.active_backend_count(), 2);
     }
 
     #[test]
@@ -0,0 +1,244 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anki::collection::Collection;
+use anki::import_export::package::import_colpkg;
+use anyhow::Result;
+use chrono::Local;
+use chrono::NaiveDateTime;
+use chrono::TimeZone;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use super::backend::open_collection;
+use super::lock_collection;
+use super::BackendManager;
+
+/// Matches the file names written by `Collection::maybe_backup`
+const BACKUP_FORMAT_STRING: &str = "backup-%Y-%m-%d-%H.%M.%S.colpkg";
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct BackupInfo {
+    pub filename: String,
+    pub created_at: i64,
+    pub size: u64,
+}
+
+impl BackendManager {
+    /// Create a backup of a user's collection if it changed since the last
+    /// one and, unless forced, the collection's minimum backup interval has
+    /// passed. Old backups are thinned using the collection's retention
+    /// settings. Returns true if a backup was written.
+    pub fn backup_collection(
+        &self,
+        user_id: i64,
+        backend: &Arc<Mutex<Collection>>,
+        force: bool,
+    ) -> Result<bool> {
+        let folder = self.get_backup_folder_path(user_id);
+        std::fs::create_dir_all(&folder)?;
+
+        let handle = lock_collection(backend).maybe_backup(folder, force)?;
+        match handle {
+            Some(handle) => {
+                handle
+                    .join()
+                    .map_err(|_| anyhow::anyhow!("Backup thread panicked"))??;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Back up every open collection that is due for a backup, returning the
+    /// number of backups written
+    pub fn backup_open_collections(&self) -> usize {
+        let mut count = 0;
+        for (user_id, backend) in self.open_backends() {
+            match self.backup_collection(user_id, &backend, false) {
+                Ok(true) => count += 1,
+                Ok(false) => {}
+                Err(e) => tracing::error!("Backup failed for user {}: {}", user_id, e),
+            }
+        }
+        count
+    }
+
+    /// List a user's backups, newest first
+    pub fn list_backups(&self, user_id: i64) -> Result<Vec<BackupInfo>> {
+        let folder = self.get_backup_folder_path(user_id);
+        if !folder.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut backups = vec![];
+        for entry in std::fs::read_dir(&folder)? {
+            let entry = entry?;
+            let filename = entry.file_name().to_string_lossy().into_owned();
+            let Some(created_at) = backup_timestamp(&filename) else {
+                continue;
+            };
+            backups.push(BackupInfo {
+                filename,
+                created_at,
+                size: entry.metadata()?.len(),
+            });
+        }
+        backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+        Ok(backups)
+    }
+
+    /// Replace a user's collection with one of their backups. The current
+    /// state is backed up first, so a restore can itself be reverted.
+    pub fn restore_backup(&self, user_id: i64, username: &str, filename: &str) -> Result<()> {
+        let backup_path = self.backup_path(user_id, filename)?;
+
+        // Work from a copy, as the backup taken below may thin out (or, within
+        // the same second, overwrite) the one being restored
+        let backup_copy = tempfile::NamedTempFile::new()?;
+        std::fs::copy(&backup_path, backup_copy.path())?;
+
+        let backend = self.get_or_create_backend(user_id, username)?;
+        self.backup_collection(user_id, &backend, true)?;
+        let progress = lock_collection(&backend).new_progress_handler();
+        drop(backend);
+
+        self.with_collection_closed(user_id, username, |col_path| {
+            let media_folder = col_path.with_extension("media");
+            let media_db = col_path.with_extension("mdb");
+            import_colpkg(
+                &backup_copy.path().to_string_lossy(),
+                &col_path.to_string_lossy(),
+                &media_folder,
+                &media_db,
+                progress,
+            )?;
+            Ok(())
+        })?;
+
+        tracing::info!("Restored backup {} for user {}", filename, user_id);
+        Ok(())
+    }
+
+    /// Write a user's collection, and optionally its media, to a `.colpkg`
+    /// file that the desktop app can import
+    pub fn export_collection(
+        &self,
+        user_id: i64,
+        username: &str,
+        out_path: &Path,
+        include_media: bool,
+    ) -> Result<()> {
+        self.with_collection_closed(user_id, username, |col_path| {
+            open_collection(col_path)?.export_colpkg(out_path, include_media, false)?;
+            Ok(())
+        })
+    }
+
+    /// Resolve a backup file name, rejecting anything that isn't one of the
+    /// user's backups
+    fn backup_path(&self, user_id: i64, filename: &str) -> Result<PathBuf> {
+        if filename.contains('/') || filename.contains('\\') || backup_timestamp(filename).is_none()
+        {
+            anyhow::bail!("Invalid backup name");
+        }
+        let path = self.get_backup_folder_path(user_id).join(filename);
+        if !path.exists() {
+            anyhow::bail!("Backup not found");
+        }
+        Ok(path)
+    }
+}
+
+/// Periodically back up open collections on the blocking thread pool
+pub fn spawn_periodic_backups(manager: Arc<BackendManager>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately; skip it so we don't back up at startup
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let manager = manager.clone();
+            match tokio::task::spawn_blocking(move || manager.backup_open_collections()).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Created {} automatic backup(s)", count),
+                Err(e) => tracing::error!("Automatic backup task failed: {}", e),
+            }
+        }
+    });
+}
+
+fn backup_timestamp(filename: &str) -> Option<i64> {
+    NaiveDateTime::parse_from_str(filename, BACKUP_FORMAT_STRING)
+        .ok()
+        .and_then(|datetime| Local.from_local_datetime(&datetime).latest())
+        .map(|datetime| datetime.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_backup_timestamp_parsing() {
+        assert!(backup_timestamp("backup-2024-01-31-13.45.10.colpkg").is_some());
+        assert!(backup_timestamp("collection.colpkg").is_none());
+        assert!(backup_timestamp("../backup-2024-01-31-13.45.10.colpkg").is_none());
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = BackendManager::new(temp_dir.path().to_path_buf());
+        assert!(manager.list_backups(1).unwrap().is_empty());
+
+        let backend = manager.get_or_create_backend(1, "alice").unwrap();
+        backend
+            .lock()
+            .unwrap()
+            .get_or_create_normal_deck("Before Backup")
+            .unwrap();
+        assert!(manager.backup_collection(1, &backend, true).unwrap());
+        drop(backend);
+
+        let backups = manager.list_backups(1).unwrap();
+        assert_eq!(backups.len(), 1);
+
+        // Make a change after the backup, then restore
+        let backend = manager.get_backend(1).unwrap();
+        backend
+            .lock()
+            .unwrap()
+            .get_or_create_normal_deck("After Backup")
+            .unwrap();
+        drop(backend);
+
+        manager
+            .restore_backup(1, "alice", &backups[0].filename)
+            .unwrap();
+
+        let backend = manager.get_backend(1).unwrap();
+        let col = backend.lock().unwrap();
+        assert!(col.get_deck_id("Before Backup").unwrap().is_some());
+        assert!(col.get_deck_id("After Backup").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_restore_rejects_invalid_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = BackendManager::new(temp_dir.path().to_path_buf());
+        assert!(manager.restore_backup(1, "alice", "../webapp.db").is_err());
+        assert!(manager
+            .restore_backup(1, "alice", "backup-2024-01-31-13.45.10.colpkg")
+            .is_err());
+    }
+}
@@ -1,3 +1,8 @@
 pub mod backend;
+pub mod backup;
 
+pub use backend::lock_collection;
 pub use backend::BackendManager;
+pub use backend::CollectionBusy;
+pub use backend::CLOSE_TIMEOUT;
+pub use backup::BackupInfo;
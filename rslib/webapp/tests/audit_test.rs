@@ -0,0 +1,104 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use serde_json::json;
+mod common;
+use common::TestContext;
+
+#[tokio::test]
+async fn test_audit_log() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    // 2. Create and delete a deck
+    let resp = ctx.client
+        .post(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "name": "Audited Deck" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .delete(format!("{}/api/v1/decks/{}", ctx.base_url, deck_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    // 3. Reads are not audited
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    // 4. Query the log
+    let resp = ctx.client
+        .get(format!("{}/api/v1/audit", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let entries = body["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+
+    let deletion = &entries[0];
+    assert_eq!(deletion["method"], "DELETE");
+    assert_eq!(deletion["route"], "/api/v1/decks/{id}");
+    assert_eq!(deletion["op"], "RemoveDeck");
+    assert_eq!(deletion["affected_ids"]["id"], deck_id);
+    assert_eq!(deletion["status"], 200);
+    assert_eq!(deletion["client_ip"], "127.0.0.1");
+    assert!(deletion["session_id"].is_string());
+    assert_eq!(entries[1]["method"], "POST");
+
+    // 5. Filter by operation
+    let resp = ctx.client
+        .get(format!("{}/api/v1/audit?op=RemoveDeck", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["entries"].as_array().unwrap().len(), 1);
+
+    // 6. Requires authentication
+    let resp = ctx.client
+        .get(format!("{}/api/v1/audit", ctx.base_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+}
@@ -90,3 +90,22 @@ async fn test_invalid_login() {
 
     assert_eq!(resp.status(), 401);
 }
+
+#[tokio::test]
+async fn test_registration_disabled() {
+    let ctx = TestContext::new().await;
+    ctx.database.settings().set_registration_enabled(false).unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "newuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .expect("Failed to send register request");
+
+    assert_eq!(resp.status(), 403);
+    assert!(ctx.database.users().get_by_username("newuser").unwrap().is_none());
+}
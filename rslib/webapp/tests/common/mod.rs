@@ -9,6 +9,7 @@ use reqwest::Client;
 use anki_webapp::auth::{AuthState, JwtManager, Claims};
 use anki_webapp::config::WebAppConfig;
 use anki_webapp::db::Database;
+use anki_webapp::jobs::JobManager;
 use anki_webapp::session::BackendManager;
 use anki_webapp::server::router::create_router;
 
@@ -23,11 +24,18 @@ pub struct TestContext {
     pub jwt_manager: Arc<JwtManager>,
     #[allow(dead_code)]
     pub backend_manager: Arc<BackendManager>,
+    #[allow(dead_code)]
+    pub job_manager: Arc<JobManager>,
     _temp_dir: TempDir,
 }
 
 impl TestContext {
     pub async fn new() -> Self {
+        Self::with_config(|_| {}).await
+    }
+
+    /// Start a server whose config is adjusted by `configure` first
+    pub async fn with_config(configure: impl FnOnce(&mut WebAppConfig)) -> Self {
         let temp_dir = TempDir::new().expect("Failed to create temp dir");
         let data_dir = temp_dir.path().to_path_buf();
         
@@ -37,7 +45,9 @@ impl TestContext {
             data_dir: data_dir.clone(),
             jwt_secret: "test-secret-must-be-long-enough-for-hs256-at-least-32-chars".to_string(),
             session_timeout_hours: 24,
+            ..WebAppConfig::default()
         };
+        configure(&mut config);
 
         let db_path = data_dir.join("webapp.db");
         let database = Arc::new(Database::open(&db_path).expect("Failed to open test database"));
@@ -45,11 +55,13 @@ impl TestContext {
 
         let jwt_manager = Arc::new(JwtManager::new(&config.jwt_secret));
         let backend_manager = Arc::new(BackendManager::new(data_dir.clone()));
+        let job_manager = Arc::new(JobManager::new());
 
         let auth_state = AuthState {
             database: database.clone(),
             jwt_manager: jwt_manager.clone(),
             backend_manager: backend_manager.clone(),
+            job_manager: job_manager.clone(),
         };
 
         let app_router = create_router(&config, auth_state);
@@ -60,7 +72,7 @@ impl TestContext {
         let base_url = format!("http://{}", addr);
 
         tokio::spawn(async move {
-            axum::serve(listener, app_router).await.expect("Server failed");
+            axum::serve(listener, app_router.into_make_service_with_connect_info::<std::net::SocketAddr>()).await.expect("Server failed");
         });
 
         let client = Client::builder()
@@ -75,6 +87,7 @@ impl TestContext {
             database,
             jwt_manager,
             backend_manager,
+            job_manager,
             _temp_dir: temp_dir,
         }
     }
@@ -0,0 +1,221 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use serde_json::json;
+mod common;
+use common::TestContext;
+
+#[tokio::test]
+async fn test_idempotency_and_preconditions() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notetypes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let notetype_id = body["notetypes"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["decks"][0]["id"].as_i64().unwrap();
+
+    let note = json!({
+        "deck_id": deck_id,
+        "notetype_id": notetype_id,
+        "fields": ["front", "back"],
+        "tags": []
+    });
+
+    // 2. A retried request with the same key is replayed, not repeated
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Idempotency-Key", "create-note-1")
+        .json(&note)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    assert!(resp.headers().get("idempotent-replayed").is_none());
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let note_id = body["note_id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Idempotency-Key", "create-note-1")
+        .json(&note)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    assert_eq!(resp.headers()["idempotent-replayed"], "true");
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["note_id"], note_id);
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/search/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "query": "" }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["note_ids"].as_array().unwrap().len(), 1);
+
+    // The same key with a different body is rejected
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Idempotency-Key", "create-note-1")
+        .json(&json!({
+            "deck_id": deck_id,
+            "notetype_id": notetype_id,
+            "fields": ["other", "back"],
+            "tags": []
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 422);
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Idempotency-Key", "not a valid key")
+        .json(&note)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // Keyed requests are buffered to fingerprint them, so they must be small
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Idempotency-Key", "create-note-2")
+        .json(&json!({
+            "deck_id": deck_id,
+            "notetype_id": notetype_id,
+            "fields": ["x".repeat(2 * 1024 * 1024), "back"],
+            "tags": []
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // 3. Notes carry an ETag; stale If-Match headers are rejected
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notes/{}", ctx.base_url, note_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let etag = resp.headers()["etag"].to_str().unwrap().to_string();
+
+    let resp = ctx.client
+        .put(format!("{}/api/v1/notes/{}", ctx.base_url, note_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("If-Match", &etag)
+        .json(&json!({ "fields": ["edited", "back"], "tags": [] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let new_etag = resp.headers()["etag"].to_str().unwrap().to_string();
+    assert_ne!(etag, new_etag);
+
+    // A second editor still holding the old tag
+    let resp = ctx.client
+        .put(format!("{}/api/v1/notes/{}", ctx.base_url, note_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("If-Match", &etag)
+        .json(&json!({ "fields": ["overwritten", "back"], "tags": [] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 412);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notes/{}", ctx.base_url, note_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.headers()["etag"].to_str().unwrap(), new_etag);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["fields"][0], "edited");
+
+    // 4. The same applies to cards
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notes/{}/cards", ctx.base_url, note_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let card_id = body["card_ids"][0].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/cards/{}", ctx.base_url, card_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let etag = resp.headers()["etag"].to_str().unwrap().to_string();
+
+    let resp = ctx.client
+        .put(format!("{}/api/v1/cards/{}", ctx.base_url, card_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("If-Match", &etag)
+        .json(&json!({ "flags": 1 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let resp = ctx.client
+        .put(format!("{}/api/v1/cards/{}", ctx.base_url, card_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("If-Match", &etag)
+        .json(&json!({ "flags": 2 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 412);
+}
@@ -77,12 +77,13 @@ async fn test_deck_crud() {
     assert_eq!(body["id"], new_deck_id);
     assert_eq!(body["name"], "New Test Deck");
 
-    // 5. Update the deck name
+    // 5. Update the deck name and collapse it
     let resp = ctx.client
         .put(format!("{}/api/v1/decks/{}", ctx.base_url, new_deck_id))
         .header("Authorization", format!("Bearer {}", token))
         .json(&json!({
-            "name": "Updated Deck Name"
+            "name": "Updated Deck Name",
+            "collapsed": true
         }))
         .send()
         .await
@@ -91,6 +92,7 @@ async fn test_deck_crud() {
     assert_eq!(resp.status(), 200);
     let body: serde_json::Value = resp.json().await.unwrap();
     assert!(body["success"].as_bool().unwrap());
+    assert_eq!(body["name"], "Updated Deck Name");
 
     // Verify rename
     let resp = ctx.client
@@ -103,6 +105,16 @@ async fn test_deck_crud() {
     let body: serde_json::Value = resp.json().await.unwrap();
     assert_eq!(body["name"], "Updated Deck Name");
 
+    // Both changes are a single undo step
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/undo-status", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["undo"], "Undo Update Deck");
+
     // 6. Delete the deck
     let resp = ctx.client
         .delete(format!("{}/api/v1/decks/{}", ctx.base_url, new_deck_id))
@@ -125,3 +137,172 @@ async fn test_deck_crud() {
 
     assert_eq!(resp.status(), 404);
 }
+
+#[tokio::test]
+async fn test_deck_tree_operations() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .expect("Failed to register");
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .expect("Failed to login");
+
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    // 2. Create two top-level decks and a note in one of them
+    let mut deck_ids = vec![];
+    for name in ["Parent", "Child"] {
+        let resp = ctx.client
+            .post(format!("{}/api/v1/decks", ctx.base_url))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({ "name": name }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        deck_ids.push(body["id"].as_i64().unwrap());
+    }
+    let (parent_id, child_id) = (deck_ids[0], deck_ids[1]);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notetypes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let notetype_id = body["notetypes"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "deck_id": child_id,
+            "notetype_id": notetype_id,
+            "fields": ["front", "back"],
+            "tags": []
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // 3. Move the child under the parent
+    let resp = ctx.client
+        .post(format!("{}/api/v1/decks/reparent", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "deck_ids": [child_id], "new_parent_id": parent_id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 1);
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/decks/reparent", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "deck_ids": [child_id], "new_parent_id": 12345 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    // 4. The tree reflects the move, with counts rolled up to the parent
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let parent = body["decks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|deck| deck["id"] == parent_id)
+        .unwrap();
+    assert_eq!(parent["children"][0]["id"], child_id);
+    assert_eq!(parent["children"][0]["level"], 2);
+    assert_eq!(parent["new_count"], 1);
+    assert_eq!(parent["total_cards"], 1);
+
+    // 5. Per-deck stats
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks/{}/stats", ctx.base_url, parent_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["cards_in_deck"], 0);
+    assert_eq!(body["cards_including_children"], 1);
+    assert_eq!(body["new_count"], 1);
+    assert_eq!(body["studied_today"]["review"], 0);
+
+    // 6. Renaming by path moves the deck back to the top level
+    let resp = ctx.client
+        .put(format!("{}/api/v1/decks/{}", ctx.base_url, child_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "name": "Renamed" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["name"], "Renamed");
+
+    // 7. Collapse state persists
+    let resp = ctx.client
+        .put(format!("{}/api/v1/decks/{}/collapse", ctx.base_url, parent_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "collapsed": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let parent = body["decks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|deck| deck["id"] == parent_id)
+        .unwrap();
+    assert_eq!(parent["collapsed"], true);
+    assert!(parent["children"].as_array().unwrap().is_empty());
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks/12345/stats", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
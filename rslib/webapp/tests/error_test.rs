@@ -0,0 +1,74 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use serde_json::json;
+mod common;
+use common::TestContext;
+
+#[tokio::test]
+async fn test_collection_error_mapping() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    // 2. A missing card is a 404, not an internal error
+    let resp = ctx.client
+        .get(format!("{}/api/v1/cards/12345", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["success"], false);
+    assert_eq!(body["error"]["code"], "not_found");
+    assert!(!body["error"]["message"].as_str().unwrap().is_empty());
+
+    // 3. An invalid search is a 400, whatever the requested language
+    for language in [None, Some("de-DE, en;q=0.5")] {
+        let mut request = ctx.client
+            .post(format!("{}/api/v1/search/notes", ctx.base_url))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({ "query": "()" }));
+        if let Some(language) = language {
+            request = request.header("Accept-Language", language);
+        }
+        let resp = request.send().await.unwrap();
+        assert_eq!(resp.status(), 400);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "search_error");
+        assert!(!body["error"]["message"].as_str().unwrap().is_empty());
+    }
+
+    // 4. Errors raised by the webapp itself carry a code too
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks/12345", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "not_found");
+}
@@ -0,0 +1,156 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use std::time::Duration;
+
+use serde_json::json;
+mod common;
+use common::TestContext;
+
+async fn wait_for_job(ctx: &TestContext, token: &str, job_id: &str) -> serde_json::Value {
+    for _ in 0..200 {
+        let resp = ctx.client
+            .get(format!("{}/api/v1/jobs/{}", ctx.base_url, job_id))
+            .header("Authorization", format!("Bearer {}", token))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        if body["status"] != "running" {
+            return body;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("job {} did not finish", job_id);
+}
+
+#[tokio::test]
+async fn test_fsrs_presets() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    // 2. List presets
+    let resp = ctx.client
+        .get(format!("{}/api/v1/fsrs/presets", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let preset = &body["presets"][0];
+    let preset_id = preset["id"].as_i64().unwrap();
+    assert_eq!(preset["name"], "Default");
+    assert_eq!(preset["search"], "preset:\"Default\" -is:suspended");
+
+    // 3. Optimizing without any reviews leaves the parameters as they are
+    let resp = ctx.client
+        .post(format!("{}/api/v1/fsrs/presets/{}/optimize", ctx.base_url, preset_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let job = wait_for_job(&ctx, token, body["job_id"].as_str().unwrap()).await;
+    assert_eq!(job["status"], "completed");
+    assert_eq!(job["result"]["fsrs_items"], 0);
+    assert_eq!(job["result"]["already_optimal"], true);
+
+    // 4. Invalid input is rejected before a job starts
+    let resp = ctx.client
+        .post(format!("{}/api/v1/fsrs/presets/{}/simulate", ctx.base_url, preset_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "retentions": [1.5] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    let resp = ctx.client
+        .put(format!("{}/api/v1/fsrs/presets/{}/params", ctx.base_url, preset_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "params": [0.5, 1.0, 2.0] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/fsrs/presets/999999/optimize", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    // FSRS 5 parameters can be evaluated, but presets only store FSRS 6 ones
+    let params: Vec<f32> = vec![
+        0.40255, 1.18385, 3.173, 15.69105, 7.1949, 0.5345, 1.4604, 0.0046, 1.54575, 0.1192,
+        1.01925, 1.9395, 0.11, 0.29605, 2.2698, 0.2315, 2.9898, 0.51655, 0.6621,
+    ];
+    let resp = ctx.client
+        .put(format!("{}/api/v1/fsrs/presets/{}/params", ctx.base_url, preset_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "params": params }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // 5. Save parameters and a new desired retention
+    let params: Vec<f32> = vec![
+        0.212, 1.2931, 2.3065, 8.2956, 6.4133, 0.8334, 3.0194, 0.001, 1.8722, 0.1666, 0.796,
+        1.4835, 0.0614, 0.2629, 1.6483, 0.6014, 1.8729, 0.5425, 0.0912, 0.0658, 0.1542,
+    ];
+    let resp = ctx.client
+        .put(format!("{}/api/v1/fsrs/presets/{}/params", ctx.base_url, preset_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "params": params, "desired_retention": 0.85 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let job = wait_for_job(&ctx, token, body["job_id"].as_str().unwrap()).await;
+    assert_eq!(job["status"], "completed");
+    assert_eq!(job["result"]["rescheduled"], false);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/fsrs/presets", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let preset = &body["presets"][0];
+    assert_eq!(preset["params"].as_array().unwrap().len(), 21);
+    assert!((preset["desired_retention"].as_f64().unwrap() - 0.85).abs() < 1e-6);
+}
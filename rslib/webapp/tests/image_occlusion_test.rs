@@ -0,0 +1,210 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use serde_json::json;
+mod common;
+use common::TestContext;
+use reqwest::multipart;
+
+#[tokio::test]
+async fn test_image_occlusion_notes() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["decks"][0]["id"].as_i64().unwrap();
+
+    // 2. Upload an image
+    let mut png = std::io::Cursor::new(Vec::new());
+    image::RgbImage::new(400, 200)
+        .write_to(&mut png, image::ImageFormat::Png)
+        .unwrap();
+    let form = multipart::Form::new()
+        .part("file", multipart::Part::bytes(png.into_inner()).file_name("heart.png"));
+    let resp = ctx.client
+        .post(format!("{}/api/v1/image-occlusion/images", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["width"], 400);
+    assert_eq!(body["height"], 200);
+    let image = body["filename"].as_str().unwrap().to_string();
+
+    let form = multipart::Form::new()
+        .part("file", multipart::Part::bytes(b"text".to_vec()).file_name("notes.txt"));
+    let resp = ctx.client
+        .post(format!("{}/api/v1/image-occlusion/images", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // 3. Create a note with two cards and a label
+    let resp = ctx.client
+        .post(format!("{}/api/v1/image-occlusion/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "image": image,
+            "deck_id": deck_id,
+            "occlusions": [
+                { "ordinal": 1, "shape": "rect", "left": 0.1, "top": 0.1, "width": 0.2, "height": 0.1 },
+                { "ordinal": 2, "shape": "ellipse", "left": 0.5, "top": 0.5, "rx": 0.1, "ry": 0.05 },
+                { "ordinal": 0, "shape": "text", "left": 0.7, "top": 0.1, "text": "Aorta" }
+            ],
+            "header": "Label the heart",
+            "tags": ["anatomy"]
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let note_id = body["note_id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notes/{}/cards", ctx.base_url, note_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["card_ids"].as_array().unwrap().len(), 2);
+
+    // 4. Fetch it for editing
+    let url = format!("{}/api/v1/image-occlusion/notes/{}", ctx.base_url, note_id);
+    let resp = ctx.client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["image"], image.as_str());
+    assert_eq!(body["header"], "Label the heart");
+    assert_eq!(body["occlude_inactive"], false);
+    let occlusions = body["occlusions"].as_array().unwrap();
+    assert_eq!(occlusions.len(), 3);
+    assert_eq!(occlusions[0]["shape"], "text");
+    assert_eq!(occlusions[0]["text"], "Aorta");
+    assert_eq!(occlusions[1]["shape"], "rect");
+    assert_eq!(occlusions[1]["width"], 0.2);
+    assert_eq!(occlusions[2]["ordinal"], 2);
+
+    // 5. Update the shapes, keeping the tags
+    let resp = ctx.client
+        .put(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "occlusions": [
+                { "ordinal": 1, "shape": "polygon", "points": [[0.1, 0.1], [0.3, 0.1], [0.2, 0.3]] }
+            ],
+            "occlude_inactive": true,
+            "header": "Find the valve"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let resp = ctx.client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["header"], "Find the valve");
+    assert_eq!(body["tags"], json!(["anatomy"]));
+    assert_eq!(body["occlude_inactive"], true);
+    let occlusions = body["occlusions"].as_array().unwrap();
+    assert_eq!(occlusions.len(), 1);
+    assert_eq!(occlusions[0]["left"], 0.1);
+    assert_eq!(occlusions[0]["points"][2], json!([0.2, 0.3]));
+
+    // 6. Invalid shapes are rejected
+    let resp = ctx.client
+        .put(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "occlusions": [{ "ordinal": 0, "shape": "rect", "left": 0.1, "top": 0.1, "width": 0.2, "height": 0.1 }]
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // 7. Other notes aren't image occlusion notes
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notetypes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let notetype_id = body["notetypes"][0]["id"].as_i64().unwrap();
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "deck_id": deck_id,
+            "notetype_id": notetype_id,
+            "fields": ["front", "back"],
+            "tags": []
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let basic_id = body["note_id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/image-occlusion/notes/{}", ctx.base_url, basic_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/image-occlusion/notes/1", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
@@ -0,0 +1,188 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use std::time::Duration;
+
+use serde_json::json;
+mod common;
+use common::TestContext;
+
+async fn wait_for_job(ctx: &TestContext, token: &str, job_id: &str) -> serde_json::Value {
+    for _ in 0..100 {
+        let resp = ctx.client
+            .get(format!("{}/api/v1/jobs/{}", ctx.base_url, job_id))
+            .header("Authorization", format!("Bearer {}", token))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        if body["status"] != "running" {
+            return body;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("job {} did not finish", job_id);
+}
+
+#[tokio::test]
+async fn test_collection_maintenance() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    // 2. Create two notes with the same front and one unique note
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notetypes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let notetype_id = body["notetypes"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notetypes/{}", ctx.base_url, notetype_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let first_field = body["fields"][0]["name"].as_str().unwrap().to_string();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["decks"][0]["id"].as_i64().unwrap();
+
+    let mut note_id = 0;
+    for front in ["<b>dog</b>", "dog", "cat"] {
+        let resp = ctx.client
+            .post(format!("{}/api/v1/notes", ctx.base_url))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({
+                "deck_id": deck_id,
+                "notetype_id": notetype_id,
+                "fields": [front, "back"],
+                "tags": []
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        note_id = body["note_id"].as_i64().unwrap();
+    }
+
+    // 3. Check database
+    let resp = ctx.client
+        .post(format!("{}/api/v1/collection/check-database", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let job = wait_for_job(&ctx, token, body["job_id"].as_str().unwrap()).await;
+    assert_eq!(job["status"], "completed");
+    assert!(job["result"]["problems"].is_array());
+
+    // 4. Empty cards report (nothing is empty)
+    let resp = ctx.client
+        .post(format!("{}/api/v1/collection/empty-cards", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let job = wait_for_job(&ctx, token, body["job_id"].as_str().unwrap()).await;
+    assert_eq!(job["status"], "completed");
+    assert_eq!(job["result"]["notes"].as_array().unwrap().len(), 0);
+
+    // Cards that aren't empty can't be deleted through the report
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notes/{}/cards", ctx.base_url, note_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let card_id = body["card_ids"][0].as_i64().unwrap();
+
+    let resp = ctx.client
+        .delete(format!("{}/api/v1/collection/empty-cards", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "card_ids": [card_id] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["removed_count"], 0);
+    assert_eq!(body["skipped_card_ids"], json!([card_id]));
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/cards/{}", ctx.base_url, card_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    // 5. Find duplicates on the first field
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes/find-duplicates", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "notetype_id": notetype_id,
+            "field_name": first_field
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let job = wait_for_job(&ctx, token, body["job_id"].as_str().unwrap()).await;
+    assert_eq!(job["status"], "completed");
+    let duplicates = job["result"]["duplicates"].as_array().unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0]["value"], "dog");
+    assert_eq!(duplicates[0]["note_ids"].as_array().unwrap().len(), 2);
+
+    // 6. Unknown jobs are not found
+    let resp = ctx.client
+        .get(format!("{}/api/v1/jobs/does-not-exist", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
@@ -1,6 +1,8 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
+use std::time::Duration;
+
 use serde_json::json;
 mod common;
 use common::TestContext;
@@ -98,4 +100,329 @@ async fn test_media_flow() {
         .unwrap();
     
     assert_eq!(resp.status(), 404);
+
+    // 7. LaTeX isn't compiled unless the server enables it
+    let resp = ctx.client
+        .post(format!("{}/api/v1/media/latex/render", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+}
+
+#[tokio::test]
+async fn test_media_streaming() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    // 2. Upload some audio
+    let audio: Vec<u8> = (0..1000).map(|i| (i % 256) as u8).collect();
+    let form = multipart::Form::new()
+        .part("file", multipart::Part::bytes(audio.clone()).file_name("clip.mp3"));
+    let resp = ctx.client
+        .post(format!("{}/api/v1/media", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let url = format!("{}/api/v1/media/{}", ctx.base_url, body["filename"].as_str().unwrap());
+
+    // 3. Full response with caching headers
+    let resp = ctx.client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "audio/mpeg");
+    assert_eq!(resp.headers()["accept-ranges"], "bytes");
+    assert!(resp.headers()["cache-control"].to_str().unwrap().contains("max-age"));
+    assert!(resp.headers().contains_key("last-modified"));
+    let etag = resp.headers()["etag"].to_str().unwrap().to_string();
+    // Derived from the file's SHA1
+    assert_eq!(etag.len(), 42);
+    assert_eq!(resp.bytes().await.unwrap().as_ref(), audio.as_slice());
+
+    // 4. Revalidation
+    let resp = ctx.client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 304);
+
+    // 5. Byte ranges
+    let resp = ctx.client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Range", "bytes=100-199")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 206);
+    assert_eq!(resp.headers()["content-range"], "bytes 100-199/1000");
+    assert_eq!(resp.bytes().await.unwrap().as_ref(), &audio[100..200]);
+
+    let resp = ctx.client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Range", "bytes=5000-")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 416);
+    assert_eq!(resp.headers()["content-range"], "bytes */1000");
+
+    // 6. Thumbnails
+    let mut png = std::io::Cursor::new(Vec::new());
+    image::RgbImage::new(400, 200)
+        .write_to(&mut png, image::ImageFormat::Png)
+        .unwrap();
+    let form = multipart::Form::new()
+        .part("file", multipart::Part::bytes(png.into_inner()).file_name("diagram.png"));
+    let resp = ctx.client
+        .post(format!("{}/api/v1/media", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let url = format!("{}/api/v1/media/{}", ctx.base_url, body["filename"].as_str().unwrap());
+
+    let resp = ctx.client
+        .get(format!("{}?width=100", url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "image/png");
+    assert!(resp.headers()["etag"].to_str().unwrap().ends_with("-100x2048\""));
+    let thumbnail = image::load_from_memory(&resp.bytes().await.unwrap()).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (100, 50));
+}
+
+#[tokio::test]
+async fn test_media_management() {
+    let ctx = TestContext::with_config(|config| config.latex_rendering = true).await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    // 2. Upload two files and reference one of them from a note
+    for name in ["used.jpg", "unused.jpg"] {
+        let form = multipart::Form::new()
+            .part("file", multipart::Part::bytes(name.as_bytes().to_vec()).file_name(name));
+        let resp = ctx.client
+            .post(format!("{}/api/v1/media", ctx.base_url))
+            .header("Authorization", format!("Bearer {}", token))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+    }
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notetypes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let notetype_id = body["notetypes"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["decks"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "deck_id": deck_id,
+            "notetype_id": notetype_id,
+            "fields": ["<img src=\"used.jpg\">", "[$]x^2[/$]"],
+            "tags": []
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let note_id = body["note_id"].as_i64().unwrap();
+
+    // 3. List files with their references
+    let resp = ctx.client
+        .get(format!("{}/api/v1/media/files", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["total"], 2);
+    let files = body["files"].as_array().unwrap();
+    assert_eq!(files[0]["filename"], "unused.jpg");
+    assert_eq!(files[0]["size"], 10);
+    assert!(files[0]["note_ids"].as_array().unwrap().is_empty());
+    assert_eq!(files[1]["filename"], "used.jpg");
+    assert_eq!(files[1]["note_ids"], json!([note_id]));
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/media/files?limit=1&offset=1", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["files"].as_array().unwrap().len(), 1);
+    assert_eq!(body["files"][0]["filename"], "used.jpg");
+
+    // 4. Trash the unused file and restore it
+    let resp = ctx.client
+        .delete(format!("{}/api/v1/media", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "filenames": ["unused.jpg"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/media/trash/restore", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 1);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/media/unused.jpg", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    // 5. Trash it again and empty the trash
+    ctx.client
+        .delete(format!("{}/api/v1/media", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "filenames": ["unused.jpg"] }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/media/trash/empty", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 1);
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/media/trash/restore", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 0);
+
+    // 6. Render the missing LaTeX. Whether it succeeds depends on LaTeX
+    // being installed, but either way the image is accounted for.
+    let resp = ctx.client
+        .post(format!("{}/api/v1/media/latex/render", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let job_id = body["job_id"].as_str().unwrap().to_string();
+
+    let mut job = serde_json::Value::Null;
+    for _ in 0..200 {
+        let resp = ctx.client
+            .get(format!("{}/api/v1/jobs/{}", ctx.base_url, job_id))
+            .header("Authorization", format!("Bearer {}", token))
+            .send()
+            .await
+            .unwrap();
+        job = resp.json().await.unwrap();
+        if job["status"] != "running" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(job["status"], "completed");
+    let rendered = job["result"]["rendered"].as_array().unwrap().len();
+    let errors = job["result"]["errors"].as_array().unwrap();
+    assert_eq!(rendered + errors.len(), 1);
+    if let Some(error) = errors.first() {
+        assert_eq!(error["note_id"], note_id);
+        assert!(error["filename"].as_str().unwrap().starts_with("latex-"));
+    }
 }
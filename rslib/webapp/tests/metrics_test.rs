@@ -0,0 +1,87 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use serde_json::json;
+mod common;
+use common::TestContext;
+
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    let ctx = TestContext::new().await;
+
+    // 1. A successful login and a rejected one
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+
+    // 2. Open the collection through a parameterised route
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["decks"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks/{}", ctx.base_url, deck_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    // 3. Scrape metrics without authentication
+    let resp = ctx.client
+        .get(format!("{}/metrics", ctx.base_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let text = resp.text().await.unwrap();
+
+    // Routes are labelled by pattern, not by the raw path
+    assert!(text.contains(
+        "anki_webapp_http_requests_total{method=\"GET\",route=\"/api/v1/decks/{id}\",status=\"200\"}"
+    ));
+    assert!(!text.contains(&format!("/api/v1/decks/{}\"", deck_id)));
+    assert!(text.contains(
+        "anki_webapp_http_requests_total{method=\"GET\",route=\"/api/v1/decks\",status=\"401\"}"
+    ));
+    assert!(text.contains("anki_webapp_http_request_duration_seconds_bucket"));
+    assert!(text.contains("anki_webapp_open_collections 1"));
+    assert!(text.contains("anki_webapp_collection_lock_wait_seconds_count"));
+    assert!(text.contains("anki_webapp_auth_failures_total"));
+}
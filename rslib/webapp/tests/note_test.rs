@@ -154,3 +154,155 @@ async fn test_note_crud() {
         .unwrap();
     assert_eq!(resp.status(), 404);
 }
+
+#[tokio::test]
+async fn test_bulk_notes() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notetypes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let notetype_id = body["notetypes"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["decks"][0]["id"].as_i64().unwrap();
+
+    let note = |front: &str, back: &str| {
+        json!({
+            "deck_id": deck_id,
+            "notetype_id": notetype_id,
+            "fields": [front, back],
+            "tags": ["vocab"]
+        })
+    };
+
+    // 2. Add a batch with a duplicate and an invalid row; duplicates are
+    // skipped by default
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes/bulk", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "notes": [
+                note("hola", "hello"),
+                note("gato", "cat"),
+                note("hola", "hi"),
+                { "deck_id": deck_id, "notetype_id": 1, "fields": ["x"], "tags": [] }
+            ]
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["added"], 2);
+    assert_eq!(body["skipped"], 1);
+    assert_eq!(body["invalid"], 1);
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results[0]["status"], "added");
+    let hola_id = results[0]["note_id"].as_i64().unwrap();
+    assert_eq!(results[2]["status"], "skipped");
+    assert_eq!(results[2]["note_id"], hola_id);
+    assert_eq!(results[3]["status"], "invalid");
+    assert!(results[3]["note_id"].is_null());
+
+    // 3. Update existing notes
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes/bulk", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "notes": [note("hola", "hi there"), note("perro", "dog")],
+            "duplicate_policy": "update_existing"
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["updated"], 1);
+    assert_eq!(body["added"], 1);
+    assert_eq!(body["results"][0]["note_id"], hola_id);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notes/{}", ctx.base_url, hola_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["fields"][1], "hi there");
+
+    // 4. The batch is a single undo step
+    let resp = ctx.client
+        .post(format!("{}/api/v1/scheduler/undo", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notes/{}", ctx.base_url, hola_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["fields"][1], "hello");
+
+    // 5. Allowing duplicates
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes/bulk", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "notes": [note("hola", "hey")],
+            "duplicate_policy": "allow"
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["added"], 1);
+    assert_ne!(body["results"][0]["note_id"], hola_id);
+
+    // 6. Empty requests are rejected
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes/bulk", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "notes": [] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+}
@@ -0,0 +1,937 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+//! Calls every operation in the served OpenAPI spec at least once, and checks
+//! that each status code and JSON body is one the spec documents.
+
+use std::collections::BTreeSet;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+
+use anki::import_export::package::ExportAnkiPackageOptions;
+use anki_webapp::openapi::openapi_spec;
+use anki_webapp::session::lock_collection;
+use reqwest::multipart;
+use reqwest::Method;
+use reqwest::RequestBuilder;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+mod common;
+use common::TestContext;
+
+/// A client that checks each exchange with the server against the spec
+struct Contract {
+    ctx: TestContext,
+    spec: Value,
+    /// `components/schemas` from the spec, converted to JSON Schema
+    schemas: Value,
+    token: Option<String>,
+    /// (method, path template) of each operation called so far
+    exercised: BTreeSet<(String, String)>,
+}
+
+impl Contract {
+    async fn new() -> Self {
+        let ctx = TestContext::with_config(|config| config.latex_rendering = true).await;
+        let resp = ctx
+            .client
+            .get(format!("{}/api-docs/openapi.json", ctx.base_url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let spec: Value = resp.json().await.unwrap();
+        let schemas = to_json_schema(&spec["components"]["schemas"]);
+
+        Self {
+            ctx,
+            spec,
+            schemas,
+            token: None,
+            exercised: BTreeSet::new(),
+        }
+    }
+
+    /// Send a request that is expected to succeed, and return its JSON body
+    /// (null for other content types)
+    async fn call(&mut self, method: Method, path: &str, body: Option<Value>) -> Value {
+        let (status, response) = self.send(method.clone(), path, body).await;
+        assert!(
+            (200..300).contains(&status),
+            "{method} {path} failed with {status}: {response}"
+        );
+        response
+    }
+
+    async fn send(&mut self, method: Method, path: &str, body: Option<Value>) -> (u16, Value) {
+        let mut request = self
+            .ctx
+            .client
+            .request(method.clone(), format!("{}{}", self.ctx.base_url, path));
+        if let Some(body) = &body {
+            request = request.json(body);
+        }
+        self.check(method, path, body.as_ref(), request).await
+    }
+
+    /// Upload a file as the `file` field of a multipart form
+    async fn upload(&mut self, path: &str, filename: &str, bytes: Vec<u8>) -> Value {
+        let form = multipart::Form::new().part(
+            "file",
+            multipart::Part::bytes(bytes).file_name(filename.to_string()),
+        );
+        let request = self
+            .ctx
+            .client
+            .post(format!("{}{}", self.ctx.base_url, path))
+            .multipart(form);
+        let (status, response) = self.check(Method::POST, path, None, request).await;
+        assert!(
+            (200..300).contains(&status),
+            "POST {path} failed with {status}: {response}"
+        );
+        response
+    }
+
+    async fn check(
+        &mut self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+        mut request: RequestBuilder,
+    ) -> (u16, Value) {
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let method_name = method.as_str().to_lowercase();
+        let template = self.template_for(&method_name, path);
+        let operation = self.spec["paths"][&template][&method_name].clone();
+
+        if let Some(body) = body {
+            let schema = &operation["requestBody"]["content"]["application/json"]["schema"];
+            assert!(
+                !schema.is_null(),
+                "{method} {template} documents no JSON request body"
+            );
+            self.assert_matches(schema, body, &format!("{method} {template} request"));
+        }
+
+        let resp = request.send().await.unwrap();
+        let status = resp.status().as_u16();
+        let is_json = resp
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+        let response_body = if is_json {
+            resp.json().await.unwrap()
+        } else {
+            resp.bytes().await.unwrap();
+            Value::Null
+        };
+
+        let mut response = operation["responses"][status.to_string()].clone();
+        assert!(
+            response.is_object(),
+            "{method} {template} returned undocumented status {status}: {response_body}"
+        );
+        if let Some(reference) = response["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/components/responses/");
+            response = self.spec["components"]["responses"][name].clone();
+        }
+        let schema = &response["content"]["application/json"]["schema"];
+        if !schema.is_null() {
+            assert!(is_json, "{method} {template} did not return JSON");
+            self.assert_matches(
+                schema,
+                &response_body,
+                &format!("{method} {template} {status} response"),
+            );
+        }
+
+        self.exercised.insert((method_name, template));
+        (status, response_body)
+    }
+
+    /// The spec path matching `path`, preferring literal segments over
+    /// parameters, so `/media/files` isn't taken for `/media/{filename}`
+    fn template_for(&self, method: &str, path: &str) -> String {
+        let path = path.split('?').next().unwrap();
+        let segments: Vec<&str> = path.split('/').collect();
+        self.spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .filter(|(_, item)| item.get(method).is_some())
+            .filter_map(|(template, _)| {
+                let parts: Vec<&str> = template.split('/').collect();
+                if parts.len() != segments.len() {
+                    return None;
+                }
+                let mut literal = 0;
+                for (part, segment) in parts.iter().zip(&segments) {
+                    if part.starts_with('{') {
+                        continue;
+                    }
+                    if part != segment {
+                        return None;
+                    }
+                    literal += 1;
+                }
+                Some((literal, template.clone()))
+            })
+            .max()
+            .map(|(_, template)| template)
+            .unwrap_or_else(|| panic!("{method} {path} is not documented"))
+    }
+
+    fn assert_matches(&self, schema: &Value, instance: &Value, context: &str) {
+        let document = json!({
+            "allOf": [to_json_schema(schema)],
+            "components": { "schemas": self.schemas },
+        });
+        let validator = jsonschema::validator_for(&document)
+            .unwrap_or_else(|e| panic!("{context} has an invalid schema: {e}"));
+        let errors: Vec<String> = validator
+            .iter_errors(instance)
+            .map(|e| format!("{e} at {}", e.instance_path))
+            .collect();
+        assert!(
+            errors.is_empty(),
+            "{context} does not match the spec:\n{}\n{instance:#}",
+            errors.join("\n")
+        );
+    }
+
+    async fn wait_for_job(&mut self, job_id: &str) -> Value {
+        for _ in 0..600 {
+            let job = self
+                .call(Method::GET, &format!("/api/v1/jobs/{job_id}"), None)
+                .await;
+            if job["status"] != "running" {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("job {job_id} did not finish");
+    }
+
+    /// Start a background job and wait for it to finish. Jobs that fail
+    /// on a collection this small still report their result in the
+    /// documented shape.
+    async fn run_job(&mut self, method: Method, path: &str, body: Option<Value>) -> Value {
+        let started = self.call(method, path, body).await;
+        self.wait_for_job(started["job_id"].as_str().unwrap()).await
+    }
+
+    fn assert_all_exercised(&self) {
+        let missing: Vec<String> = self.spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(template, item)| {
+                item.as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (method.clone(), template.clone()))
+            })
+            .filter(|operation| !self.exercised.contains(operation))
+            .map(|(method, template)| format!("{} {template}", method.to_uppercase()))
+            .collect();
+        assert!(
+            missing.is_empty(),
+            "operations not covered:\n{}",
+            missing.join("\n")
+        );
+    }
+}
+
+/// Convert an OpenAPI 3.0 schema into JSON Schema. The main difference is
+/// that OpenAPI marks optional values with `nullable` instead of a null type.
+fn to_json_schema(schema: &Value) -> Value {
+    match schema {
+        Value::Object(object) => {
+            let mut converted: Map<String, Value> = object
+                .iter()
+                .filter(|(key, _)| *key != "nullable")
+                .map(|(key, value)| (key.clone(), to_json_schema(value)))
+                .collect();
+            if object.get("nullable") == Some(&Value::Bool(true)) {
+                let Some(Value::String(ty)) = converted.get("type").cloned() else {
+                    return json!({ "anyOf": [converted, { "type": "null" }] });
+                };
+                converted.insert("type".to_string(), json!([ty, "null"]));
+                if let Some(Value::Array(values)) = converted.get_mut("enum") {
+                    values.push(Value::Null);
+                }
+            }
+            Value::Object(converted)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(to_json_schema).collect()),
+        other => other.clone(),
+    }
+}
+
+fn png() -> Vec<u8> {
+    let mut png = Cursor::new(Vec::new());
+    image::RgbImage::new(400, 200)
+        .write_to(&mut png, image::ImageFormat::Png)
+        .unwrap();
+    png.into_inner()
+}
+
+fn rect(ordinal: u32) -> Value {
+    json!({
+        "ordinal": ordinal,
+        "shape": "rect",
+        "left": 0.1,
+        "top": 0.1,
+        "width": 0.2,
+        "height": 0.1,
+    })
+}
+
+#[tokio::test]
+async fn test_spec_references_resolve() {
+    let api = Contract::new().await;
+
+    fn check(spec: &Value, value: &Value) {
+        match value {
+            Value::Object(object) => {
+                if let Some(Value::String(reference)) = object.get("$ref") {
+                    let pointer = reference.trim_start_matches('#');
+                    assert!(
+                        spec.pointer(pointer).is_some(),
+                        "unresolved reference {reference}"
+                    );
+                }
+                object.values().for_each(|value| check(spec, value));
+            }
+            Value::Array(items) => items.iter().for_each(|value| check(spec, value)),
+            _ => {}
+        }
+    }
+    check(&api.spec, &api.spec);
+}
+
+/// Routes that serve the docs themselves, or the web UI, rather than the API
+const UNDOCUMENTED_ROUTES: &[&str] =
+    &["/", "/api-docs/openapi.json", "/swagger-ui", "/swagger-ui/"];
+
+/// The (method, path) of each route registered in the router. axum can't
+/// list a router's routes, so this reads the `.route(..)` calls in its
+/// source.
+fn router_operations() -> BTreeSet<(String, String)> {
+    let source = include_str!("../src/server/router.rs");
+    let mut operations = BTreeSet::new();
+    for (start, _) in source.match_indices(".route(") {
+        // The call's arguments, up to its closing parenthesis
+        let args = &source[start + ".route(".len()..];
+        let mut depth = 1;
+        let end = args
+            .find(|c| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .unwrap();
+        let args = &args[..end];
+        let path = args.split('"').nth(1).unwrap();
+        let handlers = &args[args.find(',').unwrap()..];
+        for method in ["get", "post", "put", "delete", "patch"] {
+            let call = format!("{method}(");
+            let registered = handlers.match_indices(&call).any(|(index, _)| {
+                !handlers[..index].ends_with(|c: char| c.is_alphanumeric() || c == '_')
+            });
+            if registered {
+                operations.insert((method.to_string(), path.to_string()));
+            }
+        }
+    }
+    operations
+}
+
+#[test]
+fn test_every_route_is_documented() {
+    let spec = openapi_spec();
+    let undocumented: Vec<String> = router_operations()
+        .into_iter()
+        .filter(|(method, path)| {
+            !UNDOCUMENTED_ROUTES.contains(&path.as_str()) && spec["paths"][path][method].is_null()
+        })
+        .map(|(method, path)| format!("{} {path}", method.to_uppercase()))
+        .collect();
+    assert!(
+        undocumented.is_empty(),
+        "routes missing from the spec:\n{}",
+        undocumented.join("\n")
+    );
+}
+
+/// The client crate is generated from a checked-in copy of the spec, so it
+/// can be built without the server. Set `UPDATE_OPENAPI_SPEC=1` to rewrite
+/// it after changing the API.
+#[test]
+fn test_client_spec_is_current() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../webapp-client/openapi.json");
+    let spec = serde_json::to_string_pretty(&openapi_spec()).unwrap() + "\n";
+    if std::env::var_os("UPDATE_OPENAPI_SPEC").is_some() {
+        std::fs::write(&path, spec).unwrap();
+        return;
+    }
+    let checked_in = std::fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        checked_in == spec,
+        "{} is out of date; rerun this test with UPDATE_OPENAPI_SPEC=1",
+        path.display()
+    );
+}
+
+#[tokio::test]
+async fn test_every_operation_matches_spec() {
+    let mut api = Contract::new().await;
+
+    // Public endpoints
+    api.call(Method::GET, "/health", None).await;
+    api.call(Method::GET, "/metrics", None).await;
+    api.call(Method::GET, "/api/v1/info", None).await;
+
+    // Auth
+    let credentials = json!({ "username": "contract", "password": "password123" });
+    let registered = api
+        .call(
+            Method::POST,
+            "/api/v1/auth/register",
+            Some(credentials.clone()),
+        )
+        .await;
+    api.token = Some(registered["data"]["token"].as_str().unwrap().to_string());
+    let logged_in = api
+        .call(Method::POST, "/api/v1/auth/login", Some(credentials))
+        .await;
+    api.token = Some(logged_in["data"]["token"].as_str().unwrap().to_string());
+    let user_id = logged_in["data"]["user"]["id"].as_i64().unwrap();
+    api.call(Method::GET, "/api/v1/auth/me", None).await;
+    api.call(Method::GET, "/api/v1/auth/profile", None).await;
+
+    // Collection
+    api.call(Method::GET, "/api/v1/collection", None).await;
+    api.call(Method::GET, "/api/v1/collection/info", None).await;
+    api.call(Method::GET, "/api/v1/collections", None).await;
+    api.call(
+        Method::POST,
+        "/api/v1/collections",
+        Some(json!({ "name": "Contract" })),
+    )
+    .await;
+    api.call(
+        Method::DELETE,
+        &format!("/api/v1/collections/user_{user_id}"),
+        None,
+    )
+    .await;
+
+    // Decks
+    api.call(Method::GET, "/api/v1/decks", None).await;
+    let created = api
+        .call(
+            Method::POST,
+            "/api/v1/decks",
+            Some(json!({ "name": "Contract" })),
+        )
+        .await;
+    let deck_id = created["id"].as_i64().unwrap();
+    let deck = format!("/api/v1/decks/{deck_id}");
+    api.call(Method::GET, &deck, None).await;
+    api.call(
+        Method::PUT,
+        &deck,
+        Some(json!({ "name": "Contract Deck", "collapsed": false })),
+    )
+    .await;
+    api.call(
+        Method::PUT,
+        &format!("{deck}/collapse"),
+        Some(json!({ "collapsed": true, "scope": "browser" })),
+    )
+    .await;
+    api.call(Method::GET, &format!("{deck}/stats"), None).await;
+    let child = api
+        .call(
+            Method::POST,
+            "/api/v1/decks",
+            Some(json!({ "name": "Contract Deck::Child" })),
+        )
+        .await;
+    let child_id = child["id"].as_i64().unwrap();
+    api.call(
+        Method::POST,
+        "/api/v1/decks/reparent",
+        Some(json!({ "deck_ids": [child_id], "new_parent_id": null })),
+    )
+    .await;
+    api.call(Method::DELETE, &format!("/api/v1/decks/{child_id}"), None)
+        .await;
+
+    // Notetypes
+    let notetypes = api.call(Method::GET, "/api/v1/notetypes", None).await;
+    let basic_id = notetypes["notetypes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|notetype| notetype["name"] == "Basic")
+        .unwrap()["id"]
+        .as_i64()
+        .unwrap();
+    api.call(Method::GET, &format!("/api/v1/notetypes/{basic_id}"), None)
+        .await;
+
+    // Notes
+    let note = |front: &str| {
+        json!({
+            "deck_id": deck_id,
+            "notetype_id": basic_id,
+            "fields": [front, "back"],
+            "tags": ["contract::one"],
+        })
+    };
+    let created = api
+        .call(Method::POST, "/api/v1/notes", Some(note("contract one")))
+        .await;
+    let note_id = created["note_id"].as_i64().unwrap();
+    let bulk = api
+        .call(
+            Method::POST,
+            "/api/v1/notes/bulk",
+            Some(json!({
+                "notes": [note("two"), note("three"), note("four"), note("five")],
+                "duplicate_policy": "allow",
+            })),
+        )
+        .await;
+    let spare_notes: Vec<i64> = bulk["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["note_id"].as_i64().unwrap())
+        .collect();
+    api.call(
+        Method::POST,
+        "/api/v1/notes/check-fields",
+        Some(json!({ "notetype_id": basic_id, "fields": ["contract one", ""] })),
+    )
+    .await;
+    let note_path = format!("/api/v1/notes/{note_id}");
+    api.call(Method::GET, &note_path, None).await;
+    api.call(
+        Method::PUT,
+        &note_path,
+        Some(json!({ "fields": ["contract one", "updated"], "tags": ["contract::one"] })),
+    )
+    .await;
+    let mut card_ids = Vec::new();
+    for id in std::iter::once(note_id).chain(spare_notes.iter().copied()) {
+        let cards = api
+            .call(Method::GET, &format!("/api/v1/notes/{id}/cards"), None)
+            .await;
+        card_ids.push(cards["card_ids"][0].as_i64().unwrap());
+    }
+    let card_id = card_ids[0];
+    api.run_job(
+        Method::POST,
+        "/api/v1/notes/find-duplicates",
+        Some(json!({ "notetype_id": basic_id, "field_name": "Front" })),
+    )
+    .await;
+
+    // Cards
+    let card = format!("/api/v1/cards/{card_id}");
+    api.call(Method::GET, &card, None).await;
+    api.call(Method::PUT, &card, Some(json!({ "flags": 1 })))
+        .await;
+    api.call(
+        Method::POST,
+        &format!("{card}/flag"),
+        Some(json!({ "flag": 2 })),
+    )
+    .await;
+    api.call(Method::POST, &format!("{card}/suspend"), None)
+        .await;
+    api.call(Method::POST, &format!("{card}/bury"), None).await;
+    api.call(Method::POST, &format!("{card}/unsuspend"), None)
+        .await;
+    api.call(
+        Method::POST,
+        "/api/v1/cards/batch",
+        Some(json!({ "card_ids": [card_ids[0], card_ids[1]] })),
+    )
+    .await;
+    api.call(
+        Method::POST,
+        "/api/v1/cards/batch-update",
+        Some(json!({ "updates": [{ "card_id": card_id, "flags": 0 }] })),
+    )
+    .await;
+
+    // Search and browse
+    api.call(
+        Method::POST,
+        "/api/v1/search/cards",
+        Some(json!({ "query": "contract", "limit": 2, "fields": ["due", "flags"] })),
+    )
+    .await;
+    api.call(
+        Method::POST,
+        "/api/v1/search/notes",
+        Some(json!({ "query": "contract", "fields": ["tags"] })),
+    )
+    .await;
+    api.call(
+        Method::POST,
+        "/api/v1/search/find-replace",
+        Some(json!({ "note_ids": [note_id], "search": "updated", "replacement": "replaced" })),
+    )
+    .await;
+    api.call(
+        Method::POST,
+        "/api/v1/browse/cards",
+        Some(json!({ "ids": [card_id] })),
+    )
+    .await;
+    api.call(
+        Method::POST,
+        "/api/v1/browse/notes",
+        Some(json!({ "ids": [note_id] })),
+    )
+    .await;
+
+    // Scheduler
+    let scheduler = format!("/api/v1/scheduler/decks/{deck_id}");
+    api.call(Method::GET, &format!("{scheduler}/counts"), None)
+        .await;
+    api.call(Method::GET, &format!("{scheduler}/congrats"), None)
+        .await;
+    api.call(Method::GET, &format!("{scheduler}/queue?limit=5"), None)
+        .await;
+    let next = api
+        .call(Method::GET, &format!("{scheduler}/next"), None)
+        .await;
+    let head = next["card"]["card_id"].as_i64().unwrap();
+    api.call(
+        Method::GET,
+        &format!("{scheduler}/cards/{head}/next-states"),
+        None,
+    )
+    .await;
+    api.call(
+        Method::POST,
+        &format!("{scheduler}/cards/{head}/answer"),
+        Some(json!({ "rating": 2 })),
+    )
+    .await;
+    api.call(Method::GET, "/api/v1/scheduler/undo-status", None)
+        .await;
+    api.call(Method::POST, "/api/v1/scheduler/undo", None).await;
+    api.call(Method::POST, "/api/v1/scheduler/redo", None).await;
+    let other = card_ids[1];
+    let states = api
+        .call(
+            Method::GET,
+            &format!("{scheduler}/cards/{other}/next-states"),
+            None,
+        )
+        .await;
+    api.call(
+        Method::POST,
+        &format!("{scheduler}/cards/{other}/answer-with-states"),
+        Some(json!({ "rating": 2, "states": states["states"] })),
+    )
+    .await;
+    let batched = card_ids[2];
+    let states = api
+        .call(
+            Method::GET,
+            &format!("{scheduler}/cards/{batched}/next-states"),
+            None,
+        )
+        .await;
+    api.call(
+        Method::POST,
+        &format!("{scheduler}/answers"),
+        Some(json!({
+            "answers": [{ "card_id": batched, "rating": 3, "states": states["states"] }],
+        })),
+    )
+    .await;
+
+    // Stats
+    api.call(Method::GET, &format!("/api/v1/stats/card/{card_id}"), None)
+        .await;
+    api.call(Method::GET, "/api/v1/stats/collection", None)
+        .await;
+    api.call(Method::GET, "/api/v1/stats/today", None).await;
+    let (status, _) = api.send(Method::GET, "/api/v1/stats/graphs", None).await;
+    assert_eq!(status, 501);
+
+    // Tags
+    api.call(Method::GET, "/api/v1/tags", None).await;
+    api.call(Method::GET, "/api/v1/tags/tree", None).await;
+    api.call(Method::GET, "/api/v1/tags/complete?input=contr", None)
+        .await;
+    api.call(
+        Method::PUT,
+        "/api/v1/tags/collapse",
+        Some(json!({ "tag": "contract::one", "collapsed": true })),
+    )
+    .await;
+    api.call(
+        Method::PUT,
+        "/api/v1/tags/rename",
+        Some(json!({ "old_name": "contract::one", "new_name": "contract::two" })),
+    )
+    .await;
+    api.call(
+        Method::POST,
+        "/api/v1/tags/find-replace",
+        Some(json!({ "note_ids": [note_id], "find": "two", "replacement": "three" })),
+    )
+    .await;
+    api.call(
+        Method::POST,
+        "/api/v1/tags/reparent",
+        Some(json!({ "tags": ["contract"], "new_parent": "moved" })),
+    )
+    .await;
+    api.call(
+        Method::POST,
+        "/api/v1/tags/notes/add",
+        Some(json!({ "note_ids": [note_id], "tags": "extra" })),
+    )
+    .await;
+    api.call(
+        Method::POST,
+        "/api/v1/tags/notes/remove",
+        Some(json!({ "search": "tag:extra", "tags": "extra" })),
+    )
+    .await;
+    api.call(Method::DELETE, "/api/v1/tags/moved", None).await;
+    api.call(Method::POST, "/api/v1/tags/clear-unused", None)
+        .await;
+
+    // Media
+    let added = api.upload("/api/v1/media", "contract.png", png()).await;
+    let filename = added["filename"].as_str().unwrap().to_string();
+    api.call(Method::GET, &format!("/api/v1/media/{filename}"), None)
+        .await;
+    api.call(Method::GET, "/api/v1/media/files?limit=10", None)
+        .await;
+    api.call(Method::GET, "/api/v1/media/check", None).await;
+    api.call(
+        Method::DELETE,
+        "/api/v1/media",
+        Some(json!({ "filenames": [filename] })),
+    )
+    .await;
+    api.call(Method::POST, "/api/v1/media/trash/restore", None)
+        .await;
+    api.call(Method::POST, "/api/v1/media/trash/empty", None)
+        .await;
+    api.run_job(Method::POST, "/api/v1/media/latex/render", None)
+        .await;
+
+    // Image occlusion
+    let image = api
+        .upload("/api/v1/image-occlusion/images", "occlusion.png", png())
+        .await;
+    let created = api
+        .call(
+            Method::POST,
+            "/api/v1/image-occlusion/notes",
+            Some(json!({
+                "image": image["filename"],
+                "deck_id": deck_id,
+                "occlusions": [rect(1)],
+            })),
+        )
+        .await;
+    let occlusion_note = format!(
+        "/api/v1/image-occlusion/notes/{}",
+        created["note_id"].as_i64().unwrap()
+    );
+    api.call(Method::GET, &occlusion_note, None).await;
+    api.call(
+        Method::PUT,
+        &occlusion_note,
+        Some(json!({ "occlusions": [rect(1), rect(2)], "header": "Updated" })),
+    )
+    .await;
+
+    // Import a package exported from the collection itself
+    let export_dir = tempfile::TempDir::new().unwrap();
+    let apkg = export_dir.path().join("contract.apkg");
+    {
+        let backend = api
+            .ctx
+            .backend_manager
+            .get_or_create_backend(user_id, "contract")
+            .unwrap();
+        lock_collection(&backend)
+            .export_apkg(
+                &apkg,
+                ExportAnkiPackageOptions::default(),
+                "\"deck:Contract Deck\"",
+                None,
+            )
+            .unwrap();
+    }
+    api.upload(
+        "/api/v1/import/apkg",
+        "contract.apkg",
+        std::fs::read(&apkg).unwrap(),
+    )
+    .await;
+
+    // Maintenance
+    api.run_job(Method::POST, "/api/v1/collection/check-database", None)
+        .await;
+    api.run_job(Method::POST, "/api/v1/collection/empty-cards", None)
+        .await;
+    api.call(
+        Method::DELETE,
+        "/api/v1/collection/empty-cards",
+        Some(json!({ "card_ids": [card_ids[4]] })),
+    )
+    .await;
+    api.call(
+        Method::DELETE,
+        &format!("/api/v1/cards/{}", card_ids[3]),
+        None,
+    )
+    .await;
+    api.call(
+        Method::DELETE,
+        &format!("/api/v1/notes/{}", spare_notes[1]),
+        None,
+    )
+    .await;
+
+    // Preferences and config
+    api.call(Method::GET, "/api/v1/preferences", None).await;
+    api.call(
+        Method::PUT,
+        "/api/v1/preferences",
+        Some(json!({ "scheduling": { "rollover": 4 } })),
+    )
+    .await;
+    api.call(Method::GET, "/api/v1/config", None).await;
+    api.call(Method::GET, "/api/v1/config/applyAllParentLimits", None)
+        .await;
+    api.call(
+        Method::PUT,
+        "/api/v1/config/applyAllParentLimits",
+        Some(json!({ "value": true })),
+    )
+    .await;
+
+    // FSRS
+    let presets = api.call(Method::GET, "/api/v1/fsrs/presets", None).await;
+    let preset = format!(
+        "/api/v1/fsrs/presets/{}",
+        presets["presets"][0]["id"].as_i64().unwrap()
+    );
+    api.run_job(Method::POST, &format!("{preset}/optimize"), Some(json!({})))
+        .await;
+    api.run_job(Method::POST, &format!("{preset}/evaluate"), Some(json!({})))
+        .await;
+    api.run_job(
+        Method::POST,
+        &format!("{preset}/simulate"),
+        Some(json!({ "retentions": [0.9], "days_to_simulate": 10 })),
+    )
+    .await;
+    api.run_job(
+        Method::POST,
+        &format!("{preset}/optimal-retention"),
+        Some(json!({ "days_to_simulate": 10 })),
+    )
+    .await;
+    let params = vec![
+        0.4, 0.6, 2.4, 5.8, 4.93, 0.94, 0.86, 0.01, 1.49, 0.14, 0.94, 2.18, 0.05, 0.34, 1.26, 0.29,
+        2.61, 0.0, 0.0,
+    ];
+    api.run_job(
+        Method::PUT,
+        &format!("{preset}/params"),
+        Some(json!({ "params": params, "desired_retention": 0.85 })),
+    )
+    .await;
+
+    // Sharing, with a second user subscribing
+    let published = api
+        .call(
+            Method::POST,
+            "/api/v1/shared-decks",
+            Some(json!({ "deck_id": deck_id, "description": "Contract" })),
+        )
+        .await;
+    let shared = format!("/api/v1/shared-decks/{}", published["id"]);
+    api.call(
+        Method::POST,
+        "/api/v1/shared-decks",
+        Some(json!({ "deck_id": deck_id })),
+    )
+    .await;
+    api.call(Method::GET, "/api/v1/shared-decks", None).await;
+    let owner_token = api.token.take();
+    let subscriber = api
+        .call(
+            Method::POST,
+            "/api/v1/auth/register",
+            Some(json!({ "username": "subscriber", "password": "password123" })),
+        )
+        .await;
+    api.token = Some(subscriber["data"]["token"].as_str().unwrap().to_string());
+    api.call(Method::POST, &format!("{shared}/subscription"), None)
+        .await;
+    api.call(Method::GET, "/api/v1/shared-decks/subscriptions", None)
+        .await;
+    api.call(Method::POST, &format!("{shared}/update"), None)
+        .await;
+    api.call(Method::DELETE, &format!("{shared}/subscription"), None)
+        .await;
+    api.token = owner_token;
+    api.call(Method::DELETE, &shared, None).await;
+
+    // Jobs and audit
+    let jobs = api.call(Method::GET, "/api/v1/jobs", None).await;
+    assert!(!jobs["jobs"].as_array().unwrap().is_empty());
+    api.call(Method::GET, "/api/v1/audit", None).await;
+
+    // Backups
+    api.run_job(Method::POST, "/api/v1/collection/backups", None)
+        .await;
+    let backups = api
+        .call(Method::GET, "/api/v1/collection/backups", None)
+        .await;
+    let backup = backups["backups"][0]["filename"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    api.run_job(
+        Method::POST,
+        &format!("/api/v1/collection/backups/{backup}/restore"),
+        None,
+    )
+    .await;
+
+    api.call(Method::POST, "/api/v1/collection/close", None)
+        .await;
+    api.call(Method::POST, "/api/v1/auth/logout", None).await;
+
+    api.assert_all_exercised();
+}
@@ -0,0 +1,154 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use serde_json::json;
+mod common;
+use common::TestContext;
+
+#[tokio::test]
+async fn test_preferences_and_config() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    // 2. Get preferences
+    let resp = ctx.client
+        .get(format!("{}/api/v1/preferences", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["scheduling"]["rollover"], 4);
+    assert_eq!(body["reviewing"]["show_intervals_on_buttons"], true);
+    let learn_ahead_secs = body["scheduling"]["learn_ahead_secs"].clone();
+
+    // 3. Change the rollover hour; other fields are untouched
+    let resp = ctx.client
+        .put(format!("{}/api/v1/preferences", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "scheduling": { "rollover": 2, "new_review_mix": "reviews_first" },
+            "reviewing": { "show_intervals_on_buttons": false }
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["scheduling"]["rollover"], 2);
+    assert_eq!(body["scheduling"]["new_review_mix"], "reviews_first");
+    assert_eq!(body["scheduling"]["learn_ahead_secs"], learn_ahead_secs);
+    assert_eq!(body["reviewing"]["show_intervals_on_buttons"], false);
+
+    let resp = ctx.client
+        .put(format!("{}/api/v1/preferences", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "scheduling": { "rollover": 24 } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // 4. Undo restores the previous preferences
+    let resp = ctx.client
+        .post(format!("{}/api/v1/scheduler/undo", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/preferences", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["scheduling"]["rollover"], 4);
+    assert_eq!(body["reviewing"]["show_intervals_on_buttons"], true);
+
+    // 5. Config keys
+    let resp = ctx.client
+        .get(format!("{}/api/v1/config", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let entry = body["config"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["key"] == "applyAllParentLimits")
+        .unwrap();
+    assert_eq!(entry["type"], "bool");
+    assert_eq!(entry["value"], false);
+
+    let resp = ctx.client
+        .put(format!("{}/api/v1/config/applyAllParentLimits", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "value": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["value"], true);
+
+    let resp = ctx.client
+        .put(format!("{}/api/v1/config/applyAllParentLimits", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "value": "yes" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    let resp = ctx.client
+        .put(format!("{}/api/v1/config/setDueReviewer", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "value": "3-7" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["type"], "string");
+    assert_eq!(body["value"], "3-7");
+
+    // Keys outside the whitelist are not exposed
+    let resp = ctx.client
+        .get(format!("{}/api/v1/config/cardStateCustomizer", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
@@ -1,6 +1,8 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
+use anki::prelude::CardId;
+use anki_webapp::session::lock_collection;
 use serde_json::json;
 mod common;
 use common::TestContext;
@@ -125,3 +127,487 @@ async fn test_scheduler_flow() {
     assert_eq!(body["new"], 0);
     assert_eq!(body["learning"], 1);
 }
+
+#[tokio::test]
+async fn test_answer_with_states() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    // 2. Two notes in the default deck, and an unrelated deck
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["decks"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notetypes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let notetype_id = body["notetypes"][0]["id"].as_i64().unwrap();
+
+    let mut note_ids = Vec::new();
+    for front in ["First", "Second"] {
+        let resp = ctx.client
+            .post(format!("{}/api/v1/notes", ctx.base_url))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({
+                "deck_id": deck_id,
+                "notetype_id": notetype_id,
+                "fields": [front, "Answer"],
+                "tags": []
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        note_ids.push(body["note_id"].as_i64().unwrap());
+    }
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "name": "Other" }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let other_deck_id = body["id"].as_i64().unwrap();
+
+    // 3. The second card is not at the head of the queue
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notes/{}/cards", ctx.base_url, note_ids[1]))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let card_id = body["card_ids"][0].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/decks/{}/cards/{}/next-states", ctx.base_url, deck_id, card_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let states = body["states"].clone();
+    assert_eq!(states["current"]["kind"], "new");
+    assert_eq!(states["good"]["kind"], "learning");
+
+    // 4. Cards outside the deck are not found
+    let resp = ctx.client
+        .post(format!("{}/api/v1/scheduler/decks/{}/cards/{}/answer-with-states", ctx.base_url, other_deck_id, card_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "rating": 2, "states": states }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    // 5. custom_data must be an object
+    let resp = ctx.client
+        .post(format!("{}/api/v1/scheduler/decks/{}/cards/{}/answer-with-states", ctx.base_url, deck_id, card_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "rating": 2, "states": states, "custom_data": [1] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // 6. Answer with the fetched states
+    let resp = ctx.client
+        .post(format!("{}/api/v1/scheduler/decks/{}/cards/{}/answer-with-states", ctx.base_url, deck_id, card_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "rating": 2,
+            "states": states,
+            "custom_data": { "c": 1 },
+            "milliseconds_taken": 3000
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    // 7. The same states are now stale
+    let resp = ctx.client
+        .post(format!("{}/api/v1/scheduler/decks/{}/cards/{}/answer-with-states", ctx.base_url, deck_id, card_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "rating": 2, "states": states }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 409);
+
+    // 8. The queue reflects the answer
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/decks/{}/counts", ctx.base_url, deck_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["new"], 1);
+    assert_eq!(body["learning"], 1);
+}
+
+#[tokio::test]
+async fn test_prefetch_and_batch_answers() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    // 2. Three new cards in the default deck
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["decks"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notetypes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let notetype_id = body["notetypes"][0]["id"].as_i64().unwrap();
+
+    for front in ["One", "Two", "Three"] {
+        let resp = ctx.client
+            .post(format!("{}/api/v1/notes", ctx.base_url))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({
+                "deck_id": deck_id,
+                "notetype_id": notetype_id,
+                "fields": [front, "Answer"],
+                "tags": []
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+    }
+
+    // 3. Prefetch two of them
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/decks/{}/queue?limit=2", ctx.base_url, deck_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let cards = body["cards"].as_array().unwrap().clone();
+    assert_eq!(cards.len(), 2);
+    assert_eq!(body["counts"]["new"], 3);
+    assert!(body["learn_ahead_cutoff"].as_i64().unwrap() > body["fetched_at"].as_i64().unwrap());
+    assert!(body["next_day_at"].as_i64().unwrap() > body["fetched_at"].as_i64().unwrap());
+    assert!(cards[0]["question_html"].as_str().unwrap().contains("One"));
+    assert_eq!(cards[0]["next_states"].as_array().unwrap().len(), 4);
+    assert_eq!(cards[0]["states"]["current"]["kind"], "new");
+    assert!(cards[0]["due_at"].is_null());
+
+    // 4. Upload answers made offline, including a stale repeat and an
+    //    unknown card
+    let answered_at = body["fetched_at"].as_i64().unwrap() * 1000;
+    let answer = |card: &serde_json::Value, rating: u8| {
+        json!({
+            "card_id": card["card_id"],
+            "rating": rating,
+            "states": card["states"],
+            "answered_at": answered_at,
+            "milliseconds_taken": 4000
+        })
+    };
+    let resp = ctx.client
+        .post(format!("{}/api/v1/scheduler/decks/{}/answers", ctx.base_url, deck_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "answers": [
+                answer(&cards[1], 2),
+                answer(&cards[0], 0),
+                answer(&cards[0], 2),
+                {
+                    "card_id": 1,
+                    "rating": 2,
+                    "states": cards[0]["states"]
+                }
+            ]
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["answered"], 2);
+    let results = body["results"].as_array().unwrap();
+    let statuses: Vec<_> = results.iter().map(|r| r["status"].as_str().unwrap()).collect();
+    assert_eq!(statuses, ["answered", "answered", "conflict", "not_found"]);
+    assert_eq!(results[0]["card_id"], cards[1]["card_id"]);
+
+    // 5. The queue reflects the uploaded answers
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/decks/{}/queue", ctx.base_url, deck_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["counts"]["new"], 1);
+    assert_eq!(body["counts"]["learning"], 2);
+    let learning = body["cards"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|card| card["states"]["current"]["kind"] == "learning")
+        .unwrap();
+    assert!(learning["due_at"].is_i64());
+}
+
+#[tokio::test]
+async fn test_reviewer_settings_congrats_and_leeches() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+    let user_id = body["data"]["user"]["id"].as_i64().unwrap();
+
+    // 2. One new card in the default deck
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["decks"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notetypes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let notetype_id = body["notetypes"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "deck_id": deck_id,
+            "notetype_id": notetype_id,
+            "fields": ["Question", "Answer"],
+            "tags": []
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let note_id = body["note_id"].as_i64().unwrap();
+
+    // 3. The card carries the settings of the default preset
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/decks/{}/next", ctx.base_url, deck_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let card_id = body["card"]["card_id"].as_i64().unwrap();
+    let settings = &body["card"]["settings"];
+    assert_eq!(settings["seconds_to_show_question"], 0.0);
+    assert_eq!(settings["question_action"], "show_answer");
+    assert_eq!(settings["answer_action"], "bury_card");
+    assert_eq!(settings["autoplay"], true);
+    assert_eq!(settings["show_timer"], false);
+    assert_eq!(settings["max_answer_secs"], 60);
+    assert!(body["congrats"].is_null());
+    assert!(body["learn_ahead_cutoff"].as_i64().unwrap() > 0);
+
+    // 4. Long answer times are capped, as on the desktop
+    let resp = ctx.client
+        .post(format!("{}/api/v1/scheduler/decks/{}/cards/{}/answer", ctx.base_url, deck_id, card_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "rating": 4, // Easy
+            "milliseconds_taken": 600_000
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["milliseconds_taken"], 60_000);
+    assert_eq!(body["leech"], false);
+    assert!(body["notice"].is_null());
+
+    // 5. The finished deck reports congrats info
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/decks/{}/next", ctx.base_url, deck_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert!(body["finished"].as_bool().unwrap());
+    assert_eq!(body["congrats"]["learn_remaining"], 0);
+    assert!(body["congrats"]["secs_until_next_learn"].is_null());
+    assert_eq!(body["congrats"]["is_filtered_deck"], false);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/decks/{}/congrats", ctx.base_url, deck_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["new_remaining"], false);
+    assert_eq!(body["have_user_buried"], false);
+
+    // Looking at another deck's congrats leaves the current deck alone
+    let resp = ctx.client
+        .post(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "name": "Other" }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let other_id = body["id"].as_i64().unwrap();
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/decks/{}/congrats", ctx.base_url, other_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let backend = ctx
+        .backend_manager
+        .get_or_create_backend(user_id, "testuser")
+        .unwrap();
+    assert_eq!(lock_collection(&backend).get_current_deck().unwrap().id.0, deck_id);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/decks/12345/congrats", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    // 6. Lapse the card until it reaches the default leech threshold of 8
+    let mut body = serde_json::Value::Null;
+    for _ in 0..8 {
+        lock_collection(&backend)
+            .set_due_date(&[CardId(card_id)], "0", None)
+            .unwrap();
+        let resp = ctx.client
+            .post(format!("{}/api/v1/scheduler/decks/{}/cards/{}/answer", ctx.base_url, deck_id, card_id))
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Accept-Language", "en")
+            .json(&json!({
+                "rating": 1, // Again
+                "milliseconds_taken": 5000
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        body = resp.json().await.unwrap();
+    }
+    drop(backend);
+    assert_eq!(body["leech"], true);
+    assert_eq!(body["suspended"], false);
+    assert_eq!(body["notice"], "Card was a leech.");
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notes/{}", ctx.base_url, note_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["tags"], json!(["leech"]));
+}
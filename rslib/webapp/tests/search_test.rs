@@ -140,3 +140,161 @@ async fn test_search_and_replace() {
     let body: serde_json::Value = resp.json().await.unwrap();
     assert_eq!(body["fields"][0], "SuperBanana");
 }
+
+#[tokio::test]
+async fn test_search_sort_paging_and_fields() {
+    let ctx = TestContext::new().await;
+
+    let resp = ctx
+        .client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "pageuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .expect("Failed to register");
+    assert_eq!(resp.status(), 201);
+
+    let resp = ctx
+        .client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "pageuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .expect("Failed to login");
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    let resp = ctx
+        .client
+        .get(format!("{}/api/v1/notetypes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let notetype_id = body["notetypes"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx
+        .client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["decks"][0]["id"].as_i64().unwrap();
+
+    // Create notes whose sort fields are out of insertion order
+    for front in ["Pager C", "Pager A", "Pager B"] {
+        let resp = ctx
+            .client
+            .post(format!("{}/api/v1/notes", ctx.base_url))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({
+                "deck_id": deck_id,
+                "notetype_id": notetype_id,
+                "fields": [front, "back"],
+                "tags": ["paging"]
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+    }
+
+    // First page, sorted by sort field, with fields projected
+    let resp = ctx
+        .client
+        .post(format!("{}/api/v1/search/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "query": "tag:paging",
+            "sort_column": "noteFld",
+            "limit": 2,
+            "fields": ["fields"]
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 3);
+    assert_eq!(body["note_ids"].as_array().unwrap().len(), 2);
+    assert_eq!(body["notes"][0]["fields"][0], "Pager A");
+    assert_eq!(body["notes"][1]["fields"][0], "Pager B");
+    assert_eq!(body["notes"][0]["id"], body["note_ids"][0]);
+    assert!(body["notes"][0].get("tags").is_none());
+    let next_offset = body["next_offset"].as_u64().unwrap();
+    assert_eq!(next_offset, 2);
+
+    // Second page via the offset is the last one
+    let resp = ctx
+        .client
+        .post(format!("{}/api/v1/search/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "query": "tag:paging",
+            "sort_column": "noteFld",
+            "limit": 2,
+            "offset": next_offset,
+            "fields": ["fields"]
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 3);
+    assert_eq!(body["notes"][0]["fields"][0], "Pager C");
+    assert!(body["next_offset"].is_null());
+
+    // Reverse-sorted cards with inline card summaries
+    let resp = ctx
+        .client
+        .post(format!("{}/api/v1/search/cards", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "query": "tag:paging",
+            "sort_column": "noteFld",
+            "reverse": true,
+            "offset": 1,
+            "fields": ["deck_id", "queue"]
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 3);
+    assert_eq!(body["cards"].as_array().unwrap().len(), 2);
+    assert_eq!(body["cards"][0]["deck_id"], deck_id);
+    assert!(body["cards"][0].get("reps").is_none());
+
+    // Unknown sort columns and projected fields are rejected
+    let resp = ctx
+        .client
+        .post(format!("{}/api/v1/search/cards", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "query": "tag:paging", "sort_column": "bogus" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 422);
+
+    let resp = ctx
+        .client
+        .post(format!("{}/api/v1/search/notes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "query": "tag:paging", "fields": ["bogus"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+}
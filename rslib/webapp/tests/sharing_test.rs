@@ -0,0 +1,346 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use serde_json::json;
+use serde_json::Value;
+mod common;
+use common::TestContext;
+
+async fn register(ctx: &TestContext, username: &str) -> String {
+    let resp = ctx
+        .client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": username,
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let body: Value = resp.json().await.unwrap();
+    body["data"]["token"].as_str().unwrap().to_string()
+}
+
+async fn get(ctx: &TestContext, token: &str, path: &str) -> Value {
+    let resp = ctx
+        .client
+        .get(format!("{}{}", ctx.base_url, path))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200, "GET {path}");
+    resp.json().await.unwrap()
+}
+
+async fn send(
+    ctx: &TestContext,
+    token: &str,
+    method: reqwest::Method,
+    path: &str,
+    body: Option<Value>,
+) -> (u16, Value) {
+    let mut request = ctx
+        .client
+        .request(method, format!("{}{}", ctx.base_url, path))
+        .header("Authorization", format!("Bearer {}", token));
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let resp = request.send().await.unwrap();
+    let status = resp.status().as_u16();
+    (status, resp.json().await.unwrap_or(Value::Null))
+}
+
+async fn deck_id(ctx: &TestContext, token: &str, name: &str) -> i64 {
+    let tree = get(ctx, token, "/api/v1/decks").await;
+    tree["decks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|deck| deck["name"] == name)
+        .unwrap()["id"]
+        .as_i64()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_publish_subscribe_and_update() {
+    let ctx = TestContext::new().await;
+    let teacher = register(&ctx, "teacher").await;
+    let student = register(&ctx, "student").await;
+
+    // 1. The teacher creates a deck with a note
+    let (status, body) = send(
+        &ctx,
+        &teacher,
+        reqwest::Method::POST,
+        "/api/v1/decks",
+        Some(json!({ "name": "Spanish" })),
+    )
+    .await;
+    assert_eq!(status, 201);
+    let teacher_deck = body["id"].as_i64().unwrap();
+    let notetypes = get(&ctx, &teacher, "/api/v1/notetypes").await;
+    let basic_id = notetypes["notetypes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|notetype| notetype["name"] == "Basic")
+        .unwrap()["id"]
+        .as_i64()
+        .unwrap();
+    let (status, body) = send(
+        &ctx,
+        &teacher,
+        reqwest::Method::POST,
+        "/api/v1/notes",
+        Some(json!({
+            "deck_id": teacher_deck,
+            "notetype_id": basic_id,
+            "fields": ["hola", "hello"],
+            "tags": []
+        })),
+    )
+    .await;
+    assert_eq!(status, 201);
+    let teacher_note = body["note_id"].as_i64().unwrap();
+
+    // 2. Publishing adds it to the library
+    let (status, body) = send(
+        &ctx,
+        &teacher,
+        reqwest::Method::POST,
+        "/api/v1/shared-decks",
+        Some(json!({ "deck_id": teacher_deck, "description": "Week 1" })),
+    )
+    .await;
+    assert_eq!(status, 201);
+    assert_eq!(body["version"], 1);
+    assert_eq!(body["note_count"], 1);
+    assert_eq!(body["owner_username"], "teacher");
+    let shared = format!("/api/v1/shared-decks/{}", body["id"]);
+
+    let library = get(&ctx, &student, "/api/v1/shared-decks").await;
+    assert_eq!(library["decks"].as_array().unwrap().len(), 1);
+    assert_eq!(library["decks"][0]["name"], "Spanish");
+    assert_eq!(library["decks"][0]["description"], "Week 1");
+
+    // 3. The student subscribes, importing the deck once
+    let (status, body) = send(
+        &ctx,
+        &student,
+        reqwest::Method::POST,
+        &format!("{shared}/subscription"),
+        None,
+    )
+    .await;
+    assert_eq!(status, 201);
+    assert_eq!(body["version"], 1);
+    assert_eq!(body["notes_new"], 1);
+
+    let (status, _) = send(
+        &ctx,
+        &student,
+        reqwest::Method::POST,
+        &format!("{shared}/subscription"),
+        None,
+    )
+    .await;
+    assert_eq!(status, 409);
+    let (status, _) = send(
+        &ctx,
+        &teacher,
+        reqwest::Method::POST,
+        &format!("{shared}/subscription"),
+        None,
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    // 4. The student studies the card
+    let student_deck = deck_id(&ctx, &student, "Spanish").await;
+    let scheduler = format!("/api/v1/scheduler/decks/{student_deck}");
+    let next = get(&ctx, &student, &format!("{scheduler}/next")).await;
+    let card_id = next["card"]["card_id"].as_i64().unwrap();
+    let (status, _) = send(
+        &ctx,
+        &student,
+        reqwest::Method::POST,
+        &format!("{scheduler}/cards/{card_id}/answer"),
+        Some(json!({ "rating": 2, "milliseconds_taken": 5000 })),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let studied = get(&ctx, &student, &format!("/api/v1/cards/{card_id}")).await;
+    assert_eq!(studied["reps"], 1);
+
+    // 5. The teacher edits the note, adds another and publishes again
+    let (status, _) = send(
+        &ctx,
+        &teacher,
+        reqwest::Method::PUT,
+        &format!("/api/v1/notes/{teacher_note}"),
+        Some(json!({ "fields": ["¡hola!", "hello"], "tags": [] })),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (status, _) = send(
+        &ctx,
+        &teacher,
+        reqwest::Method::POST,
+        "/api/v1/notes",
+        Some(json!({
+            "deck_id": teacher_deck,
+            "notetype_id": basic_id,
+            "fields": ["adiós", "goodbye"],
+            "tags": []
+        })),
+    )
+    .await;
+    assert_eq!(status, 201);
+    let (status, body) = send(
+        &ctx,
+        &teacher,
+        reqwest::Method::POST,
+        "/api/v1/shared-decks",
+        Some(json!({ "deck_id": teacher_deck })),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["version"], 2);
+    assert_eq!(body["note_count"], 2);
+    assert_eq!(body["description"], "Week 1");
+
+    // 6. The student sees the update and pulls it
+    let subscriptions = get(&ctx, &student, "/api/v1/shared-decks/subscriptions").await;
+    assert_eq!(subscriptions["subscriptions"][0]["subscribed_version"], 1);
+    assert_eq!(subscriptions["subscriptions"][0]["update_available"], true);
+
+    let (status, body) = send(
+        &ctx,
+        &student,
+        reqwest::Method::POST,
+        &format!("{shared}/update"),
+        None,
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["version"], 2);
+    assert_eq!(body["notes_new"], 1);
+    assert_eq!(body["notes_updated"], 1);
+
+    // The changed note was updated, and the studied card kept its scheduling
+    let card = get(&ctx, &student, &format!("/api/v1/cards/{card_id}")).await;
+    assert_eq!(card["reps"], 1);
+    assert_eq!(card["queue"], studied["queue"]);
+    assert_eq!(card["due"], studied["due"]);
+    let note = get(
+        &ctx,
+        &student,
+        &format!("/api/v1/notes/{}", card["note_id"]),
+    )
+    .await;
+    assert_eq!(note["fields"][0], "¡hola!");
+
+    // Nothing more to pull
+    let subscriptions = get(&ctx, &student, "/api/v1/shared-decks/subscriptions").await;
+    assert_eq!(subscriptions["subscriptions"][0]["update_available"], false);
+    let (status, body) = send(
+        &ctx,
+        &student,
+        reqwest::Method::POST,
+        &format!("{shared}/update"),
+        None,
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["notes_new"], 0);
+    assert_eq!(body["notes_updated"], 0);
+}
+
+#[tokio::test]
+async fn test_unsubscribe_and_unpublish() {
+    let ctx = TestContext::new().await;
+    let teacher = register(&ctx, "teacher").await;
+    let student = register(&ctx, "student").await;
+
+    let (_, body) = send(
+        &ctx,
+        &teacher,
+        reqwest::Method::POST,
+        "/api/v1/decks",
+        Some(json!({ "name": "French" })),
+    )
+    .await;
+    let deck = body["id"].as_i64().unwrap();
+
+    // Missing decks can't be published
+    let (status, _) = send(
+        &ctx,
+        &teacher,
+        reqwest::Method::POST,
+        "/api/v1/shared-decks",
+        Some(json!({ "deck_id": 1234567 })),
+    )
+    .await;
+    assert_eq!(status, 404);
+
+    let (status, body) = send(
+        &ctx,
+        &teacher,
+        reqwest::Method::POST,
+        "/api/v1/shared-decks",
+        Some(json!({ "deck_id": deck })),
+    )
+    .await;
+    assert_eq!(status, 201);
+    let shared = format!("/api/v1/shared-decks/{}", body["id"]);
+
+    let (status, _) = send(
+        &ctx,
+        &student,
+        reqwest::Method::POST,
+        &format!("{shared}/subscription"),
+        None,
+    )
+    .await;
+    assert_eq!(status, 201);
+    let (status, _) = send(
+        &ctx,
+        &student,
+        reqwest::Method::DELETE,
+        &format!("{shared}/subscription"),
+        None,
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (status, _) = send(
+        &ctx,
+        &student,
+        reqwest::Method::POST,
+        &format!("{shared}/update"),
+        None,
+    )
+    .await;
+    assert_eq!(status, 404);
+
+    // Only the owner can unpublish
+    let (status, _) = send(&ctx, &student, reqwest::Method::DELETE, &shared, None).await;
+    assert_eq!(status, 403);
+    let (status, _) = send(&ctx, &teacher, reqwest::Method::DELETE, &shared, None).await;
+    assert_eq!(status, 200);
+
+    let library = get(&ctx, &student, "/api/v1/shared-decks").await;
+    assert!(library["decks"].as_array().unwrap().is_empty());
+    let (status, _) = send(
+        &ctx,
+        &student,
+        reqwest::Method::POST,
+        &format!("{shared}/subscription"),
+        None,
+    )
+    .await;
+    assert_eq!(status, 404);
+}
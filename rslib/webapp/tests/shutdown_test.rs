@@ -0,0 +1,99 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use std::time::Duration;
+
+use anki_webapp::WebAppConfig;
+use anki_webapp::WebAppServer;
+use serde_json::json;
+use tempfile::TempDir;
+use tokio_util::sync::CancellationToken;
+
+#[tokio::test]
+async fn test_graceful_shutdown_closes_collections() {
+    let temp_dir = TempDir::new().unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = WebAppConfig {
+        host: "127.0.0.1".parse().unwrap(),
+        port,
+        data_dir: temp_dir.path().to_path_buf(),
+        jwt_secret: "test-secret-must-be-long-enough-for-hs256-at-least-32-chars".to_string(),
+        backup_interval_mins: 0,
+        shutdown_timeout_secs: 5,
+        ..WebAppConfig::default()
+    };
+
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(WebAppServer::with_config(config).run_until(shutdown.clone()));
+    let base_url = format!("http://127.0.0.1:{}", port);
+    let client = reqwest::Client::new();
+
+    // 1. Wait for the server to come up, then open a collection
+    let mut registered = false;
+    for _ in 0..50 {
+        let resp = client
+            .post(format!("{}/api/v1/auth/register", base_url))
+            .json(&json!({
+                "username": "testuser",
+                "password": "password123"
+            }))
+            .send()
+            .await;
+        if resp.is_ok() {
+            registered = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(registered, "server did not start");
+
+    let resp = client
+        .post(format!("{}/api/v1/auth/login", base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+    let user_id = body["data"]["user"]["id"].as_i64().unwrap();
+
+    let resp = client
+        .post(format!("{}/api/v1/decks", base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "name": "Before Shutdown" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    let col_path = temp_dir
+        .path()
+        .join(format!("users/user_{}/testuser.anki2", user_id));
+    let wal_path = col_path.with_extension("anki2-wal");
+    assert!(wal_path.exists());
+
+    // 2. Shutting down returns cleanly and checkpoints the collection
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(10), server)
+        .await
+        .expect("shutdown timed out")
+        .unwrap()
+        .expect("server failed");
+    assert!(col_path.exists());
+    assert!(!wal_path.exists());
+
+    // 3. No more connections are accepted
+    assert!(client
+        .get(format!("{}/api/v1/decks", base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .is_err());
+}
@@ -0,0 +1,229 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use serde_json::json;
+mod common;
+use common::TestContext;
+
+#[tokio::test]
+async fn test_tag_operations() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    // 2. Create two notes
+    let resp = ctx.client
+        .get(format!("{}/api/v1/notetypes", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let notetype_id = body["notetypes"][0]["id"].as_i64().unwrap();
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let deck_id = body["decks"][0]["id"].as_i64().unwrap();
+
+    let mut note_ids = vec![];
+    for front in ["one", "two"] {
+        let resp = ctx.client
+            .post(format!("{}/api/v1/notes", ctx.base_url))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({
+                "deck_id": deck_id,
+                "notetype_id": notetype_id,
+                "fields": [front, "back"],
+                "tags": []
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        note_ids.push(body["note_id"].as_i64().unwrap());
+    }
+
+    // 3. Add tags by id and by search
+    let resp = ctx.client
+        .post(format!("{}/api/v1/tags/notes/add", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "note_ids": [note_ids[0]], "tags": "lang::french verbs" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 1);
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/tags/notes/add", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "search": "", "tags": "lang::german" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 2);
+
+    // Neither ids nor search
+    let resp = ctx.client
+        .post(format!("{}/api/v1/tags/notes/add", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "tags": "nothing" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // 4. Remove a tag
+    let resp = ctx.client
+        .post(format!("{}/api/v1/tags/notes/remove", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "search": "tag:verbs", "tags": "verbs" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 1);
+
+    // 5. Find and replace with a regex
+    let resp = ctx.client
+        .post(format!("{}/api/v1/tags/find-replace", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "note_ids": note_ids,
+            "find": "^lang::ger.*$",
+            "replacement": "lang::deutsch",
+            "regex": true
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 2);
+
+    // Invalid regex
+    let resp = ctx.client
+        .post(format!("{}/api/v1/tags/find-replace", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "search": "",
+            "find": "(",
+            "replacement": "x",
+            "regex": true
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "invalid_regex");
+
+    // 6. Reparent
+    let resp = ctx.client
+        .post(format!("{}/api/v1/tags/reparent", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "tags": ["lang"], "new_parent": "study" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["count"], 2);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/tags", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let tags: Vec<&str> = body["tags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t.as_str().unwrap())
+        .collect();
+    assert!(tags.contains(&"study::lang::deutsch"));
+    assert!(tags.contains(&"study::lang::french"));
+
+    // 7. Completion
+    let resp = ctx.client
+        .get(format!(
+            "{}/api/v1/tags/complete?input=study::lang::fr",
+            ctx.base_url
+        ))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["tags"], json!(["study::lang::french"]));
+
+    // 8. Collapse state
+    let resp = ctx.client
+        .put(format!("{}/api/v1/tags/collapse", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "tag": "study", "collapsed": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let resp = ctx.client
+        .get(format!("{}/api/v1/tags/tree", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let study = body["root"]["children"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|node| node["name"] == "study")
+        .unwrap();
+    assert_eq!(study["collapsed"], true);
+
+    let resp = ctx.client
+        .put(format!("{}/api/v1/tags/collapse", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "tag": "missing", "collapsed": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
@@ -0,0 +1,144 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use serde_json::json;
+mod common;
+use common::TestContext;
+
+async fn login(ctx: &TestContext) -> String {
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/login", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    body["data"]["token"].as_str().unwrap().to_string()
+}
+
+async fn undo_status(ctx: &TestContext, token: &str) -> serde_json::Value {
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/undo-status", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    resp.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_undo_and_redo() {
+    let ctx = TestContext::new().await;
+
+    // 1. Setup Auth
+    ctx.client
+        .post(format!("{}/api/v1/auth/register", ctx.base_url))
+        .json(&json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .send()
+        .await
+        .unwrap();
+    let token = login(&ctx).await;
+
+    // 2. Nothing to undo yet
+    let status = undo_status(&ctx, &token).await;
+    assert!(status["undo"].is_null());
+    assert!(status["redo"].is_null());
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/scheduler/undo", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 409);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "undo_empty");
+
+    // 3. Adding a deck can be undone
+    let resp = ctx.client
+        .post(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "name": "Spanish" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    let status = undo_status(&ctx, &token).await;
+    assert_eq!(status["undo"], "Undo Add Deck");
+    assert!(status["redo"].is_null());
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/scheduler/undo", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["op"], "Add Deck");
+    assert_eq!(body["message"], "Add Deck undone");
+    assert_eq!(body["changes"]["deck"], true);
+    assert_eq!(body["changes"]["note"], false);
+    assert_eq!(body["changes"]["study_queues"], true);
+    assert!(body["undo_status"]["undo"].is_null());
+    assert_eq!(body["undo_status"]["redo"], "Redo Add Deck");
+
+    // Reading congrats info doesn't touch the undo queue
+    let resp = ctx.client
+        .get(format!("{}/api/v1/scheduler/decks/1/congrats", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let status = undo_status(&ctx, &token).await;
+    assert_eq!(status["redo"], "Redo Add Deck");
+
+    // 4. And redone
+    let resp = ctx.client
+        .post(format!("{}/api/v1/scheduler/redo", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["message"], "Add Deck redone");
+    assert_eq!(body["undo_status"]["undo"], "Undo Add Deck");
+
+    // 5. A new session doesn't inherit the previous one's undo history,
+    // even if the collection is still open
+    let second = login(&ctx).await;
+    let status = undo_status(&ctx, &second).await;
+    assert!(status["undo"].is_null());
+
+    let resp = ctx.client
+        .post(format!("{}/api/v1/decks", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", second))
+        .json(&json!({ "name": "French" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // 6. Logging out of one session leaves the other's history alone
+    let resp = ctx.client
+        .post(format!("{}/api/v1/auth/logout", ctx.base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let status = undo_status(&ctx, &second).await;
+    assert_eq!(status["undo"], "Undo Add Deck");
+}